@@ -0,0 +1,33 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Structured event recognized in a server's console output.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ConsoleEvent {
+    ServerReady,
+    ServerStopping,
+    Authenticated { player: String, uuid: Uuid },
+    Login { player: String, address: String },
+    Join { player: String },
+    Leave { player: String },
+    Disconnect { player: String, reason: String },
+    Chat { player: String, message: String },
+    Death { player: String, message: String },
+}
+
+impl ConsoleEvent {
+    pub fn player(&self) -> Option<String> {
+        match self {
+            Self::ServerReady | Self::ServerStopping => None,
+            Self::Authenticated { player, .. }
+            | Self::Login { player, .. }
+            | Self::Join { player }
+            | Self::Leave { player }
+            | Self::Disconnect { player, .. }
+            | Self::Chat { player, .. }
+            | Self::Death { player, .. } => Some(player.clone()),
+        }
+    }
+}
@@ -0,0 +1,7 @@
+mod events;
+mod parser;
+mod patterns;
+
+pub use events::*;
+pub use parser::*;
+pub use patterns::*;
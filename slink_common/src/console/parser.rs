@@ -0,0 +1,94 @@
+use regex::Regex;
+use uuid::Uuid;
+
+use crate::types::Version;
+
+use super::{ConsoleEvent, LogFormat, LogPatterns};
+
+/// Incrementally splits raw console output into lines and extracts events from them.
+#[derive(Clone, Debug)]
+pub struct ConsoleParser {
+    patterns: LogPatterns,
+    ansi: Regex,
+    pending: String,
+}
+
+impl ConsoleParser {
+    pub fn new(format: LogFormat, version: Option<&Version>) -> Self {
+        Self {
+            patterns: LogPatterns::new(format, version),
+            ansi: Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").expect("valid ANSI pattern"),
+            pending: String::new(),
+        }
+    }
+
+    /// Consumes a chunk of output, returning events for every line it completes.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<ConsoleEvent> {
+        self.pending.push_str(&String::from_utf8_lossy(chunk));
+
+        let mut events = Vec::new();
+        while let Some(end) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=end).collect();
+            if let Some(event) = self.parse_line(&line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    pub fn parse_line(&self, line: &str) -> Option<ConsoleEvent> {
+        let line = self.ansi.replace_all(line.trim_end_matches(['\r', '\n']), "");
+        let patterns = &self.patterns;
+
+        if patterns.ready.is_match(&line) {
+            return Some(ConsoleEvent::ServerReady);
+        }
+        if patterns.stopping.is_match(&line) {
+            return Some(ConsoleEvent::ServerStopping);
+        }
+        if let Some(captures) = patterns.authenticated.captures(&line)
+            && let Ok(uuid) = Uuid::parse_str(&captures["uuid"])
+        {
+            return Some(ConsoleEvent::Authenticated {
+                player: captures["player"].to_string(),
+                uuid,
+            });
+        }
+        if let Some(captures) = patterns.login.captures(&line) {
+            return Some(ConsoleEvent::Login {
+                player: captures["player"].to_string(),
+                address: captures["address"].to_string(),
+            });
+        }
+        if let Some(captures) = patterns.join.captures(&line) {
+            return Some(ConsoleEvent::Join {
+                player: captures["player"].to_string(),
+            });
+        }
+        if let Some(captures) = patterns.leave.captures(&line) {
+            return Some(ConsoleEvent::Leave {
+                player: captures["player"].to_string(),
+            });
+        }
+        if let Some(captures) = patterns.disconnect.captures(&line) {
+            return Some(ConsoleEvent::Disconnect {
+                player: captures["player"].to_string(),
+                reason: captures["reason"].to_string(),
+            });
+        }
+        if let Some(captures) = patterns.chat.captures(&line) {
+            return Some(ConsoleEvent::Chat {
+                player: captures["player"].to_string(),
+                message: captures["message"].to_string(),
+            });
+        }
+        if let Some(captures) = patterns.death.captures(&line) {
+            return Some(ConsoleEvent::Death {
+                player: captures["player"].to_string(),
+                message: format!("{} {}", &captures["player"], &captures["message"]),
+            });
+        }
+
+        None
+    }
+}
@@ -0,0 +1,134 @@
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::types::Version;
+
+const DEATH_MESSAGES: &str = concat!(
+    r"(?:was |drowned|died|blew up|fell |hit the ground|experienced kinetic energy|",
+    r"went up in flames|went off with a bang|burned to death|walked into|tried to swim in lava|",
+    r"suffocated|starved|froze to death|withered away|discovered the floor was lava|",
+    r"didn't want to live|left the confines)"
+);
+
+/// Line layout used by the server's logger.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// `[12:00:00] [Server thread/INFO]: message`
+    Vanilla,
+
+    /// `[12:00:00 INFO]: message`
+    Paper,
+
+    /// `[12:00:00] [Server thread/INFO] (Minecraft) message`
+    Fabric,
+}
+
+impl LogFormat {
+    fn prefix(&self) -> &'static str {
+        match self {
+            Self::Vanilla => r"^\[[\d:.]+\] \[[^\]]+/INFO\]: ",
+            Self::Paper => r"^\[[\d:.]+ INFO\]: ",
+            Self::Fabric => r"^\[[\d:.]+\] \[[^\]]+/INFO\] \([^)]+\) ",
+        }
+    }
+}
+
+const READY: &str = r#"Done \([\d.,]+s\)! For help, type "help""#;
+const STOPPING: &str = r"Stopping (?:the )?server";
+const AUTHENTICATED: &str = r"UUID of player (?P<player>\w+) is (?P<uuid>[0-9a-fA-F-]{36})";
+const LOGIN: &str = r"(?P<player>\w+)\[/(?P<address>[^\]]+)\] logged in with entity id";
+const JOIN: &str = r"(?P<player>\w+) joined the game";
+const LEAVE: &str = r"(?P<player>\w+) left the game";
+const DISCONNECT: &str = r"(?P<player>\w+) lost connection: (?P<reason>.*)";
+
+/// Chat message patterns by the Minecraft version they apply from, newest first. Servers since 1.19 mark
+/// messages without a valid chat signature as `[Not Secure]`.
+fn chat_patterns() -> [(Version, &'static str); 2] {
+    [
+        (
+            Version { major: 1, minor: 19, patch: 0 },
+            r"(?:\[Not Secure\] )?<(?P<player>\w+)> (?P<message>.*)",
+        ),
+        (Version { major: 0, minor: 0, patch: 0 }, r"<(?P<player>\w+)> (?P<message>.*)"),
+    ]
+}
+
+/// Compiled patterns for one log format and Minecraft version.
+#[derive(Clone, Debug)]
+pub struct LogPatterns {
+    pub format: LogFormat,
+    pub ready: Regex,
+    pub stopping: Regex,
+    pub authenticated: Regex,
+    pub login: Regex,
+    pub join: Regex,
+    pub leave: Regex,
+    pub disconnect: Regex,
+    pub chat: Regex,
+    pub death: Regex,
+}
+
+impl LogPatterns {
+    /// Selects the newest chat pattern not newer than `version`, or the newest overall if the version is unknown.
+    pub fn new(format: LogFormat, version: Option<&Version>) -> Self {
+        let candidates = chat_patterns();
+        let (_, chat) = candidates
+            .iter()
+            .find(|(since, _)| version.is_none_or(|v| since <= v))
+            .unwrap_or(&candidates[candidates.len() - 1]);
+
+        let compile = |message: &str| {
+            Regex::new(&format!("{}{}$", format.prefix(), message)).expect("valid console pattern")
+        };
+
+        Self {
+            format,
+            ready: compile(READY),
+            stopping: compile(STOPPING),
+            authenticated: compile(AUTHENTICATED),
+            login: compile(LOGIN),
+            join: compile(JOIN),
+            leave: compile(LEAVE),
+            disconnect: compile(DISCONNECT),
+            chat: compile(chat),
+            death: compile(&format!(r"(?P<player>\w+) (?P<message>{DEATH_MESSAGES}.*)")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VANILLA_CHAT: &str = "[12:00:00] [Server thread/INFO]: [Not Secure] <Steve> hello";
+
+    #[test]
+    fn chat_pattern_follows_the_version() {
+        let modern = LogPatterns::new(LogFormat::Vanilla, Some(&Version { major: 1, minor: 20, patch: 4 }));
+        let captures = modern.chat.captures(VANILLA_CHAT).unwrap();
+        assert_eq!((&captures["player"], &captures["message"]), ("Steve", "hello"));
+
+        // Before 1.19 the marker is part of someone's message, not a chat line
+        let legacy = LogPatterns::new(LogFormat::Vanilla, Some(&Version { major: 1, minor: 18, patch: 2 }));
+        assert!(!legacy.chat.is_match(VANILLA_CHAT));
+        assert!(legacy.chat.is_match("[12:00:00] [Server thread/INFO]: <Steve> hello"));
+
+        assert!(LogPatterns::new(LogFormat::Vanilla, None).chat.is_match(VANILLA_CHAT));
+    }
+
+    #[test]
+    fn shared_patterns_apply_to_every_format() {
+        for (format, line) in [
+            (LogFormat::Vanilla, "[12:00:00] [Server thread/INFO]: Steve joined the game"),
+            (LogFormat::Paper, "[12:00:00 INFO]: Steve joined the game"),
+            (LogFormat::Fabric, "[12:00:00] [Server thread/INFO] (Minecraft) Steve joined the game"),
+        ] {
+            for version in [None, Some(Version { major: 1, minor: 12, patch: 2 })] {
+                let patterns = LogPatterns::new(format, version.as_ref());
+                assert_eq!(&patterns.join.captures(line).unwrap()["player"], "Steve", "{format:?}");
+            }
+        }
+    }
+}
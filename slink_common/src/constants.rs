@@ -12,7 +12,48 @@ pub const SERVER_BINARY_NAME: &'static str = "server.jar";
 // Security constants
 pub const HASHING_MEMORY: u32 = 16;
 pub const HASHING_ITERATIONS: u32 = 4;
+pub const PASSWORD_MIN_LENGTH: usize = 8;
+pub const TOTP_ISSUER: &'static str = "Slink";
+pub const TOTP_DIGITS: u32 = 6;
+pub const TOTP_PERIOD_SECONDS: u64 = 30;
+pub const TOTP_SKEW_STEPS: u64 = 1;
+pub const TOTP_SECRET_BYTES: usize = 20;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+pub const SESSION_COOKIE: &'static str = "slink.token";
+pub const SESSION_TOUCH_SECONDS: i64 = 60;
+pub const SESSION_SWEEP_SECONDS: u64 = 60 * 60;
+pub const SESSION_USER_AGENT_LENGTH: usize = 256;
+pub const RATE_LIMIT_MAX_KEYS: usize = 10_000;
 
 // Networking constants
 pub const USER_AGENT: &'static str = formatcp!("{APP_NAME}/{APP_VERSION}");
 pub const MINECRAFT_VERSIONS_MANIFEST: &'static str = "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
+pub const MOJANG_PROFILE_API: &'static str = "https://api.mojang.com/users/profiles/minecraft";
+pub const MODRINTH_API: &'static str = "https://api.modrinth.com/v2";
+pub const CURSEFORGE_API: &'static str = "https://api.curseforge.com/v1";
+
+// Protocol constants
+pub const PROTOCOL_TIMEOUT_SECONDS: u64 = 5;
+
+// File management constants
+pub const MAX_TEXT_FILE_SIZE: u64 = 5 * 1024 * 1024;
+pub const ARCHIVE_MAX_EXTRACTED_SIZE: u64 = 32 * 1024 * 1024 * 1024;
+pub const ARCHIVE_MAX_ENTRIES: u64 = 250_000;
+
+// Backup constants
+pub const BACKUP_SAVE_TIMEOUT_SECONDS: u64 = 60;
+pub const INCREMENTAL_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+pub const STORAGE_TIMEOUT_SECONDS: u64 = 30;
+pub const S3_PART_SIZE: usize = 16 * 1024 * 1024;
+pub const S3_PART_RETRIES: u32 = 3;
+
+// Scheduler constants
+pub const SCHEDULER_TICK_SECONDS: u64 = 15;
+pub const SCHEDULE_MISSED_GRACE_SECONDS: i64 = 300;
+
+// Content constants
+pub const CONTENT_LOCKFILE: &'static str = "slink.lock.json";
+pub const CONTENT_SEARCH_LIMIT: u32 = 20;
+pub const MODPACK_INDEX: &'static str = "modrinth.index.json";
+pub const MODPACK_DOWNLOAD_CONCURRENCY: usize = 6;
+pub const CURSEFORGE_MANIFEST: &'static str = "manifest.json";
@@ -76,7 +76,26 @@ pub enum Error {
     DeserializationError(String),
 
     #[error("Failed to serialize input: {0}")]
-    SerializationError(String)
+    SerializationError(String),
+
+    #[error("Protocol error ({protocol}) while communicating with {address}: {reason}")]
+    ProtocolError {
+        protocol: String,
+        address: String,
+        reason: String,
+    },
+
+    #[error("Invalid path {path}: {reason}")]
+    PathError {
+        path: String,
+        reason: String,
+    },
+
+    #[error("Backup target {target} failed: {reason}")]
+    StorageError {
+        target: String,
+        reason: String,
+    }
 }
 
 impl Error {
@@ -132,6 +151,32 @@ impl Error {
     pub fn serialization(error: impl Debug) -> Self {
         Self::SerializationError(format!("{error:?}"))
     }
+
+    pub fn path(path: impl std::fmt::Display, reason: impl ToString) -> Self {
+        Self::PathError {
+            path: path.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn storage(target: impl AsRef<str>, reason: impl ToString) -> Self {
+        Self::StorageError {
+            target: target.as_ref().to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn protocol(
+        protocol: impl AsRef<str>,
+        address: impl AsRef<str>,
+        reason: impl ToString,
+    ) -> Self {
+        Self::ProtocolError {
+            protocol: protocol.as_ref().to_string(),
+            address: address.as_ref().to_string(),
+            reason: reason.to_string(),
+        }
+    }
 }
 
 pub type Res<T> = Result<T, Error>;
@@ -166,7 +211,19 @@ pub enum ApiError {
 
     #[error("Resource referenced by {0} not found.")]
     #[response(status = 404)]
-    NotFound(String)
+    NotFound(String),
+
+    #[error("Operation not possible in the current state: {0}")]
+    #[response(status = 409)]
+    InvalidState(String),
+
+    #[error("Bad request: {0}")]
+    #[response(status = 400)]
+    BadRequest(String),
+
+    #[error("Too many requests: {0}")]
+    #[response(status = 429)]
+    RateLimited(String)
 }
 
 impl ApiError {
@@ -196,11 +253,26 @@ impl ApiError {
     pub fn not_found(resource: impl Into<String>) -> Self {
         Self::NotFound(resource.into())
     }
+
+    pub fn invalid_state(reason: impl Into<String>) -> Self {
+        Self::InvalidState(reason.into())
+    }
+
+    pub fn bad_request(reason: impl Into<String>) -> Self {
+        Self::BadRequest(reason.into())
+    }
+
+    pub fn rate_limited(reason: impl Into<String>) -> Self {
+        Self::RateLimited(reason.into())
+    }
 }
 
 impl From<Error> for ApiError {
     fn from(value: Error) -> Self {
-        Self::Internal(Json(value))
+        match value {
+            Error::PathError { .. } => Self::BadRequest(value.to_string()),
+            _ => Self::Internal(Json(value))
+        }
     }
 }
 
@@ -225,6 +297,16 @@ impl OpenApiResponderInner for ApiError {
             401,
             "User is not authorized to perform this request."
         );
+        response!(
+            items,
+            409,
+            "Request conflicts with the current state of the resource."
+        );
+        response!(
+            items,
+            429,
+            "Too many attempts; retry later."
+        );
 
         Ok(Responses {
             responses: items,
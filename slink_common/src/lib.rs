@@ -2,7 +2,10 @@ pub mod error;
 pub mod runners;
 pub mod types;
 pub mod providers;
+pub mod protocols;
+pub mod console;
 pub mod utilities;
+pub mod storage;
 
 mod constants;
 
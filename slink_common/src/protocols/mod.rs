@@ -0,0 +1,7 @@
+mod ping;
+mod query;
+mod rcon;
+
+pub use ping::*;
+pub use query::*;
+pub use rcon::*;
@@ -0,0 +1,236 @@
+use std::time::{Duration, Instant};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+use crate::{Error, PROTOCOL_TIMEOUT_SECONDS, Res};
+
+const PROTOCOL_NAME: &str = "server_list_ping";
+const MAX_PACKET_LENGTH: i32 = 2_097_151;
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+pub struct ServerListVersion {
+    pub name: String,
+    pub protocol: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+pub struct ServerListPlayer {
+    pub name: String,
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+pub struct ServerListPlayers {
+    pub max: u32,
+    pub online: u32,
+
+    #[serde(default)]
+    pub sample: Vec<ServerListPlayer>,
+}
+
+/// Raw status document returned by the server in response to a status request.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerListResponse {
+    pub version: ServerListVersion,
+    pub players: Option<ServerListPlayers>,
+
+    #[serde(default)]
+    pub description: Value,
+
+    #[serde(default)]
+    pub favicon: Option<String>,
+
+    #[serde(default)]
+    pub enforces_secure_chat: Option<bool>,
+}
+
+/// Result of a Server List Ping, with the MOTD flattened to plain text.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct ServerListPing {
+    pub motd: String,
+    pub version: ServerListVersion,
+    pub players: ServerListPlayers,
+    pub favicon: Option<String>,
+
+    /// Round-trip time of the ping packet, in milliseconds
+    pub latency: u64,
+}
+
+impl ServerListPing {
+    pub async fn fetch(host: impl AsRef<str>, port: u16) -> Res<Self> {
+        let address = format!("{}:{}", host.as_ref(), port);
+        timeout(
+            Duration::from_secs(PROTOCOL_TIMEOUT_SECONDS),
+            Self::exchange(host.as_ref(), port),
+        )
+        .await
+        .map_err(|_| Error::protocol(PROTOCOL_NAME, &address, "Timed out"))?
+        .map_err(|e| Error::protocol(PROTOCOL_NAME, &address, e))
+    }
+
+    async fn exchange(host: &str, port: u16) -> Result<Self, String> {
+        let mut stream = TcpStream::connect((host, port))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let mut handshake = Vec::new();
+        write_varint(&mut handshake, -1);
+        write_string(&mut handshake, host);
+        handshake.extend_from_slice(&port.to_be_bytes());
+        write_varint(&mut handshake, 1);
+        send_packet(&mut stream, 0x00, &handshake).await?;
+        send_packet(&mut stream, 0x00, &[]).await?;
+
+        let (packet_id, body) = read_packet(&mut stream).await?;
+        if packet_id != 0x00 {
+            return Err(format!("Unexpected status packet ID {packet_id}"));
+        }
+        let mut cursor = body.as_slice();
+        let json = read_string(&mut cursor)?;
+        let response = serde_json::from_str::<ServerListResponse>(&json)
+            .map_err(|e| format!("Malformed status response: {e}"))?;
+
+        let payload = chrono::Utc::now().timestamp_millis();
+        let sent = Instant::now();
+        send_packet(&mut stream, 0x01, &payload.to_be_bytes()).await?;
+        let (pong_id, pong) = read_packet(&mut stream).await?;
+        let latency = sent.elapsed().as_millis() as u64;
+        if pong_id != 0x01 || pong.as_slice() != payload.to_be_bytes() {
+            return Err(String::from("Server returned a mismatched pong"));
+        }
+
+        Ok(Self {
+            motd: flatten_text(&response.description),
+            version: response.version,
+            players: response.players.unwrap_or(ServerListPlayers {
+                max: 0,
+                online: 0,
+                sample: Vec::new(),
+            }),
+            favicon: response.favicon,
+            latency,
+        })
+    }
+}
+
+/// Flattens a chat component (string, object or array) into plain text, dropping legacy formatting codes.
+pub fn flatten_text(component: &Value) -> String {
+    fn walk(component: &Value, output: &mut String) {
+        match component {
+            Value::String(text) => output.push_str(text),
+            Value::Array(parts) => parts.iter().for_each(|p| walk(p, output)),
+            Value::Object(map) => {
+                if let Some(text) = map.get("text") {
+                    walk(text, output);
+                }
+                if let Some(extra) = map.get("extra") {
+                    walk(extra, output);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut raw = String::new();
+    walk(component, &mut raw);
+
+    let mut output = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            chars.next();
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+fn write_varint(buffer: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7F == 0 {
+            buffer.push(value as u8);
+            return;
+        }
+        buffer.push(((value & 0x7F) | 0x80) as u8);
+        value >>= 7;
+    }
+}
+
+fn write_string(buffer: &mut Vec<u8>, value: &str) {
+    write_varint(buffer, value.len() as i32);
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+fn read_varint(cursor: &mut &[u8]) -> Result<i32, String> {
+    let mut value: u32 = 0;
+    for position in 0..5 {
+        let (byte, rest) = cursor
+            .split_first()
+            .ok_or(String::from("Unexpected end of packet"))?;
+        *cursor = rest;
+        value |= ((byte & 0x7F) as u32) << (7 * position);
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(String::from("VarInt is too long"))
+}
+
+fn read_string(cursor: &mut &[u8]) -> Result<String, String> {
+    let length = read_varint(cursor)?;
+    if length < 0 || length as usize > cursor.len() {
+        return Err(format!("Invalid string length {length}"));
+    }
+    let (text, rest) = cursor.split_at(length as usize);
+    *cursor = rest;
+    String::from_utf8(text.to_vec()).map_err(|e| e.to_string())
+}
+
+async fn read_stream_varint(stream: &mut TcpStream) -> Result<i32, String> {
+    let mut value: u32 = 0;
+    for position in 0..5 {
+        let byte = stream.read_u8().await.map_err(|e| e.to_string())?;
+        value |= ((byte & 0x7F) as u32) << (7 * position);
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(String::from("VarInt is too long"))
+}
+
+async fn send_packet(stream: &mut TcpStream, packet_id: i32, body: &[u8]) -> Result<(), String> {
+    let mut payload = Vec::new();
+    write_varint(&mut payload, packet_id);
+    payload.extend_from_slice(body);
+
+    let mut packet = Vec::new();
+    write_varint(&mut packet, payload.len() as i32);
+    packet.extend(payload);
+    stream.write_all(&packet).await.map_err(|e| e.to_string())
+}
+
+async fn read_packet(stream: &mut TcpStream) -> Result<(i32, Vec<u8>), String> {
+    let length = read_stream_varint(stream).await?;
+    if length <= 0 || length > MAX_PACKET_LENGTH {
+        return Err(format!("Invalid packet length {length}"));
+    }
+    let mut data = vec![0u8; length as usize];
+    stream
+        .read_exact(&mut data)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut cursor = data.as_slice();
+    let packet_id = read_varint(&mut cursor)?;
+    Ok((packet_id, cursor.to_vec()))
+}
@@ -0,0 +1,151 @@
+use std::{collections::HashMap, time::Duration};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::{net::UdpSocket, time::timeout};
+
+use crate::{Error, PROTOCOL_TIMEOUT_SECONDS, Res};
+
+const PROTOCOL_NAME: &str = "query";
+const MAGIC: [u8; 2] = [0xFE, 0xFD];
+const TYPE_HANDSHAKE: u8 = 0x09;
+const TYPE_STAT: u8 = 0x00;
+const STAT_PADDING: usize = 11;
+const PLAYER_PADDING: usize = 10;
+
+/// Full stat response of the GameSpy4 Query protocol.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct QueryStatus {
+    pub motd: String,
+    pub game_type: String,
+    pub game_id: String,
+    pub version: String,
+    pub plugins: String,
+    pub map: String,
+    pub online_players: u32,
+    pub max_players: u32,
+    pub host_port: u16,
+    pub host_ip: String,
+    pub players: Vec<String>,
+}
+
+impl QueryStatus {
+    pub async fn fetch(host: impl AsRef<str>, port: u16) -> Res<Self> {
+        let address = format!("{}:{}", host.as_ref(), port);
+        timeout(
+            Duration::from_secs(PROTOCOL_TIMEOUT_SECONDS),
+            Self::exchange(host.as_ref(), port),
+        )
+        .await
+        .map_err(|_| Error::protocol(PROTOCOL_NAME, &address, "Timed out"))?
+        .map_err(|e| Error::protocol(PROTOCOL_NAME, &address, e))
+    }
+
+    async fn exchange(host: &str, port: u16) -> Result<Self, String> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .await
+            .map_err(|e| e.to_string())?;
+        socket
+            .connect((host, port))
+            .await
+            .map_err(|e| e.to_string())?;
+        let session_id = (rand_session() & 0x0F0F0F0F).to_be_bytes();
+
+        let mut handshake = MAGIC.to_vec();
+        handshake.push(TYPE_HANDSHAKE);
+        handshake.extend_from_slice(&session_id);
+        let response = Self::request(&socket, &handshake).await?;
+        let token = Self::check_header(&response, TYPE_HANDSHAKE, &session_id)?;
+        let challenge = read_cstring(&mut &token[..])?
+            .parse::<i32>()
+            .map_err(|e| format!("Invalid challenge token: {e}"))?;
+
+        let mut stat = MAGIC.to_vec();
+        stat.push(TYPE_STAT);
+        stat.extend_from_slice(&session_id);
+        stat.extend_from_slice(&challenge.to_be_bytes());
+        stat.extend_from_slice(&[0u8; 4]);
+        let response = Self::request(&socket, &stat).await?;
+        let body = Self::check_header(&response, TYPE_STAT, &session_id)?;
+        Self::parse_full_stat(body)
+    }
+
+    async fn request(socket: &UdpSocket, packet: &[u8]) -> Result<Vec<u8>, String> {
+        socket.send(packet).await.map_err(|e| e.to_string())?;
+        let mut buffer = vec![0u8; 65535];
+        let length = socket
+            .recv(&mut buffer)
+            .await
+            .map_err(|e| e.to_string())?;
+        buffer.truncate(length);
+        Ok(buffer)
+    }
+
+    fn check_header<'a>(
+        response: &'a [u8],
+        packet_type: u8,
+        session_id: &[u8; 4],
+    ) -> Result<&'a [u8], String> {
+        if response.len() < 5 || response[0] != packet_type || &response[1..5] != session_id {
+            return Err(String::from("Response does not match the request session"));
+        }
+        Ok(&response[5..])
+    }
+
+    fn parse_full_stat(body: &[u8]) -> Result<Self, String> {
+        if body.len() < STAT_PADDING {
+            return Err(String::from("Truncated full stat response"));
+        }
+        let mut cursor = &body[STAT_PADDING..];
+
+        let mut values: HashMap<String, String> = HashMap::new();
+        loop {
+            let key = read_cstring(&mut cursor)?;
+            if key.is_empty() {
+                break;
+            }
+            values.insert(key, read_cstring(&mut cursor)?);
+        }
+
+        let mut players = Vec::new();
+        if cursor.len() >= PLAYER_PADDING {
+            cursor = &cursor[PLAYER_PADDING..];
+            while !cursor.is_empty() {
+                let player = read_cstring(&mut cursor)?;
+                if player.is_empty() {
+                    break;
+                }
+                players.push(player);
+            }
+        }
+
+        let value = |key: &str| values.get(key).cloned().unwrap_or_default();
+        Ok(Self {
+            motd: value("hostname"),
+            game_type: value("gametype"),
+            game_id: value("game_id"),
+            version: value("version"),
+            plugins: value("plugins"),
+            map: value("map"),
+            online_players: value("numplayers").parse().unwrap_or(0),
+            max_players: value("maxplayers").parse().unwrap_or(0),
+            host_port: value("hostport").parse().unwrap_or(0),
+            host_ip: value("hostip"),
+            players,
+        })
+    }
+}
+
+fn rand_session() -> u32 {
+    uuid::Uuid::new_v4().as_u128() as u32
+}
+
+fn read_cstring(cursor: &mut &[u8]) -> Result<String, String> {
+    let end = cursor
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or(String::from("Unterminated string in response"))?;
+    let value = String::from_utf8_lossy(&cursor[..end]).to_string();
+    *cursor = &cursor[end + 1..];
+    Ok(value)
+}
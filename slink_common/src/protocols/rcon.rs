@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+use crate::{Error, PROTOCOL_TIMEOUT_SECONDS, Res};
+
+const PROTOCOL_NAME: &str = "rcon";
+const TYPE_LOGIN: i32 = 3;
+const TYPE_COMMAND: i32 = 2;
+const MAX_PAYLOAD_LENGTH: usize = 1446;
+
+/// Authenticated connection to a server's RCON port.
+pub struct RconClient {
+    stream: TcpStream,
+    address: String,
+    next_id: i32,
+}
+
+impl RconClient {
+    pub async fn connect(host: impl AsRef<str>, port: u16, password: impl AsRef<str>) -> Res<Self> {
+        let address = format!("{}:{}", host.as_ref(), port);
+        let stream = Self::timed(&address, async {
+            TcpStream::connect((host.as_ref(), port))
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await?;
+
+        let mut client = Self {
+            stream,
+            address,
+            next_id: 1,
+        };
+        let id = client.send(TYPE_LOGIN, password.as_ref()).await?;
+        let (response_id, _) = client.receive().await?;
+        if response_id == -1 || response_id != id {
+            return Err(Error::protocol(PROTOCOL_NAME, &client.address, "Authentication failed"));
+        }
+
+        Ok(client)
+    }
+
+    /// Runs a command and returns the server's textual response.
+    pub async fn execute(&mut self, command: impl AsRef<str>) -> Res<String> {
+        if command.as_ref().len() > MAX_PAYLOAD_LENGTH {
+            return Err(Error::value_error(command.as_ref(), "Command is too long for RCON"));
+        }
+
+        let id = self.send(TYPE_COMMAND, command.as_ref()).await?;
+        loop {
+            let (response_id, payload) = self.receive().await?;
+            if response_id == id {
+                return Ok(payload);
+            }
+        }
+    }
+
+    async fn timed<T>(
+        address: &str,
+        action: impl Future<Output = Result<T, String>>,
+    ) -> Res<T> {
+        timeout(Duration::from_secs(PROTOCOL_TIMEOUT_SECONDS), action)
+            .await
+            .map_err(|_| Error::protocol(PROTOCOL_NAME, address, "Timed out"))?
+            .map_err(|e| Error::protocol(PROTOCOL_NAME, address, e))
+    }
+
+    async fn send(&mut self, packet_type: i32, payload: &str) -> Res<i32> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+
+        let mut packet = Vec::with_capacity(payload.len() + 14);
+        packet.extend_from_slice(&((payload.len() + 10) as i32).to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&packet_type.to_le_bytes());
+        packet.extend_from_slice(payload.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+
+        let address = self.address.clone();
+        let stream = &mut self.stream;
+        Self::timed(&address, async move {
+            stream.write_all(&packet).await.map_err(|e| e.to_string())
+        })
+        .await?;
+        Ok(id)
+    }
+
+    async fn receive(&mut self) -> Res<(i32, String)> {
+        let address = self.address.clone();
+        let stream = &mut self.stream;
+        Self::timed(&address, async move {
+            let length = stream.read_i32_le().await.map_err(|e| e.to_string())?;
+            if !(10..=4110).contains(&length) {
+                return Err(format!("Invalid packet length {length}"));
+            }
+
+            let mut data = vec![0u8; length as usize];
+            stream
+                .read_exact(&mut data)
+                .await
+                .map_err(|e| e.to_string())?;
+            let id = i32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+            let payload = String::from_utf8_lossy(&data[8..data.len() - 2]).to_string();
+            Ok((id, payload))
+        })
+        .await
+    }
+}
@@ -0,0 +1,214 @@
+use std::path::Path;
+
+use reqwest::{Client, ClientBuilder, RequestBuilder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::json;
+
+use crate::{Error, Res, USER_AGENT};
+
+use super::{
+    downloads::{FileHash, download_verified},
+    error::{ProviderError, ProviderType},
+};
+
+/// CurseForge's `classId` for mods. Other classes (resource packs, shaders, worlds) are not needed on servers.
+pub const CLASS_MODS: u64 = 6;
+
+/// `algo` value of SHA-1 file hashes.
+const HASH_SHA1: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestLoader {
+    pub id: String,
+
+    #[serde(default)]
+    pub primary: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestMinecraft {
+    pub version: String,
+
+    #[serde(default)]
+    pub mod_loaders: Vec<ManifestLoader>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct ManifestFile {
+    #[serde(rename = "projectID")]
+    pub project_id: u64,
+
+    #[serde(rename = "fileID")]
+    pub file_id: u64,
+
+    #[serde(default = "default_required")]
+    pub required: bool,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+fn default_overrides() -> String {
+    String::from("overrides")
+}
+
+/// `manifest.json` of a CurseForge client modpack.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CurseManifest {
+    pub minecraft: ManifestMinecraft,
+    pub manifest_type: String,
+    pub name: String,
+
+    #[serde(default)]
+    pub version: Option<String>,
+    pub files: Vec<ManifestFile>,
+
+    #[serde(default = "default_overrides")]
+    pub overrides: String,
+}
+
+impl CurseManifest {
+    /// The primary mod loader as a name and version, e.g. `("forge", "47.2.0")`.
+    pub fn loader(&self) -> Option<(String, String)> {
+        let loader = self
+            .minecraft
+            .mod_loaders
+            .iter()
+            .find(|l| l.primary)
+            .or(self.minecraft.mod_loaders.first())?;
+        let (name, version) = loader.id.split_once('-')?;
+        Some((name.to_string(), version.to_string()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct CurseHash {
+    pub value: String,
+    pub algo: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CurseFile {
+    pub id: u64,
+    pub mod_id: u64,
+    pub display_name: String,
+    pub file_name: String,
+
+    /// Missing when the author does not allow third-party downloads
+    #[serde(default)]
+    pub download_url: Option<String>,
+
+    #[serde(default)]
+    pub hashes: Vec<CurseHash>,
+
+    #[serde(default)]
+    pub file_length: u64,
+}
+
+impl CurseFile {
+    pub fn sha1(&self) -> Option<&str> {
+        self.hashes
+            .iter()
+            .find(|h| h.algo == HASH_SHA1)
+            .map(|h| h.value.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CurseLinks {
+    #[serde(default)]
+    pub website_url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CurseMod {
+    pub id: u64,
+    pub name: String,
+    pub slug: String,
+
+    #[serde(default)]
+    pub class_id: Option<u64>,
+
+    #[serde(default)]
+    pub links: CurseLinks,
+}
+
+#[derive(Deserialize)]
+struct DataResponse<T> {
+    data: T,
+}
+
+/// Client for the CurseForge Core API. All requests need an API key.
+#[derive(Clone, Debug)]
+pub struct CurseForgeClient {
+    pub base_url: String,
+    pub api_key: String,
+}
+
+impl CurseForgeClient {
+    pub fn new(base_url: impl AsRef<str>, api_key: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.as_ref().trim_end_matches('/').to_string(),
+            api_key: api_key.into(),
+        }
+    }
+
+    fn client() -> Client {
+        ClientBuilder::new()
+            .user_agent(format!("{} providers/content/curseforge", USER_AGENT))
+            .build()
+            .unwrap()
+    }
+
+    fn error(err: ProviderError) -> Error {
+        Error::provider_error(ProviderType::Content, "curseforge", err)
+    }
+
+    async fn data<T: DeserializeOwned>(&self, request: RequestBuilder) -> Res<T> {
+        ProviderError::response_as::<DataResponse<T>>(request.header("x-api-key", &self.api_key).send().await)
+            .await
+            .map(|r| r.data)
+            .map_err(Self::error)
+    }
+
+    pub async fn files(&self, ids: &[u64]) -> Res<Vec<CurseFile>> {
+        self.data(
+            Self::client()
+                .post(format!("{}/mods/files", self.base_url))
+                .json(&json!({ "fileIds": ids })),
+        )
+        .await
+    }
+
+    pub async fn mods(&self, ids: &[u64]) -> Res<Vec<CurseMod>> {
+        self.data(
+            Self::client()
+                .post(format!("{}/mods", self.base_url))
+                .json(&json!({ "modIds": ids })),
+        )
+        .await
+    }
+
+    /// Downloads a file, verifying its SHA-1. Fails for files that are restricted from third-party downloads.
+    pub async fn download(&self, file: &CurseFile, destination: &Path) -> Res<()> {
+        let url = file.download_url.as_ref().ok_or(Self::error(ProviderError::DownloadError {
+            path: destination.display().to_string(),
+            reason: String::from("Distribution restricted by the author"),
+        }))?;
+        let sha1 = file.sha1().ok_or(Self::error(ProviderError::InvalidValue(format!(
+            "No SHA-1 hash for file {}",
+            file.id
+        ))))?;
+        download_verified(url, &FileHash::Sha1(sha1.to_string()), destination)
+            .await
+            .map_err(Self::error)
+    }
+}
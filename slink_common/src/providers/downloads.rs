@@ -0,0 +1,89 @@
+use std::path::Path;
+
+use futures::StreamExt;
+use reqwest::ClientBuilder;
+use sha1::Sha1;
+use sha2::{Digest, Sha512, digest::DynDigest};
+use tokio::io::AsyncWriteExt;
+
+use crate::USER_AGENT;
+
+use super::error::ProviderError;
+
+/// Expected hex-encoded digest of a downloaded file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileHash {
+    Sha512(String),
+    Sha1(String),
+}
+
+impl FileHash {
+    fn hasher(&self) -> Box<dyn DynDigest + Send> {
+        match self {
+            Self::Sha512(_) => Box::new(Sha512::new()),
+            Self::Sha1(_) => Box::new(Sha1::new()),
+        }
+    }
+
+    fn expected(&self) -> &str {
+        match self {
+            Self::Sha512(hash) | Self::Sha1(hash) => hash,
+        }
+    }
+}
+
+/// Streams a URL to `destination`, checking the content's hash before moving it into place.
+/// Nothing is left at `destination` if the download fails or the hash does not match.
+pub async fn download_verified(url: &str, hash: &FileHash, destination: &Path) -> Result<(), ProviderError> {
+    let path = destination.display().to_string();
+    let download_error = |reason: String| ProviderError::DownloadError {
+        path: path.clone(),
+        reason,
+    };
+
+    let response = ProviderError::response(
+        ClientBuilder::new()
+            .user_agent(format!("{} providers/content", USER_AGENT))
+            .build()
+            .unwrap()
+            .get(url)
+            .send()
+            .await,
+    )?;
+    let partial = destination.with_extension("partial");
+    let mut file = tokio::fs::File::create(&partial)
+        .await
+        .map_err(|e| download_error(e.to_string()))?;
+    let mut hasher = hash.hasher();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&partial).await;
+                return Err(download_error(e.to_string()));
+            }
+        };
+        hasher.update(&chunk);
+        if let Err(e) = file.write_all(&chunk).await {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(download_error(e.to_string()));
+        }
+    }
+    let _ = file.flush().await;
+    drop(file);
+
+    let actual: String = hasher.finalize().iter().map(|b| format!("{b:02x}")).collect();
+    if !actual.eq_ignore_ascii_case(hash.expected()) {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(ProviderError::HashMismatch {
+            path,
+            expected: hash.expected().to_string(),
+            actual,
+        });
+    }
+
+    tokio::fs::rename(&partial, destination)
+        .await
+        .map_err(|e| download_error(e.to_string()))
+}
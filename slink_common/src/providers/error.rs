@@ -8,12 +8,18 @@ use serde::{Deserialize, Serialize, de::DeserializeOwned};
 #[serde(rename_all = "snake_case")]
 pub enum ProviderType {
     ServerBinary,
+    Profile,
+    Content,
+    Identity,
 }
 
 impl Display for ProviderType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
             Self::ServerBinary => "server_binary",
+            Self::Profile => "profile",
+            Self::Content => "content",
+            Self::Identity => "identity",
         })
     }
 }
@@ -52,7 +58,17 @@ pub enum ProviderError {
     },
 
     #[error("Incorrect provider argument (expected {0})")]
-    IncorrectArg(String)
+    IncorrectArg(String),
+
+    #[error("Invalid value returned by provider: {0}")]
+    InvalidValue(String),
+
+    #[error("Checksum mismatch for {path}: expected {expected}, got {actual}")]
+    HashMismatch {
+        path: String,
+        expected: String,
+        actual: String
+    }
 }
 
 impl ProviderError {
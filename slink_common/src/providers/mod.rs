@@ -1,4 +1,9 @@
+pub mod curseforge;
+pub mod downloads;
 pub mod error;
+pub mod modrinth;
+pub mod oidc;
+pub mod profiles;
 pub(in crate::providers) mod server_binary;
 
 pub mod servers {
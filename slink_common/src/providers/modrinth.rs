@@ -0,0 +1,403 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::Path,
+};
+
+use reqwest::{Client, ClientBuilder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, MODRINTH_API, Res, USER_AGENT, types::ContentKind};
+
+use super::{
+    downloads::{FileHash, download_verified},
+    error::{ProviderError, ProviderType},
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SideSupport {
+    Required,
+    Optional,
+    Unsupported,
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct SearchHit {
+    pub project_id: String,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub project_type: String,
+    pub author: String,
+    pub downloads: u64,
+
+    #[serde(default)]
+    pub icon_url: Option<String>,
+
+    #[serde(default)]
+    pub latest_version: Option<String>,
+    pub client_side: SideSupport,
+    pub server_side: SideSupport,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct SearchResults {
+    pub hits: Vec<SearchHit>,
+    pub offset: u32,
+    pub limit: u32,
+    pub total_hits: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct Project {
+    pub id: String,
+    pub slug: String,
+    pub title: String,
+    pub project_type: String,
+    pub client_side: SideSupport,
+    pub server_side: SideSupport,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyType {
+    Required,
+    Optional,
+    Incompatible,
+    Embedded,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct Dependency {
+    #[serde(default)]
+    pub version_id: Option<String>,
+
+    #[serde(default)]
+    pub project_id: Option<String>,
+
+    #[serde(default)]
+    pub file_name: Option<String>,
+    pub dependency_type: DependencyType,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct FileHashes {
+    pub sha512: String,
+    pub sha1: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct VersionFile {
+    pub hashes: FileHashes,
+    pub url: String,
+    pub filename: String,
+    pub primary: bool,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct ProjectVersion {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    pub version_number: String,
+    pub version_type: String,
+    pub game_versions: Vec<String>,
+    pub loaders: Vec<String>,
+    pub dependencies: Vec<Dependency>,
+    pub files: Vec<VersionFile>,
+    pub date_published: String,
+}
+
+impl ProjectVersion {
+    /// The file to install: the one marked primary, or the first one.
+    pub fn primary_file(&self) -> Option<&VersionFile> {
+        self.files.iter().find(|f| f.primary).or(self.files.first())
+    }
+}
+
+/// Minecraft version and loaders that installed content has to support.
+#[derive(Clone, Debug)]
+pub struct VersionFilter {
+    pub game_version: String,
+    pub loaders: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct ResolvedVersion {
+    pub project: Project,
+    pub version: ProjectVersion,
+
+    /// Whether the version was pulled in as a dependency rather than requested
+    pub dependency: bool,
+}
+
+/// Outcome of resolving a version and its required dependencies.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct Resolution {
+    pub versions: Vec<ResolvedVersion>,
+
+    /// Required dependencies without a compatible version, as project (or version) IDs
+    pub missing: Vec<String>,
+
+    /// Projects that were skipped because they do not run on servers
+    pub client_only: Vec<String>,
+}
+
+/// Client for the Modrinth v2 API.
+#[derive(Clone, Debug)]
+pub struct ModrinthClient {
+    pub base_url: String,
+}
+
+impl Default for ModrinthClient {
+    fn default() -> Self {
+        Self {
+            base_url: MODRINTH_API.to_string(),
+        }
+    }
+}
+
+impl ModrinthClient {
+    pub fn new(base_url: impl AsRef<str>) -> Self {
+        Self {
+            base_url: base_url.as_ref().trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn client() -> Client {
+        ClientBuilder::new()
+            .user_agent(format!("{} providers/content/modrinth", USER_AGENT))
+            .build()
+            .unwrap()
+    }
+
+    fn error(err: ProviderError) -> Error {
+        Error::provider_error(ProviderType::Content, "modrinth", err)
+    }
+
+    fn json_list(values: &[impl AsRef<str>]) -> String {
+        serde_json::to_string(&values.iter().map(|v| v.as_ref()).collect::<Vec<&str>>()).unwrap_or_default()
+    }
+
+    pub async fn search(
+        &self,
+        query: &str,
+        kind: ContentKind,
+        filter: &VersionFilter,
+        limit: u32,
+        offset: u32,
+    ) -> Res<SearchResults> {
+        let mut facets = vec![
+            vec![format!("project_type:{}", kind.project_type())],
+            vec![format!("versions:{}", filter.game_version)],
+            vec![String::from("server_side!=unsupported")],
+        ];
+        if !filter.loaders.is_empty() {
+            facets.push(filter.loaders.iter().map(|l| format!("categories:{l}")).collect());
+        }
+
+        ProviderError::response_as::<SearchResults>(
+            Self::client()
+                .get(format!("{}/search", self.base_url))
+                .query(&[
+                    ("query", query.to_string()),
+                    ("facets", serde_json::to_string(&facets).unwrap_or_default()),
+                    ("limit", limit.to_string()),
+                    ("offset", offset.to_string()),
+                ])
+                .send()
+                .await,
+        )
+        .await
+        .map_err(Self::error)
+    }
+
+    /// Fetches a project by ID or slug.
+    pub async fn project(&self, id: &str) -> Res<Project> {
+        ProviderError::response_as::<Project>(
+            Self::client()
+                .get(format!("{}/project/{id}", self.base_url))
+                .send()
+                .await,
+        )
+        .await
+        .map_err(Self::error)
+    }
+
+    pub async fn version(&self, id: &str) -> Res<ProjectVersion> {
+        ProviderError::response_as::<ProjectVersion>(
+            Self::client()
+                .get(format!("{}/version/{id}", self.base_url))
+                .send()
+                .await,
+        )
+        .await
+        .map_err(Self::error)
+    }
+
+    /// Lists a project's versions that support the filter's Minecraft version and loaders, newest first.
+    pub async fn versions(&self, project: &str, filter: &VersionFilter) -> Res<Vec<ProjectVersion>> {
+        let mut query = vec![("game_versions", Self::json_list(&[&filter.game_version]))];
+        if !filter.loaders.is_empty() {
+            query.push(("loaders", Self::json_list(&filter.loaders)));
+        }
+
+        ProviderError::response_as::<Vec<ProjectVersion>>(
+            Self::client()
+                .get(format!("{}/project/{project}/version", self.base_url))
+                .query(&query)
+                .send()
+                .await,
+        )
+        .await
+        .map_err(Self::error)
+    }
+
+    /// The newest compatible version of a project, preferring releases over betas and alphas.
+    pub async fn latest(&self, project: &str, filter: &VersionFilter) -> Res<Option<ProjectVersion>> {
+        let versions = self.versions(project, filter).await?;
+        Ok(["release", "beta", "alpha"]
+            .iter()
+            .find_map(|kind| versions.iter().find(|v| v.version_type == *kind))
+            .cloned())
+    }
+
+    /// Resolves the requested version (or the latest compatible one) of a project, along with
+    /// its required dependencies. Projects listed in `installed` are not resolved again.
+    pub async fn resolve(
+        &self,
+        project: &str,
+        version: Option<&str>,
+        filter: &VersionFilter,
+        installed: &HashSet<String>,
+    ) -> Res<Resolution> {
+        let mut resolution = Resolution::default();
+        let mut seen: HashSet<String> = installed.clone();
+        let mut queue: VecDeque<(Option<String>, Option<String>, bool)> =
+            VecDeque::from([(Some(project.to_string()), version.map(|v| v.to_string()), false)]);
+        let mut projects: HashMap<String, Project> = HashMap::new();
+
+        while let Some((project_id, version_id, dependency)) = queue.pop_front() {
+            let version = match (&project_id, &version_id) {
+                (_, Some(version_id)) => self.version(version_id).await?,
+                (Some(project_id), None) => match self.latest(project_id, filter).await? {
+                    Some(version) => version,
+                    None if dependency => {
+                        resolution.missing.push(project_id.clone());
+                        continue;
+                    }
+                    None => {
+                        return Err(Self::error(ProviderError::NoVersions {
+                            component: project_id.clone(),
+                            mc_version: filter.game_version.clone(),
+                        }));
+                    }
+                },
+                (None, None) => continue,
+            };
+            if !seen.insert(version.project_id.clone()) && dependency {
+                continue;
+            }
+
+            let project = match projects.get(&version.project_id) {
+                Some(project) => project.clone(),
+                None => self.project(&version.project_id).await?,
+            };
+            projects.insert(project.id.clone(), project.clone());
+            if project.server_side == SideSupport::Unsupported {
+                resolution.client_only.push(project.slug.clone());
+                continue;
+            }
+
+            for dep in version
+                .dependencies
+                .iter()
+                .filter(|d| d.dependency_type == DependencyType::Required)
+            {
+                if dep.project_id.as_ref().is_some_and(|p| seen.contains(p)) {
+                    continue;
+                }
+                queue.push_back((dep.project_id.clone(), dep.version_id.clone(), true));
+            }
+            resolution.versions.push(ResolvedVersion {
+                project,
+                version,
+                dependency,
+            });
+        }
+
+        Ok(resolution)
+    }
+
+    /// Downloads a version file, verifying its SHA-512 before moving it into place.
+    pub async fn download(&self, file: &VersionFile, destination: &Path) -> Res<()> {
+        download_verified(&file.url, &FileHash::Sha512(file.hashes.sha512.clone()), destination)
+            .await
+            .map_err(Self::error)
+    }
+}
+
+/// `env` entry of a modpack file, saying whether each side needs it.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct ModpackEnv {
+    #[serde(default)]
+    pub client: Option<String>,
+
+    #[serde(default)]
+    pub server: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct ModpackHashes {
+    pub sha1: String,
+    pub sha512: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModpackFile {
+    /// Destination, relative to the instance directory
+    pub path: String,
+    pub hashes: ModpackHashes,
+
+    #[serde(default)]
+    pub env: Option<ModpackEnv>,
+    pub downloads: Vec<String>,
+
+    #[serde(default)]
+    pub file_size: u64,
+}
+
+/// `modrinth.index.json` at the root of an `.mrpack` file.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModpackIndex {
+    pub format_version: u32,
+    pub game: String,
+    pub version_id: String,
+    pub name: String,
+
+    #[serde(default)]
+    pub summary: Option<String>,
+    pub files: Vec<ModpackFile>,
+
+    /// Minecraft and loader versions, keyed by `minecraft`, `fabric-loader`, `quilt-loader`, `forge` or `neoforge`
+    pub dependencies: HashMap<String, String>,
+}
+
+impl ModpackFile {
+    /// Project and version IDs, if the file is hosted on Modrinth's CDN.
+    pub fn modrinth_ids(&self) -> Option<(String, String)> {
+        self.downloads.iter().find_map(|url| {
+            let path = url.split_once("://")?.1.split_once('/')?.1;
+            let mut parts = path.split('/');
+            match (parts.next()?, parts.next()?, parts.next()?, parts.next()?) {
+                ("data", project, "versions", version) => Some((project.to_string(), version.to_string())),
+                _ => None,
+            }
+        })
+    }
+}
@@ -0,0 +1,101 @@
+use md5::{Digest, Md5};
+use reqwest::{ClientBuilder, StatusCode};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::{Builder, Uuid};
+
+use crate::{Error, MOJANG_PROFILE_API, Res, USER_AGENT, types::ServerProperties};
+
+use super::error::{ProviderError, ProviderType};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+pub struct PlayerProfile {
+    pub id: Uuid,
+    pub name: String,
+}
+
+#[async_trait::async_trait]
+pub trait ProfileResolver: Send + Sync {
+    fn name(&self) -> String;
+    async fn resolve(&self, username: &str) -> Res<Option<PlayerProfile>>;
+
+    fn error(&self, err: ProviderError) -> Error {
+        Error::provider_error(ProviderType::Profile, self.name(), err)
+    }
+}
+
+/// Resolves usernames against Mojang's profile API, for online-mode servers.
+#[derive(Clone, Debug)]
+pub struct MojangProfileResolver {
+    pub base_url: String,
+}
+
+impl Default for MojangProfileResolver {
+    fn default() -> Self {
+        Self {
+            base_url: MOJANG_PROFILE_API.to_string(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ProfileResolver for MojangProfileResolver {
+    fn name(&self) -> String {
+        String::from("mojang")
+    }
+
+    async fn resolve(&self, username: &str) -> Res<Option<PlayerProfile>> {
+        let client = ClientBuilder::new()
+            .user_agent(format!("{} providers/profile/mojang", USER_AGENT))
+            .build()
+            .unwrap();
+        let response = client
+            .get(format!("{}/{}", self.base_url, username))
+            .send()
+            .await
+            .map_err(|e| self.error(ProviderError::RequestError(e.to_string())))?;
+
+        if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::NO_CONTENT) {
+            return Ok(None);
+        }
+
+        ProviderError::response_as::<PlayerProfile>(Ok(response))
+            .await
+            .map(Some)
+            .map_err(|e| self.error(e))
+    }
+}
+
+/// Derives profiles the way offline-mode servers do, without contacting any upstream.
+#[derive(Clone, Debug, Default)]
+pub struct OfflineProfileResolver;
+
+impl OfflineProfileResolver {
+    pub fn offline_uuid(username: &str) -> Uuid {
+        let digest: [u8; 16] = Md5::digest(format!("OfflinePlayer:{username}").as_bytes()).into();
+        Builder::from_md5_bytes(digest).into_uuid()
+    }
+}
+
+#[async_trait::async_trait]
+impl ProfileResolver for OfflineProfileResolver {
+    fn name(&self) -> String {
+        String::from("offline")
+    }
+
+    async fn resolve(&self, username: &str) -> Res<Option<PlayerProfile>> {
+        Ok(Some(PlayerProfile {
+            id: Self::offline_uuid(username),
+            name: username.to_string(),
+        }))
+    }
+}
+
+/// Picks the resolver matching the server's `online-mode` setting.
+pub fn resolver_for(properties: &ServerProperties) -> Box<dyn ProfileResolver> {
+    if properties.online_mode {
+        Box::new(MojangProfileResolver::default())
+    } else {
+        Box::new(OfflineProfileResolver)
+    }
+}
@@ -3,12 +3,14 @@ use std::{fmt::Debug, pin::Pin};
 use bytes::Bytes;
 use bytesize::ByteSize;
 use futures::Stream;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use tokio::io::AsyncWrite;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 use uuid::Uuid;
 
 use crate::{
     error::{Error, Res},
+    protocols::{QueryStatus, ServerListPing},
     types::minecraft::JavaVersion,
 };
 
@@ -76,9 +78,12 @@ pub struct MinecraftRunnerConfig {
     pub ports: Vec<MinecraftRunnerPort>
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub enum MinecraftRunnerStatus {
     Uninitialized,
+
+    /// The process is up, but the server has not yet answered a status ping.
+    Starting,
     Running,
     Offline(Option<Error>),
     Failed(Error)
@@ -100,7 +105,16 @@ impl MinecraftRunnerStatus {
         }
     }
 
+    /// Whether the underlying process is up, regardless of whether it is accepting players yet.
     pub fn running(&self) -> bool {
+        match self {
+            Self::Starting | Self::Running => true,
+            _ => false
+        }
+    }
+
+    /// Whether the server has answered a status ping and is accepting players.
+    pub fn online(&self) -> bool {
         if let Self::Running = self {
             true
         } else {
@@ -109,6 +123,13 @@ impl MinecraftRunnerStatus {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct MinecraftServerStatus {
+    pub runner: MinecraftRunnerStatus,
+    pub ping: Option<ServerListPing>,
+    pub query: Option<QueryStatus>
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct MinecraftRunnerMetrics {
     pub memory: Option<ByteSize>,
@@ -145,4 +166,43 @@ pub trait MinecraftRunner: Clone {
     async fn metrics(&self) -> Res<Box<dyn Stream<Item = Option<MinecraftRunnerMetrics>> + Send>>;
     async fn get_reader(&self) -> Res<Pin<Box<dyn Stream<Item = Option<Bytes>> + Send>>>;
     async fn get_writer(&self) -> Res<Box<dyn AsyncWrite + Send>>;
+
+    /// Returns the host through which the server's ports can be reached from Slink.
+    async fn address(&self) -> Res<String>;
+
+    /// Writes a single command line to the server's console.
+    async fn send_command(&self, command: &str) -> Res<()> {
+        let mut writer = Box::into_pin(self.get_writer().await?);
+        writer.write_all(format!("{}\n", command.trim_end()).as_bytes()).await.or_else(|e| Error::unexpected(e))?;
+        writer.flush().await.or_else(|e| Error::unexpected(e))
+    }
+
+    async fn ping(&self) -> Res<ServerListPing> {
+        let port = self.config().ports.iter().find_map(|p| match p {
+            MinecraftRunnerPort::Server(local, ..) => Some(*local),
+            _ => None
+        }).ok_or(Error::value_error(self.id(), "Runner does not expose a server port"))?;
+        ServerListPing::fetch(self.address().await?, port).await
+    }
+
+    async fn query(&self) -> Res<QueryStatus> {
+        let port = self.config().ports.iter().find_map(|p| match p {
+            MinecraftRunnerPort::Query(local, ..) => Some(*local),
+            _ => None
+        }).ok_or(Error::value_error(self.id(), "Runner does not expose a query port"))?;
+        QueryStatus::fetch(self.address().await?, port).await
+    }
+
+    async fn server_status(&mut self) -> MinecraftServerStatus where Self: Sync {
+        let runner = self.status().await;
+        if !runner.online() {
+            return MinecraftServerStatus { runner, ping: None, query: None };
+        }
+
+        MinecraftServerStatus {
+            runner,
+            ping: self.ping().await.ok(),
+            query: self.query().await.ok()
+        }
+    }
 }
@@ -249,8 +249,8 @@ impl MinecraftRunner for DockerHostRunner {
             .await
             .or_else(|e| Err(self.wrap(DockerHostError::DockerError(e.to_string()))))?;
 
-        self.status = MinecraftRunnerStatus::Running;
-        Ok(MinecraftRunnerStatus::Running)
+        self.status = MinecraftRunnerStatus::Starting;
+        Ok(MinecraftRunnerStatus::Starting)
     }
 
     async fn stop(&mut self) -> Res<MinecraftRunnerStatus> {
@@ -269,7 +269,7 @@ impl MinecraftRunner for DockerHostRunner {
 
     async fn status(&mut self) -> MinecraftRunnerStatus {
         let new_status = match self.status.clone() {
-            MinecraftRunnerStatus::Running => {
+            current @ (MinecraftRunnerStatus::Starting | MinecraftRunnerStatus::Running) => {
                 match self
                     .connection
                     .inspect_container(&self.container_name(), None)
@@ -278,7 +278,11 @@ impl MinecraftRunner for DockerHostRunner {
                     Ok(inspection) => {
                         if let Some(state) = inspection.state {
                             if let Some(secret::ContainerStateStatusEnum::RUNNING) = state.status {
-                                MinecraftRunnerStatus::Running
+                                if current.online() || self.ping().await.is_ok() {
+                                    MinecraftRunnerStatus::Running
+                                } else {
+                                    MinecraftRunnerStatus::Starting
+                                }
                             } else if let Some(err) = state.error {
                                 MinecraftRunnerStatus::Offline(Some(
                                     self.wrap(DockerHostError::DockerError(err)),
@@ -376,4 +380,22 @@ impl MinecraftRunner for DockerHostRunner {
             .or_else(|e| Err(self.wrap(DockerHostError::DockerError(e.to_string()))))?;
         Ok(Box::new(attach.input))
     }
+
+    async fn address(&self) -> Res<String> {
+        let inspection = self
+            .connection
+            .inspect_container(&self.container_name(), None)
+            .await
+            .or_else(|e| Err(self.wrap(DockerHostError::DockerError(e.to_string()))))?;
+
+        inspection
+            .network_settings
+            .and_then(|settings| settings.networks)
+            .and_then(|networks| networks.get(&self.options.network).cloned())
+            .and_then(|endpoint| endpoint.ip_address)
+            .filter(|ip| !ip.is_empty())
+            .ok_or(self.wrap(DockerHostError::StatusError(String::from(
+                "Container has no address on the runner network.",
+            ))))
+    }
 }
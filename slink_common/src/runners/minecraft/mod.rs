@@ -1,4 +1,4 @@
 mod base;
 pub mod docker_host;
 
-pub use base::{MinecraftRunner, MinecraftRunnerConfig, MinecraftRunnerMetrics, MinecraftRunnerPort, MinecraftRunnerStatus, PortExposure};
\ No newline at end of file
+pub use base::{MinecraftRunner, MinecraftRunnerConfig, MinecraftRunnerMetrics, MinecraftRunnerPort, MinecraftRunnerStatus, MinecraftServerStatus, PortExposure};
\ No newline at end of file
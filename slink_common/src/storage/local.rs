@@ -0,0 +1,52 @@
+use std::path::{Path, PathBuf};
+
+use crate::{Res, utilities::Sandbox};
+
+use super::BackupTarget;
+
+/// Copies backups into another directory, such as a mounted network share.
+#[derive(Clone, Debug)]
+pub struct LocalTarget {
+    pub name: String,
+    pub root: PathBuf,
+}
+
+impl LocalTarget {
+    fn path(&self, key: &str) -> Res<PathBuf> {
+        Ok(self.root.join(Sandbox::normalize(key)?))
+    }
+}
+
+#[async_trait::async_trait]
+impl BackupTarget for LocalTarget {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn upload(&self, source: &Path, key: &str) -> Res<()> {
+        let target = self.path(key)?;
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|e| self.error(e))?;
+        }
+
+        // Copy next to the target first, so that an interrupted copy never looks complete
+        let partial = target.with_extension("partial");
+        tokio::fs::copy(source, &partial).await.map_err(|e| self.error(e))?;
+        tokio::fs::rename(&partial, &target).await.map_err(|e| self.error(e))?;
+        Ok(())
+    }
+
+    async fn download(&self, key: &str, destination: &Path) -> Res<()> {
+        tokio::fs::copy(self.path(key)?, destination)
+            .await
+            .map_err(|e| self.error(e))?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Res<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(self.error(e)),
+            _ => Ok(()),
+        }
+    }
+}
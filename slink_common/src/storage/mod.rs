@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use crate::{Error, Res, types::BackupTargetConfig};
+
+mod local;
+mod s3;
+mod sftp;
+
+pub use local::LocalTarget;
+pub use s3::S3Target;
+pub use sftp::SftpTarget;
+
+/// Somewhere backup archives are kept. Keys are `/`-separated paths relative to the target's root.
+#[async_trait::async_trait]
+pub trait BackupTarget: Send + Sync {
+    fn name(&self) -> String;
+
+    /// Uploads a local file, streaming it from disk. Interrupted uploads resume where they left
+    /// off when retried with the same source and key, where the target supports it.
+    async fn upload(&self, source: &Path, key: &str) -> Res<()>;
+    async fn download(&self, key: &str, destination: &Path) -> Res<()>;
+    async fn delete(&self, key: &str) -> Res<()>;
+
+    fn error(&self, reason: impl ToString) -> Error
+    where
+        Self: Sized,
+    {
+        Error::storage(self.name(), reason)
+    }
+}
+
+pub fn target_for(name: impl AsRef<str>, config: &BackupTargetConfig) -> Box<dyn BackupTarget> {
+    let name = name.as_ref().to_string();
+    match config.clone() {
+        BackupTargetConfig::Local { path } => Box::new(LocalTarget { name, root: path }),
+        BackupTargetConfig::Sftp {
+            host,
+            port,
+            username,
+            password,
+            private_key,
+            passphrase,
+            path,
+        } => Box::new(SftpTarget {
+            name,
+            host,
+            port,
+            username,
+            password,
+            private_key,
+            passphrase,
+            root: path,
+        }),
+        BackupTargetConfig::S3 {
+            endpoint,
+            region,
+            bucket,
+            access_key,
+            secret_key,
+            prefix,
+            path_style,
+        } => Box::new(S3Target {
+            name,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            region,
+            bucket,
+            access_key,
+            secret_key,
+            prefix,
+            path_style,
+        }),
+    }
+}
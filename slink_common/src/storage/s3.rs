@@ -0,0 +1,332 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use futures::StreamExt;
+use hmac::{Hmac, Mac};
+use reqwest::{ClientBuilder, Method, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::{Error, Res, S3_PART_RETRIES, S3_PART_SIZE, USER_AGENT, utilities::Sandbox};
+
+use super::BackupTarget;
+
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+/// Progress of an interrupted multipart upload, kept next to the source file so a retry can resume it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct MultipartState {
+    key: String,
+    upload_id: String,
+    size: u64,
+
+    /// ETags of the uploaded parts, in order
+    parts: Vec<String>,
+}
+
+/// Stores backups in an S3-compatible bucket, signing requests with AWS Signature Version 4.
+/// Files larger than one part are sent as resumable multipart uploads.
+#[derive(Clone, Debug)]
+pub struct S3Target {
+    pub name: String,
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub prefix: Option<String>,
+    pub path_style: bool,
+}
+
+/// A failed request, with the S3 error code from the response body if there was one.
+#[derive(Debug)]
+struct RequestError {
+    code: Option<String>,
+    error: Error,
+}
+
+impl From<RequestError> for Error {
+    fn from(value: RequestError) -> Self {
+        value.error
+    }
+}
+
+fn encode(value: &str, encode_slash: bool) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            b'/' if !encode_slash => String::from("/"),
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn xml_value(body: &str, tag: &str) -> Option<String> {
+    let start = body.find(&format!("<{tag}>"))? + tag.len() + 2;
+    let end = body[start..].find(&format!("</{tag}>"))? + start;
+    Some(body[start..end].to_string())
+}
+
+impl S3Target {
+    fn object_key(&self, key: &str) -> Res<String> {
+        let key = Sandbox::normalize(key)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<String>>()
+            .join("/");
+        Ok(match self.prefix.as_deref().map(|p| p.trim_matches('/')) {
+            Some(prefix) if !prefix.is_empty() => format!("{prefix}/{key}"),
+            _ => key,
+        })
+    }
+
+    fn state_path(source: &Path) -> PathBuf {
+        let name = source
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        source.with_file_name(format!("{name}.multipart"))
+    }
+
+    /// Sends a signed request for an object, failing on any non-success status.
+    async fn request(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, String)],
+        body: Vec<u8>,
+    ) -> Result<Response, RequestError> {
+        let (scheme, authority) = self.endpoint.split_once("://").unwrap_or(("https", &self.endpoint));
+        let (host, uri) = if self.path_style {
+            (authority.to_string(), format!("/{}/{}", self.bucket, encode(key, false)))
+        } else {
+            (format!("{}.{authority}", self.bucket), format!("/{}", encode(key, false)))
+        };
+
+        let mut query: Vec<(String, String)> = query.iter().map(|(k, v)| (encode(k, true), encode(v, true))).collect();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<String>>()
+            .join("&");
+
+        let payload_hash = hex(&Sha256::digest(&body));
+        let now = Utc::now();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let canonical_request = format!(
+            "{method}\n{uri}\n{canonical_query}\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{timestamp}\n\n{SIGNED_HEADERS}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = [date.as_str(), self.region.as_str(), "s3", "aws4_request"]
+            .into_iter()
+            .fold(format!("AWS4{}", self.secret_key).into_bytes(), |key, part| hmac(&key, part));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={SIGNED_HEADERS}, Signature={}",
+            self.access_key,
+            hex(&hmac(&signing_key, &string_to_sign))
+        );
+
+        let url = if canonical_query.is_empty() {
+            format!("{scheme}://{host}{uri}")
+        } else {
+            format!("{scheme}://{host}{uri}?{canonical_query}")
+        };
+        let client = ClientBuilder::new()
+            .user_agent(format!("{} storage/s3", USER_AGENT))
+            .build()
+            .unwrap();
+        let response = client
+            .request(method, url)
+            .header("x-amz-date", timestamp)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("Authorization", authorization)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| RequestError {
+                code: None,
+                error: self.error(e),
+            })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            let code = xml_value(&body, "Code");
+            let mut reason = format!("{} ({status})", code.clone().unwrap_or(status.to_string()));
+            if let Some(message) = xml_value(&body, "Message").filter(|m| !m.is_empty()) {
+                reason = format!("{reason}: {message}");
+            }
+            return Err(RequestError {
+                error: self.error(reason),
+                code,
+            });
+        }
+        Ok(response)
+    }
+
+    async fn save_state(&self, path: &Path, state: &MultipartState) -> Res<()> {
+        let data = serde_json::to_vec(state).map_err(|e| self.error(e))?;
+        tokio::fs::write(path, data).await.map_err(|e| self.error(e))
+    }
+
+    /// Continues the multipart upload recorded next to `source`, or starts a new one.
+    async fn resume_or_start(&self, source: &Path, key: &str, size: u64) -> Res<MultipartState> {
+        if let Ok(data) = tokio::fs::read(Self::state_path(source)).await
+            && let Ok(state) = serde_json::from_slice::<MultipartState>(&data)
+            && state.key == key && state.size == size
+        {
+            return Ok(state);
+        }
+
+        let body = self
+            .request(Method::POST, key, &[("uploads", String::new())], Vec::new())
+            .await?
+            .text()
+            .await
+            .map_err(|e| self.error(e))?;
+        let state = MultipartState {
+            key: key.to_string(),
+            upload_id: xml_value(&body, "UploadId").ok_or(self.error("Missing UploadId in response"))?,
+            size,
+            parts: Vec::new(),
+        };
+        self.save_state(&Self::state_path(source), &state).await?;
+        Ok(state)
+    }
+
+    async fn upload_part(&self, state: &MultipartState, number: usize, data: Vec<u8>) -> Result<String, RequestError> {
+        let query = [("partNumber", number.to_string()), ("uploadId", state.upload_id.clone())];
+        let mut attempt = 1;
+        loop {
+            match self.request(Method::PUT, &state.key, &query, data.clone()).await {
+                Ok(response) => {
+                    return response
+                        .headers()
+                        .get("ETag")
+                        .and_then(|v| v.to_str().ok())
+                        .map(|v| v.to_string())
+                        .ok_or(RequestError {
+                            code: None,
+                            error: self.error("Missing ETag in part response"),
+                        });
+                }
+                Err(e) if attempt >= S3_PART_RETRIES => return Err(e),
+                Err(_) => attempt += 1,
+            }
+        }
+    }
+
+    async fn upload_multipart(&self, source: &Path, key: &str, size: u64) -> Res<()> {
+        let state_path = Self::state_path(source);
+        let mut state = self.resume_or_start(source, key, size).await?;
+
+        let mut file = tokio::fs::File::open(source).await.map_err(|e| self.error(e))?;
+        let mut offset = (state.parts.len() * S3_PART_SIZE) as u64;
+        file.seek(std::io::SeekFrom::Start(offset)).await.map_err(|e| self.error(e))?;
+
+        while offset < size {
+            let mut buffer = Vec::with_capacity(S3_PART_SIZE);
+            (&mut file)
+                .take(S3_PART_SIZE as u64)
+                .read_to_end(&mut buffer)
+                .await
+                .map_err(|e| self.error(e))?;
+            if buffer.is_empty() {
+                break;
+            }
+            offset += buffer.len() as u64;
+
+            match self.upload_part(&state, state.parts.len() + 1, buffer).await {
+                Ok(etag) => state.parts.push(etag),
+                Err(e) => {
+                    // An expired or aborted upload cannot be resumed, so the next attempt starts over
+                    if e.code.as_deref() == Some("NoSuchUpload") {
+                        let _ = tokio::fs::remove_file(&state_path).await;
+                    }
+                    return Err(e.into());
+                }
+            }
+            self.save_state(&state_path, &state).await?;
+        }
+
+        let parts = state
+            .parts
+            .iter()
+            .enumerate()
+            .map(|(index, etag)| format!("<Part><PartNumber>{}</PartNumber><ETag>{etag}</ETag></Part>", index + 1))
+            .collect::<String>();
+        let body = self
+            .request(
+                Method::POST,
+                key,
+                &[("uploadId", state.upload_id.clone())],
+                format!("<CompleteMultipartUpload>{parts}</CompleteMultipartUpload>").into_bytes(),
+            )
+            .await?
+            .text()
+            .await
+            .map_err(|e| self.error(e))?;
+
+        // Completion can fail after the response has started, in which case the error is in the body
+        if let Some(code) = xml_value(&body, "Code") {
+            let _ = tokio::fs::remove_file(&state_path).await;
+            return Err(self.error(code));
+        }
+
+        let _ = tokio::fs::remove_file(&state_path).await;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl BackupTarget for S3Target {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn upload(&self, source: &Path, key: &str) -> Res<()> {
+        let key = self.object_key(key)?;
+        let size = tokio::fs::metadata(source).await.map_err(|e| self.error(e))?.len();
+        if size > S3_PART_SIZE as u64 {
+            return self.upload_multipart(source, &key, size).await;
+        }
+
+        let data = tokio::fs::read(source).await.map_err(|e| self.error(e))?;
+        self.request(Method::PUT, &key, &[], data).await?;
+        Ok(())
+    }
+
+    async fn download(&self, key: &str, destination: &Path) -> Res<()> {
+        let response = self.request(Method::GET, &self.object_key(key)?, &[], Vec::new()).await?;
+        let mut file = tokio::fs::File::create(destination).await.map_err(|e| self.error(e))?;
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| self.error(e))?;
+            file.write_all(&chunk).await.map_err(|e| self.error(e))?;
+        }
+        file.flush().await.map_err(|e| self.error(e))
+    }
+
+    async fn delete(&self, key: &str) -> Res<()> {
+        self.request(Method::DELETE, &self.object_key(key)?, &[], Vec::new()).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    fs::File,
+    io::{Seek, SeekFrom},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use ssh2::{ErrorCode, OpenFlags, OpenType, Session, Sftp};
+
+use crate::{Res, STORAGE_TIMEOUT_SECONDS, utilities::Sandbox};
+
+use super::BackupTarget;
+
+/// `LIBSSH2_FX_NO_SUCH_FILE`
+const SFTP_NO_SUCH_FILE: i32 = 2;
+
+/// Stores backups on a remote host over SFTP, authenticating with a key file, a password or the SSH agent.
+#[derive(Clone, Debug)]
+pub struct SftpTarget {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: Option<String>,
+    pub private_key: Option<PathBuf>,
+    pub passphrase: Option<String>,
+    pub root: String,
+}
+
+impl SftpTarget {
+    fn connect(&self) -> Res<Sftp> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port)).map_err(|e| self.error(e))?;
+        let timeout = Some(Duration::from_secs(STORAGE_TIMEOUT_SECONDS));
+        tcp.set_read_timeout(timeout).map_err(|e| self.error(e))?;
+        tcp.set_write_timeout(timeout).map_err(|e| self.error(e))?;
+
+        let mut session = Session::new().map_err(|e| self.error(e))?;
+        session.set_tcp_stream(tcp);
+        session.handshake().map_err(|e| self.error(e))?;
+        match (&self.private_key, &self.password) {
+            (Some(key), _) => session.userauth_pubkey_file(&self.username, None, key, self.passphrase.as_deref()),
+            (None, Some(password)) => session.userauth_password(&self.username, password),
+            (None, None) => session.userauth_agent(&self.username),
+        }
+        .map_err(|e| self.error(e))?;
+
+        session.sftp().map_err(|e| self.error(e))
+    }
+
+    fn remote(&self, key: &str) -> Res<PathBuf> {
+        Ok(PathBuf::from(&self.root).join(Sandbox::normalize(key)?))
+    }
+
+    fn create_parents(&self, sftp: &Sftp, path: &Path) -> Res<()> {
+        let mut ancestors: Vec<&Path> = path.ancestors().skip(1).collect();
+        ancestors.reverse();
+        for directory in ancestors.into_iter().filter(|d| !d.as_os_str().is_empty()) {
+            if sftp.stat(directory).is_err() {
+                sftp.mkdir(directory, 0o755).map_err(|e| self.error(e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes into `<key>.partial`, continuing from its current size if a previous upload was interrupted,
+    /// and renames it into place once complete.
+    fn upload_blocking(&self, source: &Path, key: &str) -> Res<()> {
+        let sftp = self.connect()?;
+        let remote = self.remote(key)?;
+        let partial = PathBuf::from(format!("{}.partial", remote.display()));
+        self.create_parents(&sftp, &remote)?;
+
+        let mut local = File::open(source).map_err(|e| self.error(e))?;
+        let size = local.metadata().map_err(|e| self.error(e))?.len();
+        let offset = sftp
+            .stat(&partial)
+            .ok()
+            .and_then(|stat| stat.size)
+            .filter(|existing| *existing <= size)
+            .unwrap_or(0);
+
+        let mut upload = if offset > 0 {
+            let mut file = sftp
+                .open_mode(&partial, OpenFlags::WRITE, 0o644, OpenType::File)
+                .map_err(|e| self.error(e))?;
+            file.seek(SeekFrom::Start(offset)).map_err(|e| self.error(e))?;
+            local.seek(SeekFrom::Start(offset)).map_err(|e| self.error(e))?;
+            file
+        } else {
+            sftp.create(&partial).map_err(|e| self.error(e))?
+        };
+        std::io::copy(&mut local, &mut upload).map_err(|e| self.error(e))?;
+        drop(upload);
+
+        let _ = sftp.unlink(&remote);
+        sftp.rename(&partial, &remote, None).map_err(|e| self.error(e))
+    }
+
+    fn download_blocking(&self, key: &str, destination: &Path) -> Res<()> {
+        let sftp = self.connect()?;
+        let mut remote = sftp.open(&self.remote(key)?).map_err(|e| self.error(e))?;
+        let mut local = File::create(destination).map_err(|e| self.error(e))?;
+        std::io::copy(&mut remote, &mut local).map_err(|e| self.error(e))?;
+        Ok(())
+    }
+
+    fn delete_blocking(&self, key: &str) -> Res<()> {
+        let sftp = self.connect()?;
+        match sftp.unlink(&self.remote(key)?) {
+            Err(e) if e.code() != ErrorCode::SFTP(SFTP_NO_SUCH_FILE) => Err(self.error(e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl BackupTarget for SftpTarget {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn upload(&self, source: &Path, key: &str) -> Res<()> {
+        let (target, source, key) = (self.clone(), source.to_path_buf(), key.to_string());
+        tokio::task::spawn_blocking(move || target.upload_blocking(&source, &key))
+            .await
+            .map_err(|e| self.error(e))?
+    }
+
+    async fn download(&self, key: &str, destination: &Path) -> Res<()> {
+        let (target, key, destination) = (self.clone(), key.to_string(), destination.to_path_buf());
+        tokio::task::spawn_blocking(move || target.download_blocking(&key, &destination))
+            .await
+            .map_err(|e| self.error(e))?
+    }
+
+    async fn delete(&self, key: &str) -> Res<()> {
+        let (target, key) = (self.clone(), key.to_string());
+        tokio::task::spawn_blocking(move || target.delete_blocking(&key))
+            .await
+            .map_err(|e| self.error(e))?
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Datelike, TimeDelta, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupTrigger {
+    Manual,
+    Scheduled,
+
+    /// Taken automatically before a backup is restored over the server
+    PreRestore,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupFormat {
+    /// A self-contained `.tar.gz` archive
+    #[default]
+    Archive,
+
+    /// A snapshot in the shared, deduplicated backup repository
+    Incremental,
+}
+
+/// Which backups to keep. Backups matched by any rule are kept; with no rules set, all backups are kept.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct RetentionPolicy {
+    /// Keep the most recent N backups
+    #[serde(default)]
+    pub keep_last: Option<u32>,
+
+    /// Keep the newest backup of each day, for this many days
+    #[serde(default)]
+    pub daily: Option<u32>,
+
+    /// Keep the newest backup of each week, for this many weeks
+    #[serde(default)]
+    pub weekly: Option<u32>,
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.keep_last.is_none() && self.daily.is_none() && self.weekly.is_none()
+    }
+
+    /// Decides which backups to keep, given their creation times ordered newest first.
+    pub fn retain(&self, created: &[DateTime<Utc>], now: DateTime<Utc>) -> Vec<bool> {
+        if self.is_empty() {
+            return vec![true; created.len()];
+        }
+
+        let mut keep = vec![false; created.len()];
+        if let Some(count) = self.keep_last {
+            keep.iter_mut().take(count as usize).for_each(|k| *k = true);
+        }
+
+        if let Some(days) = self.daily {
+            let mut seen = HashSet::new();
+            for (index, time) in created.iter().enumerate() {
+                if now - *time <= TimeDelta::days(days as i64) && seen.insert(time.date_naive()) {
+                    keep[index] = true;
+                }
+            }
+        }
+
+        if let Some(weeks) = self.weekly {
+            let mut seen = HashSet::new();
+            for (index, time) in created.iter().enumerate() {
+                let week = time.iso_week();
+                if now - *time <= TimeDelta::weeks(weeks as i64) && seen.insert((week.year(), week.week())) {
+                    keep[index] = true;
+                }
+            }
+        }
+
+        keep
+    }
+}
+
+fn default_sftp_port() -> u16 {
+    22
+}
+
+fn default_path_style() -> bool {
+    true
+}
+
+/// A place backup archives can be uploaded to, besides the local backup directory.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BackupTargetConfig {
+    Local {
+        path: PathBuf,
+    },
+    Sftp {
+        host: String,
+        #[serde(default = "default_sftp_port")]
+        port: u16,
+        username: String,
+        #[serde(default)]
+        password: Option<String>,
+        #[serde(default)]
+        private_key: Option<PathBuf>,
+        #[serde(default)]
+        passphrase: Option<String>,
+
+        /// Remote directory backups are stored in
+        path: String,
+    },
+
+    /// Any S3-compatible object store, such as AWS S3 or MinIO
+    S3 {
+        endpoint: String,
+        region: String,
+        bucket: String,
+        access_key: String,
+        secret_key: String,
+        #[serde(default)]
+        prefix: Option<String>,
+
+        /// Address buckets as `endpoint/bucket` rather than `bucket.endpoint`, as MinIO expects
+        #[serde(default = "default_path_style")]
+        path_style: bool,
+    },
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct BackupConfig {
+    /// Where backup archives are stored, defaults to `.backups` next to the server directories
+    #[serde(default)]
+    pub directory: Option<PathBuf>,
+
+    /// Where incremental backups are stored, defaults to `repository` inside the backup directory
+    #[serde(default)]
+    pub repository: Option<PathBuf>,
+
+    /// Retention applied to servers that do not define their own
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+
+    /// Named remote targets that servers can select for their archive backups
+    #[serde(default)]
+    pub targets: HashMap<String, BackupTargetConfig>,
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
 use mongodb::options::ClientOptions;
 use rocket::{
@@ -9,7 +9,7 @@ use rocket_okapi::OpenApiFromRequest;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::{ApiError, runners::docker_host::DockerHostRunnerOptions};
+use crate::{ApiError, runners::docker_host::DockerHostRunnerOptions, types::{BackupConfig, ContentConfig}, utilities::ArchiveLimits};
 
 #[derive(Deserialize, Clone, Debug)]
 #[serde(untagged)]
@@ -61,6 +61,12 @@ impl RunnerConfig {
         }
     }
 
+    pub fn server_directory(&self, id: impl ToString) -> PathBuf {
+        match self {
+            Self::DockerHost { host_base_path, .. } => host_base_path.join(id.to_string())
+        }
+    }
+
     pub fn mode(&self) -> RunnerMode {
         match self {
             Self::DockerHost { .. } => RunnerMode::DockerHost
@@ -68,15 +74,135 @@ impl RunnerConfig {
     }
 }
 
+fn default_oidc_scopes() -> Vec<String> {
+    vec![String::from("openid"), String::from("profile"), String::from("email")]
+}
+
+fn default_username_claim() -> String {
+    String::from("preferred_username")
+}
+
+fn default_groups_claim() -> String {
+    String::from("groups")
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// OpenID Connect provider used for single sign-on, with the authorization code flow and PKCE.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OidcConfig {
+    /// Issuer URL; the provider is discovered from `<issuer>/.well-known/openid-configuration`
+    pub issuer: String,
+    pub client_id: String,
+
+    /// Omit for public clients, which rely on PKCE alone
+    #[serde(default)]
+    pub client_secret: Option<String>,
+
+    /// Must point at `/auth/oidc/callback` and be registered with the provider
+    pub redirect_uri: String,
+
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+
+    /// Claim used as the username of provisioned users
+    #[serde(default = "default_username_claim")]
+    pub username_claim: String,
+
+    /// Claim listing the user's groups
+    #[serde(default = "default_groups_claim")]
+    pub groups_claim: String,
+
+    /// Members of these groups become superusers, and others lose superuser status. Ignored when empty.
+    #[serde(default)]
+    pub superuser_groups: Vec<String>,
+
+    /// Group names mapped to the names of roles applied to every server. When set, the user's global roles are
+    /// replaced on every login.
+    #[serde(default)]
+    pub role_groups: HashMap<String, Vec<String>>,
+
+    /// Create users that log in for the first time
+    #[serde(default = "default_true")]
+    pub auto_provision: bool,
+
+    /// Link unlinked local accounts with the same username on first login, instead of refusing it
+    #[serde(default)]
+    pub link_by_username: bool,
+
+    /// Where the browser is sent after logging in, unless the login asked for another path
+    #[serde(default)]
+    pub post_login_redirect: Option<String>,
+
+    /// Where the provider sends the browser after logging out
+    #[serde(default)]
+    pub post_logout_redirect: Option<String>,
+}
+
+fn default_session_lifetime() -> chrono::TimeDelta {
+    chrono::TimeDelta::weeks(1)
+}
+
+/// Throttling of login attempts. Buckets hold `capacity` attempts and regain one every `refill_seconds`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LoginLimits {
+    pub ip_capacity: u32,
+    pub ip_refill_seconds: u64,
+    pub username_capacity: u32,
+    pub username_refill_seconds: u64,
+
+    /// Failed logins, each within the failure window of the previous one, before an account is locked
+    pub lockout_threshold: u32,
+
+    /// Failed logins are forgotten once none happened for this long and the account is not locked
+    pub failure_window_seconds: u64,
+
+    /// First lockout duration, doubled with every further failure
+    pub lockout_base_seconds: u64,
+    pub lockout_max_seconds: u64,
+}
+
+impl Default for LoginLimits {
+    fn default() -> Self {
+        Self {
+            ip_capacity: 20,
+            ip_refill_seconds: 6,
+            username_capacity: 10,
+            username_refill_seconds: 30,
+            lockout_threshold: 5,
+            failure_window_seconds: 15 * 60,
+            lockout_base_seconds: 30,
+            lockout_max_seconds: 60 * 60,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AuthenticationConfig {
-    pub session_max_lifetime: chrono::TimeDelta
+    #[serde(default = "default_session_lifetime")]
+    pub session_max_lifetime: chrono::TimeDelta,
+
+    /// Only send the session cookie over HTTPS. Disable for local development without TLS.
+    #[serde(default = "default_true")]
+    pub secure_cookies: bool,
+
+    #[serde(default)]
+    pub login_limits: LoginLimits,
+
+    #[serde(default)]
+    pub oidc: Option<OidcConfig>
 }
 
 impl Default for AuthenticationConfig {
     fn default() -> Self {
         Self {
-            session_max_lifetime: chrono::TimeDelta::weeks(1)
+            session_max_lifetime: default_session_lifetime(),
+            secure_cookies: true,
+            login_limits: LoginLimits::default(),
+            oidc: None
         }
     }
 }
@@ -89,9 +215,36 @@ pub struct AppConfig {
     #[serde(default)]
     pub authentication: AuthenticationConfig,
     #[serde(default)]
+    pub archives: ArchiveLimits,
+    #[serde(default)]
+    pub backups: BackupConfig,
+    #[serde(default)]
+    pub content: ContentConfig,
+    #[serde(default)]
     pub admin_user: Option<(String, String)>
 }
 
+impl AppConfig {
+    pub fn backup_directory(&self) -> PathBuf {
+        self.backups
+            .directory
+            .clone()
+            .unwrap_or_else(|| self.runner.server_directory(".backups"))
+    }
+
+    /// Where template seed files are kept, next to the server directories.
+    pub fn template_directory(&self) -> PathBuf {
+        self.runner.server_directory(".templates")
+    }
+
+    pub fn backup_repository(&self) -> PathBuf {
+        self.backups
+            .repository
+            .clone()
+            .unwrap_or_else(|| self.backup_directory().join("repository"))
+    }
+}
+
 #[async_trait::async_trait]
 impl<'r> FromRequest<'r> for AppConfig {
     type Error = ApiError;
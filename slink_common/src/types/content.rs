@@ -0,0 +1,154 @@
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{CONTENT_LOCKFILE, CURSEFORGE_API, Error, MODRINTH_API, Res};
+
+fn default_modrinth_api() -> String {
+    MODRINTH_API.to_string()
+}
+
+fn default_curseforge_api() -> String {
+    CURSEFORGE_API.to_string()
+}
+
+/// Upstream catalogs that mods and plugins are installed from.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ContentConfig {
+    #[serde(default = "default_modrinth_api")]
+    pub modrinth_api: String,
+
+    #[serde(default = "default_curseforge_api")]
+    pub curseforge_api: String,
+
+    /// Required for CurseForge imports, see https://console.curseforge.com
+    #[serde(default)]
+    pub curseforge_api_key: Option<String>,
+}
+
+impl Default for ContentConfig {
+    fn default() -> Self {
+        Self {
+            modrinth_api: default_modrinth_api(),
+            curseforge_api: default_curseforge_api(),
+            curseforge_api_key: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, JsonSchema, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentKind {
+    #[default]
+    Mod,
+    Plugin,
+}
+
+impl ContentKind {
+    /// Directory the content is installed to, relative to the server directory.
+    pub fn directory(&self) -> &'static str {
+        match self {
+            Self::Mod => "mods",
+            Self::Plugin => "plugins",
+        }
+    }
+
+    /// Modrinth `project_type` facet value.
+    pub fn project_type(&self) -> &'static str {
+        match self {
+            Self::Mod => "mod",
+            Self::Plugin => "plugin",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentSource {
+    Modrinth,
+}
+
+/// A mod or plugin installed from a catalog.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct LockedContent {
+    pub source: ContentSource,
+    pub project_id: String,
+    pub version_id: String,
+    pub name: String,
+    pub version_number: String,
+    pub kind: ContentKind,
+
+    /// Installed file, relative to the server directory
+    pub file: String,
+
+    /// Hex-encoded SHA-512 of the installed file
+    pub sha512: String,
+
+    /// Whether the content was only installed to satisfy another project's dependencies
+    #[serde(default)]
+    pub dependency: bool,
+    pub installed: DateTime<Utc>,
+}
+
+/// A file that a modpack needs but its author does not allow to be downloaded through the API.
+/// It has to be downloaded by hand and uploaded to `file`.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+pub struct RestrictedContent {
+    pub project_id: u64,
+    pub file_id: u64,
+    pub name: String,
+
+    /// Expected location, relative to the server directory
+    pub file: String,
+
+    /// Page the file can be downloaded from
+    pub url: String,
+}
+
+/// Per-server record of installed catalog content, kept in the server directory.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct ContentLock {
+    #[serde(default)]
+    pub content: Vec<LockedContent>,
+
+    /// Files from imported packs that still have to be uploaded manually
+    #[serde(default)]
+    pub restricted: Vec<RestrictedContent>,
+}
+
+impl ContentLock {
+    pub async fn load(directory: impl AsRef<Path>) -> Res<Self> {
+        let path = directory.as_ref().join(CONTENT_LOCKFILE);
+        match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data).map_err(Error::deserialization),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(Error::path(path.display(), e)),
+        }
+    }
+
+    pub async fn save(&self, directory: impl AsRef<Path>) -> Res<()> {
+        let path = directory.as_ref().join(CONTENT_LOCKFILE);
+        let data = serde_json::to_vec_pretty(self).map_err(Error::serialization)?;
+        tokio::fs::write(&path, data)
+            .await
+            .map_err(|e| Error::path(path.display(), e))
+    }
+
+    pub fn get(&self, project_id: &str) -> Option<&LockedContent> {
+        self.content.iter().find(|c| c.project_id == project_id)
+    }
+
+    /// Adds or replaces the entry for a project, returning the replaced entry.
+    pub fn insert(&mut self, entry: LockedContent) -> Option<LockedContent> {
+        let previous = self.remove(&entry.project_id);
+        self.content.push(entry);
+        previous
+    }
+
+    pub fn remove(&mut self, project_id: &str) -> Option<LockedContent> {
+        let index = self.content.iter().position(|c| c.project_id == project_id)?;
+        Some(self.content.remove(index))
+    }
+}
@@ -3,9 +3,15 @@ pub mod config;
 pub mod networking;
 pub mod versioning;
 pub mod server;
+pub mod players;
+pub mod backups;
+pub mod content;
 
 pub use minecraft::*;
 pub use config::*;
 pub use networking::*;
 pub use versioning::Version;
-pub use server::*;
\ No newline at end of file
+pub use server::*;
+pub use players::*;
+pub use backups::*;
+pub use content::*;
\ No newline at end of file
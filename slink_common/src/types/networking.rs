@@ -11,6 +11,12 @@ impl RequestId {
     }
 }
 
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl Into<Header<'_>> for RequestId {
     fn into(self) -> Header<'static> {
         Header::new("X-SLR-ID", self.0.to_string())
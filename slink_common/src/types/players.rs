@@ -0,0 +1,171 @@
+use std::path::Path;
+
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use uuid::Uuid;
+
+use crate::{Error, Res};
+
+use super::PermissionLevel;
+
+const BAN_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+const BAN_FOREVER: &str = "forever";
+
+pub trait PlayerListEntry: Serialize + DeserializeOwned + Clone + Send + Sync {
+    const FILENAME: &'static str;
+
+    /// Key identifying the entry: the player's UUID, or the address for IP bans.
+    fn key(&self) -> String;
+
+    fn matches(&self, query: &str) -> bool;
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct WhitelistEntry {
+    pub uuid: Uuid,
+    pub name: String,
+}
+
+impl PlayerListEntry for WhitelistEntry {
+    const FILENAME: &'static str = "whitelist.json";
+
+    fn key(&self) -> String {
+        self.uuid.to_string()
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        self.name.eq_ignore_ascii_case(query) || self.uuid.to_string() == query
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorEntry {
+    pub uuid: Uuid,
+    pub name: String,
+    pub level: PermissionLevel,
+
+    #[serde(default)]
+    pub bypasses_player_limit: bool,
+}
+
+impl PlayerListEntry for OperatorEntry {
+    const FILENAME: &'static str = "ops.json";
+
+    fn key(&self) -> String {
+        self.uuid.to_string()
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        self.name.eq_ignore_ascii_case(query) || self.uuid.to_string() == query
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct BanDetails {
+    pub created: String,
+    pub source: String,
+    pub expires: String,
+    pub reason: String,
+}
+
+impl BanDetails {
+    pub fn new(source: impl Into<String>, reason: Option<String>, expires: Option<chrono::DateTime<Utc>>) -> Self {
+        Self {
+            created: Utc::now().format(BAN_DATE_FORMAT).to_string(),
+            source: source.into(),
+            expires: expires
+                .map(|e| e.format(BAN_DATE_FORMAT).to_string())
+                .unwrap_or(BAN_FOREVER.to_string()),
+            reason: reason.unwrap_or(String::from("Banned by an operator.")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct BannedPlayerEntry {
+    pub uuid: Uuid,
+    pub name: String,
+
+    #[serde(flatten)]
+    pub details: BanDetails,
+}
+
+impl PlayerListEntry for BannedPlayerEntry {
+    const FILENAME: &'static str = "banned-players.json";
+
+    fn key(&self) -> String {
+        self.uuid.to_string()
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        self.name.eq_ignore_ascii_case(query) || self.uuid.to_string() == query
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct BannedIpEntry {
+    pub ip: String,
+
+    #[serde(flatten)]
+    pub details: BanDetails,
+}
+
+impl PlayerListEntry for BannedIpEntry {
+    const FILENAME: &'static str = "banned-ips.json";
+
+    fn key(&self) -> String {
+        self.ip.clone()
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        self.ip == query
+    }
+}
+
+/// One of the JSON player lists kept in a server's directory.
+#[derive(Clone, Debug)]
+pub struct PlayerList<T: PlayerListEntry> {
+    pub entries: Vec<T>,
+}
+
+impl<T: PlayerListEntry> PlayerList<T> {
+    pub async fn load(directory: impl AsRef<Path>) -> Res<Self> {
+        let path = directory.as_ref().join(T::FILENAME);
+        let entries = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) if contents.trim().is_empty() => Vec::new(),
+            Ok(contents) => {
+                serde_json::from_str::<Vec<T>>(&contents).map_err(Error::deserialization)?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Error::unexpected(e),
+        };
+
+        Ok(Self { entries })
+    }
+
+    pub async fn save(&self, directory: impl AsRef<Path>) -> Res<()> {
+        let serialized =
+            serde_json::to_string_pretty(&self.entries).map_err(Error::serialization)?;
+        tokio::fs::write(directory.as_ref().join(T::FILENAME), serialized)
+            .await
+            .or_else(Error::unexpected)
+    }
+
+    pub fn find(&self, query: impl AsRef<str>) -> Option<T> {
+        self.entries.iter().find(|e| e.matches(query.as_ref())).cloned()
+    }
+
+    /// Inserts an entry, replacing any existing entry with the same key.
+    pub fn upsert(&mut self, entry: T) {
+        self.entries.retain(|e| e.key() != entry.key());
+        self.entries.push(entry);
+    }
+
+    pub fn remove(&mut self, query: impl AsRef<str>) -> Option<T> {
+        let found = self.find(query.as_ref())?;
+        self.entries.retain(|e| e.key() != found.key());
+        Some(found)
+    }
+}
@@ -59,7 +59,7 @@ pub enum CompressionType {
     None,
 }
 
-#[derive(Serialize_repr, Deserialize_repr, Clone, Debug, JsonSchema)]
+#[derive(Serialize_repr, Deserialize_repr, Clone, Debug, JsonSchema, PartialEq, Eq)]
 #[repr(u8)]
 pub enum PermissionLevel {
     All = 0,
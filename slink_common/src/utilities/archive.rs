@@ -0,0 +1,472 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{ARCHIVE_MAX_ENTRIES, ARCHIVE_MAX_EXTRACTED_SIZE, Error, Res, utilities::Sandbox};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub fn detect(name: impl AsRef<str>) -> Option<Self> {
+        let name = name.as_ref().to_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Upper bounds applied to an archive before and during extraction.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ArchiveLimits {
+    /// Maximum total uncompressed size, in bytes
+    pub max_size: u64,
+    pub max_entries: u64,
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        Self {
+            max_size: ARCHIVE_MAX_EXTRACTED_SIZE,
+            max_entries: ARCHIVE_MAX_ENTRIES,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct ArchiveProgress {
+    pub entries: u64,
+    pub total_entries: u64,
+    pub bytes: u64,
+    pub total_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct ExtractionSummary {
+    pub entries: u64,
+    pub bytes: u64,
+
+    /// Single top-level folder that was stripped from every entry, if the archive had one
+    pub stripped_prefix: Option<String>,
+
+    /// Entries that were not extracted, such as symlinks and device files
+    pub skipped: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EntryKind {
+    File,
+    Directory,
+    Unsupported,
+}
+
+#[derive(Clone, Debug)]
+struct ArchiveEntry {
+    name: String,
+    path: PathBuf,
+    kind: EntryKind,
+    size: u64,
+}
+
+/// A `.zip` or `.tar.gz` file on disk. All operations are blocking and should be run on a blocking thread.
+#[derive(Clone, Debug)]
+pub struct Archive {
+    path: PathBuf,
+    format: ArchiveFormat,
+}
+
+impl Archive {
+    pub fn new(path: impl AsRef<Path>, format: ArchiveFormat) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            format,
+        }
+    }
+
+    pub fn format(&self) -> ArchiveFormat {
+        self.format
+    }
+
+    fn error(&self, reason: impl ToString) -> Error {
+        Error::path(self.path.display(), reason)
+    }
+
+    /// Visits every entry in order. Entry names are normalized, so any entry that would escape
+    /// the extraction root fails the whole archive.
+    fn visit(&self, mut visitor: impl FnMut(ArchiveEntry, &mut dyn Read) -> Res<()>) -> Res<()> {
+        let file = File::open(&self.path).map_err(|e| self.error(e))?;
+        match self.format {
+            ArchiveFormat::Zip => {
+                let mut archive = zip::ZipArchive::new(file).map_err(|e| self.error(e))?;
+                for index in 0..archive.len() {
+                    let mut item = archive.by_index(index).map_err(|e| self.error(e))?;
+                    let name = item.name().to_string();
+                    let is_symlink = item.unix_mode().is_some_and(|mode| mode & 0o170000 == 0o120000);
+                    let kind = if is_symlink {
+                        EntryKind::Unsupported
+                    } else if item.is_dir() {
+                        EntryKind::Directory
+                    } else {
+                        EntryKind::File
+                    };
+                    let entry = ArchiveEntry {
+                        path: Sandbox::normalize(&name)?,
+                        name,
+                        kind,
+                        size: item.size(),
+                    };
+                    visitor(entry, &mut item)?;
+                }
+            }
+            ArchiveFormat::TarGz => {
+                let mut archive = tar::Archive::new(GzDecoder::new(file));
+                for item in archive.entries().map_err(|e| self.error(e))? {
+                    let mut item = item.map_err(|e| self.error(e))?;
+                    let name = item.path().map_err(|e| self.error(e))?.to_string_lossy().to_string();
+                    let kind = match item.header().entry_type() {
+                        tar::EntryType::Regular | tar::EntryType::Continuous => EntryKind::File,
+                        tar::EntryType::Directory => EntryKind::Directory,
+                        _ => EntryKind::Unsupported,
+                    };
+                    let entry = ArchiveEntry {
+                        path: Sandbox::normalize(&name)?,
+                        name,
+                        kind,
+                        size: item.size(),
+                    };
+                    visitor(entry, &mut item)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the folder every entry lives in, if the archive wraps its contents in a single top-level folder.
+    fn common_root(entries: &[ArchiveEntry]) -> Option<PathBuf> {
+        let mut root: Option<PathBuf> = None;
+        let mut nested = false;
+        for entry in entries.iter().filter(|e| e.kind != EntryKind::Unsupported && !e.path.as_os_str().is_empty()) {
+            let mut components = entry.path.components();
+            let first = PathBuf::from(components.next()?.as_os_str());
+            let has_rest = components.next().is_some();
+
+            // A file at the top level means there is no wrapping folder
+            if !has_rest && entry.kind == EntryKind::File {
+                return None;
+            }
+            nested |= has_rest;
+
+            match &root {
+                Some(existing) if *existing != first => return None,
+                Some(_) => {}
+                None => root = Some(first),
+            }
+        }
+
+        if nested { root } else { None }
+    }
+
+    /// Extracts the archive into `destination`, which must already be confined to a sandbox.
+    /// Symlinks are never created, and existing symlinks inside the destination are not followed out of it.
+    pub fn extract(
+        &self,
+        destination: impl AsRef<Path>,
+        limits: &ArchiveLimits,
+        mut progress: impl FnMut(&ArchiveProgress),
+    ) -> Res<ExtractionSummary> {
+        std::fs::create_dir_all(destination.as_ref()).map_err(|e| self.error(e))?;
+        let destination = std::fs::canonicalize(destination.as_ref()).map_err(|e| self.error(e))?;
+
+        let mut entries = Vec::new();
+        self.visit(|entry, _| {
+            entries.push(entry);
+            if entries.len() as u64 > limits.max_entries {
+                return Err(self.error(format!("Archive contains more than {} entries", limits.max_entries)));
+            }
+            Ok(())
+        })?;
+
+        let mut state = ArchiveProgress {
+            total_entries: entries.len() as u64,
+            total_bytes: entries.iter().map(|e| e.size).sum(),
+            ..Default::default()
+        };
+        if state.total_bytes > limits.max_size {
+            return Err(self.error(format!("Archive expands to more than {} bytes", limits.max_size)));
+        }
+        progress(&state);
+
+        let prefix = Self::common_root(&entries);
+        let mut summary = ExtractionSummary {
+            stripped_prefix: prefix.as_ref().map(|p| p.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+
+        self.visit(|entry, reader| {
+            state.entries += 1;
+            let relative = match &prefix {
+                Some(prefix) => entry.path.strip_prefix(prefix).unwrap_or(&entry.path).to_path_buf(),
+                None => entry.path.clone(),
+            };
+            if relative.as_os_str().is_empty() {
+                progress(&state);
+                return Ok(());
+            }
+
+            let target = destination.join(&relative);
+            match entry.kind {
+                EntryKind::Unsupported => summary.skipped.push(entry.name.clone()),
+                EntryKind::Directory => {
+                    self.prepare_directory(&destination, &target)?;
+                }
+                EntryKind::File => {
+                    if let Some(parent) = target.parent() {
+                        self.prepare_directory(&destination, parent)?;
+                    }
+                    if std::fs::symlink_metadata(&target).is_ok_and(|m| m.is_symlink()) {
+                        return Err(self.error(format!("{} would overwrite a symlink", entry.name)));
+                    }
+
+                    let remaining = limits.max_size.saturating_sub(state.bytes);
+                    let mut output = File::create(&target).map_err(|e| self.error(e))?;
+                    let written = std::io::copy(&mut reader.take(remaining.saturating_add(1)), &mut output)
+                        .map_err(|e| self.error(e))?;
+                    if written > remaining {
+                        drop(output);
+                        let _ = std::fs::remove_file(&target);
+                        return Err(self.error(format!("Archive expands to more than {} bytes", limits.max_size)));
+                    }
+
+                    state.bytes += written;
+                    summary.entries += 1;
+                }
+            }
+
+            progress(&state);
+            Ok(())
+        })?;
+
+        summary.bytes = state.bytes;
+        Ok(summary)
+    }
+
+    /// Creates a directory and verifies that it did not resolve outside of `root` through an existing symlink.
+    fn prepare_directory(&self, root: &Path, directory: &Path) -> Res<()> {
+        std::fs::create_dir_all(directory).map_err(|e| self.error(e))?;
+        let real = std::fs::canonicalize(directory).map_err(|e| self.error(e))?;
+        if !real.starts_with(root) {
+            return Err(Error::path(directory.display(), "Path escapes the extraction directory"));
+        }
+        Ok(())
+    }
+
+    /// Writes `source` as a gzipped tarball into `writer`, under a single top-level folder named `name`.
+    /// Symlinks are stored as links rather than followed.
+    pub fn write_tar_gz(source: impl AsRef<Path>, name: impl AsRef<Path>, writer: impl Write) -> Res<()> {
+        let error = |e: std::io::Error| Error::path(source.as_ref().display(), e);
+        let mut builder = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+        builder.follow_symlinks(false);
+        builder.append_dir_all(name, source.as_ref()).map_err(&error)?;
+        builder
+            .into_inner()
+            .map_err(&error)?
+            .finish()
+            .map_err(error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+    use crate::utilities::testing::TestDir;
+
+    fn zip(path: &Path, build: impl FnOnce(&mut ZipWriter<File>)) -> Archive {
+        let mut writer = ZipWriter::new(File::create(path).unwrap());
+        build(&mut writer);
+        writer.finish().unwrap();
+        Archive::new(path, ArchiveFormat::Zip)
+    }
+
+    fn zip_file(writer: &mut ZipWriter<File>, name: &str, data: &[u8]) {
+        writer.start_file(name, SimpleFileOptions::default()).unwrap();
+        writer.write_all(data).unwrap();
+    }
+
+    /// Builds a `.tar.gz` from raw entries. Names are written into the header directly, since the tar crate
+    /// refuses to create the malicious ones these tests need.
+    fn tar_gz(path: &Path, entries: &[(&str, tar::EntryType, &[u8], &str)]) -> Archive {
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(path).unwrap(), Compression::default()));
+        for (name, kind, data, link) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(*kind);
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            if !link.is_empty() {
+                header.set_link_name(link).unwrap();
+            }
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+        Archive::new(path, ArchiveFormat::TarGz)
+    }
+
+    fn extract(archive: &Archive, destination: &Path) -> Res<ExtractionSummary> {
+        archive.extract(destination, &ArchiveLimits::default(), |_| {})
+    }
+
+    #[test]
+    fn detects_formats_by_extension() {
+        assert_eq!(ArchiveFormat::detect("World.ZIP"), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::detect("world.tar.gz"), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::detect("world.tgz"), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::detect("world.rar"), None);
+    }
+
+    #[test]
+    fn strips_a_single_top_level_folder() {
+        let dir = TestDir::new();
+        let archive = zip(&dir.path().join("pack.zip"), |writer| {
+            writer.add_directory("pack/", SimpleFileOptions::default()).unwrap();
+            zip_file(writer, "pack/server.properties", b"motd=hi");
+            zip_file(writer, "pack/world/level.dat", b"level");
+        });
+
+        let summary = extract(&archive, &dir.path().join("out")).unwrap();
+        assert_eq!(summary.stripped_prefix.as_deref(), Some("pack"));
+        assert_eq!(summary.entries, 2);
+        assert_eq!(summary.bytes, 12);
+        assert_eq!(std::fs::read(dir.path().join("out/world/level.dat")).unwrap(), b"level");
+    }
+
+    #[test]
+    fn keeps_archives_without_a_single_folder() {
+        let dir = TestDir::new();
+        let archive = tar_gz(
+            &dir.path().join("world.tar.gz"),
+            &[
+                ("world/level.dat", tar::EntryType::Regular, b"level", ""),
+                ("ops.json", tar::EntryType::Regular, b"[]", ""),
+            ],
+        );
+
+        let summary = extract(&archive, &dir.path().join("out")).unwrap();
+        assert_eq!(summary.stripped_prefix, None);
+        assert!(dir.path().join("out/world/level.dat").exists());
+        assert!(dir.path().join("out/ops.json").exists());
+    }
+
+    #[test]
+    fn rejects_zip_slip() {
+        let dir = TestDir::new();
+        let archive = zip(&dir.path().join("evil.zip"), |writer| {
+            zip_file(writer, "world/level.dat", b"level");
+            zip_file(writer, "../../evil.txt", b"evil");
+        });
+        assert!(extract(&archive, &dir.path().join("out/nested")).is_err());
+        assert!(!dir.path().join("evil.txt").exists());
+        assert!(!dir.path().join("out/nested/world/level.dat").exists());
+
+        let archive = tar_gz(&dir.path().join("evil.tar.gz"), &[("../evil.txt", tar::EntryType::Regular, b"evil", "")]);
+        assert!(extract(&archive, &dir.path().join("out/nested")).is_err());
+        assert!(!dir.path().join("out/evil.txt").exists());
+    }
+
+    #[test]
+    fn skips_symlink_entries() {
+        let dir = TestDir::new();
+        let archive = zip(&dir.path().join("links.zip"), |writer| {
+            zip_file(writer, "level.dat", b"level");
+            writer.add_symlink("passwd", "/etc/passwd", SimpleFileOptions::default()).unwrap();
+        });
+        let summary = extract(&archive, &dir.path().join("zip")).unwrap();
+        assert_eq!(summary.skipped, vec!["passwd"]);
+        assert!(std::fs::symlink_metadata(dir.path().join("zip/passwd")).is_err());
+
+        let archive = tar_gz(
+            &dir.path().join("links.tar.gz"),
+            &[
+                ("level.dat", tar::EntryType::Regular, b"level", ""),
+                ("passwd", tar::EntryType::Symlink, b"", "/etc/passwd"),
+                ("hard", tar::EntryType::Link, b"", "level.dat"),
+            ],
+        );
+        let summary = extract(&archive, &dir.path().join("tar")).unwrap();
+        assert_eq!(summary.skipped, vec!["passwd", "hard"]);
+        assert!(std::fs::symlink_metadata(dir.path().join("tar/passwd")).is_err());
+        assert!(std::fs::symlink_metadata(dir.path().join("tar/hard")).is_err());
+    }
+
+    #[test]
+    fn does_not_follow_existing_symlinks_out() {
+        let dir = TestDir::new();
+        std::fs::create_dir_all(dir.path().join("outside")).unwrap();
+        std::fs::write(dir.path().join("outside/secret.txt"), "secret").unwrap();
+        std::fs::create_dir_all(dir.path().join("out")).unwrap();
+        symlink(dir.path().join("outside"), dir.path().join("out/escape")).unwrap();
+        symlink(dir.path().join("outside/secret.txt"), dir.path().join("out/secret.txt")).unwrap();
+
+        let archive = zip(&dir.path().join("through.zip"), |writer| {
+            zip_file(writer, "escape/planted.txt", b"evil");
+            zip_file(writer, "other.txt", b"other");
+        });
+        assert!(extract(&archive, &dir.path().join("out")).is_err());
+        assert!(!dir.path().join("outside/planted.txt").exists());
+
+        let archive = zip(&dir.path().join("over.zip"), |writer| {
+            zip_file(writer, "secret.txt", b"evil");
+            zip_file(writer, "other.txt", b"other");
+        });
+        assert!(extract(&archive, &dir.path().join("out")).is_err());
+        assert_eq!(std::fs::read(dir.path().join("outside/secret.txt")).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn enforces_limits() {
+        let dir = TestDir::new();
+        let archive = zip(&dir.path().join("big.zip"), |writer| {
+            zip_file(writer, "a.txt", &[0; 600]);
+            zip_file(writer, "b.txt", &[0; 600]);
+        });
+        let size = ArchiveLimits { max_size: 1000, max_entries: 10 };
+        assert!(archive.extract(dir.path().join("size"), &size, |_| {}).is_err());
+        assert!(!dir.path().join("size/a.txt").exists());
+
+        let entries = ArchiveLimits { max_size: 10_000, max_entries: 1 };
+        assert!(archive.extract(dir.path().join("entries"), &entries, |_| {}).is_err());
+        assert!(!dir.path().join("entries/a.txt").exists());
+    }
+
+    #[test]
+    fn writes_tarballs_under_a_folder() {
+        let dir = TestDir::new();
+        std::fs::create_dir_all(dir.path().join("server/world")).unwrap();
+        std::fs::write(dir.path().join("server/world/level.dat"), "level").unwrap();
+        let path = dir.path().join("server.tar.gz");
+        Archive::write_tar_gz(dir.path().join("server"), "survival", File::create(&path).unwrap()).unwrap();
+
+        let summary = extract(&Archive::new(&path, ArchiveFormat::TarGz), &dir.path().join("out")).unwrap();
+        assert_eq!(summary.stripped_prefix.as_deref(), Some("survival"));
+        assert_eq!(std::fs::read(dir.path().join("out/world/level.dat")).unwrap(), b"level");
+    }
+}
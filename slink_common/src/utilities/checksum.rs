@@ -0,0 +1,60 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Res};
+
+/// Wraps a writer, hashing and counting everything written through it.
+pub struct ChecksumWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+    written: u64,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            written: 0,
+        }
+    }
+
+    /// Returns the inner writer, the hex-encoded SHA-256 and the number of bytes written.
+    pub fn finish(self) -> (W, String, u64) {
+        (self.inner, format!("{:x}", self.hasher.finalize()), self.written)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Computes the hex-encoded SHA-256 of a file. Blocking.
+pub fn sha256_file(path: impl AsRef<Path>) -> Res<String> {
+    let error = |e: std::io::Error| Error::path(path.as_ref().display(), e);
+    let mut file = File::open(path.as_ref()).map_err(&error)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).map_err(&error)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,192 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Minecraft version and mod loader of an existing server directory, as far as they could be determined.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema, PartialEq, Eq)]
+pub struct DetectedServer {
+    pub minecraft_version: Option<String>,
+
+    /// `fabric`, `quilt`, `forge` or `neoforge`; `None` for vanilla servers or when undetermined
+    pub loader: Option<String>,
+    pub loader_version: Option<String>,
+
+    /// Where each value was found, for reporting
+    pub evidence: Vec<String>,
+}
+
+impl DetectedServer {
+    fn merge(&mut self, other: DetectedServer) {
+        if self.minecraft_version.is_none() {
+            self.minecraft_version = other.minecraft_version;
+        }
+        if self.loader.is_none() {
+            self.loader = other.loader;
+            self.loader_version = other.loader_version;
+        } else if self.loader == other.loader && self.loader_version.is_none() {
+            self.loader_version = other.loader_version;
+        }
+        self.evidence.extend(other.evidence);
+    }
+
+    fn found(minecraft_version: Option<String>, loader: Option<(&str, String)>, evidence: impl Into<String>) -> Self {
+        Self {
+            minecraft_version,
+            loader_version: loader.as_ref().map(|(_, v)| v.clone()),
+            loader: loader.map(|(l, _)| l.to_string()),
+            evidence: vec![evidence.into()],
+        }
+    }
+}
+
+fn subdirectories(path: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|e| e.file_name().to_str().map(|n| n.to_string()))
+        .collect();
+    names.sort();
+    names
+}
+
+fn files(path: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .filter_map(|e| e.file_name().to_str().map(|n| n.to_string()))
+        .collect();
+    names.sort();
+    names
+}
+
+/// `variables.txt`, as shipped with ServerPackCreator packs.
+fn from_variables(root: &Path) -> Option<DetectedServer> {
+    let content = std::fs::read_to_string(root.join("variables.txt")).ok()?;
+    let value = |key: &str| {
+        content.lines().find_map(|line| {
+            let (k, v) = line.split_once('=')?;
+            (k.trim() == key).then(|| v.trim().trim_matches('"').to_string())
+        })
+    };
+    let loader = value("MODLOADER")
+        .map(|l| l.to_lowercase())
+        .filter(|l| !l.is_empty() && l != "vanilla");
+    Some(DetectedServer {
+        minecraft_version: value("MINECRAFT_VERSION").filter(|v| !v.is_empty()),
+        loader_version: loader.as_ref().and_then(|_| value("MODLOADER_VERSION")),
+        loader,
+        evidence: vec![String::from("variables.txt")],
+    })
+}
+
+/// Fabric's installer-generated launcher, `fabric-server-mc.<mc>-loader.<loader>-launcher.<launcher>.jar`.
+fn from_fabric_launcher(root: &Path) -> Option<DetectedServer> {
+    files(root).into_iter().find_map(|name| {
+        let rest = name.strip_prefix("fabric-server-mc.")?;
+        let (minecraft, rest) = rest.split_once("-loader.")?;
+        let (loader, _) = rest.split_once("-launcher.")?;
+        Some(DetectedServer::found(
+            Some(minecraft.to_string()),
+            Some(("fabric", loader.to_string())),
+            name,
+        ))
+    })
+}
+
+/// Loader libraries installed by the Fabric, Quilt, Forge and NeoForge installers.
+fn from_libraries(root: &Path) -> Option<DetectedServer> {
+    let libraries = root.join("libraries");
+    let latest = |path: &str| subdirectories(&libraries.join(path)).pop();
+
+    if let Some(version) = latest("net/fabricmc/fabric-loader") {
+        return Some(DetectedServer::found(None, Some(("fabric", version)), "libraries/net/fabricmc/fabric-loader"));
+    }
+    if let Some(version) = latest("org/quiltmc/quilt-loader") {
+        return Some(DetectedServer::found(None, Some(("quilt", version)), "libraries/org/quiltmc/quilt-loader"));
+    }
+    if let Some(version) = latest("net/neoforged/neoforge") {
+        return Some(DetectedServer::found(None, Some(("neoforge", version)), "libraries/net/neoforged/neoforge"));
+    }
+    if let Some(version) = latest("net/minecraftforge/forge") {
+        // Forge versions are `<minecraft>-<forge>`
+        let (minecraft, forge) = version.split_once('-').unwrap_or(("", &version));
+        return Some(DetectedServer::found(
+            Some(minecraft.to_string()).filter(|v| !v.is_empty()),
+            Some(("forge", forge.to_string())),
+            "libraries/net/minecraftforge/forge",
+        ));
+    }
+    None
+}
+
+/// The Minecraft server itself, from `version.json` inside a jar in the root, or from the `versions` directory
+/// that newer bundled server jars extract themselves into.
+fn from_server_jar(root: &Path) -> Option<DetectedServer> {
+    let candidates = ["server.jar", "minecraft_server.jar"]
+        .into_iter()
+        .map(String::from)
+        .chain(files(root).into_iter().filter(|n| n.starts_with("minecraft_server.") && n.ends_with(".jar")));
+    for name in candidates {
+        let Ok(file) = File::open(root.join(&name)) else {
+            continue;
+        };
+        let Ok(mut archive) = zip::ZipArchive::new(file) else {
+            continue;
+        };
+        let Ok(mut entry) = archive.by_name("version.json") else {
+            continue;
+        };
+        let mut content = String::new();
+        if entry.read_to_string(&mut content).is_err() {
+            continue;
+        }
+        let version = serde_json::from_str::<serde_json::Value>(&content)
+            .ok()
+            .and_then(|v| v.get("id").and_then(|id| id.as_str().map(|id| id.to_string())));
+        if version.is_some() {
+            return Some(DetectedServer::found(version, None, format!("{name}/version.json")));
+        }
+    }
+
+    subdirectories(&root.join("versions"))
+        .into_iter()
+        .find(|v| root.join("versions").join(v).join(format!("server-{v}.jar")).is_file())
+        .map(|v| DetectedServer::found(Some(v.clone()), None, format!("versions/{v}")))
+}
+
+/// Looks for the Minecraft version and loader of a server directory, most specific source first.
+pub fn detect_server(root: impl AsRef<Path>) -> DetectedServer {
+    let root = root.as_ref();
+    let mut detected = DetectedServer::default();
+    for source in [from_variables, from_fabric_launcher, from_libraries, from_server_jar] {
+        if let Some(found) = source(root) {
+            detected.merge(found);
+        }
+        if detected.minecraft_version.is_some() && detected.loader_version.is_some() {
+            break;
+        }
+    }
+    detected
+}
+
+/// The directory of an extracted archive that holds the server. Archives often wrap everything in a single
+/// top-level folder.
+pub fn server_root(extracted: impl AsRef<Path>) -> PathBuf {
+    let mut root = extracted.as_ref().to_path_buf();
+    loop {
+        let (directories, files) = (subdirectories(&root), files(&root));
+        match (directories.as_slice(), files.is_empty()) {
+            ([only], true) => root = root.join(only),
+            _ => return root,
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use std::{
+    fs::File,
+    io::{Cursor, Read, Seek},
+    path::Path,
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use zip::ZipArchive;
+
+use crate::{Error, Res};
+
+/// How deep jar-in-jar bundles are followed.
+const MAX_NESTING: usize = 2;
+
+/// Largest metadata file read from a jar. Anything bigger is treated as missing.
+const MAX_METADATA_SIZE: u64 = 1024 * 1024;
+
+/// Largest jar-in-jar bundle loaded into memory to be inspected.
+const MAX_NESTED_JAR_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Metadata file a jar was described by.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JarFormat {
+    Fabric,
+    Quilt,
+    Forge,
+    NeoForge,
+    Bukkit,
+    Paper,
+}
+
+impl JarFormat {
+    /// Loaders that can run jars of this format.
+    pub fn loaders(&self) -> &'static [&'static str] {
+        match self {
+            Self::Fabric => &["fabric", "quilt"],
+            Self::Quilt => &["quilt"],
+            Self::Forge => &["forge"],
+            Self::NeoForge => &["neoforge"],
+            Self::Bukkit => &["paper", "purpur", "spigot", "bukkit", "folia"],
+            Self::Paper => &["paper", "purpur", "folia"],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JarEnvironment {
+    #[default]
+    Any,
+    Client,
+    Server,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+pub struct JarDependency {
+    pub id: String,
+
+    /// Version requirement as written in the metadata
+    pub version: Option<String>,
+    pub required: bool,
+}
+
+/// One mod or plugin declared in a jar. Forge jars may declare several.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct JarMetadata {
+    pub format: JarFormat,
+    pub id: String,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub environment: JarEnvironment,
+    pub dependencies: Vec<JarDependency>,
+
+    /// Minecraft version requirement (or plugin API version)
+    pub minecraft: Option<String>,
+
+    /// Loader version requirement
+    pub loader: Option<String>,
+
+    /// Additional IDs this jar satisfies, including those of bundled jars
+    pub provides: Vec<String>,
+}
+
+impl JarMetadata {
+    fn new(format: JarFormat, id: impl Into<String>) -> Self {
+        Self {
+            format,
+            id: id.into(),
+            name: None,
+            version: None,
+            environment: JarEnvironment::Any,
+            dependencies: Vec::new(),
+            minecraft: None,
+            loader: None,
+            provides: Vec::new(),
+        }
+    }
+}
+
+fn read_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Option<String> {
+    String::from_utf8(read_bytes(archive, name, MAX_METADATA_SIZE)?).ok()
+}
+
+/// Reads an entry of at most `limit` bytes. The declared size is checked first, and the read is bounded too,
+/// since the declared size can be forged.
+fn read_bytes<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str, limit: u64) -> Option<Vec<u8>> {
+    let entry = archive.by_name(name).ok()?;
+    if entry.size() > limit {
+        return None;
+    }
+    let mut content = Vec::with_capacity(entry.size() as usize);
+    entry.take(limit + 1).read_to_end(&mut content).ok()?;
+    match content.len() as u64 > limit {
+        true => None,
+        false => Some(content),
+    }
+}
+
+fn json_str(value: &serde_json::Value, key: &str) -> Option<String> {
+    value.get(key)?.as_str().map(|s| s.to_string())
+}
+
+/// Fabric version predicates are either a string or a list of alternatives.
+fn fabric_predicate(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Array(items) => Some(
+            items
+                .iter()
+                .filter_map(|i| i.as_str())
+                .collect::<Vec<&str>>()
+                .join(" || "),
+        ),
+        _ => None,
+    }
+}
+
+fn fabric<R: Read + Seek>(archive: &mut ZipArchive<R>, content: &str, depth: usize) -> Res<Vec<JarMetadata>> {
+    let json: serde_json::Value = serde_json::from_str(content).map_err(Error::deserialization)?;
+    let mut metadata = JarMetadata::new(
+        JarFormat::Fabric,
+        json_str(&json, "id").ok_or(Error::value_error("fabric.mod.json", "missing id"))?,
+    );
+    metadata.name = json_str(&json, "name");
+    metadata.version = json_str(&json, "version");
+    metadata.environment = match json_str(&json, "environment").as_deref() {
+        Some("client") => JarEnvironment::Client,
+        Some("server") => JarEnvironment::Server,
+        _ => JarEnvironment::Any,
+    };
+    metadata.provides = json
+        .get("provides")
+        .and_then(|p| p.as_array())
+        .map(|p| p.iter().filter_map(|i| i.as_str().map(|s| s.to_string())).collect())
+        .unwrap_or_default();
+
+    for (key, required) in [("depends", true), ("recommends", false)] {
+        for (id, predicate) in json.get(key).and_then(|d| d.as_object()).into_iter().flatten() {
+            let version = fabric_predicate(predicate);
+            match id.as_str() {
+                "minecraft" => metadata.minecraft = version,
+                "fabricloader" => metadata.loader = version,
+                _ => metadata.dependencies.push(JarDependency {
+                    id: id.clone(),
+                    version,
+                    required,
+                }),
+            }
+        }
+    }
+
+    let nested: Vec<String> = json
+        .get("jars")
+        .and_then(|j| j.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|j| json_str(j, "file"))
+        .collect();
+    bundled(archive, &mut metadata, nested, depth);
+    Ok(vec![metadata])
+}
+
+fn quilt<R: Read + Seek>(archive: &mut ZipArchive<R>, content: &str, depth: usize) -> Res<Vec<JarMetadata>> {
+    let json: serde_json::Value = serde_json::from_str(content).map_err(Error::deserialization)?;
+    let loader = json
+        .get("quilt_loader")
+        .ok_or(Error::value_error("quilt.mod.json", "missing quilt_loader"))?;
+    let mut metadata = JarMetadata::new(
+        JarFormat::Quilt,
+        json_str(loader, "id").ok_or(Error::value_error("quilt.mod.json", "missing id"))?,
+    );
+    metadata.name = loader.get("metadata").and_then(|m| json_str(m, "name"));
+    metadata.version = json_str(loader, "version");
+    metadata.environment = match json.get("minecraft").and_then(|m| json_str(m, "environment")).as_deref() {
+        Some("client") => JarEnvironment::Client,
+        Some("dedicated_server") => JarEnvironment::Server,
+        _ => JarEnvironment::Any,
+    };
+    metadata.provides = loader
+        .get("provides")
+        .and_then(|p| p.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|p| match p {
+            serde_json::Value::String(s) => Some(s.clone()),
+            other => json_str(other, "id"),
+        })
+        .collect();
+
+    for dependency in loader.get("depends").and_then(|d| d.as_array()).into_iter().flatten() {
+        let (id, version, required) = match dependency {
+            serde_json::Value::String(id) => (id.clone(), None, true),
+            other => (
+                match json_str(other, "id") {
+                    Some(id) => id,
+                    None => continue,
+                },
+                other.get("versions").and_then(fabric_predicate),
+                !other.get("optional").and_then(|o| o.as_bool()).unwrap_or(false),
+            ),
+        };
+        match id.as_str() {
+            "minecraft" => metadata.minecraft = version,
+            "quilt_loader" => metadata.loader = version,
+            _ => metadata.dependencies.push(JarDependency { id, version, required }),
+        }
+    }
+
+    let nested: Vec<String> = loader
+        .get("jars")
+        .and_then(|j| j.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|j| j.as_str().map(|s| s.to_string()))
+        .collect();
+    bundled(archive, &mut metadata, nested, depth);
+    Ok(vec![metadata])
+}
+
+/// Adds the IDs of jar-in-jar bundles to `provides`, so that bundled libraries do not show up as missing.
+fn bundled<R: Read + Seek>(archive: &mut ZipArchive<R>, metadata: &mut JarMetadata, nested: Vec<String>, depth: usize) {
+    if depth >= MAX_NESTING {
+        return;
+    }
+    for path in nested {
+        let Some(bytes) = read_bytes(archive, &path, MAX_NESTED_JAR_SIZE) else {
+            continue;
+        };
+        let Ok(mut inner) = ZipArchive::new(Cursor::new(bytes)) else {
+            continue;
+        };
+        for found in inspect_archive(&mut inner, depth + 1).unwrap_or_default() {
+            metadata.provides.push(found.id);
+            metadata.provides.extend(found.provides);
+        }
+    }
+}
+
+fn toml_str(value: &toml::Value, key: &str) -> Option<String> {
+    value.get(key)?.as_str().map(|s| s.to_string())
+}
+
+/// `META-INF/mods.toml` (Forge) and `META-INF/neoforge.mods.toml` (NeoForge).
+fn forge<R: Read + Seek>(archive: &mut ZipArchive<R>, content: &str, format: JarFormat) -> Res<Vec<JarMetadata>> {
+    let toml: toml::Value = toml::from_str(content).map_err(Error::deserialization)?;
+    let jar_version = read_entry(archive, "META-INF/MANIFEST.MF").and_then(|manifest| {
+        manifest.lines().find_map(|line| {
+            line.strip_prefix("Implementation-Version:")
+                .map(|v| v.trim().to_string())
+        })
+    });
+    let loader = toml_str(&toml, "loaderVersion");
+    let client_only = toml.get("clientSideOnly").and_then(|c| c.as_bool()).unwrap_or(false);
+    let loader_id = match format {
+        JarFormat::NeoForge => "neoforge",
+        _ => "forge",
+    };
+
+    let mut mods = Vec::new();
+    for entry in toml.get("mods").and_then(|m| m.as_array()).into_iter().flatten() {
+        let Some(id) = toml_str(entry, "modId") else {
+            continue;
+        };
+        let mut metadata = JarMetadata::new(format, &id);
+        metadata.name = toml_str(entry, "displayName");
+        metadata.version = match toml_str(entry, "version") {
+            Some(v) if v == "${file.jarVersion}" => jar_version.clone(),
+            other => other,
+        };
+        metadata.loader = loader.clone();
+        if client_only {
+            metadata.environment = JarEnvironment::Client;
+        }
+
+        let dependencies = toml.get("dependencies").and_then(|d| d.get(&id)).and_then(|d| d.as_array());
+        for dependency in dependencies.into_iter().flatten() {
+            let Some(dependency_id) = toml_str(dependency, "modId") else {
+                continue;
+            };
+            // Client-side dependencies do not need to be present on servers
+            if toml_str(dependency, "side").is_some_and(|s| s.eq_ignore_ascii_case("client")) {
+                continue;
+            }
+            let required = match (dependency.get("mandatory"), toml_str(dependency, "type")) {
+                (Some(mandatory), _) => mandatory.as_bool().unwrap_or(true),
+                (None, Some(kind)) => kind.eq_ignore_ascii_case("required"),
+                (None, None) => true,
+            };
+            let version = toml_str(dependency, "versionRange");
+            match dependency_id.as_str() {
+                "minecraft" => metadata.minecraft = version,
+                id if id == loader_id => metadata.loader = version.or(metadata.loader),
+                _ => metadata.dependencies.push(JarDependency {
+                    id: dependency_id,
+                    version,
+                    required,
+                }),
+            }
+        }
+        mods.push(metadata);
+    }
+    Ok(mods)
+}
+
+fn yaml_str(value: &serde_yaml::Value, key: &str) -> Option<String> {
+    match value.get(key)? {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn yaml_list(value: &serde_yaml::Value, key: &str) -> Vec<String> {
+    value
+        .get(key)
+        .and_then(|v| v.as_sequence())
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.as_str().map(|s| s.to_string()))
+        .collect()
+}
+
+/// `plugin.yml` (Bukkit and its forks) and `paper-plugin.yml`.
+fn plugin(content: &str, format: JarFormat) -> Res<Vec<JarMetadata>> {
+    let yaml: serde_yaml::Value = serde_yaml::from_str(content).map_err(Error::deserialization)?;
+    let name = yaml_str(&yaml, "name").ok_or(Error::value_error("plugin.yml", "missing name"))?;
+    let mut metadata = JarMetadata::new(format, &name);
+    metadata.name = Some(name);
+    metadata.version = yaml_str(&yaml, "version");
+    metadata.minecraft = yaml_str(&yaml, "api-version");
+    metadata.environment = JarEnvironment::Server;
+    metadata.provides = yaml_list(&yaml, "provides");
+
+    match format {
+        JarFormat::Paper => {
+            let dependencies = yaml.get("dependencies").and_then(|d| d.get("server")).and_then(|s| s.as_mapping());
+            for (id, options) in dependencies.into_iter().flatten() {
+                let Some(id) = id.as_str() else {
+                    continue;
+                };
+                metadata.dependencies.push(JarDependency {
+                    id: id.to_string(),
+                    version: None,
+                    required: options.get("required").and_then(|r| r.as_bool()).unwrap_or(true),
+                });
+            }
+        }
+        _ => {
+            for (key, required) in [("depend", true), ("softdepend", false)] {
+                metadata.dependencies.extend(yaml_list(&yaml, key).into_iter().map(|id| JarDependency {
+                    id,
+                    version: None,
+                    required,
+                }));
+            }
+        }
+    }
+    Ok(vec![metadata])
+}
+
+fn inspect_archive<R: Read + Seek>(archive: &mut ZipArchive<R>, depth: usize) -> Res<Vec<JarMetadata>> {
+    if let Some(content) = read_entry(archive, "quilt.mod.json") {
+        return quilt(archive, &content, depth);
+    }
+    if let Some(content) = read_entry(archive, "fabric.mod.json") {
+        return fabric(archive, &content, depth);
+    }
+    if let Some(content) = read_entry(archive, "META-INF/neoforge.mods.toml") {
+        return forge(archive, &content, JarFormat::NeoForge);
+    }
+    if let Some(content) = read_entry(archive, "META-INF/mods.toml") {
+        return forge(archive, &content, JarFormat::Forge);
+    }
+    if let Some(content) = read_entry(archive, "paper-plugin.yml") {
+        return plugin(&content, JarFormat::Paper);
+    }
+    if let Some(content) = read_entry(archive, "plugin.yml") {
+        return plugin(&content, JarFormat::Bukkit);
+    }
+    Ok(Vec::new())
+}
+
+/// Reads the mod or plugin metadata of a jar. Returns an empty list for jars without any known metadata file.
+pub fn inspect_jar(path: impl AsRef<Path>) -> Res<Vec<JarMetadata>> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| Error::path(path.display(), e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| Error::path(path.display(), e))?;
+    inspect_archive(&mut archive, 0)
+}
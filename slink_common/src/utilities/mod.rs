@@ -1,5 +1,22 @@
 mod datapath;
 mod caching;
+mod sandbox;
+mod archive;
+mod checksum;
+mod repository;
+mod detection;
+mod jars;
+mod totp;
+
+#[cfg(test)]
+pub(crate) mod testing;
 
 pub use datapath::*;
-pub use caching::*;
\ No newline at end of file
+pub use caching::*;
+pub use sandbox::*;
+pub use archive::*;
+pub use checksum::*;
+pub use repository::*;
+pub use detection::*;
+pub use jars::*;
+pub use totp::*;
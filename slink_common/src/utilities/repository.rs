@@ -0,0 +1,463 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use flate2::{Compression, read::ZlibDecoder, write::ZlibEncoder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    INCREMENTAL_CHUNK_SIZE, Error, Res,
+    utilities::{ChecksumWriter, Sandbox},
+};
+
+/// Size of a region file sector, and of each of the two header tables.
+const REGION_SECTOR: usize = 4096;
+
+/// A contiguous range of a file, stored as one object.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct SnapshotPiece {
+    pub offset: u64,
+    pub length: u64,
+    pub hash: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SnapshotEntry {
+    Directory { path: String },
+    File { path: String, size: u64, pieces: Vec<SnapshotPiece> },
+}
+
+/// The list of files in one backup point, referencing objects in the repository.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct SnapshotManifest {
+    pub id: String,
+    pub created: DateTime<Utc>,
+    pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct SnapshotStats {
+    pub files: u64,
+
+    /// Total size of the snapshotted files, in bytes
+    pub bytes: u64,
+    pub new_objects: u64,
+
+    /// Compressed size of the objects added by this snapshot, in bytes
+    pub new_bytes: u64,
+    pub reused_objects: u64,
+
+    /// Hex-encoded SHA-256 of the manifest file
+    pub checksum: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct RepositoryCheck {
+    pub snapshots: u64,
+    pub objects: u64,
+
+    /// Objects referenced by a snapshot but missing from the repository
+    pub missing: Vec<String>,
+
+    /// Objects whose contents no longer match their hash
+    pub corrupt: Vec<String>,
+
+    /// Manifests that could not be read
+    pub unreadable: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct RepositoryPrune {
+    pub removed_snapshots: Vec<String>,
+    pub removed_objects: u64,
+    pub freed_bytes: u64,
+}
+
+/// A content-addressed store of file pieces shared by all incremental backups.
+///
+/// Region files (`.mca`) are split along their chunks, so a chunk that did not change between two
+/// backups is only stored once; other files are split into fixed-size pieces. Objects are zlib
+/// compressed and named by the SHA-256 of their uncompressed contents. All operations are blocking.
+#[derive(Clone, Debug)]
+pub struct BackupRepository {
+    root: PathBuf,
+}
+
+impl BackupRepository {
+    pub fn open(root: impl AsRef<Path>) -> Res<Self> {
+        let root = root.as_ref().to_path_buf();
+        for directory in [root.join("objects"), root.join("snapshots")] {
+            std::fs::create_dir_all(&directory).map_err(|e| Error::path(directory.display(), e))?;
+        }
+        Ok(Self { root })
+    }
+
+    fn object_path(&self, hash: &str) -> PathBuf {
+        self.root.join("objects").join(&hash[..2]).join(hash)
+    }
+
+    fn manifest_path(&self, id: &str) -> PathBuf {
+        self.root.join("snapshots").join(format!("{id}.json"))
+    }
+
+    fn error(path: &Path) -> impl Fn(std::io::Error) -> Error + '_ {
+        move |e| Error::path(path.display(), e)
+    }
+
+    /// Stores a piece unless an identical one already exists.
+    fn store(&self, data: &[u8], offset: u64, stats: &mut SnapshotStats) -> Res<SnapshotPiece> {
+        let hash = format!("{:x}", Sha256::digest(data));
+        let path = self.object_path(&hash);
+        if path.exists() {
+            stats.reused_objects += 1;
+        } else {
+            let parent = path.parent().unwrap_or(&self.root);
+            std::fs::create_dir_all(parent).map_err(|e| Self::error(parent)(e))?;
+
+            let temporary = path.with_file_name(format!("{hash}.{}.tmp", uuid::Uuid::new_v4()));
+            let mut encoder = ZlibEncoder::new(File::create(&temporary).map_err(|e| Self::error(&temporary)(e))?, Compression::default());
+            encoder.write_all(data).map_err(|e| Self::error(&temporary)(e))?;
+            let file = encoder.finish().map_err(|e| Self::error(&temporary)(e))?;
+            stats.new_bytes += file.metadata().map(|m| m.len()).unwrap_or_default();
+            std::fs::rename(&temporary, &path).map_err(|e| Self::error(&path)(e))?;
+            stats.new_objects += 1;
+        }
+
+        Ok(SnapshotPiece {
+            offset,
+            length: data.len() as u64,
+            hash,
+        })
+    }
+
+    fn load(&self, hash: &str) -> Res<Vec<u8>> {
+        let path = self.object_path(hash);
+        let mut data = Vec::new();
+        ZlibDecoder::new(File::open(&path).map_err(|e| Self::error(&path)(e))?)
+            .read_to_end(&mut data)
+            .map_err(|e| Self::error(&path)(e))?;
+        if format!("{:x}", Sha256::digest(&data)) != hash {
+            return Err(Error::path(path.display(), "Object does not match its hash"));
+        }
+        Ok(data)
+    }
+
+    /// Splits a region file into its header and one piece per stored chunk. Returns `None` if the file
+    /// does not look like a region file.
+    fn region_pieces(data: &[u8]) -> Option<Vec<(usize, usize)>> {
+        if data.len() < REGION_SECTOR * 2 {
+            return None;
+        }
+
+        let mut pieces = vec![(0, REGION_SECTOR * 2)];
+        for location in data[..REGION_SECTOR].chunks_exact(4) {
+            let sector = u32::from_be_bytes([0, location[0], location[1], location[2]]) as usize;
+            let count = location[3] as usize;
+            if sector < 2 || count == 0 {
+                continue;
+            }
+
+            let start = sector * REGION_SECTOR;
+            let end = (start + count * REGION_SECTOR).min(data.len());
+            if start + 4 > end {
+                return None;
+            }
+
+            // Only the chunk's own bytes are stored; the rest of its last sector is padding
+            let length = u32::from_be_bytes([data[start], data[start + 1], data[start + 2], data[start + 3]]) as usize;
+            pieces.push((start, (4 + length).min(end - start)));
+        }
+        Some(pieces)
+    }
+
+    fn snapshot_file(&self, path: &Path, size: u64, stats: &mut SnapshotStats) -> Res<Vec<SnapshotPiece>> {
+        let mut file = File::open(path).map_err(|e| Self::error(path)(e))?;
+        let is_region = path.extension().is_some_and(|e| e == "mca");
+
+        if is_region {
+            let mut data = Vec::with_capacity(size as usize);
+            file.read_to_end(&mut data).map_err(|e| Self::error(path)(e))?;
+            if let Some(ranges) = Self::region_pieces(&data) {
+                return ranges
+                    .into_iter()
+                    .map(|(start, length)| self.store(&data[start..start + length], start as u64, stats))
+                    .collect();
+            }
+            return data
+                .chunks(INCREMENTAL_CHUNK_SIZE)
+                .enumerate()
+                .map(|(index, chunk)| self.store(chunk, (index * INCREMENTAL_CHUNK_SIZE) as u64, stats))
+                .collect();
+        }
+
+        let mut pieces = Vec::new();
+        let mut buffer = vec![0u8; INCREMENTAL_CHUNK_SIZE];
+        let mut offset = 0u64;
+        loop {
+            let mut filled = 0;
+            while filled < buffer.len() {
+                let read = file.read(&mut buffer[filled..]).map_err(|e| Self::error(path)(e))?;
+                if read == 0 {
+                    break;
+                }
+                filled += read;
+            }
+            if filled == 0 {
+                break;
+            }
+            pieces.push(self.store(&buffer[..filled], offset, stats)?);
+            offset += filled as u64;
+        }
+        Ok(pieces)
+    }
+
+    /// Records the contents of `source` as snapshot `id`. Symlinks are not followed or recorded.
+    pub fn snapshot(&self, source: impl AsRef<Path>, id: impl AsRef<str>) -> Res<SnapshotStats> {
+        let source = source.as_ref();
+        let mut stats = SnapshotStats::default();
+        let mut entries = Vec::new();
+        let mut pending = vec![source.to_path_buf()];
+
+        while let Some(directory) = pending.pop() {
+            let mut children = std::fs::read_dir(&directory)
+                .map_err(|e| Self::error(&directory)(e))?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .collect::<Vec<PathBuf>>();
+            children.sort();
+
+            for child in children {
+                let metadata = std::fs::symlink_metadata(&child).map_err(|e| Self::error(&child)(e))?;
+                let relative = relative_path(source, &child);
+                if metadata.is_dir() {
+                    entries.push(SnapshotEntry::Directory { path: relative });
+                    pending.push(child);
+                } else if metadata.is_file() {
+                    let pieces = self.snapshot_file(&child, metadata.len(), &mut stats)?;
+                    stats.files += 1;
+                    stats.bytes += metadata.len();
+                    entries.push(SnapshotEntry::File {
+                        path: relative,
+                        size: metadata.len(),
+                        pieces,
+                    });
+                }
+            }
+        }
+
+        let manifest = SnapshotManifest {
+            id: id.as_ref().to_string(),
+            created: Utc::now(),
+            entries,
+        };
+        let path = self.manifest_path(id.as_ref());
+        stats.checksum = self.write_manifest(&manifest, &path)?;
+        Ok(stats)
+    }
+
+    /// Writes a manifest next to `path` and moves it into place, so that a failed write never leaves a
+    /// truncated manifest behind. Returns the manifest's checksum.
+    fn write_manifest(&self, manifest: &SnapshotManifest, path: &Path) -> Res<String> {
+        let temporary = path.with_file_name(format!("{}.{}.tmp", manifest.id, uuid::Uuid::new_v4()));
+        let result = File::create(&temporary)
+            .map_err(Self::error(&temporary))
+            .and_then(|file| {
+                let mut writer = ChecksumWriter::new(BufWriter::new(file));
+                serde_json::to_writer(&mut writer, manifest).map_err(Error::serialization)?;
+                let (inner, checksum, _) = writer.finish();
+                let file = inner.into_inner().map_err(|e| Self::error(&temporary)(e.into_error()))?;
+                file.sync_all().map_err(Self::error(&temporary))?;
+                Ok(checksum)
+            })
+            .and_then(|checksum| std::fs::rename(&temporary, path).map(|_| checksum).map_err(Self::error(path)));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temporary);
+        }
+        result
+    }
+
+    pub fn manifest(&self, id: impl AsRef<str>) -> Res<SnapshotManifest> {
+        let path = self.manifest_path(id.as_ref());
+        let file = File::open(&path).map_err(|e| Self::error(&path)(e))?;
+        serde_json::from_reader(std::io::BufReader::new(file)).map_err(Error::deserialization)
+    }
+
+    pub fn manifest_file(&self, id: impl AsRef<str>) -> PathBuf {
+        self.manifest_path(id.as_ref())
+    }
+
+    /// Recreates snapshot `id` inside `destination`, verifying every object as it is read.
+    pub fn restore(&self, id: impl AsRef<str>, destination: impl AsRef<Path>) -> Res<()> {
+        let destination = destination.as_ref();
+        std::fs::create_dir_all(destination).map_err(|e| Self::error(destination)(e))?;
+
+        for entry in self.manifest(id)?.entries {
+            match entry {
+                SnapshotEntry::Directory { path } => {
+                    let target = destination.join(Sandbox::normalize(&path)?);
+                    std::fs::create_dir_all(&target).map_err(|e| Self::error(&target)(e))?;
+                }
+                SnapshotEntry::File { path, size, pieces } => {
+                    let target = destination.join(Sandbox::normalize(&path)?);
+                    if let Some(parent) = target.parent() {
+                        std::fs::create_dir_all(parent).map_err(|e| Self::error(parent)(e))?;
+                    }
+
+                    let mut file = File::create(&target).map_err(|e| Self::error(&target)(e))?;
+                    file.set_len(size).map_err(|e| Self::error(&target)(e))?;
+                    for piece in pieces {
+                        let data = self.load(&piece.hash)?;
+                        file.seek(SeekFrom::Start(piece.offset)).map_err(|e| Self::error(&target)(e))?;
+                        file.write_all(&data).map_err(|e| Self::error(&target)(e))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove_snapshot(&self, id: impl AsRef<str>) -> Res<()> {
+        let path = self.manifest_path(id.as_ref());
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Self::error(&path)(e)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn snapshots(&self) -> Res<Vec<String>> {
+        let directory = self.root.join("snapshots");
+        Ok(std::fs::read_dir(&directory)
+            .map_err(|e| Self::error(&directory)(e))?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|name| name.strip_suffix(".json"))
+                    .map(|id| id.to_string())
+            })
+            .collect())
+    }
+
+    fn objects(&self) -> Res<Vec<(String, PathBuf)>> {
+        let directory = self.root.join("objects");
+        let mut objects = Vec::new();
+        for bucket in std::fs::read_dir(&directory).map_err(|e| Self::error(&directory)(e))? {
+            let bucket = bucket.map_err(|e| Self::error(&directory)(e))?.path();
+            if !bucket.is_dir() {
+                continue;
+            }
+            for object in std::fs::read_dir(&bucket).map_err(|e| Self::error(&bucket)(e))? {
+                let path = object.map_err(|e| Self::error(&bucket)(e))?.path();
+                if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+                    objects.push((name.to_string(), path.clone()));
+                }
+            }
+        }
+        Ok(objects)
+    }
+
+    fn referenced(manifest: &SnapshotManifest, into: &mut HashSet<String>) {
+        for entry in &manifest.entries {
+            if let SnapshotEntry::File { pieces, .. } = entry {
+                into.extend(pieces.iter().map(|p| p.hash.clone()));
+            }
+        }
+    }
+
+    /// Verifies that every referenced object exists and matches its hash.
+    pub fn check(&self) -> Res<RepositoryCheck> {
+        let mut report = RepositoryCheck::default();
+        let mut referenced = HashSet::new();
+        for id in self.snapshots()? {
+            report.snapshots += 1;
+            match self.manifest(&id) {
+                Ok(manifest) => Self::referenced(&manifest, &mut referenced),
+                Err(_) => report.unreadable.push(id),
+            }
+        }
+
+        let objects: HashSet<String> = self.objects()?.into_iter().map(|(hash, _)| hash).collect();
+        report.objects = objects.len() as u64;
+        for hash in referenced {
+            if !objects.contains(&hash) {
+                report.missing.push(hash);
+            } else if self.load(&hash).is_err() {
+                report.corrupt.push(hash);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Removes snapshots not listed in `keep`, then every object no remaining snapshot references.
+    pub fn prune(&self, keep: &HashSet<String>) -> Res<RepositoryPrune> {
+        let mut report = RepositoryPrune::default();
+        let mut referenced = HashSet::new();
+        for id in self.snapshots()? {
+            if !keep.contains(&id) {
+                self.remove_snapshot(&id)?;
+                report.removed_snapshots.push(id);
+                continue;
+            }
+            // An unreadable manifest must not cause its objects to be deleted
+            Self::referenced(&self.manifest(&id)?, &mut referenced);
+        }
+
+        for (hash, path) in self.objects()? {
+            if referenced.contains(&hash) {
+                continue;
+            }
+            let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or_default();
+            std::fs::remove_file(&path).map_err(|e| Self::error(&path)(e))?;
+            report.removed_objects += 1;
+            report.freed_bytes += size;
+        }
+        Ok(report)
+    }
+}
+
+fn relative_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::testing::TestDir;
+
+    #[test]
+    fn manifests_are_moved_into_place() {
+        let dir = TestDir::new();
+        let source = dir.path().join("world");
+        std::fs::create_dir_all(source.join("region")).unwrap();
+        std::fs::write(source.join("level.dat"), "level").unwrap();
+        let repository = BackupRepository::open(dir.path().join("repository")).unwrap();
+
+        let stats = repository.snapshot(&source, "first").unwrap();
+        let snapshots: Vec<String> = std::fs::read_dir(dir.path().join("repository/snapshots"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(snapshots, vec!["first.json"]);
+        let written = std::fs::read(repository.manifest_file("first")).unwrap();
+        assert_eq!(stats.checksum, format!("{:x}", Sha256::digest(&written)));
+
+        // A later snapshot with the same ID replaces the manifest as a whole
+        std::fs::write(source.join("level.dat"), "changed").unwrap();
+        repository.snapshot(&source, "first").unwrap();
+        assert_eq!(repository.snapshots().unwrap(), vec!["first"]);
+        assert_eq!(repository.manifest("first").unwrap().entries.len(), 2);
+    }
+}
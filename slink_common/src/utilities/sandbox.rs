@@ -0,0 +1,253 @@
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Res};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct FileEntry {
+    pub name: String,
+
+    /// Path relative to the sandbox root, using `/` as separator
+    pub path: String,
+    pub kind: FileKind,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// A directory that all resolved paths are confined to, including through symlinks.
+#[derive(Clone, Debug)]
+pub struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    pub async fn new(root: impl AsRef<Path>) -> Res<Self> {
+        let root = tokio::fs::canonicalize(root.as_ref())
+            .await
+            .map_err(|e| Error::path(root.as_ref().display(), e))?;
+        Ok(Self { root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Lexically normalizes a client-supplied path, rejecting anything that would climb above the root.
+    pub fn normalize(relative: impl AsRef<str>) -> Res<PathBuf> {
+        let mut normalized = PathBuf::new();
+        for component in Path::new(relative.as_ref().trim_start_matches(['/', '\\'])).components() {
+            match component {
+                Component::Normal(part) => normalized.push(part),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !normalized.pop() {
+                        return Err(Error::path(relative.as_ref(), "Path escapes the server directory"));
+                    }
+                }
+                Component::RootDir | Component::Prefix(_) => {
+                    return Err(Error::path(relative.as_ref(), "Absolute paths are not allowed"));
+                }
+            }
+        }
+        Ok(normalized)
+    }
+
+    /// Resolves a path for reading or writing, following symlinks as long as they stay inside the root.
+    /// The target itself does not need to exist yet.
+    pub async fn resolve(&self, relative: impl AsRef<str>) -> Res<PathBuf> {
+        let mut existing = self.root.join(Self::normalize(relative.as_ref())?);
+        let mut missing = Vec::new();
+        loop {
+            match tokio::fs::canonicalize(&existing).await {
+                Ok(real) => {
+                    if !real.starts_with(&self.root) {
+                        return Err(Error::path(relative.as_ref(), "Path escapes the server directory"));
+                    }
+                    return Ok(missing.into_iter().rev().fold(real, |path: PathBuf, part| path.join(part)));
+                }
+                Err(_) => {
+                    if tokio::fs::symlink_metadata(&existing).await.is_ok() {
+                        return Err(Error::path(relative.as_ref(), "Path traverses a dangling symlink"));
+                    }
+                    let name = existing.file_name().map(|n| n.to_os_string());
+                    let parent = existing.parent().map(|p| p.to_path_buf());
+                    match (name, parent) {
+                        (Some(name), Some(parent)) => {
+                            missing.push(name);
+                            existing = parent;
+                        }
+                        _ => return Err(Error::path(relative.as_ref(), "Path cannot be resolved")),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Resolves a path to the entry itself without following a final symlink, for renaming or deleting it.
+    pub async fn resolve_entry(&self, relative: impl AsRef<str>) -> Res<PathBuf> {
+        let normalized = Self::normalize(relative.as_ref())?;
+        let name = normalized
+            .file_name()
+            .ok_or(Error::path(relative.as_ref(), "Path refers to the server directory itself"))?
+            .to_os_string();
+        let parent = normalized
+            .parent()
+            .and_then(|p| p.to_str())
+            .unwrap_or_default()
+            .to_string();
+        Ok(self.resolve(parent).await?.join(name))
+    }
+
+    pub fn relative(&self, absolute: impl AsRef<Path>) -> String {
+        absolute
+            .as_ref()
+            .strip_prefix(&self.root)
+            .unwrap_or(absolute.as_ref())
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect::<Vec<String>>()
+            .join("/")
+    }
+
+    pub async fn entry(&self, absolute: impl AsRef<Path>) -> Res<FileEntry> {
+        let path = absolute.as_ref();
+        let metadata = tokio::fs::symlink_metadata(path)
+            .await
+            .map_err(|e| Error::path(self.relative(path), e))?;
+        let kind = if metadata.is_symlink() {
+            FileKind::Symlink
+        } else if metadata.is_dir() {
+            FileKind::Directory
+        } else {
+            FileKind::File
+        };
+
+        Ok(FileEntry {
+            name: path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            path: self.relative(path),
+            kind,
+            size: metadata.len(),
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+        })
+    }
+
+    pub async fn list(&self, relative: impl AsRef<str>) -> Res<Vec<FileEntry>> {
+        let directory = self.resolve(relative.as_ref()).await?;
+        let mut reader = tokio::fs::read_dir(&directory)
+            .await
+            .map_err(|e| Error::path(relative.as_ref(), e))?;
+
+        let mut entries = Vec::new();
+        while let Some(item) = reader
+            .next_entry()
+            .await
+            .map_err(|e| Error::path(relative.as_ref(), e))?
+        {
+            entries.push(self.entry(item.path()).await?);
+        }
+
+        entries.sort_by(|a, b| {
+            (a.kind != FileKind::Directory, a.name.to_lowercase())
+                .cmp(&(b.kind != FileKind::Directory, b.name.to_lowercase()))
+        });
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+    use crate::utilities::testing::TestDir;
+
+    /// A sandbox at `<dir>/root`, next to an `outside` directory it must not reach.
+    async fn sandbox(dir: &TestDir) -> Sandbox {
+        std::fs::create_dir_all(dir.path().join("root/world")).unwrap();
+        std::fs::create_dir_all(dir.path().join("outside")).unwrap();
+        std::fs::write(dir.path().join("outside/secret.txt"), "secret").unwrap();
+        Sandbox::new(dir.path().join("root")).await.unwrap()
+    }
+
+    #[test]
+    fn normalize_rejects_climbing_out() {
+        assert_eq!(Sandbox::normalize("world/./region/../level.dat").unwrap(), PathBuf::from("world/level.dat"));
+        assert_eq!(Sandbox::normalize("/world/level.dat").unwrap(), PathBuf::from("world/level.dat"));
+        assert_eq!(Sandbox::normalize("").unwrap(), PathBuf::new());
+        assert!(Sandbox::normalize("../outside").is_err());
+        assert!(Sandbox::normalize("world/../../outside").is_err());
+    }
+
+    #[tokio::test]
+    async fn resolves_paths_that_do_not_exist_yet() {
+        let dir = TestDir::new();
+        let sandbox = sandbox(&dir).await;
+        assert_eq!(
+            sandbox.resolve("world/new/level.dat").await.unwrap(),
+            dir.path().join("root/world/new/level.dat")
+        );
+        assert!(sandbox.resolve("../outside/secret.txt").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn follows_symlinks_only_within_the_root() {
+        let dir = TestDir::new();
+        let sandbox = sandbox(&dir).await;
+        symlink(dir.path().join("root/world"), dir.path().join("root/inside")).unwrap();
+        symlink(dir.path().join("outside"), dir.path().join("root/escape")).unwrap();
+        symlink(dir.path().join("outside/secret.txt"), dir.path().join("root/secret.txt")).unwrap();
+
+        assert_eq!(sandbox.resolve("inside/level.dat").await.unwrap(), dir.path().join("root/world/level.dat"));
+        assert!(sandbox.resolve("escape").await.is_err());
+        assert!(sandbox.resolve("escape/secret.txt").await.is_err());
+        assert!(sandbox.resolve("escape/new.txt").await.is_err());
+        assert!(sandbox.resolve("secret.txt").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_dangling_symlinks() {
+        let dir = TestDir::new();
+        let sandbox = sandbox(&dir).await;
+        symlink(dir.path().join("outside/missing"), dir.path().join("root/dangling")).unwrap();
+        assert!(sandbox.resolve("dangling").await.is_err());
+        assert!(sandbox.resolve("dangling/file.txt").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn resolves_entries_without_following_them() {
+        let dir = TestDir::new();
+        let sandbox = sandbox(&dir).await;
+        symlink(dir.path().join("outside"), dir.path().join("root/escape")).unwrap();
+
+        // The link itself can be renamed or deleted, but not anything behind it
+        assert_eq!(sandbox.resolve_entry("escape").await.unwrap(), dir.path().join("root/escape"));
+        assert!(sandbox.resolve_entry("escape/secret.txt").await.is_err());
+        assert!(sandbox.resolve_entry("").await.is_err());
+        assert!(sandbox.resolve_entry("..").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn relative_paths_use_slashes() {
+        let dir = TestDir::new();
+        let sandbox = sandbox(&dir).await;
+        assert_eq!(sandbox.relative(dir.path().join("root/world/level.dat")), "world/level.dat");
+        assert_eq!(sandbox.relative(sandbox.root()), "");
+    }
+}
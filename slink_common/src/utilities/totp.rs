@@ -0,0 +1,171 @@
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+use crate::{TOTP_DIGITS, TOTP_PERIOD_SECONDS, TOTP_SKEW_STEPS};
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Encodes bytes as unpadded RFC 4648 base32, the format authenticator apps expect for secrets.
+pub fn base32_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity((data.len() * 8).div_ceil(5));
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+/// Decodes base32, ignoring case, padding and whitespace. Returns `None` for other invalid characters.
+pub fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for c in encoded.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a as char == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+/// The RFC 6238 time step containing a Unix timestamp.
+pub fn totp_step(timestamp: i64) -> u64 {
+    (timestamp.max(0) as u64) / TOTP_PERIOD_SECONDS
+}
+
+/// RFC 4226 HOTP code for a counter, using HMAC-SHA1 as authenticator apps do by default.
+pub fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let truncated = u32::from_be_bytes([digest[offset], digest[offset + 1], digest[offset + 2], digest[offset + 3]]);
+    (truncated & 0x7fff_ffff) % 10u32.pow(TOTP_DIGITS)
+}
+
+/// Checks a TOTP code against the steps around `timestamp`, allowing for clock skew. Returns the matching
+/// step, so that callers can reject codes that were already used.
+pub fn verify_totp(secret: &[u8], code: &str, timestamp: i64) -> Option<u64> {
+    let code = code.trim().replace(' ', "");
+    if code.len() != TOTP_DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+
+    let current = totp_step(timestamp);
+    (current.saturating_sub(TOTP_SKEW_STEPS)..=current + TOTP_SKEW_STEPS).find(|step| hotp(secret, *step) == code)
+}
+
+/// `otpauth://` URI for enrolling a secret in an authenticator app, usually shown as a QR code.
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &[u8]) -> String {
+    let escape = |value: &str| {
+        value
+            .bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+                _ => format!("%{b:02X}"),
+            })
+            .collect::<String>()
+    };
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={TOTP_DIGITS}&period={TOTP_PERIOD_SECONDS}",
+        escape(issuer),
+        escape(account),
+        base32_encode(secret),
+        escape(issuer)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shared secret of the RFC 4226 and RFC 6238 SHA-1 test vectors.
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn base32_matches_rfc_4648() {
+        let vectors = [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ];
+        for (data, encoded) in vectors {
+            assert_eq!(base32_encode(data.as_bytes()), encoded);
+            assert_eq!(base32_decode(encoded).unwrap(), data.as_bytes());
+        }
+        assert_eq!(base32_decode("mzxw 6ytb oi======").unwrap(), b"foobar");
+        assert_eq!(base32_decode("MZXW1"), None);
+    }
+
+    #[test]
+    fn hotp_matches_rfc_4226() {
+        let codes = [755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489];
+        for (counter, code) in codes.iter().enumerate() {
+            assert_eq!(hotp(SECRET, counter as u64), *code);
+        }
+    }
+
+    #[test]
+    fn totp_matches_rfc_6238() {
+        // The RFC lists eight digit codes; six digit codes are their last six digits
+        let vectors = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ];
+        for (timestamp, code) in vectors {
+            assert_eq!(verify_totp(SECRET, code, timestamp), Some(totp_step(timestamp)), "{timestamp}");
+        }
+    }
+
+    #[test]
+    fn totp_allows_one_step_of_skew() {
+        let timestamp = 1111111111;
+        let step = totp_step(timestamp);
+        let code = |step: u64| format!("{:06}", hotp(SECRET, step));
+
+        assert_eq!(verify_totp(SECRET, &code(step - 1), timestamp), Some(step - 1));
+        assert_eq!(verify_totp(SECRET, &code(step + 1), timestamp), Some(step + 1));
+        assert_eq!(verify_totp(SECRET, &code(step + 2), timestamp), None);
+        assert_eq!(verify_totp(SECRET, &code(step - 2), timestamp), None);
+    }
+
+    #[test]
+    fn totp_rejects_malformed_codes() {
+        assert_eq!(verify_totp(SECRET, " 050 471 ", 1111111111), Some(totp_step(1111111111)));
+        assert_eq!(verify_totp(SECRET, "50471", 1111111111), None);
+        assert_eq!(verify_totp(SECRET, "0504710", 1111111111), None);
+        assert_eq!(verify_totp(SECRET, "05047a", 1111111111), None);
+        assert_eq!(verify_totp(SECRET, "", 1111111111), None);
+    }
+
+    #[test]
+    fn otpauth_uris_escape_labels() {
+        assert_eq!(
+            otpauth_uri("Slink", "steve@example.com", b"foobar"),
+            "otpauth://totp/Slink:steve%40example.com?secret=MZXW6YTBOI&issuer=Slink&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Resolves and downloads content with [ModrinthClient] against responses recorded from the Modrinth API,
+//! served from `tests/fixtures/modrinth`.
+mod common;
+
+use std::{collections::HashSet, path::PathBuf};
+
+use common::{Response, StubServer, TempDir};
+use slink_common::providers::modrinth::{ModrinthClient, VersionFilter};
+
+fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/modrinth")
+}
+
+/// Serves `/<path>` from `<path>.json`, or from `<path>` for files under `/cdn`. Recorded URLs point back at
+/// the stub through a `{base}` placeholder.
+async fn modrinth_server() -> StubServer {
+    let server = StubServer::bind().await;
+    let base = server.url.clone();
+    server.serve(move |request| {
+        let path = request.path.trim_start_matches('/');
+        if path.contains("..") {
+            return Response::not_found();
+        }
+        if path.starts_with("cdn/") {
+            return match std::fs::read(fixtures().join(path)) {
+                Ok(data) => Response::new(200, data).header("Content-Type", "application/java-archive"),
+                Err(_) => Response::not_found(),
+            };
+        }
+        match std::fs::read_to_string(fixtures().join(format!("{path}.json"))) {
+            Ok(data) => Response::new(200, data.replace("{base}", &base)).header("Content-Type", "application/json"),
+            Err(_) => Response::not_found(),
+        }
+    });
+    server
+}
+
+fn filter() -> VersionFilter {
+    VersionFilter {
+        game_version: String::from("1.21.4"),
+        loaders: vec![String::from("fabric")],
+    }
+}
+
+#[tokio::test]
+async fn resolves_required_dependencies() {
+    let server = modrinth_server().await;
+    let client = ModrinthClient::new(&server.url);
+
+    let resolution = client
+        .resolve("gvQqBUqZ", Some("LiTh0015"), &filter(), &HashSet::new())
+        .await
+        .unwrap();
+
+    let versions: Vec<(&str, &str, bool)> = resolution
+        .versions
+        .iter()
+        .map(|r| (r.project.slug.as_str(), r.version.id.as_str(), r.dependency))
+        .collect();
+    assert_eq!(versions, vec![("lithium", "LiTh0015", false), ("fabric-api", "FaRel117", true)]);
+    assert_eq!(resolution.client_only, vec!["modmenu"]);
+    assert_eq!(resolution.missing, vec!["ghostlib"]);
+
+    // Optional and incompatible dependencies are never looked up
+    let requests = server.requests();
+    assert!(!requests.iter().any(|r| r.path.contains("AANobbMI") || r.path.contains("uXXizFIs")));
+    let listing = requests.iter().find(|r| r.path == "/project/P7dR8mSH/version").unwrap();
+    assert_eq!(listing.params().get("game_versions").unwrap(), "[\"1.21.4\"]");
+    assert_eq!(listing.params().get("loaders").unwrap(), "[\"fabric\"]");
+}
+
+#[tokio::test]
+async fn skips_installed_dependencies() {
+    let server = modrinth_server().await;
+    let client = ModrinthClient::new(&server.url);
+    let installed = HashSet::from([String::from("P7dR8mSH"), String::from("mOgUt4GM")]);
+
+    let resolution = client
+        .resolve("gvQqBUqZ", Some("LiTh0015"), &filter(), &installed)
+        .await
+        .unwrap();
+    assert_eq!(resolution.versions.len(), 1);
+    assert!(resolution.client_only.is_empty());
+    assert!(!server.requests().iter().any(|r| r.path.starts_with("/project/P7dR8mSH")));
+}
+
+#[tokio::test]
+async fn fails_when_the_requested_project_has_no_versions() {
+    let server = modrinth_server().await;
+    let client = ModrinthClient::new(&server.url);
+    assert!(client.resolve("ghostlib", None, &filter(), &HashSet::new()).await.is_err());
+}
+
+#[tokio::test]
+async fn downloads_verified_files() {
+    let server = modrinth_server().await;
+    let client = ModrinthClient::new(&server.url);
+    let dir = TempDir::new();
+    let version = client.version("LiTh0015").await.unwrap();
+    let file = version.primary_file().unwrap();
+    let destination = dir.0.join(&file.filename);
+
+    client.download(file, &destination).await.unwrap();
+    assert_eq!(
+        std::fs::read(&destination).unwrap(),
+        std::fs::read(fixtures().join("cdn/lithium-fabric-0.15.0.jar")).unwrap()
+    );
+    assert!(!destination.with_extension("partial").exists());
+}
+
+#[tokio::test]
+async fn discards_downloads_with_mismatched_hashes() {
+    let server = modrinth_server().await;
+    let client = ModrinthClient::new(&server.url);
+    let dir = TempDir::new();
+    let version = client.version("LiTh0015").await.unwrap();
+    let mut file = version.primary_file().unwrap().clone();
+    file.hashes.sha512 = "0".repeat(128);
+    let destination = dir.0.join(&file.filename);
+
+    let error = client.download(&file, &destination).await.unwrap_err();
+    assert!(error.to_string().to_lowercase().contains("hash"), "{error}");
+    assert!(!destination.exists());
+    assert!(!destination.with_extension("partial").exists());
+}
@@ -0,0 +1,275 @@
+//! Runs the OpenID Connect authorization code flow with PKCE through [OidcClient] against a mock provider.
+mod common;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use common::{Response, StubServer};
+use reqwest::{Url, redirect::Policy};
+use serde_json::json;
+use slink_common::{
+    providers::oidc::{OidcClient, pkce_challenge, state_matches, url_safe_random},
+    types::OidcConfig,
+};
+
+const CLIENT_ID: &str = "slink";
+const CLIENT_SECRET: &str = "slink-secret";
+const REDIRECT_URI: &str = "https://slink.example/auth/oidc/callback";
+const SUBJECT: &str = "248289761001";
+
+/// What the provider remembers about an authorization request until its code is redeemed.
+struct Authorization {
+    challenge: String,
+    nonce: String,
+    redirect_uri: String,
+}
+
+fn token_error(error: &str) -> Response {
+    Response::new(400, json!({ "error": error }).to_string()).header("Content-Type", "application/json")
+}
+
+fn unsigned_jwt(claims: serde_json::Value) -> String {
+    format!(
+        "{}.{}.{}",
+        URL_SAFE_NO_PAD.encode(json!({ "alg": "RS256", "kid": "mock" }).to_string()),
+        URL_SAFE_NO_PAD.encode(claims.to_string()),
+        URL_SAFE_NO_PAD.encode("not checked")
+    )
+}
+
+/// Starts a provider that implements discovery, authorization, the token endpoint and userinfo.
+async fn oidc_provider() -> StubServer {
+    let server = StubServer::bind().await;
+    let issuer = server.url.clone();
+    let codes: Arc<Mutex<HashMap<String, Authorization>>> = Arc::new(Mutex::new(HashMap::new()));
+
+    server.serve(move |request| match (request.method.as_str(), request.path.as_str()) {
+        ("GET", path) if path.ends_with("/.well-known/openid-configuration") => Response::json(&json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{issuer}/authorize"),
+            "token_endpoint": format!("{issuer}/token"),
+            "userinfo_endpoint": format!("{issuer}/userinfo"),
+            "end_session_endpoint": format!("{issuer}/logout"),
+        })),
+        ("GET", "/authorize") => {
+            let params = request.params();
+            if params.get("client_id").map(|c| c.as_str()) != Some(CLIENT_ID)
+                || params.get("response_type").map(|t| t.as_str()) != Some("code")
+                || params.get("code_challenge_method").map(|m| m.as_str()) != Some("S256")
+            {
+                return Response::new(400, "Invalid authorization request");
+            }
+            let code = uuid::Uuid::new_v4().to_string();
+            codes.lock().unwrap().insert(
+                code.clone(),
+                Authorization {
+                    challenge: params["code_challenge"].clone(),
+                    nonce: params["nonce"].clone(),
+                    redirect_uri: params["redirect_uri"].clone(),
+                },
+            );
+            let mut location = Url::parse(&params["redirect_uri"]).unwrap();
+            location
+                .query_pairs_mut()
+                .append_pair("code", &code)
+                .append_pair("state", &params["state"]);
+            Response::new(302, "").header("Location", location)
+        }
+        ("POST", "/token") => {
+            let form = request.form();
+            if form.get("grant_type").map(|g| g.as_str()) != Some("authorization_code")
+                || form.get("client_id").map(|c| c.as_str()) != Some(CLIENT_ID)
+                || form.get("client_secret").map(|s| s.as_str()) != Some(CLIENT_SECRET)
+            {
+                return token_error("invalid_client");
+            }
+            // Codes are single use, even when the exchange fails
+            let Some(authorization) = form.get("code").and_then(|c| codes.lock().unwrap().remove(c)) else {
+                return token_error("invalid_grant");
+            };
+            if form.get("redirect_uri") != Some(&authorization.redirect_uri)
+                || form.get("code_verifier").map(|v| pkce_challenge(v)) != Some(authorization.challenge)
+            {
+                return token_error("invalid_grant");
+            }
+            Response::json(&json!({
+                "access_token": "mock-access-token",
+                "token_type": "Bearer",
+                "id_token": unsigned_jwt(json!({
+                    "iss": issuer,
+                    "aud": CLIENT_ID,
+                    "sub": SUBJECT,
+                    "exp": chrono::Utc::now().timestamp() + 300,
+                    "iat": chrono::Utc::now().timestamp(),
+                    "nonce": authorization.nonce,
+                    "preferred_username": "steve",
+                })),
+            }))
+        }
+        ("GET", "/userinfo") => match request.header("authorization") {
+            Some("Bearer mock-access-token") => Response::json(&json!({
+                "sub": SUBJECT,
+                "preferred_username": "someone-else",
+                "email": "steve@slink.example",
+                "groups": ["minecraft-admins", "players"],
+            })),
+            _ => Response::new(401, ""),
+        },
+        _ => Response::not_found(),
+    });
+    server
+}
+
+fn client(provider: &StubServer) -> OidcClient {
+    let mut config: OidcConfig = serde_json::from_value(json!({
+        "issuer": provider.url,
+        "client_id": CLIENT_ID,
+        "redirect_uri": REDIRECT_URI,
+    }))
+    .unwrap();
+    config.client_secret = Some(String::from(CLIENT_SECRET));
+    OidcClient::new(config)
+}
+
+/// A login as the server starts it: fresh state, nonce and PKCE verifier.
+struct Attempt {
+    state: String,
+    nonce: String,
+    verifier: String,
+}
+
+impl Attempt {
+    fn new() -> Self {
+        let random = |uuids: usize| -> String {
+            let bytes: Vec<u8> = (0..uuids).flat_map(|_| uuid::Uuid::new_v4().into_bytes()).collect();
+            url_safe_random(&bytes)
+        };
+        Self {
+            state: random(2),
+            nonce: random(2),
+            verifier: random(3),
+        }
+    }
+}
+
+/// Follows the authorization URL like a browser would, returning the code and state sent to the callback.
+async fn authorize(url: &str) -> (String, Option<String>) {
+    let response = reqwest::ClientBuilder::new()
+        .redirect(Policy::none())
+        .build()
+        .unwrap()
+        .get(url)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 302);
+    let location = Url::parse(response.headers()["location"].to_str().unwrap()).unwrap();
+    assert!(location.as_str().starts_with(REDIRECT_URI));
+    let params: HashMap<String, String> = location.query_pairs().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    (params["code"].clone(), params.get("state").cloned())
+}
+
+#[tokio::test]
+async fn completes_the_authorization_code_flow() {
+    let provider = oidc_provider().await;
+    let client = client(&provider);
+    let discovery = client.discover().await.unwrap();
+    let attempt = Attempt::new();
+
+    let url = client
+        .authorization_url(&discovery, &attempt.state, &attempt.nonce, &attempt.verifier)
+        .unwrap();
+    assert!(!url.contains(&attempt.verifier));
+    let (code, state) = authorize(&url).await;
+    assert!(state_matches(&attempt.state, state.as_deref()));
+
+    let identity = client
+        .exchange(&discovery, &code, &attempt.verifier, &attempt.nonce)
+        .await
+        .unwrap();
+    assert_eq!(identity.issuer, provider.url);
+    assert_eq!(identity.subject, SUBJECT);
+    // ID token claims take precedence over userinfo
+    assert_eq!(identity.string_claim("preferred_username").as_deref(), Some("steve"));
+    assert_eq!(identity.string_claim("email").as_deref(), Some("steve@slink.example"));
+    assert_eq!(identity.list_claim("groups"), vec!["minecraft-admins", "players"]);
+
+    let token = provider.requests().into_iter().find(|r| r.path == "/token").unwrap();
+    assert_eq!(token.form().get("code_verifier"), Some(&attempt.verifier));
+}
+
+#[tokio::test]
+async fn rejects_callbacks_with_another_state() {
+    let provider = oidc_provider().await;
+    let client = client(&provider);
+    let discovery = client.discover().await.unwrap();
+    let attempt = Attempt::new();
+    let forged = Attempt::new();
+
+    // A callback carrying the code and state of a login started elsewhere
+    let url = client
+        .authorization_url(&discovery, &forged.state, &forged.nonce, &forged.verifier)
+        .unwrap();
+    let (_, state) = authorize(&url).await;
+    assert!(!state_matches(&attempt.state, state.as_deref()));
+    assert!(!state_matches(&attempt.state, None));
+    assert!(!state_matches(&attempt.state, Some("")));
+    assert!(!state_matches(&attempt.state, Some(&attempt.state[1..])));
+}
+
+#[tokio::test]
+async fn rejects_mismatched_nonces() {
+    let provider = oidc_provider().await;
+    let client = client(&provider);
+    let discovery = client.discover().await.unwrap();
+    let attempt = Attempt::new();
+
+    let url = client
+        .authorization_url(&discovery, &attempt.state, &attempt.nonce, &attempt.verifier)
+        .unwrap();
+    let (code, _) = authorize(&url).await;
+    let error = client
+        .exchange(&discovery, &code, &attempt.verifier, &Attempt::new().nonce)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("nonce"), "{error}");
+}
+
+#[tokio::test]
+async fn rejects_codes_redeemed_without_the_verifier() {
+    let provider = oidc_provider().await;
+    let client = client(&provider);
+    let discovery = client.discover().await.unwrap();
+    let attempt = Attempt::new();
+
+    let url = client
+        .authorization_url(&discovery, &attempt.state, &attempt.nonce, &attempt.verifier)
+        .unwrap();
+    let (code, _) = authorize(&url).await;
+    assert!(
+        client
+            .exchange(&discovery, &code, &Attempt::new().verifier, &attempt.nonce)
+            .await
+            .is_err()
+    );
+
+    // The code was used up by the failed attempt
+    assert!(
+        client
+            .exchange(&discovery, &code, &attempt.verifier, &attempt.nonce)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn rejects_providers_with_another_issuer() {
+    let provider = oidc_provider().await;
+    let mut client = client(&provider);
+    client.config.issuer = format!("{}/realms/other", provider.url);
+    let error = client.discover().await.unwrap_err();
+    assert!(error.to_string().contains("does not match"), "{error}");
+}
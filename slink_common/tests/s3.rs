@@ -0,0 +1,260 @@
+//! Exercises [S3Target] against a stand-in for an S3-compatible server that checks every signature.
+mod common;
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use common::{Request, Response, StubServer, TempDir, hex};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use slink_common::{
+    S3_PART_RETRIES, S3_PART_SIZE,
+    storage::{BackupTarget, S3Target},
+};
+
+const ACCESS_KEY: &str = "slink-access";
+const SECRET_KEY: &str = "slink-secret";
+const REGION: &str = "eu-test-1";
+const BUCKET: &str = "backups";
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn error(status: u16, code: &str) -> Response {
+    error_message(status, code, "")
+}
+
+fn error_message(status: u16, code: &str, message: &str) -> Response {
+    Response::new(
+        status,
+        format!("<?xml version=\"1.0\"?><Error><Code>{code}</Code><Message>{message}</Message></Error>"),
+    )
+}
+
+/// Recomputes the AWS Signature Version 4 of a request, independently of the client.
+fn verify_signature(request: &Request) -> Result<(), String> {
+    let authorization = request.header("authorization").ok_or("Missing Authorization")?;
+    let rest = authorization
+        .strip_prefix("AWS4-HMAC-SHA256 ")
+        .ok_or("Unexpected algorithm")?;
+    let fields: HashMap<&str, &str> = rest.split(", ").filter_map(|f| f.split_once('=')).collect();
+    let credential: Vec<&str> = fields.get("Credential").ok_or("Missing Credential")?.split('/').collect();
+    let signed_headers = *fields.get("SignedHeaders").ok_or("Missing SignedHeaders")?;
+    let signature = *fields.get("Signature").ok_or("Missing Signature")?;
+    if credential.len() != 5 || credential[0] != ACCESS_KEY || credential[2] != REGION || credential[3] != "s3" {
+        return Err(format!("Unexpected credential {credential:?}"));
+    }
+
+    let payload_hash = request.header("x-amz-content-sha256").ok_or("Missing payload hash")?;
+    if payload_hash != hex(&Sha256::digest(&request.body)) {
+        return Err(String::from("Payload hash does not match the body"));
+    }
+    let timestamp = request.header("x-amz-date").ok_or("Missing x-amz-date")?;
+    if !timestamp.starts_with(credential[1]) {
+        return Err(String::from("Credential date does not match x-amz-date"));
+    }
+
+    let mut query: Vec<&str> = request.query.split('&').filter(|p| !p.is_empty()).collect();
+    query.sort();
+    let canonical_headers = signed_headers
+        .split(';')
+        .map(|name| format!("{name}:{}\n", request.header(name).unwrap_or_default()))
+        .collect::<String>();
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{canonical_headers}\n{signed_headers}\n{payload_hash}",
+        request.method,
+        request.path,
+        query.join("&")
+    );
+    let scope = format!("{}/{REGION}/s3/aws4_request", credential[1]);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let signing_key = [credential[1], REGION, "s3", "aws4_request"]
+        .into_iter()
+        .fold(format!("AWS4{SECRET_KEY}").into_bytes(), |key, part| hmac(&key, part));
+    if hex(&hmac(&signing_key, &string_to_sign)) != signature {
+        return Err(String::from("Signature does not match"));
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Bucket {
+    objects: HashMap<String, Vec<u8>>,
+
+    /// Parts of in-progress multipart uploads, by upload ID and part number
+    uploads: HashMap<String, HashMap<usize, Vec<u8>>>,
+}
+
+/// Starts a path-style S3 stand-in. Part 2 of multipart uploads fails while `fail_part_two` is set.
+async fn s3_server(fail_part_two: Arc<AtomicBool>) -> (StubServer, Arc<Mutex<Bucket>>) {
+    let server = StubServer::bind().await;
+    let bucket = Arc::new(Mutex::new(Bucket::default()));
+    let state = bucket.clone();
+    server.serve(move |request| {
+        if let Err(reason) = verify_signature(request) {
+            return error_message(403, "SignatureDoesNotMatch", &reason);
+        }
+        let Some(key) = request.path.strip_prefix(&format!("/{BUCKET}/")).map(|k| k.to_string()) else {
+            return error(404, "NoSuchBucket");
+        };
+        let params = request.params();
+        let mut bucket = state.lock().unwrap();
+
+        match (request.method.as_str(), params.get("uploadId")) {
+            ("POST", None) if params.contains_key("uploads") => {
+                let upload_id = uuid::Uuid::new_v4().to_string();
+                bucket.uploads.insert(upload_id.clone(), HashMap::new());
+                Response::new(200, format!("<InitiateMultipartUploadResult><UploadId>{upload_id}</UploadId></InitiateMultipartUploadResult>"))
+            }
+            ("PUT", Some(upload_id)) => {
+                let number: usize = params["partNumber"].parse().unwrap();
+                if number == 2 && fail_part_two.load(Ordering::SeqCst) {
+                    return error(500, "InternalError");
+                }
+                let Some(parts) = bucket.uploads.get_mut(upload_id) else {
+                    return error(404, "NoSuchUpload");
+                };
+                let etag = format!("\"{}\"", hex(&Sha256::digest(&request.body)));
+                parts.insert(number, request.body.clone());
+                Response::new(200, "").header("ETag", etag)
+            }
+            ("POST", Some(upload_id)) => {
+                let Some(mut parts) = bucket.uploads.remove(upload_id) else {
+                    return error(404, "NoSuchUpload");
+                };
+                let body = String::from_utf8_lossy(&request.body).to_string();
+                let mut object = Vec::new();
+                for number in 1..=parts.len() {
+                    let Some(part) = parts.remove(&number) else {
+                        return error(400, "InvalidPart");
+                    };
+                    let etag = format!("<PartNumber>{number}</PartNumber><ETag>\"{}\"</ETag>", hex(&Sha256::digest(&part)));
+                    if !body.contains(&etag) {
+                        return error(400, "InvalidPart");
+                    }
+                    object.extend(part);
+                }
+                bucket.objects.insert(key, object);
+                Response::new(200, "<CompleteMultipartUploadResult></CompleteMultipartUploadResult>")
+            }
+            ("PUT", None) => {
+                bucket.objects.insert(key, request.body.clone());
+                Response::new(200, "")
+            }
+            ("GET", None) => match bucket.objects.get(&key) {
+                Some(object) => Response::new(200, object.clone()),
+                None => error(404, "NoSuchKey"),
+            },
+            ("DELETE", None) => {
+                bucket.objects.remove(&key);
+                Response::new(204, "")
+            }
+            _ => error(400, "InvalidRequest"),
+        }
+    });
+    (server, bucket)
+}
+
+fn target(server: &StubServer, secret_key: &str) -> S3Target {
+    S3Target {
+        name: String::from("s3"),
+        endpoint: server.url.clone(),
+        region: String::from(REGION),
+        bucket: String::from(BUCKET),
+        access_key: String::from(ACCESS_KEY),
+        secret_key: String::from(secret_key),
+        prefix: Some(String::from("/slink/")),
+        path_style: true,
+    }
+}
+
+#[tokio::test]
+async fn uploads_downloads_and_deletes_signed_objects() {
+    let (server, bucket) = s3_server(Arc::new(AtomicBool::new(false))).await;
+    let target = target(&server, SECRET_KEY);
+    let dir = TempDir::new();
+    let source = dir.0.join("backup.tar.gz");
+    tokio::fs::write(&source, b"world data").await.unwrap();
+
+    target.upload(&source, "survival/2024 backup+1.tar.gz").await.unwrap();
+    assert_eq!(
+        server.requests()[0].path,
+        format!("/{BUCKET}/slink/survival/2024%20backup%2B1.tar.gz")
+    );
+    assert_eq!(
+        bucket.lock().unwrap().objects.get("slink/survival/2024%20backup%2B1.tar.gz"),
+        Some(&b"world data".to_vec())
+    );
+
+    let destination = dir.0.join("restored.tar.gz");
+    target.download("survival/2024 backup+1.tar.gz", &destination).await.unwrap();
+    assert_eq!(tokio::fs::read(&destination).await.unwrap(), b"world data");
+
+    target.delete("survival/2024 backup+1.tar.gz").await.unwrap();
+    assert!(bucket.lock().unwrap().objects.is_empty());
+}
+
+#[tokio::test]
+async fn rejects_keys_outside_the_prefix() {
+    let (server, _) = s3_server(Arc::new(AtomicBool::new(false))).await;
+    let target = target(&server, SECRET_KEY);
+    assert!(target.delete("../other/backup.tar.gz").await.is_err());
+    assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn reports_signature_mismatches() {
+    let (server, bucket) = s3_server(Arc::new(AtomicBool::new(false))).await;
+    let target = target(&server, "wrong-secret");
+    let dir = TempDir::new();
+    let source = dir.0.join("backup.tar.gz");
+    tokio::fs::write(&source, b"world data").await.unwrap();
+
+    let error = target.upload(&source, "backup.tar.gz").await.unwrap_err();
+    assert!(error.to_string().contains("SignatureDoesNotMatch"), "{error}");
+    assert!(error.to_string().contains("Signature does not match"), "{error}");
+    assert!(bucket.lock().unwrap().objects.is_empty());
+}
+
+#[tokio::test]
+async fn resumes_interrupted_multipart_uploads() {
+    let fail_part_two = Arc::new(AtomicBool::new(true));
+    let (server, bucket) = s3_server(fail_part_two.clone()).await;
+    let target = target(&server, SECRET_KEY);
+    let dir = TempDir::new();
+    let source = dir.0.join("backup.tar.gz");
+    let data: Vec<u8> = (0..2 * S3_PART_SIZE + 100).map(|i| (i % 251) as u8).collect();
+    tokio::fs::write(&source, &data).await.unwrap();
+    let state_path = dir.0.join("backup.tar.gz.multipart");
+
+    assert!(target.upload(&source, "backup.tar.gz").await.is_err());
+    let requests = server.requests();
+    let part_two = requests.iter().filter(|r| r.params().get("partNumber") == Some(&String::from("2")));
+    assert_eq!(part_two.count(), S3_PART_RETRIES as usize);
+    let state: serde_json::Value = serde_json::from_slice(&tokio::fs::read(&state_path).await.unwrap()).unwrap();
+    assert_eq!(state["parts"].as_array().unwrap().len(), 1);
+
+    fail_part_two.store(false, Ordering::SeqCst);
+    target.upload(&source, "backup.tar.gz").await.unwrap();
+    let resumed = &server.requests()[requests.len()..];
+    assert!(!resumed.iter().any(|r| r.params().contains_key("uploads")));
+    let parts: Vec<String> = resumed
+        .iter()
+        .filter_map(|r| r.params().get("partNumber").cloned())
+        .collect();
+    assert_eq!(parts, vec!["2", "3"]);
+
+    assert_eq!(bucket.lock().unwrap().objects.get("slink/backup.tar.gz"), Some(&data));
+    assert!(!state_path.exists());
+}
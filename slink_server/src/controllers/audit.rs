@@ -0,0 +1,153 @@
+use bson::{Document, doc};
+use chrono::{DateTime, Utc};
+use okapi::openapi3::{MediaType, OpenApi, RefOr, Response as OpenApiResponse, Responses};
+use rocket::{
+    Request, Response,
+    http::{ContentType, Header},
+    response::{self, Responder},
+    serde::json::Json,
+};
+use rocket_okapi::{openapi, openapi_get_routes_spec, response::OpenApiResponderInner};
+use slink_common::{ApiError, ApiResult, Error};
+use uuid::Uuid;
+
+use crate::models::{AuditEntry, AuditEntryInfo, User};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+fn require_superuser(user: &User) -> ApiResult<()> {
+    if !user.superuser {
+        return Err(ApiError::missing_auth("Superuser"));
+    }
+    Ok(())
+}
+
+fn parse_time(name: &str, value: Option<&str>) -> ApiResult<Option<DateTime<Utc>>> {
+    value
+        .map(|v| {
+            DateTime::parse_from_rfc3339(v)
+                .map(|t| t.with_timezone(&Utc))
+                .or(Err(ApiError::bad_request(format!("{name} must be an RFC 3339 timestamp"))))
+        })
+        .transpose()
+}
+
+/// Builds the query for the filters. `action` matches dotted prefixes, so `players` includes
+/// `players.bans.add`.
+fn query(
+    actor: Option<Uuid>,
+    server: Option<Uuid>,
+    action: Option<&str>,
+    since: Option<&str>,
+    until: Option<&str>,
+) -> ApiResult<Document> {
+    let mut filter = Document::new();
+    if let Some(actor) = actor {
+        filter.insert("actor", bson::Uuid::from(actor));
+    }
+    if let Some(server) = server {
+        filter.insert("server", bson::Uuid::from(server));
+    }
+    if let Some(action) = action {
+        filter.insert(
+            "$or",
+            vec![
+                doc! {"action": action},
+                doc! {"action": {"$regex": format!("^{}\\.", regex::escape(action))}},
+            ],
+        );
+    }
+
+    let mut timestamp = Document::new();
+    if let Some(since) = parse_time("since", since)? {
+        timestamp.insert("$gte", bson::DateTime::from_chrono(since));
+    }
+    if let Some(until) = parse_time("until", until)? {
+        timestamp.insert("$lt", bson::DateTime::from_chrono(until));
+    }
+    if !timestamp.is_empty() {
+        filter.insert("timestamp", timestamp);
+    }
+    Ok(filter)
+}
+
+/// Audit entries as JSON Lines, one entry per line.
+struct AuditExport {
+    body: String,
+}
+
+impl<'r> Responder<'r, 'static> for AuditExport {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        Response::build_from(self.body.respond_to(req)?)
+            .header(ContentType::new("application", "jsonl"))
+            .header(Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"audit-{}.jsonl\"", Utc::now().format("%Y%m%d%H%M%S")),
+            ))
+            .ok()
+    }
+}
+
+impl OpenApiResponderInner for AuditExport {
+    fn responses(_: &mut rocket_okapi::r#gen::OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut content = rocket_okapi::okapi::schemars::Map::new();
+        content.insert("application/jsonl".to_string(), MediaType::default());
+
+        let mut responses = Responses::default();
+        responses.responses.insert(
+            "200".to_string(),
+            RefOr::Object(OpenApiResponse {
+                description: "Audit entries as JSON Lines, oldest first".to_string(),
+                content,
+                ..Default::default()
+            }),
+        );
+        Ok(responses)
+    }
+}
+
+/// Lists audit entries, newest first. Timestamps are RFC 3339, with `since` inclusive and `until` exclusive.
+/// `limit` defaults to 100 and is capped at 1000; use the export for more. Superuser only.
+#[openapi(tag = "Audit")]
+#[get("/?<actor>&<server>&<action>&<since>&<until>&<limit>")]
+async fn list_entries(
+    user: User,
+    actor: Option<Uuid>,
+    server: Option<Uuid>,
+    action: Option<&str>,
+    since: Option<&str>,
+    until: Option<&str>,
+    limit: Option<usize>,
+) -> ApiResult<Json<Vec<AuditEntryInfo>>> {
+    require_superuser(&user)?;
+    let filter = query(actor, server, action, since, until)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT) as i64;
+    let entries = AuditEntry::find(filter, true, Some(limit)).await?;
+    Ok(Json(entries.iter().map(|e| e.info()).collect()))
+}
+
+/// Exports every matching audit entry as JSON Lines, oldest first, with the same filters as listing them.
+/// Superuser only.
+#[openapi(tag = "Audit")]
+#[get("/export?<actor>&<server>&<action>&<since>&<until>")]
+async fn export_entries(
+    user: User,
+    actor: Option<Uuid>,
+    server: Option<Uuid>,
+    action: Option<&str>,
+    since: Option<&str>,
+    until: Option<&str>,
+) -> ApiResult<AuditExport> {
+    require_superuser(&user)?;
+    let mut body = String::new();
+    for entry in AuditEntry::find(query(actor, server, action, since, until)?, false, None).await? {
+        body.push_str(&serde_json::to_string(&entry.info()).map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?);
+        body.push('\n');
+    }
+    Ok(AuditExport { body })
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![list_entries, export_entries]
+}
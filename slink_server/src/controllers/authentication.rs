@@ -1,13 +1,18 @@
+use chrono::{DateTime, TimeDelta, Utc};
 use log::debug;
 use manor::{Link, Model};
 use okapi::openapi3::OpenApi;
-use rocket::serde::json::Json;
+use rocket::{http::CookieJar, serde::json::Json};
 use rocket_okapi::{openapi, openapi_get_routes_spec};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use slink_common::{ApiError, ApiResult, Error};
+use slink_common::{ApiError, ApiResult, Error, SESSION_COOKIE, types::AppConfig};
+use uuid::Uuid;
 
-use crate::models::{RedactedUser, Session, User};
+use crate::{
+    models::{ApiToken, Auditor, PendingUser, RedactedToken, RedactedUser, RequestToken, Session, SessionInfo, TokenScope, User},
+    util::{rate_limit::LoginThrottle, security::HashedPassword},
+};
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub struct LoginModel {
@@ -15,30 +20,250 @@ pub struct LoginModel {
     pub password: String,
 }
 
+/// Logs in with a username and password. Users with two-factor authentication get a `second_factor_required`
+/// error, and complete the login by posting a code to `/auth/2fa/verify`. Attempts are throttled per address and
+/// username, and repeated failures lock the account for a while.
 #[openapi(tag = "Authentication")]
 #[post("/login", data = "<login>")]
-pub async fn login(mut session: Session, login: Json<LoginModel>) -> ApiResult<Json<RedactedUser>> {
-    if let Some(user) = User::from_username(login.username.clone()).await {
-        if user.hashed_password.verify(login.password.clone()) {
-            session.user = Some(Link::from(user.clone()));
-            session.save().await.or_else(|e| Err::<_, ApiError>(Error::Unexpected(e.to_string()).into()))?;
-            debug!("User {} ({}) logged in successfully.", user.username, user.id);
-
-            return Ok(Json(user.redact()));
+pub async fn login(
+    mut session: Session,
+    cookies: &CookieJar<'_>,
+    config: AppConfig,
+    throttle: LoginThrottle,
+    audit: Auditor,
+    login: Json<LoginModel>,
+) -> ApiResult<Json<RedactedUser>> {
+    throttle.attempt(&login.username)?;
+
+    // Unknown users are checked against a dummy hash, so that they take as long to reject as wrong passwords.
+    let user = User::from_username(login.username.clone()).await;
+    let verified = match &user {
+        Some(user) => user.hashed_password.verify(login.password.clone()),
+        None => {
+            HashedPassword::dummy().verify(login.password.clone());
+            false
         }
+    };
+    let (Some(user), true) = (user, verified) else {
+        throttle.failed(&login.username).await;
+        return Err(ApiError::bad_login());
+    };
+
+    if user.disabled {
+        return Err(ApiError::missing_auth("account_disabled"));
     }
+    throttle.succeeded(&login.username);
+    session.user = Some(Link::from(user.clone()));
+    session.pending_second_factor = user.two_factor_enabled();
+    session.oidc_id_token = None;
+    session.rotate(cookies, &config).await?;
+    audit
+        .entry("auth.login")
+        .target(&user.username)
+        .details(serde_json::json!({ "pending_second_factor": session.pending_second_factor }))
+        .record()
+        .await;
+    if session.pending_second_factor {
+        debug!("User {} ({}) entered their password, waiting for a second factor.", user.username, user.id);
+        return Err(ApiError::missing_auth("second_factor_required"));
+    }
+    debug!("User {} ({}) logged in successfully.", user.username, user.id);
 
-    Err(ApiError::bad_login())
+    Ok(Json(user.redact()))
 }
 
 #[openapi(tag = "Authentication")]
 #[delete("/login")]
-pub async fn logout(mut session: Session, _user: User) -> ApiResult<()> {
-    session.user = None;
-    let _ = session.save().await;
+pub async fn logout(session: Session, cookies: &CookieJar<'_>) -> ApiResult<()> {
+    if session.user.is_none() {
+        return Err(ApiError::missing_auth("requires_login"));
+    }
+    session.end(cookies).await
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct PasswordChangeModel {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// Changes the logged-in user's password, clearing a required reset. Other sessions of the user are logged out.
+#[openapi(tag = "Authentication")]
+#[post("/password", data = "<change>")]
+pub async fn change_password(
+    mut session: Session,
+    cookies: &CookieJar<'_>,
+    config: AppConfig,
+    user: PendingUser,
+    token: RequestToken,
+    audit: Auditor,
+    change: Json<PasswordChangeModel>,
+) -> ApiResult<Json<RedactedUser>> {
+    token.require_session()?;
+    let PendingUser(mut user) = user;
+    if !user.hashed_password.verify(change.current_password.clone()) {
+        return Err(ApiError::bad_login());
+    }
+    if change.current_password == change.new_password {
+        return Err(ApiError::bad_request("The new password must differ from the current one"));
+    }
+
+    user.set_password(change.new_password.clone())?;
+    user.password_reset_required = false;
+    user.save().await.or_else(|e| Err::<_, ApiError>(Error::Unexpected(e.to_string()).into()))?;
+    Session::invalidate_user(user.id, Some(session.id)).await?;
+    session.rotate(cookies, &config).await?;
+    audit.entry("auth.password").target(&user.username).record().await;
+    debug!("User {} ({}) changed their password.", user.username, user.id);
+    Ok(Json(user.redact()))
+}
+
+/// Lists the logged-in user's active sessions, most recently used first.
+#[openapi(tag = "Authentication")]
+#[get("/sessions")]
+pub async fn list_sessions(session: Session, user: User, config: AppConfig) -> ApiResult<Json<Vec<SessionInfo>>> {
+    let sessions = Session::for_user(user.id, config.authentication.session_max_lifetime).await?;
+    Ok(Json(sessions.iter().map(|s| s.listed(session.id)).collect()))
+}
+
+/// Logs out one of the logged-in user's sessions. Revoking the current session is the same as logging out.
+#[openapi(tag = "Authentication")]
+#[delete("/sessions/<id>")]
+pub async fn revoke_session(
+    session: Session,
+    cookies: &CookieJar<'_>,
+    user: User,
+    audit: Auditor,
+    id: Uuid,
+) -> ApiResult<()> {
+    if session.id == bson::Uuid::from(id) {
+        return session.end(cookies).await;
+    }
+    Session::revoke(user.id, id).await?;
+    audit
+        .entry("auth.sessions.revoke")
+        .target(&user.username)
+        .details(serde_json::json!({ "session": id }))
+        .record()
+        .await;
+    Ok(())
+}
+
+/// Logs the user out everywhere. With `keep_current`, the session making the request stays logged in.
+#[openapi(tag = "Authentication")]
+#[delete("/sessions?<keep_current>")]
+pub async fn revoke_sessions(
+    session: Session,
+    cookies: &CookieJar<'_>,
+    user: User,
+    audit: Auditor,
+    keep_current: Option<bool>,
+) -> ApiResult<()> {
+    let keep_current = keep_current.unwrap_or(false);
+    Session::invalidate_user(user.id, keep_current.then_some(session.id)).await?;
+    if !keep_current && session.user.is_some() {
+        cookies.remove_private(SESSION_COOKIE);
+    }
+    audit
+        .entry("auth.sessions.revoke_all")
+        .target(&user.username)
+        .details(serde_json::json!({ "keep_current": keep_current }))
+        .record()
+        .await;
+    debug!("User {} ({}) logged out everywhere.", user.username, user.id);
+    Ok(())
+}
+
+fn default_token_lifetime() -> Option<u32> {
+    Some(90)
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct TokenCreationModel {
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+
+    /// Days until the token expires, or `null` for a token that never expires
+    #[serde(default = "default_token_lifetime")]
+    pub expires_in_days: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct CreatedToken {
+    pub token: RedactedToken,
+
+    /// The token to send as `Authorization: Bearer <value>`. It is only shown once.
+    pub value: String,
+}
+
+#[openapi(tag = "Authentication")]
+#[get("/tokens")]
+pub async fn list_tokens(user: User) -> ApiResult<Json<Vec<RedactedToken>>> {
+    Ok(Json(ApiToken::for_user(user.id).await?.iter().map(|t| t.redact()).collect()))
+}
+
+/// Creates a personal API token. Tokens cannot be created by requests authenticated with another token.
+#[openapi(tag = "Authentication")]
+#[post("/tokens", data = "<params>")]
+pub async fn create_token(
+    user: User,
+    token: RequestToken,
+    audit: Auditor,
+    params: Json<TokenCreationModel>,
+) -> ApiResult<Json<CreatedToken>> {
+    token.require_session()?;
+    let params = params.into_inner();
+    if params.name.trim().is_empty() {
+        return Err(ApiError::bad_request("Token names cannot be empty"));
+    }
+    if params.scopes.is_empty() {
+        return Err(ApiError::bad_request("Tokens need at least one scope"));
+    }
+
+    let expires: Option<DateTime<Utc>> = match params.expires_in_days {
+        Some(0) => return Err(ApiError::bad_request("Tokens must be valid for at least one day")),
+        Some(days) => Some(Utc::now() + TimeDelta::days(days.into())),
+        None => None,
+    };
+    let (created, value) = ApiToken::create(&user, params.name, params.scopes, expires)?;
+    created.save().await.or_else(|e| Err::<_, ApiError>(Error::Unexpected(e.to_string()).into()))?;
+    audit
+        .entry("auth.tokens.create")
+        .target(&created.name)
+        .details(serde_json::json!({ "token": created.id.to_string(), "scopes": created.scopes, "expires": expires }))
+        .record()
+        .await;
+    debug!("User {} ({}) created API token {}.", user.username, user.id, created.id);
+
+    Ok(Json(CreatedToken {
+        token: created.redact(),
+        value,
+    }))
+}
+
+#[openapi(tag = "Authentication")]
+#[delete("/tokens/<id>")]
+pub async fn revoke_token(user: User, audit: Auditor, id: Uuid) -> ApiResult<()> {
+    let token = ApiToken::get_for(id, user.id).await?;
+    let entry = audit
+        .entry("auth.tokens.revoke")
+        .target(&token.name)
+        .details(serde_json::json!({ "token": id }));
+    token.delete().await.or_else(|e| Err::<_, ApiError>(Error::Unexpected(e.to_string()).into()))?;
+    entry.record().await;
     Ok(())
 }
 
 pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
-    openapi_get_routes_spec![login, logout]
+    openapi_get_routes_spec![
+        login,
+        logout,
+        change_password,
+        list_sessions,
+        revoke_session,
+        revoke_sessions,
+        list_tokens,
+        create_token,
+        revoke_token
+    ]
 }
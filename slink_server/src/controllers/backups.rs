@@ -0,0 +1,53 @@
+use okapi::openapi3::OpenApi;
+use rocket::serde::json::Json;
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use slink_common::{
+    ApiError, ApiResult,
+    types::AppConfig,
+    utilities::{RepositoryCheck, RepositoryPrune},
+};
+
+use crate::{
+    models::{Auditor, User},
+    services::backups::BackupEngine,
+};
+
+/// Lists the names of the configured backup targets.
+#[openapi(tag = "Backups")]
+#[get("/targets")]
+async fn list_targets(_user: User, config: AppConfig) -> Json<Vec<String>> {
+    let mut targets: Vec<String> = config.backups.targets.keys().cloned().collect();
+    targets.sort();
+    Json(targets)
+}
+
+/// Verifies every object referenced by an incremental backup. Superuser only.
+#[openapi(tag = "Backups")]
+#[post("/repository/check")]
+async fn check_repository(user: User, config: AppConfig, engine: BackupEngine) -> ApiResult<Json<RepositoryCheck>> {
+    if !user.superuser {
+        return Err(ApiError::missing_auth("Superuser"));
+    }
+    Ok(Json(engine.check_repository(&config).await?))
+}
+
+/// Reclaims space used by deleted incremental backups. Superuser only.
+#[openapi(tag = "Backups")]
+#[post("/repository/prune")]
+async fn prune_repository(
+    user: User,
+    config: AppConfig,
+    engine: BackupEngine,
+    audit: Auditor,
+) -> ApiResult<Json<RepositoryPrune>> {
+    if !user.superuser {
+        return Err(ApiError::missing_auth("Superuser"));
+    }
+    let pruned = engine.prune_repository(&config).await?;
+    audit.entry("backups.prune").details(&pruned).record().await;
+    Ok(Json(pruned))
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![list_targets, check_repository, prune_repository]
+}
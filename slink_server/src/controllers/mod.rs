@@ -6,26 +6,38 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use slink_common::types::{AppConfig, RunnerMode};
 
-use crate::models::{OptionalUser, RedactedUser, Session};
+use crate::models::{OptionalUser, RedactedUser, Session, SessionInfo};
 
+pub mod audit;
 pub mod authentication;
+pub mod backups;
+pub mod oidc;
+pub mod roles;
 pub mod servers;
+pub mod settings;
+pub mod templates;
+pub mod two_factor;
+pub mod users;
 pub mod providers;
 
 #[derive(JsonSchema, Serialize, Deserialize, Clone, Debug)]
 pub struct IndexInfo {
-    pub session: Session,
+    pub session: SessionInfo,
     pub runner_mode: RunnerMode,
-    pub user: Option<RedactedUser>
+    pub user: Option<RedactedUser>,
+
+    /// Whether users can log in through `/auth/oidc/login`
+    pub oidc_enabled: bool
 }
 
 #[openapi]
 #[get("/")]
 async fn get_index(session: Session, config: AppConfig, user: OptionalUser) -> Json<IndexInfo> {
     Json(IndexInfo {
-        session: session.clone(),
+        session: session.info(),
         runner_mode: config.runner.mode(),
-        user: user.redacted()
+        user: user.redacted(),
+        oidc_enabled: config.authentication.oidc.is_some()
     })
 }
 
@@ -35,7 +47,26 @@ pub fn apply(mut rocket: Rocket<rocket::Build>) -> Rocket<rocket::Build> {
         rocket, "/".to_owned(), settings,
         "/" => openapi_get_routes_spec![get_index],
         "/auth" => authentication::routes(),
+        "/auth" => two_factor::routes(),
+        "/auth/oidc" => oidc::routes(),
         "/servers" => servers::global::routes(),
+        "/servers" => servers::instance::routes(),
+        "/servers" => servers::players::routes(),
+        "/servers" => servers::console::routes(),
+        "/servers" => servers::files::routes(),
+        "/servers" => servers::archives::routes(),
+        "/servers" => servers::backups::routes(),
+        "/servers" => servers::schedules::routes(),
+        "/servers" => servers::content::routes(),
+        "/servers" => servers::imports::routes(),
+        "/servers" => servers::templates::routes(),
+        "/servers" => servers::sharing::routes(),
+        "/templates" => templates::routes(),
+        "/users" => users::routes(),
+        "/roles" => roles::routes(),
+        "/settings" => settings::routes(),
+        "/audit" => audit::routes(),
+        "/backups" => backups::routes(),
         "/providers/minecraft" => providers::minecraft_version::routes(),
         "/providers/server_binary" => providers::server_binary::routes()
     };
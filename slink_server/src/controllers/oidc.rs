@@ -0,0 +1,193 @@
+use chrono::{TimeDelta, Utc};
+use log::{debug, warn};
+use manor::Link;
+use okapi::openapi3::OpenApi;
+use rocket::{http::CookieJar, response::Redirect, serde::json::Json};
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{
+    ApiError, ApiResult,
+    providers::oidc::{OidcClient, state_matches, url_safe_random},
+    types::{AppConfig, OidcConfig},
+};
+
+use crate::{
+    models::{Auditor, OidcLogin, RequestToken, Session, User},
+    services::oidc,
+    util::security::random_bytes,
+};
+
+/// How long the provider may take to send the browser back.
+const LOGIN_TIMEOUT_MINUTES: i64 = 10;
+
+fn oidc_config(config: &AppConfig) -> ApiResult<OidcConfig> {
+    config
+        .authentication
+        .oidc
+        .clone()
+        .ok_or(ApiError::configuration("OpenID Connect is not configured"))
+}
+
+/// Only same-origin paths are accepted as redirect targets, so that logins cannot be used as open redirects.
+fn local_path(redirect: Option<&str>) -> Option<String> {
+    redirect
+        .filter(|r| r.starts_with('/') && !r.starts_with("//") && !r.contains('\\'))
+        .map(|r| r.to_string())
+}
+
+/// Records a login attempt on the session and returns the provider's authorization URL.
+async fn begin(
+    mut session: Session,
+    cookies: &CookieJar<'_>,
+    config: &AppConfig,
+    redirect: Option<String>,
+    link_user: Option<bson::Uuid>,
+) -> ApiResult<Redirect> {
+    let client = OidcClient::new(oidc_config(config)?);
+    let discovery = client.discover().await?;
+    let attempt = OidcLogin {
+        state: url_safe_random(&random_bytes(24)?),
+        nonce: url_safe_random(&random_bytes(24)?),
+        verifier: url_safe_random(&random_bytes(48)?),
+        started: Utc::now(),
+        redirect,
+        link_user,
+    };
+    let url = client.authorization_url(&discovery, &attempt.state, &attempt.nonce, &attempt.verifier)?;
+
+    session.oidc = Some(attempt);
+    session.persist(cookies, config).await?;
+    Ok(Redirect::to(url))
+}
+
+/// Redirects the browser to the identity provider. `redirect` is the path to return to afterwards.
+#[openapi(tag = "Authentication")]
+#[get("/login?<redirect>")]
+pub async fn login(session: Session, cookies: &CookieJar<'_>, config: AppConfig, redirect: Option<&str>) -> ApiResult<Redirect> {
+    begin(session, cookies, &config, local_path(redirect), None).await
+}
+
+/// Like login, but links the identity to the logged-in user, so that they can log in through the provider.
+#[openapi(tag = "Authentication")]
+#[get("/link?<redirect>")]
+pub async fn link(
+    session: Session,
+    cookies: &CookieJar<'_>,
+    user: User,
+    token: RequestToken,
+    config: AppConfig,
+    redirect: Option<&str>,
+) -> ApiResult<Redirect> {
+    token.require_session()?;
+    begin(session, cookies, &config, local_path(redirect), Some(user.id)).await
+}
+
+/// Where the provider sends the browser back to. Completes the login or link started by this session.
+#[openapi(tag = "Authentication")]
+#[get("/callback?<code>&<state>&<error>&<error_description>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn callback(
+    mut session: Session,
+    cookies: &CookieJar<'_>,
+    config: AppConfig,
+    audit: Auditor,
+    code: Option<&str>,
+    state: Option<&str>,
+    error: Option<&str>,
+    error_description: Option<&str>,
+) -> ApiResult<Redirect> {
+    let oidc_config = oidc_config(&config)?;
+    let attempt = session
+        .oidc
+        .take()
+        .ok_or(ApiError::invalid_state("No OpenID Connect login in progress"))?;
+    session.persist(cookies, &config).await?;
+
+    if let Some(error) = error {
+        return Err(ApiError::missing_auth(format!(
+            "oidc_error:{error}{}",
+            error_description.map(|d| format!(" ({d})")).unwrap_or_default()
+        )));
+    }
+    if !state_matches(&attempt.state, state) {
+        return Err(ApiError::bad_request("OpenID Connect state does not match the login"));
+    }
+    if attempt.started + TimeDelta::minutes(LOGIN_TIMEOUT_MINUTES) < Utc::now() {
+        return Err(ApiError::invalid_state("OpenID Connect login timed out"));
+    }
+    let code = code.ok_or(ApiError::bad_request("Missing authorization code"))?;
+
+    let client = OidcClient::new(oidc_config.clone());
+    let discovery = client.discover().await?;
+    let identity = client
+        .exchange(&discovery, code, &attempt.verifier, &attempt.nonce)
+        .await
+        .map_err(|e| {
+            warn!("OpenID Connect code exchange failed: {e:?}");
+            ApiError::from(e)
+        })?;
+
+    let action = match attempt.link_user {
+        Some(_) => "auth.oidc.link",
+        None => "auth.oidc.login",
+    };
+    let user = match attempt.link_user {
+        Some(id) => oidc::link(User::get(id).await?, &identity).await?,
+        None => oidc::resolve_user(&identity, &oidc_config).await?,
+    };
+    if user.disabled {
+        return Err(ApiError::missing_auth("account_disabled"));
+    }
+
+    session.user = Some(Link::from(user.clone()));
+    session.pending_second_factor = false;
+    session.oidc_id_token = Some(identity.id_token.clone());
+    session.rotate(cookies, &config).await?;
+    audit
+        .entry(action)
+        .target(&user.username)
+        .details(serde_json::json!({ "subject": identity.subject }))
+        .record()
+        .await;
+    debug!("User {} ({}) logged in through OpenID Connect.", user.username, user.id);
+
+    let target = attempt
+        .redirect
+        .or(oidc_config.post_login_redirect.clone())
+        .unwrap_or(String::from("/"));
+    Ok(Redirect::to(target))
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct LogoutModel {
+    /// Provider URL that ends the single sign-on session too, if the provider supports it
+    pub end_session_url: Option<String>,
+}
+
+/// Logs out locally, and returns where to send the browser to log out at the provider.
+#[openapi(tag = "Authentication")]
+#[post("/logout")]
+pub async fn logout(session: Session, cookies: &CookieJar<'_>, config: AppConfig) -> ApiResult<Json<LogoutModel>> {
+    let id_token = session.oidc_id_token.clone();
+    session.end(cookies).await?;
+
+    let end_session_url = match config.authentication.oidc {
+        Some(oidc_config) => {
+            let client = OidcClient::new(oidc_config);
+            match client.discover().await {
+                Ok(discovery) => client.end_session_url(&discovery, id_token.as_deref()),
+                Err(e) => {
+                    warn!("OpenID Connect discovery failed during logout: {e:?}");
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    Ok(Json(LogoutModel { end_session_url }))
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![login, link, callback, logout]
+}
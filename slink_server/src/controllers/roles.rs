@@ -0,0 +1,180 @@
+use bson::doc;
+use futures::TryStreamExt;
+use manor::{Collection, Model};
+use okapi::openapi3::OpenApi;
+use rocket::serde::json::Json;
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{ApiError, ApiResult, Error};
+use uuid::Uuid;
+
+use crate::models::{Auditor, CommandFilter, Permission, Role, ServerGrant, User};
+
+fn require_superuser(user: &User) -> ApiResult<()> {
+    if !user.superuser {
+        return Err(ApiError::missing_auth("Superuser"));
+    }
+    Ok(())
+}
+
+fn unexpected(e: impl ToString) -> ApiError {
+    ApiError::from(Error::Unexpected(e.to_string()))
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct RoleCreationParams {
+    pub name: String,
+
+    #[serde(default)]
+    pub description: Option<String>,
+
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+
+    /// Console commands users with the role may send
+    #[serde(default)]
+    pub commands: CommandFilter,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct RoleUpdateParams {
+    #[serde(default)]
+    pub name: Option<String>,
+
+    #[serde(default)]
+    pub description: Option<String>,
+
+    #[serde(default)]
+    pub permissions: Option<Vec<Permission>>,
+
+    #[serde(default)]
+    pub commands: Option<CommandFilter>,
+}
+
+fn normalize(permissions: &[Permission]) -> Vec<Permission> {
+    Permission::ALL.into_iter().filter(|p| permissions.contains(p)).collect()
+}
+
+async fn ensure_unique(name: &str, except: Option<bson::Uuid>) -> ApiResult<()> {
+    if name.trim().is_empty() {
+        return Err(ApiError::bad_request("Role names cannot be empty"));
+    }
+    match Role::all().await?.into_iter().find(|r| r.name == name && Some(r.id) != except) {
+        Some(_) => Err(ApiError::invalid_state(format!("Role {name} already exists"))),
+        None => Ok(()),
+    }
+}
+
+/// Lists all roles, so that any user can pick them when sharing a server.
+#[openapi(tag = "Roles")]
+#[get("/")]
+async fn list_roles(_user: User) -> ApiResult<Json<Vec<Role>>> {
+    Ok(Json(Role::all().await?))
+}
+
+#[openapi(tag = "Roles")]
+#[get("/<id>")]
+async fn get_role(_user: User, id: Uuid) -> ApiResult<Json<Role>> {
+    Ok(Json(Role::get(id).await?))
+}
+
+#[openapi(tag = "Roles")]
+#[post("/", data = "<params>")]
+async fn create_role(user: User, audit: Auditor, params: Json<RoleCreationParams>) -> ApiResult<Json<Role>> {
+    require_superuser(&user)?;
+    let params = params.into_inner();
+    ensure_unique(&params.name, None).await?;
+    params.commands.validate()?;
+
+    let mut role = Role::new(params.name, params.description, normalize(&params.permissions));
+    role.commands = params.commands;
+    role.save().await.map_err(unexpected)?;
+    audit
+        .entry("roles.create")
+        .target(&role.name)
+        .details(serde_json::json!({
+            "role": role.id.to_string(),
+            "permissions": role.permissions,
+            "commands": role.commands,
+        }))
+        .record()
+        .await;
+    Ok(Json(role))
+}
+
+#[openapi(tag = "Roles")]
+#[patch("/<id>", data = "<params>")]
+async fn update_role(user: User, audit: Auditor, id: Uuid, params: Json<RoleUpdateParams>) -> ApiResult<Json<Role>> {
+    require_superuser(&user)?;
+    let params = params.into_inner();
+    let mut role = Role::get(id).await?;
+    let previous = role.clone();
+
+    if let Some(name) = params.name.filter(|n| *n != role.name) {
+        ensure_unique(&name, Some(role.id)).await?;
+        role.name = name;
+    }
+    if params.description.is_some() {
+        role.description = params.description;
+    }
+    if let Some(permissions) = params.permissions {
+        role.permissions = normalize(&permissions);
+    }
+    if let Some(commands) = params.commands {
+        commands.validate()?;
+        role.commands = commands;
+    }
+    role.save().await.map_err(unexpected)?;
+    audit
+        .entry("roles.update")
+        .target(&role.name)
+        .diff(&previous, &role)
+        .record()
+        .await;
+    Ok(Json(role))
+}
+
+/// Deletes a role, removing it from every user and grant that has it.
+#[openapi(tag = "Roles")]
+#[delete("/<id>")]
+async fn delete_role(user: User, audit: Auditor, id: Uuid) -> ApiResult<()> {
+    require_superuser(&user)?;
+    let role = Role::get(id).await?;
+
+    let holders = Collection::<User>::new()
+        .find_many(doc! {"roles": role.id})
+        .await
+        .map_err(unexpected)?
+        .try_collect::<Vec<User>>()
+        .await
+        .map_err(unexpected)?;
+    for mut holder in holders {
+        holder.roles.retain(|r| *r != role.id);
+        holder.save().await.map_err(unexpected)?;
+    }
+
+    let grants = Collection::<ServerGrant>::new()
+        .find_many(doc! {"roles": role.id})
+        .await
+        .map_err(unexpected)?
+        .try_collect::<Vec<ServerGrant>>()
+        .await
+        .map_err(unexpected)?;
+    for mut grant in grants {
+        grant.roles.retain(|r| *r != role.id);
+        grant.save().await.map_err(unexpected)?;
+    }
+
+    let entry = audit
+        .entry("roles.delete")
+        .target(&role.name)
+        .details(serde_json::json!({ "role": role.id.to_string() }));
+    role.delete().await.map_err(unexpected)?;
+    entry.record().await;
+    Ok(())
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![list_roles, get_role, create_role, update_role, delete_role]
+}
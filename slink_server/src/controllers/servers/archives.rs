@@ -0,0 +1,215 @@
+use okapi::openapi3::{MediaType, OpenApi, RefOr, Response as OpenApiResponse, Responses};
+use rocket::{
+    Data, Request, Response,
+    http::{ContentType, Header},
+    response::{
+        self, Responder,
+        stream::{One, ReaderStream},
+    },
+    serde::json::Json,
+};
+use rocket_okapi::{openapi, openapi_get_routes_spec, response::OpenApiResponderInner};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{
+    ApiError, ApiResult, Error,
+    types::AppConfig,
+    utilities::{Archive, ArchiveFormat},
+};
+use tokio::io::DuplexStream;
+use uuid::Uuid;
+
+use crate::{
+    models::{Auditor, ServerAccess, perms},
+    services::archives::{ArchiveJob, ArchiveJobs},
+    util::UploadLimit,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct ExtractParams {
+    /// Archive to extract, relative to the server directory
+    pub path: String,
+
+    /// Directory to extract into, defaults to the server directory itself
+    #[serde(default)]
+    pub destination: Option<String>,
+
+    /// Remove the archive once it has been extracted
+    #[serde(default)]
+    pub remove: bool,
+}
+
+/// A gzipped tarball, compressed while it is being sent.
+struct ArchiveDownload {
+    stream: ReaderStream<One<DuplexStream>>,
+    disposition: Header<'static>,
+}
+
+impl<'r> Responder<'r, 'r> for ArchiveDownload {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'r> {
+        Response::build_from(self.stream.respond_to(req)?)
+            .header(ContentType::GZIP)
+            .header(self.disposition)
+            .ok()
+    }
+}
+
+impl OpenApiResponderInner for ArchiveDownload {
+    fn responses(_: &mut rocket_okapi::r#gen::OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut content = rocket_okapi::okapi::schemars::Map::new();
+        content.insert("application/gzip".to_string(), MediaType::default());
+
+        let mut responses = Responses::default();
+        responses.responses.insert(
+            "200".to_string(),
+            RefOr::Object(OpenApiResponse {
+                description: "Directory contents as a `.tar.gz` archive".to_string(),
+                content,
+                ..Default::default()
+            }),
+        );
+        Ok(responses)
+    }
+}
+
+fn archive_format(path: &str) -> ApiResult<ArchiveFormat> {
+    ArchiveFormat::detect(path).ok_or(ApiError::bad_request(format!(
+        "{path} is not a supported archive (.zip, .tar.gz or .tgz)"
+    )))
+}
+
+/// Uploads an archive and extracts it in the background. Poll the returned job for progress.
+#[openapi(tag = "Servers", tag = "Archives")]
+#[post("/<id>/archives/upload?<name>&<destination>", data = "<data>")]
+#[allow(clippy::too_many_arguments)]
+async fn upload_archive(
+    access: ServerAccess<perms::Files>,
+    config: AppConfig,
+    jobs: ArchiveJobs,
+    limit: UploadLimit,
+    audit: Auditor,
+    id: Uuid,
+    name: &str,
+    destination: Option<&str>,
+    data: Data<'_>,
+) -> ApiResult<Json<ArchiveJob>> {
+    let server = access.server(id)?;
+    let sandbox = server.sandbox(&config).await?;
+    let format = archive_format(name)?;
+    let destination = destination.unwrap_or_default();
+    let target = sandbox.resolve(destination).await?;
+
+    let upload = sandbox.root().join(format!(".upload-{}", Uuid::new_v4()));
+    let written = data
+        .open(limit.0)
+        .into_file(&upload)
+        .await
+        .map_err(|e| ApiError::from(Error::path(name, e)))?;
+    if !written.is_complete() {
+        let _ = tokio::fs::remove_file(&upload).await;
+        return Err(ApiError::bad_request(format!("Upload exceeds the limit of {}", limit.0)));
+    }
+    audit
+        .entry("archives.upload")
+        .server(server.id)
+        .target(name)
+        .details(serde_json::json!({ "destination": sandbox.relative(&target) }))
+        .record()
+        .await;
+
+    Ok(Json(jobs.extract(
+        ArchiveJob::new(server.id, name, sandbox.relative(&target)),
+        Archive::new(&upload, format),
+        target,
+        config.archives.clone(),
+        Some(upload),
+    )))
+}
+
+/// Extracts an archive that is already inside the server directory.
+#[openapi(tag = "Servers", tag = "Archives")]
+#[post("/<id>/archives/extract", data = "<params>")]
+async fn extract_archive(
+    access: ServerAccess<perms::Files>,
+    config: AppConfig,
+    jobs: ArchiveJobs,
+    audit: Auditor,
+    id: Uuid,
+    params: Json<ExtractParams>,
+) -> ApiResult<Json<ArchiveJob>> {
+    let server = access.server(id)?;
+    let sandbox = server.sandbox(&config).await?;
+    let format = archive_format(&params.path)?;
+    let source = sandbox.resolve(&params.path).await?;
+    if !tokio::fs::metadata(&source).await.is_ok_and(|m| m.is_file()) {
+        return Err(ApiError::not_found(format!("File: {}", params.path)));
+    }
+    let target = sandbox
+        .resolve(params.destination.clone().unwrap_or_default())
+        .await?;
+    audit
+        .entry("archives.extract")
+        .server(server.id)
+        .target(sandbox.relative(&source))
+        .details(serde_json::json!({ "destination": sandbox.relative(&target), "remove": params.remove }))
+        .record()
+        .await;
+
+    Ok(Json(jobs.extract(
+        ArchiveJob::new(server.id, sandbox.relative(&source), sandbox.relative(&target)),
+        Archive::new(&source, format),
+        target,
+        config.archives.clone(),
+        if params.remove { Some(source) } else { None },
+    )))
+}
+
+#[openapi(tag = "Servers", tag = "Archives")]
+#[get("/<id>/archives/jobs")]
+async fn list_jobs(access: ServerAccess<perms::Files>, jobs: ArchiveJobs, id: Uuid) -> ApiResult<Json<Vec<ArchiveJob>>> {
+    let server = access.server(id)?;
+    Ok(Json(jobs.list(server.id)))
+}
+
+#[openapi(tag = "Servers", tag = "Archives")]
+#[get("/<id>/archives/jobs/<job>")]
+async fn get_job(access: ServerAccess<perms::Files>, jobs: ArchiveJobs, id: Uuid, job: Uuid) -> ApiResult<Json<ArchiveJob>> {
+    let server = access.server(id)?;
+    jobs.get(server.id, job.into())
+        .ok_or(ApiError::not_found(format!("Archive job: {job}")))
+        .map(Json)
+}
+
+/// Downloads a directory as a streamed `.tar.gz` archive.
+#[openapi(tag = "Servers", tag = "Archives")]
+#[get("/<id>/archives/download?<path>")]
+async fn download_archive(access: ServerAccess<perms::Files>, config: AppConfig, id: Uuid, path: Option<&str>) -> ApiResult<ArchiveDownload> {
+    let server = access.server(id)?;
+    let sandbox = server.sandbox(&config).await?;
+    let source = sandbox.resolve(path.unwrap_or_default()).await?;
+    if !tokio::fs::metadata(&source).await.is_ok_and(|m| m.is_dir()) {
+        return Err(ApiError::not_found(format!("Directory: {}", path.unwrap_or_default())));
+    }
+
+    let name = source
+        .file_name()
+        .filter(|_| source != sandbox.root())
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or(server.name.clone())
+        .replace(['"', '\\', '/'], "_");
+
+    Ok(ArchiveDownload {
+        stream: ReaderStream::one(ArchiveJobs::stream_directory(source, name.clone())),
+        disposition: Header::new("Content-Disposition", format!("attachment; filename=\"{name}.tar.gz\"")),
+    })
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![
+        upload_archive,
+        extract_archive,
+        list_jobs,
+        get_job,
+        download_archive
+    ]
+}
@@ -0,0 +1,274 @@
+use manor::Model;
+use okapi::openapi3::OpenApi;
+use rocket::{fs::NamedFile, serde::json::Json};
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{
+    ApiError, ApiResult, Error,
+    types::{AppConfig, BackupFormat, BackupTrigger, RetentionPolicy},
+};
+use uuid::Uuid;
+
+use crate::{
+    models::{Auditor, Backup, MinecraftServer, ServerAccess, perms},
+    services::backups::BackupEngine,
+    util::Runners,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct BackupParams {
+    #[serde(default)]
+    pub note: Option<String>,
+
+    #[serde(default)]
+    pub format: BackupFormat,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct RestoreAsNewParams {
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct RetentionSettings {
+    /// Policy set on this server, if any
+    pub server: Option<RetentionPolicy>,
+
+    /// Policy currently in effect
+    pub effective: RetentionPolicy,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct TargetParams {
+    /// Name of a backup target from the configuration, or `null` to keep backups local
+    #[serde(default)]
+    pub target: Option<String>,
+}
+
+#[openapi(tag = "Servers", tag = "Backups")]
+#[get("/<id>/backups")]
+async fn list_backups(access: ServerAccess<perms::Backups>, id: Uuid) -> ApiResult<Json<Vec<Backup>>> {
+    let server = access.server(id)?;
+    Ok(Json(Backup::for_server(server.id).await?))
+}
+
+/// Takes a manual backup, then prunes backups outside the server's retention policy.
+#[openapi(tag = "Servers", tag = "Backups")]
+#[post("/<id>/backups", data = "<params>")]
+async fn create_backup(
+    access: ServerAccess<perms::Backups>,
+    config: AppConfig,
+    runners: Runners,
+    engine: BackupEngine,
+    audit: Auditor,
+    id: Uuid,
+    params: Json<BackupParams>,
+) -> ApiResult<Json<Backup>> {
+    let server = access.server(id)?;
+    let backup = engine
+        .create(&server, &config, &runners, BackupTrigger::Manual, params.format, params.note.clone())
+        .await?;
+    audit.entry("backups.create").server(server.id).target(backup.id).record().await;
+    BackupEngine::apply_retention(&server, &config).await?;
+    Ok(Json(backup))
+}
+
+#[openapi(tag = "Servers", tag = "Backups")]
+#[get("/<id>/backups/<backup>")]
+async fn get_backup(access: ServerAccess<perms::Backups>, id: Uuid, backup: Uuid) -> ApiResult<Json<Backup>> {
+    let server = access.server(id)?;
+    Ok(Json(Backup::get_for(server.id, backup).await?))
+}
+
+#[openapi(tag = "Servers", tag = "Backups")]
+#[delete("/<id>/backups/<backup>")]
+async fn delete_backup(access: ServerAccess<perms::Backups>, config: AppConfig, audit: Auditor, id: Uuid, backup: Uuid) -> ApiResult<()> {
+    let server = access.server(id)?;
+    BackupEngine::delete(Backup::get_for(server.id, backup).await?, &config).await?;
+    audit.entry("backups.delete").server(server.id).target(backup).record().await;
+    Ok(())
+}
+
+#[openapi(tag = "Servers", tag = "Backups")]
+#[get("/<id>/backups/<backup>/download")]
+async fn download_backup(access: ServerAccess<perms::Backups>, config: AppConfig, id: Uuid, backup: Uuid) -> ApiResult<NamedFile> {
+    let server = access.server(id)?;
+    let backup = Backup::get_for(server.id, backup).await?;
+    if backup.format == BackupFormat::Incremental {
+        return Err(ApiError::bad_request("Incremental backups can only be restored, not downloaded"));
+    }
+    NamedFile::open(BackupEngine::fetch(&backup, &config).await?)
+        .await
+        .map_err(|_| ApiError::not_found(format!("Backup archive: {}", backup.id)))
+}
+
+/// Uploads an archive backup to the given target, or the server's target if none is given.
+/// Retrying an interrupted upload resumes it where the target supports it.
+#[openapi(tag = "Servers", tag = "Backups")]
+#[post("/<id>/backups/<backup>/upload", data = "<params>")]
+async fn upload_backup(
+    access: ServerAccess<perms::Backups>,
+    config: AppConfig,
+    engine: BackupEngine,
+    audit: Auditor,
+    id: Uuid,
+    backup: Uuid,
+    params: Json<TargetParams>,
+) -> ApiResult<Json<Backup>> {
+    let server = access.server(id)?;
+    let mut backup = Backup::get_for(server.id, backup).await?;
+    let Some(target) = params.into_inner().target.or(server.backup_target.clone()) else {
+        return Err(ApiError::bad_request("No backup target given or configured for this server"));
+    };
+    engine.upload(&mut backup, &target, &config).await?;
+    audit
+        .entry("backups.upload")
+        .server(server.id)
+        .target(backup.id)
+        .details(serde_json::json!({ "target": target }))
+        .record()
+        .await;
+    Ok(Json(backup))
+}
+
+/// Replaces the (stopped) server's files with a backup, after backing up the current state.
+#[openapi(tag = "Servers", tag = "Backups")]
+#[post("/<id>/backups/<backup>/restore")]
+async fn restore_backup(
+    access: ServerAccess<perms::Backups>,
+    config: AppConfig,
+    runners: Runners,
+    engine: BackupEngine,
+    audit: Auditor,
+    id: Uuid,
+    backup: Uuid,
+) -> ApiResult<()> {
+    let server = access.server(id)?;
+    let backup = Backup::get_for(server.id, backup).await?;
+    engine.restore(&backup, &server, &config, &runners).await?;
+    audit.entry("backups.restore").server(server.id).target(backup.id).record().await;
+    Ok(())
+}
+
+/// Creates a new server from a backup, owned by the current user.
+#[openapi(tag = "Servers", tag = "Backups")]
+#[post("/<id>/backups/<backup>/restore_new", data = "<params>")]
+async fn restore_backup_as_new(
+    access: ServerAccess<perms::Backups>,
+    config: AppConfig,
+    engine: BackupEngine,
+    audit: Auditor,
+    id: Uuid,
+    backup: Uuid,
+    params: Json<RestoreAsNewParams>,
+) -> ApiResult<Json<MinecraftServer>> {
+    let user = access.user.clone();
+    let server = access.server(id)?;
+    let backup = Backup::get_for(server.id, backup).await?;
+    let restored = engine
+        .restore_as_new(&backup, &server, params.name.clone(), user, &config)
+        .await?;
+    audit
+        .entry("backups.restore_new")
+        .server(server.id)
+        .target(backup.id)
+        .details(serde_json::json!({ "new_server": restored.id.to_string() }))
+        .record()
+        .await;
+    Ok(Json(restored))
+}
+
+#[openapi(tag = "Servers", tag = "Backups")]
+#[get("/<id>/backup_retention")]
+async fn get_retention(access: ServerAccess<perms::Backups>, config: AppConfig, id: Uuid) -> ApiResult<Json<RetentionSettings>> {
+    let server = access.server(id)?;
+    Ok(Json(RetentionSettings {
+        server: server.backup_retention.clone(),
+        effective: server.retention(&config),
+    }))
+}
+
+/// Sets the server's retention policy (or clears it with `null`) and prunes backups accordingly.
+#[openapi(tag = "Servers", tag = "Backups")]
+#[put("/<id>/backup_retention", data = "<policy>")]
+async fn set_retention(
+    access: ServerAccess<perms::Backups>,
+    config: AppConfig,
+    audit: Auditor,
+    id: Uuid,
+    policy: Json<Option<RetentionPolicy>>,
+) -> ApiResult<Json<Vec<Backup>>> {
+    let mut server = access.server(id)?;
+    let previous = server.backup_retention.clone();
+    server.backup_retention = policy.into_inner();
+    server
+        .save()
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(format!("{e:?}"))))?;
+    audit
+        .entry("backups.retention")
+        .server(server.id)
+        .diff(&previous, &server.backup_retention)
+        .record()
+        .await;
+    Ok(Json(BackupEngine::apply_retention(&server, &config).await?))
+}
+
+#[openapi(tag = "Servers", tag = "Backups")]
+#[get("/<id>/backup_target")]
+async fn get_target(access: ServerAccess<perms::Backups>, id: Uuid) -> ApiResult<Json<TargetParams>> {
+    let server = access.server(id)?;
+    Ok(Json(TargetParams {
+        target: server.backup_target.clone(),
+    }))
+}
+
+/// Sets where new archive backups of this server are uploaded. Existing backups are not moved.
+#[openapi(tag = "Servers", tag = "Backups")]
+#[put("/<id>/backup_target", data = "<params>")]
+async fn set_target(
+    access: ServerAccess<perms::Backups>,
+    config: AppConfig,
+    audit: Auditor,
+    id: Uuid,
+    params: Json<TargetParams>,
+) -> ApiResult<Json<TargetParams>> {
+    let mut server = access.server(id)?;
+    let params = params.into_inner();
+    if let Some(target) = &params.target
+        && !config.backups.targets.contains_key(target)
+    {
+        return Err(ApiError::bad_request(format!("Unknown backup target: {target}")));
+    }
+    let previous = server.backup_target.clone();
+    server.backup_target = params.target.clone();
+    server
+        .save()
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(format!("{e:?}"))))?;
+    audit
+        .entry("backups.target")
+        .server(server.id)
+        .diff(&previous, &server.backup_target)
+        .record()
+        .await;
+    Ok(Json(params))
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![
+        list_backups,
+        create_backup,
+        get_backup,
+        delete_backup,
+        download_backup,
+        upload_backup,
+        restore_backup,
+        restore_backup_as_new,
+        get_retention,
+        set_retention,
+        get_target,
+        set_target
+    ]
+}
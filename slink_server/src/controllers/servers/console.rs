@@ -0,0 +1,108 @@
+use futures::{SinkExt, StreamExt};
+use okapi::openapi3::OpenApi;
+use rocket::serde::json::Json;
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use rocket_ws as ws;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{ApiError, ApiResult, types::AppConfig};
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::{
+    models::{Auditor, Permission, ServerAccess, perms},
+    util::Runners,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct CommandParams {
+    pub command: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct CommandResult {
+    /// Command output, only available when the command was sent over RCON.
+    pub output: Option<String>,
+}
+
+/// Streams console output as text messages; text messages sent by the client are run as commands, if the user
+/// may write to the console and the command passes their command filter.
+#[openapi(tag = "Servers", tag = "Console")]
+#[get("/<id>/console")]
+async fn console_socket(
+    ws: ws::WebSocket,
+    access: ServerAccess<perms::ConsoleRead>,
+    config: AppConfig,
+    runners: Runners,
+    audit: Auditor,
+    id: Uuid,
+) -> ApiResult<ws::Channel<'static>> {
+    let writable = access.has(Permission::ConsoleWrite) && !access.read_only;
+    let commands = access.commands().await?;
+    let server = access.server(id)?;
+    let properties = server.properties(&config).await;
+    let instance = runners.instance(&server, &config).await?;
+    let mut receiver = instance
+        .subscribe()
+        .await
+        .ok_or(ApiError::invalid_state("Server is not running"))?;
+
+    Ok(ws.channel(move |mut stream| {
+        Box::pin(async move {
+            loop {
+                tokio::select! {
+                    output = receiver.recv() => match output {
+                        Ok(chunk) => stream.send(ws::Message::Text(String::from_utf8_lossy(&chunk).to_string())).await?,
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    },
+                    message = stream.next() => match message {
+                        Some(Ok(ws::Message::Text(_))) if !writable => {
+                            stream.send(ws::Message::Text("Missing permission: console_write".to_string())).await?
+                        }
+                        Some(Ok(ws::Message::Text(command))) if !commands.allows(&command) => {
+                            audit.entry("console.denied").server(server.id).target(command.trim()).record().await;
+                            stream.send(ws::Message::Text(format!("Command not allowed: {}", command.trim()))).await?
+                        }
+                        Some(Ok(ws::Message::Text(command))) => {
+                            audit.entry("console.command").server(server.id).target(command.trim()).record().await;
+                            match instance.execute(command.trim(), &properties).await {
+                                Ok(Some(output)) => stream.send(ws::Message::Text(output)).await?,
+                                Ok(None) => {}
+                                Err(e) => stream.send(ws::Message::Text(e.to_string())).await?,
+                            }
+                        }
+                        Some(Ok(ws::Message::Close(_))) | None | Some(Err(_)) => break,
+                        Some(Ok(_)) => continue,
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }))
+}
+
+/// Runs a console command. Commands refused by the user's command filter fail with `command_denied`.
+#[openapi(tag = "Servers", tag = "Console")]
+#[post("/<id>/console", data = "<params>")]
+async fn send_command(
+    access: ServerAccess<perms::ConsoleWrite>,
+    config: AppConfig,
+    runners: Runners,
+    audit: Auditor,
+    id: Uuid,
+    params: Json<CommandParams>,
+) -> ApiResult<Json<CommandResult>> {
+    access.require_command(&params.command, &audit).await?;
+    let server = access.server(id)?;
+    let properties = server.properties(&config).await;
+    let instance = runners.instance(&server, &config).await?;
+    audit.entry("console.command").server(server.id).target(params.command.trim()).record().await;
+    let output = instance.execute(params.command.trim(), &properties).await?;
+    Ok(Json(CommandResult { output }))
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![console_socket, send_command]
+}
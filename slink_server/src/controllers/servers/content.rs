@@ -0,0 +1,194 @@
+use okapi::openapi3::OpenApi;
+use rocket::serde::json::Json;
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{
+    ApiResult, CONTENT_SEARCH_LIMIT,
+    providers::modrinth::{ProjectVersion, SearchResults},
+    types::{AppConfig, ContentKind, ContentLock, LockedContent, RestrictedContent},
+};
+use uuid::Uuid;
+
+use crate::{
+    models::{Auditor, ServerAccess, perms},
+    services::{
+        content::{ContentChanges, ContentManager},
+        inspection::{self, ContentInspection},
+    },
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct InstallParams {
+    /// Modrinth project ID or slug
+    pub project: String,
+
+    /// Specific version ID to install, instead of the latest compatible one
+    #[serde(default)]
+    pub version: Option<String>,
+
+    #[serde(default)]
+    pub kind: ContentKind,
+}
+
+fn content_kind(kind: Option<&str>) -> ContentKind {
+    match kind {
+        Some("plugin") => ContentKind::Plugin,
+        _ => ContentKind::Mod,
+    }
+}
+
+/// Searches Modrinth for content compatible with the server's Minecraft version and loader.
+/// `kind` is either `mod` (default) or `plugin`.
+#[openapi(tag = "Servers", tag = "Content")]
+#[get("/<id>/content/search?<query>&<kind>&<limit>&<offset>")]
+async fn search_content(
+    access: ServerAccess<perms::View>,
+    config: AppConfig,
+    id: Uuid,
+    query: Option<String>,
+    kind: Option<&str>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+) -> ApiResult<Json<SearchResults>> {
+    let server = access.server(id)?;
+    let kind = content_kind(kind);
+    Ok(Json(
+        ContentManager::client(&config)
+            .search(
+                &query.unwrap_or_default(),
+                kind,
+                &ContentManager::filter(&server, kind)?,
+                limit.unwrap_or(CONTENT_SEARCH_LIMIT),
+                offset.unwrap_or(0),
+            )
+            .await?,
+    ))
+}
+
+/// Lists a project's versions that are compatible with the server, newest first.
+#[openapi(tag = "Servers", tag = "Content")]
+#[get("/<id>/content/projects/<project>/versions?<kind>")]
+async fn list_project_versions(
+    access: ServerAccess<perms::View>,
+    config: AppConfig,
+    id: Uuid,
+    project: &str,
+    kind: Option<&str>,
+) -> ApiResult<Json<Vec<ProjectVersion>>> {
+    let server = access.server(id)?;
+    let filter = ContentManager::filter(&server, content_kind(kind))?;
+    Ok(Json(ContentManager::client(&config).versions(project, &filter).await?))
+}
+
+/// Lists content installed from catalogs, as recorded in the server's lockfile.
+#[openapi(tag = "Servers", tag = "Content")]
+#[get("/<id>/content")]
+async fn list_content(access: ServerAccess<perms::View>, config: AppConfig, id: Uuid) -> ApiResult<Json<Vec<LockedContent>>> {
+    let server = access.server(id)?;
+    Ok(Json(ContentLock::load(server.directory(&config)).await?.content))
+}
+
+/// Reads the metadata of every jar in `mods/` and `plugins/`, including content that was not installed from a
+/// catalog, and flags missing dependencies, client-only mods and mods for other loaders.
+#[openapi(tag = "Servers", tag = "Content")]
+#[get("/<id>/content/inspect")]
+async fn inspect_content(access: ServerAccess<perms::View>, config: AppConfig, id: Uuid) -> ApiResult<Json<ContentInspection>> {
+    let server = access.server(id)?;
+    Ok(Json(inspection::inspect(&server, &config).await?))
+}
+
+/// Lists files from imported packs that could not be downloaded automatically, with the page to download
+/// each from. Upload them to the listed path; uploaded files disappear from the list.
+#[openapi(tag = "Servers", tag = "Content")]
+#[get("/<id>/content/restricted")]
+async fn list_restricted_content(
+    access: ServerAccess<perms::View>,
+    config: AppConfig,
+    manager: ContentManager,
+    id: Uuid,
+) -> ApiResult<Json<Vec<RestrictedContent>>> {
+    let server = access.server(id)?;
+    Ok(Json(manager.restricted(&server, &config).await?))
+}
+
+/// Installs a project and its required dependencies into `mods/` or `plugins/`.
+#[openapi(tag = "Servers", tag = "Content")]
+#[post("/<id>/content", data = "<params>")]
+async fn install_content(
+    access: ServerAccess<perms::Files>,
+    config: AppConfig,
+    manager: ContentManager,
+    audit: Auditor,
+    id: Uuid,
+    params: Json<InstallParams>,
+) -> ApiResult<Json<ContentChanges>> {
+    let server = access.server(id)?;
+    let changes = manager
+        .install(&server, &config, params.kind, &params.project, params.version.as_deref())
+        .await?;
+    audit.entry("content.install").server(server.id).target(&params.project).details(&changes).record().await;
+    Ok(Json(changes))
+}
+
+/// Updates all installed content to the latest compatible versions.
+#[openapi(tag = "Servers", tag = "Content")]
+#[post("/<id>/content/update")]
+async fn update_all_content(
+    access: ServerAccess<perms::Files>,
+    config: AppConfig,
+    manager: ContentManager,
+    audit: Auditor,
+    id: Uuid,
+) -> ApiResult<Json<ContentChanges>> {
+    let server = access.server(id)?;
+    let changes = manager.update(&server, &config, None).await?;
+    audit.entry("content.update").server(server.id).details(&changes).record().await;
+    Ok(Json(changes))
+}
+
+#[openapi(tag = "Servers", tag = "Content")]
+#[post("/<id>/content/<project>/update")]
+async fn update_content(
+    access: ServerAccess<perms::Files>,
+    config: AppConfig,
+    manager: ContentManager,
+    audit: Auditor,
+    id: Uuid,
+    project: &str,
+) -> ApiResult<Json<ContentChanges>> {
+    let server = access.server(id)?;
+    let changes = manager.update(&server, &config, Some(project)).await?;
+    audit.entry("content.update").server(server.id).target(project).details(&changes).record().await;
+    Ok(Json(changes))
+}
+
+#[openapi(tag = "Servers", tag = "Content")]
+#[delete("/<id>/content/<project>")]
+async fn remove_content(
+    access: ServerAccess<perms::Files>,
+    config: AppConfig,
+    manager: ContentManager,
+    audit: Auditor,
+    id: Uuid,
+    project: &str,
+) -> ApiResult<Json<LockedContent>> {
+    let server = access.server(id)?;
+    let removed = manager.remove(&server, &config, project).await?;
+    audit.entry("content.remove").server(server.id).target(project).details(&removed).record().await;
+    Ok(Json(removed))
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![
+        search_content,
+        list_project_versions,
+        list_content,
+        list_restricted_content,
+        inspect_content,
+        install_content,
+        update_all_content,
+        update_content,
+        remove_content
+    ]
+}
@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+
+use okapi::openapi3::OpenApi;
+use rocket::{Data, fs::NamedFile, serde::json::Json};
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{
+    ApiError, ApiResult, Error, MAX_TEXT_FILE_SIZE,
+    types::AppConfig,
+    utilities::{FileEntry, FileKind, Sandbox},
+};
+use uuid::Uuid;
+
+use crate::{
+    models::{Auditor, ServerAccess, perms},
+    util::UploadLimit,
+};
+
+fn io_error(path: &str, error: std::io::Error) -> ApiError {
+    if error.kind() == std::io::ErrorKind::NotFound {
+        ApiError::not_found(format!("File: {path}"))
+    } else {
+        Error::path(path, error).into()
+    }
+}
+
+async fn sandbox(access: ServerAccess<perms::Files>, config: &AppConfig, id: Uuid) -> ApiResult<Sandbox> {
+    access.server(id)?.sandbox(config).await
+}
+
+/// Keys and values of a `.properties` file, so that edits to server configuration are audited as a diff.
+fn properties_map(content: &str) -> BTreeMap<String, String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct TextFile {
+    pub path: String,
+    pub content: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct RenameParams {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct DirectoryParams {
+    pub path: String,
+}
+
+#[openapi(tag = "Servers", tag = "Files")]
+#[get("/<id>/files?<path>")]
+async fn list_files(access: ServerAccess<perms::Files>, config: AppConfig, id: Uuid, path: Option<&str>) -> ApiResult<Json<Vec<FileEntry>>> {
+    let sandbox = sandbox(access, &config, id).await?;
+    let path = path.unwrap_or_default();
+    if !tokio::fs::metadata(sandbox.resolve(path).await?).await.is_ok_and(|m| m.is_dir()) {
+        return Err(ApiError::not_found(format!("Directory: {path}")));
+    }
+    Ok(Json(sandbox.list(path).await?))
+}
+
+#[openapi(tag = "Servers", tag = "Files")]
+#[get("/<id>/files/content?<path>")]
+async fn read_file(access: ServerAccess<perms::Files>, config: AppConfig, id: Uuid, path: &str) -> ApiResult<Json<TextFile>> {
+    let sandbox = sandbox(access, &config, id).await?;
+    let target = sandbox.resolve(path).await?;
+    let metadata = tokio::fs::metadata(&target).await.map_err(|e| io_error(path, e))?;
+    if !metadata.is_file() {
+        return Err(ApiError::bad_request(format!("{path} is not a file")));
+    }
+    if metadata.len() > MAX_TEXT_FILE_SIZE {
+        return Err(ApiError::bad_request(format!("{path} is too large to edit; download it instead")));
+    }
+
+    let bytes = tokio::fs::read(&target).await.map_err(|e| io_error(path, e))?;
+    let content = String::from_utf8(bytes).map_err(|_| ApiError::bad_request(format!("{path} is not a text file")))?;
+    Ok(Json(TextFile { path: sandbox.relative(&target), content }))
+}
+
+#[openapi(tag = "Servers", tag = "Files")]
+#[put("/<id>/files/content", data = "<file>")]
+async fn write_file(access: ServerAccess<perms::Files>, config: AppConfig, audit: Auditor, id: Uuid, file: Json<TextFile>) -> ApiResult<Json<FileEntry>> {
+    let sandbox = sandbox(access, &config, id).await?;
+    let target = sandbox.resolve(&file.path).await?;
+    if tokio::fs::metadata(&target).await.is_ok_and(|m| m.is_dir()) {
+        return Err(ApiError::bad_request(format!("{} is a directory", file.path)));
+    }
+    let previous = match file.path.ends_with(".properties") {
+        true => Some(tokio::fs::read_to_string(&target).await.unwrap_or_default()),
+        false => None,
+    };
+
+    tokio::fs::write(&target, file.content.as_bytes()).await.map_err(|e| io_error(&file.path, e))?;
+    let mut entry = audit.entry("files.write").server(id).target(sandbox.relative(&target));
+    if let Some(previous) = previous {
+        entry = entry.diff(&properties_map(&previous), &properties_map(&file.content));
+    }
+    entry.record().await;
+    Ok(Json(sandbox.entry(&target).await?))
+}
+
+/// Streams the request body into a file, replacing it only once the upload has completed.
+#[openapi(tag = "Servers", tag = "Files")]
+#[post("/<id>/files/upload?<path>", data = "<data>")]
+async fn upload_file(
+    access: ServerAccess<perms::Files>,
+    config: AppConfig,
+    limit: UploadLimit,
+    audit: Auditor,
+    id: Uuid,
+    path: &str,
+    data: Data<'_>,
+) -> ApiResult<Json<FileEntry>> {
+    let sandbox = sandbox(access, &config, id).await?;
+    let target = sandbox.resolve(path).await?;
+    let name = target
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or(ApiError::bad_request("Uploads require a file name"))?;
+    if tokio::fs::metadata(&target).await.is_ok_and(|m| m.is_dir()) {
+        return Err(ApiError::bad_request(format!("{path} is a directory")));
+    }
+
+    let temporary = target.with_file_name(format!(".{name}.upload-{}", Uuid::new_v4()));
+    let written = data.open(limit.0).into_file(&temporary).await.map_err(|e| io_error(path, e))?;
+    if !written.is_complete() {
+        let _ = tokio::fs::remove_file(&temporary).await;
+        return Err(ApiError::bad_request(format!("Upload exceeds the limit of {}", limit.0)));
+    }
+
+    tokio::fs::rename(&temporary, &target).await.map_err(|e| io_error(path, e))?;
+    audit.entry("files.upload").server(id).target(sandbox.relative(&target)).record().await;
+    Ok(Json(sandbox.entry(&target).await?))
+}
+
+#[openapi(tag = "Servers", tag = "Files")]
+#[get("/<id>/files/download?<path>")]
+async fn download_file(access: ServerAccess<perms::Files>, config: AppConfig, id: Uuid, path: &str) -> ApiResult<NamedFile> {
+    let sandbox = sandbox(access, &config, id).await?;
+    let target = sandbox.resolve(path).await?;
+    if !tokio::fs::metadata(&target).await.map_err(|e| io_error(path, e))?.is_file() {
+        return Err(ApiError::bad_request(format!("{path} is not a file")));
+    }
+    NamedFile::open(&target).await.map_err(|e| io_error(path, e))
+}
+
+#[openapi(tag = "Servers", tag = "Files")]
+#[post("/<id>/files/rename", data = "<params>")]
+async fn rename_file(access: ServerAccess<perms::Files>, config: AppConfig, audit: Auditor, id: Uuid, params: Json<RenameParams>) -> ApiResult<Json<FileEntry>> {
+    let sandbox = sandbox(access, &config, id).await?;
+    let source = sandbox.resolve_entry(&params.from).await?;
+    let destination = sandbox.resolve_entry(&params.to).await?;
+    tokio::fs::symlink_metadata(&source).await.map_err(|e| io_error(&params.from, e))?;
+    if tokio::fs::symlink_metadata(&destination).await.is_ok() {
+        return Err(ApiError::invalid_state(format!("{} already exists", params.to)));
+    }
+    if destination.starts_with(&source) {
+        return Err(ApiError::bad_request("Cannot move a directory into itself"));
+    }
+
+    tokio::fs::rename(&source, &destination).await.map_err(|e| io_error(&params.from, e))?;
+    audit
+        .entry("files.rename")
+        .server(id)
+        .target(sandbox.relative(&source))
+        .details(serde_json::json!({ "to": sandbox.relative(&destination) }))
+        .record()
+        .await;
+    Ok(Json(sandbox.entry(&destination).await?))
+}
+
+/// Deletes a file, symlink or (with `recursive`) a directory. Symlinks are removed, never their targets.
+#[openapi(tag = "Servers", tag = "Files")]
+#[delete("/<id>/files?<path>&<recursive>")]
+async fn delete_file(
+    access: ServerAccess<perms::Files>,
+    config: AppConfig,
+    audit: Auditor,
+    id: Uuid,
+    path: &str,
+    recursive: Option<bool>,
+) -> ApiResult<()> {
+    let sandbox = sandbox(access, &config, id).await?;
+    let target = sandbox.resolve_entry(path).await?;
+    let entry = sandbox.entry(&target).await.map_err(|_| ApiError::not_found(format!("File: {path}")))?;
+
+    match entry.kind {
+        FileKind::Directory if recursive.unwrap_or(false) => tokio::fs::remove_dir_all(&target).await,
+        FileKind::Directory => tokio::fs::remove_dir(&target).await,
+        FileKind::File | FileKind::Symlink => tokio::fs::remove_file(&target).await,
+    }
+    .map_err(|e| io_error(path, e))?;
+    audit.entry("files.delete").server(id).target(&entry.path).record().await;
+    Ok(())
+}
+
+#[openapi(tag = "Servers", tag = "Files")]
+#[post("/<id>/files/directory", data = "<params>")]
+async fn create_directory(
+    access: ServerAccess<perms::Files>,
+    config: AppConfig,
+    audit: Auditor,
+    id: Uuid,
+    params: Json<DirectoryParams>,
+) -> ApiResult<Json<FileEntry>> {
+    let sandbox = sandbox(access, &config, id).await?;
+    let target = sandbox.resolve(&params.path).await?;
+    tokio::fs::create_dir_all(&target).await.map_err(|e| io_error(&params.path, e))?;
+    audit.entry("files.create_directory").server(id).target(sandbox.relative(&target)).record().await;
+    Ok(Json(sandbox.entry(&target).await?))
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![
+        list_files,
+        read_file,
+        write_file,
+        upload_file,
+        download_file,
+        rename_file,
+        delete_file,
+        create_directory
+    ]
+}
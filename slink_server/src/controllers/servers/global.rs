@@ -8,7 +8,7 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use slink_common::{providers::servers::ServerBinaryVersion, types::MinecraftVersion, ApiError, ApiResult, Error};
 
-use crate::{models::{MinecraftServer, MinecraftServerBuilder, User}, util::Docs};
+use crate::{models::{Auditor, MinecraftServer, Permission, ServerGrant, User}, util::Docs};
 
 #[openapi(tag = "Servers", tag = "GlobalServers")]
 #[get("/owned")]
@@ -19,6 +19,23 @@ async fn get_owned_servers(user: User, servers: Docs<MinecraftServer>) -> ApiRes
     Ok(Json(results))
 }
 
+/// Servers other users have shared with the current user.
+#[openapi(tag = "Servers", tag = "GlobalServers")]
+#[get("/shared")]
+async fn get_shared_servers(user: User, servers: Docs<MinecraftServer>) -> ApiResult<Json<Vec<MinecraftServer>>> {
+    let ids: Vec<bson::Uuid> = ServerGrant::for_user(user.id).await?.into_iter().map(|g| g.server).collect();
+    let cursor = servers.find_many(doc! {"_id": {"$in": ids}, "owner.id": {"$ne": user.id}}).await.or_else(|e| Err(ApiError::from(Error::Unexpected(e.to_string()))))?;
+    let candidates = cursor.try_collect::<Vec<MinecraftServer>>().await.or_else(|e| Err(ApiError::from(Error::Unexpected(e.to_string()))))?;
+
+    let mut results = Vec::new();
+    for server in candidates {
+        if server.permissions(&user).await?.contains(&Permission::View) {
+            results.push(server);
+        }
+    }
+    Ok(Json(results))
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 struct ServerCreationParams {
     pub name: String,
@@ -30,7 +47,7 @@ struct ServerCreationParams {
 
 #[openapi(tag = "Servers", tag = "GlobalServers")]
 #[post("/create", data = "<create>")]
-async fn create_server(user: User, create: Json<ServerCreationParams>) -> ApiResult<Json<MinecraftServer>> {
+async fn create_server(user: User, audit: Auditor, create: Json<ServerCreationParams>) -> ApiResult<Json<MinecraftServer>> {
     let params = create.into_inner();
     let minecraft_version = match MinecraftVersion::from_id(params.minecraft_version.clone()).await {
         Ok(Some(version)) => match version.metadata().await {
@@ -41,14 +58,10 @@ async fn create_server(user: User, create: Json<ServerCreationParams>) -> ApiRes
         Err(e) => {return Err(ApiError::from(e));}
     };
 
-    let mut server_builder = &mut MinecraftServerBuilder::default();
-    server_builder = server_builder.name(params.name.clone()).minecraft_version(minecraft_version).owner(user.clone());
-    if let Some(modloader) = params.mod_loader.clone() {
-        server_builder = server_builder.modloader_version(modloader);
-    }
-    let new_server = server_builder.build().or_else(|e| Err(ApiError::from(Error::Unexpected(e.to_string()))))?;
-    
+    let new_server = MinecraftServer::create(params.name.clone(), user.clone(), minecraft_version, params.mod_loader.clone());
+
     new_server.save().await.or_else(|e| Err(ApiError::from(Error::Unexpected(e.to_string()))))?;
+    audit.entry("server.create").server(new_server.id).target(&new_server.name).record().await;
 
     Ok(Json(new_server))
 }
@@ -56,6 +69,7 @@ async fn create_server(user: User, create: Json<ServerCreationParams>) -> ApiRes
 pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
     openapi_get_routes_spec![
         get_owned_servers,
+        get_shared_servers,
         create_server
     ]
 }
@@ -0,0 +1,185 @@
+use std::path::PathBuf;
+
+use okapi::openapi3::OpenApi;
+use rocket::{Data, serde::json::Json};
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{ApiError, ApiResult, Error, types::AppConfig, utilities::ArchiveFormat};
+use uuid::Uuid;
+
+use crate::{
+    models::{Auditor, User},
+    services::{
+        imports::{ImportJob, ImportJobs},
+        modpacks,
+    },
+    util::UploadLimit,
+};
+
+/// Streams an uploaded pack next to the server directories, for an import job to pick up.
+async fn receive(data: Data<'_>, limit: UploadLimit, config: &AppConfig, extension: &str) -> ApiResult<PathBuf> {
+    let upload = config
+        .runner
+        .server_directory(format!(".upload-{}.{extension}", Uuid::new_v4()));
+    let written = data
+        .open(limit.0)
+        .into_file(&upload)
+        .await
+        .map_err(|e| ApiError::from(Error::path(upload.display(), e)))?;
+    if !written.is_complete() {
+        let _ = tokio::fs::remove_file(&upload).await;
+        return Err(ApiError::bad_request(format!("Upload exceeds the limit of {}", limit.0)));
+    }
+    Ok(upload)
+}
+
+/// Audits the start of an import. The server it creates is only known once the job has finished.
+async fn record(audit: &Auditor, job: &ImportJob, kind: &str) {
+    audit
+        .entry("servers.import")
+        .target(&job.source)
+        .details(serde_json::json!({ "job": job.id.to_string(), "kind": kind }))
+        .record()
+        .await;
+}
+
+/// Creates a new server from a Modrinth `.mrpack` file, in the background. Poll the returned job for the
+/// created server and a report of skipped or unsupported files.
+#[openapi(tag = "Servers", tag = "Imports")]
+#[post("/import/mrpack?<name>", data = "<data>")]
+async fn import_mrpack(
+    user: User,
+    config: AppConfig,
+    jobs: ImportJobs,
+    audit: Auditor,
+    limit: UploadLimit,
+    name: Option<String>,
+    data: Data<'_>,
+) -> ApiResult<Json<ImportJob>> {
+    let upload = receive(data, limit, &config, "mrpack").await?;
+    let job = ImportJob::new(user.id, name.clone().unwrap_or(String::from("modpack.mrpack")));
+    record(&audit, &job, "mrpack").await;
+    Ok(Json(jobs.spawn(job, modpacks::import_mrpack(upload, name, user, config))))
+}
+
+/// Creates a new server from a CurseForge pack, in the background. Client packs (with a `manifest.json`)
+/// need `content.curseforge_api_key` to be configured; server packs are used as they are. Files that cannot be
+/// downloaded automatically are listed in the report and under `/servers/<id>/content/restricted`.
+#[openapi(tag = "Servers", tag = "Imports")]
+#[post("/import/curseforge?<name>", data = "<data>")]
+async fn import_curseforge(
+    user: User,
+    config: AppConfig,
+    jobs: ImportJobs,
+    audit: Auditor,
+    limit: UploadLimit,
+    name: Option<String>,
+    data: Data<'_>,
+) -> ApiResult<Json<ImportJob>> {
+    let upload = receive(data, limit, &config, "zip").await?;
+    let job = ImportJob::new(user.id, name.clone().unwrap_or(String::from("curseforge.zip")));
+    record(&audit, &job, "curseforge").await;
+    Ok(Json(jobs.spawn(job, modpacks::import_curseforge(upload, name, user, config))))
+}
+
+/// Registers an uploaded server directory (`.zip` by default, or `format=tar_gz`) as a new server, in the
+/// background. The Minecraft version and loader are detected unless `version` is given.
+#[openapi(tag = "Servers", tag = "Imports")]
+#[post("/import/archive?<name>&<version>&<format>", data = "<data>")]
+#[allow(clippy::too_many_arguments)]
+async fn import_archive(
+    user: User,
+    config: AppConfig,
+    jobs: ImportJobs,
+    audit: Auditor,
+    limit: UploadLimit,
+    name: Option<String>,
+    version: Option<String>,
+    format: Option<&str>,
+    data: Data<'_>,
+) -> ApiResult<Json<ImportJob>> {
+    let format = match format {
+        None | Some("zip") => ArchiveFormat::Zip,
+        Some("tar_gz") => ArchiveFormat::TarGz,
+        Some(other) => return Err(ApiError::bad_request(format!("Unknown archive format: {other}"))),
+    };
+    let extension = match format {
+        ArchiveFormat::Zip => "zip",
+        ArchiveFormat::TarGz => "tar.gz",
+    };
+    let upload = receive(data, limit, &config, extension).await?;
+    let job = ImportJob::new(user.id, name.clone().unwrap_or(format!("server.{extension}")));
+    record(&audit, &job, "archive").await;
+    Ok(Json(jobs.spawn(
+        job,
+        modpacks::import_archive(upload, format, name, version, user, config),
+    )))
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct DirectoryImportParams {
+    /// Absolute path of the server directory on the Slink host. It is copied, not moved.
+    pub path: String,
+
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Minecraft version, if it cannot be detected
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+/// Registers an existing server directory on the host as a new server, in the background. Superuser only.
+#[openapi(tag = "Servers", tag = "Imports")]
+#[post("/import/directory", data = "<params>")]
+async fn import_directory(
+    user: User,
+    config: AppConfig,
+    jobs: ImportJobs,
+    audit: Auditor,
+    params: Json<DirectoryImportParams>,
+) -> ApiResult<Json<ImportJob>> {
+    if !user.superuser {
+        return Err(ApiError::missing_auth("Superuser"));
+    }
+    let params = params.into_inner();
+    let path = PathBuf::from(&params.path);
+    if !path.is_absolute() {
+        return Err(ApiError::bad_request("The directory path must be absolute"));
+    }
+    if !tokio::fs::metadata(&path).await.is_ok_and(|m| m.is_dir()) {
+        return Err(ApiError::not_found(format!("Directory: {}", params.path)));
+    }
+
+    let job = ImportJob::new(user.id, params.path.clone());
+    record(&audit, &job, "directory").await;
+    Ok(Json(jobs.spawn(job, async move {
+        modpacks::import_server_directory(path, params.name, params.version, user, &config).await
+    })))
+}
+
+#[openapi(tag = "Servers", tag = "Imports")]
+#[get("/import/jobs")]
+async fn list_import_jobs(user: User, jobs: ImportJobs) -> Json<Vec<ImportJob>> {
+    Json(jobs.list(user.id))
+}
+
+#[openapi(tag = "Servers", tag = "Imports")]
+#[get("/import/jobs/<job>")]
+async fn get_import_job(user: User, jobs: ImportJobs, job: Uuid) -> ApiResult<Json<ImportJob>> {
+    jobs.get(user.id, job.into())
+        .map(Json)
+        .ok_or(ApiError::not_found(format!("Import job: {job}")))
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![
+        import_mrpack,
+        import_curseforge,
+        import_archive,
+        import_directory,
+        list_import_jobs,
+        get_import_job
+    ]
+}
@@ -0,0 +1,104 @@
+use okapi::openapi3::OpenApi;
+use rocket::serde::json::Json;
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use manor::Model;
+use slink_common::{
+    ApiError, ApiResult, Error,
+    runners::{MinecraftRunner, MinecraftRunnerStatus, MinecraftServerStatus},
+    types::AppConfig,
+};
+use uuid::Uuid;
+
+use crate::{
+    models::{Auditor, MinecraftServer, ScheduledTask, ServerAccess, ServerGrant, perms},
+    util::Runners,
+};
+
+#[openapi(tag = "Servers", tag = "ServerInstance")]
+#[get("/<id>")]
+async fn get_server(access: ServerAccess<perms::View>, id: Uuid) -> ApiResult<Json<MinecraftServer>> {
+    Ok(Json(access.server(id)?))
+}
+
+#[openapi(tag = "Servers", tag = "ServerInstance")]
+#[get("/<id>/status")]
+async fn get_server_status(
+    access: ServerAccess<perms::View>,
+    config: AppConfig,
+    runners: Runners,
+    id: Uuid,
+) -> ApiResult<Json<MinecraftServerStatus>> {
+    let server = access.server(id)?;
+    let instance = runners.instance(&server, &config).await?;
+    let status = instance.runner.lock().await.server_status().await;
+    Ok(Json(status))
+}
+
+#[openapi(tag = "Servers", tag = "ServerInstance")]
+#[post("/<id>/start")]
+async fn start_server(
+    access: ServerAccess<perms::Power>,
+    config: AppConfig,
+    runners: Runners,
+    audit: Auditor,
+    id: Uuid,
+) -> ApiResult<Json<MinecraftRunnerStatus>> {
+    let server = access.server(id)?;
+    let instance = runners.prepare(&server, &config).await?;
+    let status = instance.start(&server).await?;
+    audit.entry("server.start").server(server.id).record().await;
+    Ok(Json(status))
+}
+
+#[openapi(tag = "Servers", tag = "ServerInstance")]
+#[post("/<id>/stop")]
+async fn stop_server(
+    access: ServerAccess<perms::Power>,
+    config: AppConfig,
+    runners: Runners,
+    audit: Auditor,
+    id: Uuid,
+) -> ApiResult<Json<MinecraftRunnerStatus>> {
+    let server = access.server(id)?;
+    let instance = runners.instance(&server, &config).await?;
+    let status = instance.stop().await?;
+    audit.entry("server.stop").server(server.id).record().await;
+    Ok(Json(status))
+}
+
+/// Deletes a stopped server with its files, scheduled tasks and grants. Backups are kept.
+#[openapi(tag = "Servers", tag = "ServerInstance")]
+#[delete("/<id>")]
+async fn delete_server(
+    access: ServerAccess<perms::Delete>,
+    config: AppConfig,
+    runners: Runners,
+    audit: Auditor,
+    id: Uuid,
+) -> ApiResult<()> {
+    let server = access.server(id)?;
+    if let Some(instance) = runners.get(&server.id).await
+        && instance.runner.lock().await.status().await.running()
+    {
+        return Err(ApiError::invalid_state("Server must be stopped before it is deleted"));
+    }
+
+    for task in ScheduledTask::for_server(server.id).await? {
+        task.delete().await.map_err(|e| ApiError::from(Error::Unexpected(format!("{e:?}"))))?;
+    }
+    ServerGrant::revoke_all(server.id).await?;
+    runners.remove(&server.id).await;
+
+    let directory = server.directory(&config);
+    let entry = audit.entry("server.delete").server(server.id).target(&server.name);
+    server.delete().await.map_err(|e| ApiError::from(Error::Unexpected(format!("{e:?}"))))?;
+    entry.record().await;
+    match tokio::fs::remove_dir_all(&directory).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::path(directory.display(), e).into()),
+        _ => Ok(()),
+    }
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![get_server, get_server_status, start_server, stop_server, delete_server]
+}
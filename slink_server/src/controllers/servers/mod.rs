@@ -1 +1,12 @@
-pub mod global;
\ No newline at end of file
+pub mod archives;
+pub mod backups;
+pub mod console;
+pub mod content;
+pub mod files;
+pub mod global;
+pub mod imports;
+pub mod instance;
+pub mod players;
+pub mod schedules;
+pub mod sharing;
+pub mod templates;
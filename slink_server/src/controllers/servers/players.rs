@@ -0,0 +1,338 @@
+use std::{net::IpAddr, path::PathBuf};
+
+use bson::doc;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use okapi::openapi3::OpenApi;
+use rocket::serde::json::Json;
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{
+    ApiError, ApiResult, Error,
+    providers::profiles::{PlayerProfile, resolver_for},
+    runners::MinecraftRunner,
+    types::{
+        AppConfig, BanDetails, BannedIpEntry, BannedPlayerEntry, OperatorEntry, PermissionLevel,
+        PlayerList, PlayerListEntry, ServerProperties, WhitelistEntry,
+    },
+};
+use uuid::Uuid;
+
+use crate::{
+    models::{Auditor, OnlinePlayer, PlayerRecord, PlayerSession, RequiredPermission, ServerAccess, perms},
+    util::{Docs, Runners, ServerInstance},
+};
+
+/// A server's player lists, changed through the console while it runs. Console changes are subject to the
+/// user's console permission and command filter.
+struct PlayerContext<P: RequiredPermission> {
+    access: ServerAccess<P>,
+    directory: PathBuf,
+    properties: ServerProperties,
+    instance: Option<ServerInstance>,
+}
+
+impl<P: RequiredPermission> PlayerContext<P> {
+    async fn load(access: ServerAccess<P>, id: Uuid, config: &AppConfig, runners: &Runners) -> ApiResult<Self> {
+        let server = access.server_ref(id)?;
+        let mut instance = runners.get(&server.id).await;
+        if let Some(existing) = &instance
+            && !existing.runner.lock().await.status().await.running()
+        {
+            instance = None;
+        }
+
+        Ok(Self {
+            directory: server.directory(config),
+            properties: server.properties(config).await,
+            instance,
+            access,
+        })
+    }
+
+    fn running(&self) -> bool {
+        self.instance.is_some()
+    }
+
+    async fn resolve(&self, name: &str) -> ApiResult<PlayerProfile> {
+        if name.is_empty() || name.len() > 16 || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(Error::value_error(name, "Not a valid Minecraft username").into());
+        }
+
+        resolver_for(&self.properties)
+            .resolve(name)
+            .await?
+            .ok_or(ApiError::not_found(format!("Player: {name}")))
+    }
+
+    async fn list<T: PlayerListEntry>(&self) -> ApiResult<PlayerList<T>> {
+        Ok(PlayerList::<T>::load(&self.directory).await?)
+    }
+
+    /// Applies a change through the console if the server is running, or directly to the list file otherwise.
+    async fn apply<T: PlayerListEntry>(&self, audit: &Auditor, command: String, update: impl FnOnce(&mut PlayerList<T>)) -> ApiResult<()> {
+        if let Some(instance) = &self.instance {
+            self.access.require_command(&command, audit).await?;
+            instance.execute(command, &self.properties).await?;
+        } else {
+            let mut list = self.list::<T>().await?;
+            update(&mut list);
+            list.save(&self.directory).await?;
+        }
+        Ok(())
+    }
+
+    async fn remove<T: PlayerListEntry>(&self, audit: &Auditor, query: &str, command: impl FnOnce(&T) -> String) -> ApiResult<()> {
+        let existing = self
+            .list::<T>()
+            .await?
+            .find(query)
+            .ok_or(ApiError::not_found(query.to_string()))?;
+        self.apply::<T>(audit, command(&existing), |list| {
+            list.remove(existing.key());
+        })
+        .await
+    }
+}
+
+fn sanitize_reason(reason: &Option<String>) -> Option<String> {
+    reason
+        .clone()
+        .map(|r| r.replace(['\r', '\n'], " ").trim().to_string())
+        .filter(|r| !r.is_empty())
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct PlayerParams {
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct OperatorParams {
+    pub name: String,
+
+    /// Only configurable while the server is stopped; defaults to the server's `op-permission-level`.
+    #[serde(default)]
+    pub level: Option<PermissionLevel>,
+
+    #[serde(default)]
+    pub bypasses_player_limit: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct PlayerBanParams {
+    pub name: String,
+
+    #[serde(default)]
+    pub reason: Option<String>,
+
+    /// Only configurable while the server is stopped; bans are permanent otherwise.
+    #[serde(default)]
+    pub expires: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct IpBanParams {
+    #[schemars(with = "String")]
+    pub ip: IpAddr,
+
+    #[serde(default)]
+    pub reason: Option<String>,
+
+    /// Only configurable while the server is stopped; bans are permanent otherwise.
+    #[serde(default)]
+    pub expires: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct PlayerOverview {
+    pub online: Vec<OnlinePlayer>,
+    pub history: Vec<PlayerRecord>,
+}
+
+#[openapi(tag = "Servers", tag = "Players")]
+#[get("/<id>/players")]
+async fn get_players(access: ServerAccess<perms::View>, runners: Runners, records: Docs<PlayerRecord>, id: Uuid) -> ApiResult<Json<PlayerOverview>> {
+    let server = access.server(id)?;
+    let mut online: Vec<OnlinePlayer> = match runners.get(&server.id).await {
+        Some(instance) => instance.players.read().await.values().cloned().collect(),
+        None => Vec::new(),
+    };
+    online.sort_by_key(|p| p.joined);
+
+    let cursor = records.find_many(doc! {"server": server.id}).await.map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+    let history = cursor.try_collect::<Vec<PlayerRecord>>().await.map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+
+    Ok(Json(PlayerOverview { online, history }))
+}
+
+#[openapi(tag = "Servers", tag = "Players")]
+#[get("/<id>/players/<player>/sessions")]
+async fn get_player_sessions(access: ServerAccess<perms::View>, sessions: Docs<PlayerSession>, id: Uuid, player: &str) -> ApiResult<Json<Vec<PlayerSession>>> {
+    let server = access.server(id)?;
+    let cursor = sessions.find_many(doc! {"server": server.id, "name": player}).await.map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+    let mut results = cursor.try_collect::<Vec<PlayerSession>>().await.map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+    results.sort_by_key(|s| std::cmp::Reverse(s.joined));
+
+    Ok(Json(results))
+}
+
+#[openapi(tag = "Servers", tag = "Players")]
+#[get("/<id>/whitelist")]
+async fn get_whitelist(access: ServerAccess<perms::View>, config: AppConfig, runners: Runners, id: Uuid) -> ApiResult<Json<Vec<WhitelistEntry>>> {
+    let context = PlayerContext::load(access, id, &config, &runners).await?;
+    Ok(Json(context.list::<WhitelistEntry>().await?.entries))
+}
+
+#[openapi(tag = "Servers", tag = "Players")]
+#[post("/<id>/whitelist", data = "<params>")]
+async fn add_to_whitelist(access: ServerAccess<perms::Properties>, config: AppConfig, runners: Runners, audit: Auditor, id: Uuid, params: Json<PlayerParams>) -> ApiResult<Json<WhitelistEntry>> {
+    let context = PlayerContext::load(access, id, &config, &runners).await?;
+    let profile = context.resolve(&params.name).await?;
+    let entry = WhitelistEntry { uuid: profile.id, name: profile.name.clone() };
+
+    context.apply::<WhitelistEntry>(&audit, format!("whitelist add {}", profile.name), |list| list.upsert(entry.clone())).await?;
+    audit.entry("players.whitelist.add").server(id).target(&entry.name).record().await;
+    Ok(Json(entry))
+}
+
+#[openapi(tag = "Servers", tag = "Players")]
+#[delete("/<id>/whitelist/<player>")]
+async fn remove_from_whitelist(access: ServerAccess<perms::Properties>, config: AppConfig, runners: Runners, audit: Auditor, id: Uuid, player: &str) -> ApiResult<()> {
+    let context = PlayerContext::load(access, id, &config, &runners).await?;
+    context.remove::<WhitelistEntry>(&audit, player, |e| format!("whitelist remove {}", e.name)).await?;
+    audit.entry("players.whitelist.remove").server(id).target(player).record().await;
+    Ok(())
+}
+
+#[openapi(tag = "Servers", tag = "Players")]
+#[get("/<id>/operators")]
+async fn get_operators(access: ServerAccess<perms::View>, config: AppConfig, runners: Runners, id: Uuid) -> ApiResult<Json<Vec<OperatorEntry>>> {
+    let context = PlayerContext::load(access, id, &config, &runners).await?;
+    Ok(Json(context.list::<OperatorEntry>().await?.entries))
+}
+
+#[openapi(tag = "Servers", tag = "Players")]
+#[post("/<id>/operators", data = "<params>")]
+async fn add_operator(access: ServerAccess<perms::Properties>, config: AppConfig, runners: Runners, audit: Auditor, id: Uuid, params: Json<OperatorParams>) -> ApiResult<Json<OperatorEntry>> {
+    let context = PlayerContext::load(access, id, &config, &runners).await?;
+    let default_level = context.properties.op_permission_level.clone();
+    if context.running() && (params.bypasses_player_limit || params.level.as_ref().is_some_and(|l| *l != default_level)) {
+        return Err(ApiError::invalid_state("Custom operator settings can only be applied while the server is stopped"));
+    }
+
+    let profile = context.resolve(&params.name).await?;
+    let entry = OperatorEntry {
+        uuid: profile.id,
+        name: profile.name.clone(),
+        level: params.level.clone().unwrap_or(default_level),
+        bypasses_player_limit: params.bypasses_player_limit,
+    };
+
+    context.apply::<OperatorEntry>(&audit, format!("op {}", profile.name), |list| list.upsert(entry.clone())).await?;
+    audit.entry("players.operators.add").server(id).target(&entry.name).details(&entry).record().await;
+    Ok(Json(entry))
+}
+
+#[openapi(tag = "Servers", tag = "Players")]
+#[delete("/<id>/operators/<player>")]
+async fn remove_operator(access: ServerAccess<perms::Properties>, config: AppConfig, runners: Runners, audit: Auditor, id: Uuid, player: &str) -> ApiResult<()> {
+    let context = PlayerContext::load(access, id, &config, &runners).await?;
+    context.remove::<OperatorEntry>(&audit, player, |e| format!("deop {}", e.name)).await?;
+    audit.entry("players.operators.remove").server(id).target(player).record().await;
+    Ok(())
+}
+
+#[openapi(tag = "Servers", tag = "Players")]
+#[get("/<id>/bans/players")]
+async fn get_banned_players(access: ServerAccess<perms::View>, config: AppConfig, runners: Runners, id: Uuid) -> ApiResult<Json<Vec<BannedPlayerEntry>>> {
+    let context = PlayerContext::load(access, id, &config, &runners).await?;
+    Ok(Json(context.list::<BannedPlayerEntry>().await?.entries))
+}
+
+#[openapi(tag = "Servers", tag = "Players")]
+#[post("/<id>/bans/players", data = "<params>")]
+async fn ban_player(access: ServerAccess<perms::Properties>, config: AppConfig, runners: Runners, audit: Auditor, id: Uuid, params: Json<PlayerBanParams>) -> ApiResult<Json<BannedPlayerEntry>> {
+    let context = PlayerContext::load(access, id, &config, &runners).await?;
+    if context.running() && params.expires.is_some() {
+        return Err(ApiError::invalid_state("Temporary bans can only be applied while the server is stopped"));
+    }
+
+    let profile = context.resolve(&params.name).await?;
+    let reason = sanitize_reason(&params.reason);
+    let entry = BannedPlayerEntry {
+        uuid: profile.id,
+        name: profile.name.clone(),
+        details: BanDetails::new(context.access.user.username.clone(), reason.clone(), params.expires),
+    };
+
+    let command = format!("ban {} {}", profile.name, reason.unwrap_or_default());
+    context.apply::<BannedPlayerEntry>(&audit, command, |list| list.upsert(entry.clone())).await?;
+    audit.entry("players.bans.add").server(id).target(&entry.name).details(&entry.details).record().await;
+    Ok(Json(entry))
+}
+
+#[openapi(tag = "Servers", tag = "Players")]
+#[delete("/<id>/bans/players/<player>")]
+async fn pardon_player(access: ServerAccess<perms::Properties>, config: AppConfig, runners: Runners, audit: Auditor, id: Uuid, player: &str) -> ApiResult<()> {
+    let context = PlayerContext::load(access, id, &config, &runners).await?;
+    context.remove::<BannedPlayerEntry>(&audit, player, |e| format!("pardon {}", e.name)).await?;
+    audit.entry("players.bans.remove").server(id).target(player).record().await;
+    Ok(())
+}
+
+#[openapi(tag = "Servers", tag = "Players")]
+#[get("/<id>/bans/ips")]
+async fn get_banned_ips(access: ServerAccess<perms::View>, config: AppConfig, runners: Runners, id: Uuid) -> ApiResult<Json<Vec<BannedIpEntry>>> {
+    let context = PlayerContext::load(access, id, &config, &runners).await?;
+    Ok(Json(context.list::<BannedIpEntry>().await?.entries))
+}
+
+#[openapi(tag = "Servers", tag = "Players")]
+#[post("/<id>/bans/ips", data = "<params>")]
+async fn ban_ip(access: ServerAccess<perms::Properties>, config: AppConfig, runners: Runners, audit: Auditor, id: Uuid, params: Json<IpBanParams>) -> ApiResult<Json<BannedIpEntry>> {
+    let context = PlayerContext::load(access, id, &config, &runners).await?;
+    if context.running() && params.expires.is_some() {
+        return Err(ApiError::invalid_state("Temporary bans can only be applied while the server is stopped"));
+    }
+
+    let reason = sanitize_reason(&params.reason);
+    let entry = BannedIpEntry {
+        ip: params.ip.to_string(),
+        details: BanDetails::new(context.access.user.username.clone(), reason.clone(), params.expires),
+    };
+
+    let command = format!("ban-ip {} {}", entry.ip, reason.unwrap_or_default());
+    context.apply::<BannedIpEntry>(&audit, command, |list| list.upsert(entry.clone())).await?;
+    audit.entry("players.ip_bans.add").server(id).target(&entry.ip).details(&entry.details).record().await;
+    Ok(Json(entry))
+}
+
+#[openapi(tag = "Servers", tag = "Players")]
+#[delete("/<id>/bans/ips/<ip>")]
+async fn pardon_ip(access: ServerAccess<perms::Properties>, config: AppConfig, runners: Runners, audit: Auditor, id: Uuid, ip: &str) -> ApiResult<()> {
+    let context = PlayerContext::load(access, id, &config, &runners).await?;
+    context.remove::<BannedIpEntry>(&audit, ip, |e| format!("pardon-ip {}", e.ip)).await?;
+    audit.entry("players.ip_bans.remove").server(id).target(ip).record().await;
+    Ok(())
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![
+        get_players,
+        get_player_sessions,
+        get_whitelist,
+        add_to_whitelist,
+        remove_from_whitelist,
+        get_operators,
+        add_operator,
+        remove_operator,
+        get_banned_players,
+        ban_player,
+        pardon_player,
+        get_banned_ips,
+        ban_ip,
+        pardon_ip
+    ]
+}
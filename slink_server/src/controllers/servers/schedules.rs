@@ -0,0 +1,188 @@
+use chrono::Utc;
+use manor::Model;
+use okapi::openapi3::OpenApi;
+use rocket::serde::json::Json;
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{ApiError, ApiResult, Error, types::AppConfig};
+use uuid::Uuid;
+
+use crate::{
+    models::{Auditor, MissedRunPolicy, ScheduledAction, ScheduledTask, ServerAccess, perms},
+    services::{
+        backups::BackupEngine,
+        schedules::{Scheduler, TaskContext},
+    },
+    util::Runners,
+};
+
+fn default_timezone() -> String {
+    String::from("UTC")
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct ScheduleParams {
+    pub name: String,
+
+    /// Cron expression, either `min hour day month weekday` or with a leading seconds field
+    pub cron: String,
+
+    /// IANA time zone, such as `Europe/Berlin`
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    pub action: ScheduledAction,
+
+    #[serde(default)]
+    pub missed: MissedRunPolicy,
+
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+#[openapi(tag = "Servers", tag = "Schedules")]
+#[get("/<id>/schedules")]
+async fn list_schedules(access: ServerAccess<perms::View>, id: Uuid) -> ApiResult<Json<Vec<ScheduledTask>>> {
+    let server = access.server(id)?;
+    Ok(Json(ScheduledTask::for_server(server.id).await?))
+}
+
+#[openapi(tag = "Servers", tag = "Schedules")]
+#[post("/<id>/schedules", data = "<params>")]
+async fn create_schedule(
+    access: ServerAccess<perms::Properties>,
+    audit: Auditor,
+    id: Uuid,
+    params: Json<ScheduleParams>,
+) -> ApiResult<Json<ScheduledTask>> {
+    let params = params.into_inner();
+    if let Some(command) = params.action.command() {
+        access.require_command(&command, &audit).await?;
+    }
+    let author = access.user.id;
+    let server = access.server(id)?;
+    let mut task = ScheduledTask::create(
+        server.id,
+        params.name,
+        params.cron,
+        params.timezone,
+        params.action,
+        params.missed,
+        author,
+    )?;
+    if !params.enabled {
+        task.enabled = false;
+        task.reschedule(Utc::now())?;
+    }
+    task.save()
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(format!("{e:?}"))))?;
+    audit
+        .entry("schedules.create")
+        .server(server.id)
+        .target(&task.name)
+        .details(serde_json::json!({ "task": task.id.to_string() }))
+        .record()
+        .await;
+    Ok(Json(task))
+}
+
+#[openapi(tag = "Servers", tag = "Schedules")]
+#[get("/<id>/schedules/<task>")]
+async fn get_schedule(access: ServerAccess<perms::View>, id: Uuid, task: Uuid) -> ApiResult<Json<ScheduledTask>> {
+    let server = access.server(id)?;
+    Ok(Json(ScheduledTask::get_for(server.id, task).await?))
+}
+
+/// Replaces a task's schedule and action. The next run is recalculated from now.
+#[openapi(tag = "Servers", tag = "Schedules")]
+#[put("/<id>/schedules/<task>", data = "<params>")]
+async fn update_schedule(
+    access: ServerAccess<perms::Properties>,
+    audit: Auditor,
+    id: Uuid,
+    task: Uuid,
+    params: Json<ScheduleParams>,
+) -> ApiResult<Json<ScheduledTask>> {
+    let params = params.into_inner();
+    if let Some(command) = params.action.command() {
+        access.require_command(&command, &audit).await?;
+    }
+    let author = access.user.id;
+    let server = access.server(id)?;
+    let mut task = ScheduledTask::get_for(server.id, task).await?;
+    let previous = task.clone();
+    task.name = params.name;
+    task.cron = params.cron;
+    task.timezone = params.timezone;
+    task.action = params.action;
+    task.missed = params.missed;
+    task.enabled = params.enabled;
+    task.author = Some(author);
+    task.reschedule(Utc::now())?;
+    task.save()
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(format!("{e:?}"))))?;
+    audit
+        .entry("schedules.update")
+        .server(server.id)
+        .target(&task.name)
+        .diff(&previous, &task)
+        .record()
+        .await;
+    Ok(Json(task))
+}
+
+#[openapi(tag = "Servers", tag = "Schedules")]
+#[delete("/<id>/schedules/<task>")]
+async fn delete_schedule(access: ServerAccess<perms::Properties>, audit: Auditor, id: Uuid, task: Uuid) -> ApiResult<()> {
+    let server = access.server(id)?;
+    let task = ScheduledTask::get_for(server.id, task).await?;
+    let entry = audit.entry("schedules.delete").server(server.id).target(&task.name);
+    task.delete()
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(format!("{e:?}"))))?;
+    entry.record().await;
+    Ok(())
+}
+
+/// Starts a task immediately in the background, without changing its next scheduled run.
+/// Restarts still count down through their warnings first.
+#[openapi(tag = "Servers", tag = "Schedules")]
+#[post("/<id>/schedules/<task>/run")]
+#[allow(clippy::too_many_arguments)]
+async fn run_schedule(
+    access: ServerAccess<perms::Properties>,
+    config: AppConfig,
+    runners: Runners,
+    engine: BackupEngine,
+    scheduler: Scheduler,
+    audit: Auditor,
+    id: Uuid,
+    task: Uuid,
+) -> ApiResult<()> {
+    let task = ScheduledTask::get_for(access.server.id, task).await?;
+    if let Some(command) = task.action.command() {
+        access.require_command(&command, &audit).await?;
+    }
+    let server = access.server(id)?;
+    let entry = audit.entry("schedules.run").server(server.id).target(&task.name);
+    scheduler.run_now(task, TaskContext { config, runners, engine })?;
+    entry.record().await;
+    Ok(())
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![
+        list_schedules,
+        create_schedule,
+        get_schedule,
+        update_schedule,
+        delete_schedule,
+        run_schedule
+    ]
+}
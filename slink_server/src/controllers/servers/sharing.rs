@@ -0,0 +1,144 @@
+use bson::Bson;
+use chrono::Utc;
+use manor::Model;
+use okapi::openapi3::OpenApi;
+use rocket::serde::json::Json;
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{ApiError, ApiResult, Error};
+use uuid::Uuid;
+
+use crate::models::{Auditor, MinecraftServer, Permission, RedactedUser, Role, ServerAccess, ServerGrant, User, perms};
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct EffectivePermissions {
+    pub owner: bool,
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct SharedGrant {
+    pub user: RedactedUser,
+    pub grant: ServerGrant,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct GrantParams {
+    pub username: String,
+
+    #[serde(default)]
+    pub roles: Vec<Uuid>,
+
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+}
+
+/// Only the owner and superusers may share a server.
+fn require_owner(server: &MinecraftServer, user: &User) -> ApiResult<()> {
+    match server.owned_by(user) {
+        true => Ok(()),
+        false => Err(ApiError::missing_auth("Server owner")),
+    }
+}
+
+/// What the current user may do on the server.
+#[openapi(tag = "Servers", tag = "Sharing")]
+#[get("/<id>/permissions")]
+async fn get_permissions(access: ServerAccess<perms::View>, id: Uuid) -> ApiResult<Json<EffectivePermissions>> {
+    let permissions: Vec<Permission> = Permission::ALL.into_iter().filter(|p| access.has(*p)).collect();
+    let user = access.user.clone();
+    let server = access.server(id)?;
+    Ok(Json(EffectivePermissions {
+        owner: server.owned_by(&user),
+        permissions,
+    }))
+}
+
+#[openapi(tag = "Servers", tag = "Sharing")]
+#[get("/<id>/grants")]
+async fn list_grants(access: ServerAccess<perms::View>, id: Uuid) -> ApiResult<Json<Vec<SharedGrant>>> {
+    let user = access.user.clone();
+    let server = access.server(id)?;
+    require_owner(&server, &user)?;
+
+    let mut shared = Vec::new();
+    for grant in ServerGrant::for_server(server.id).await? {
+        if let Ok(user) = User::get(grant.user).await {
+            shared.push(SharedGrant {
+                user: user.redact(),
+                grant,
+            });
+        }
+    }
+    Ok(Json(shared))
+}
+
+/// Shares the server with a user, replacing any roles and permissions they were given before.
+#[openapi(tag = "Servers", tag = "Sharing")]
+#[post("/<id>/grants", data = "<params>")]
+async fn set_grant(access: ServerAccess<perms::View>, audit: Auditor, id: Uuid, params: Json<GrantParams>) -> ApiResult<Json<SharedGrant>> {
+    let user = access.user.clone();
+    let server = access.server(id)?;
+    require_owner(&server, &user)?;
+    let params = params.into_inner();
+
+    let target = User::from_username(&params.username)
+        .await
+        .ok_or(ApiError::not_found(format!("User: {}", params.username)))?;
+    if Bson::from(server.owner.id) == Bson::from(target.id) {
+        return Err(ApiError::bad_request("The owner already has every permission"));
+    }
+
+    let roles = Role::resolve(&params.roles).await?;
+    let permissions: Vec<Permission> = Permission::ALL
+        .into_iter()
+        .filter(|p| params.permissions.contains(p))
+        .collect();
+
+    let mut grant = ServerGrant::get(server.id, target.id)
+        .await?
+        .unwrap_or(ServerGrant::new(server.id, target.id, user.id));
+    let previous = (grant.roles.clone(), grant.permissions.clone());
+    grant.roles = roles;
+    grant.permissions = permissions;
+    grant.granted_by = user.id;
+    grant.updated = Utc::now();
+    grant
+        .save()
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+    audit
+        .entry("sharing.grant")
+        .server(server.id)
+        .target(&target.username)
+        .diff(&previous, &(grant.roles.clone(), grant.permissions.clone()))
+        .record()
+        .await;
+    Ok(Json(SharedGrant {
+        user: target.redact(),
+        grant,
+    }))
+}
+
+#[openapi(tag = "Servers", tag = "Sharing")]
+#[delete("/<id>/grants/<grantee>")]
+async fn revoke_grant(access: ServerAccess<perms::View>, audit: Auditor, id: Uuid, grantee: Uuid) -> ApiResult<()> {
+    let user = access.user.clone();
+    let server = access.server(id)?;
+    require_owner(&server, &user)?;
+
+    let grant = ServerGrant::get(server.id, grantee.into())
+        .await?
+        .ok_or(ApiError::not_found(format!("Grant: {grantee}")))?;
+    grant
+        .delete()
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+    audit.entry("sharing.revoke").server(server.id).target(grantee).record().await;
+    Ok(())
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![get_permissions, list_grants, set_grant, revoke_grant]
+}
@@ -0,0 +1,115 @@
+use okapi::openapi3::OpenApi;
+use rocket::serde::json::Json;
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{ApiResult, types::AppConfig};
+use uuid::Uuid;
+
+use crate::{
+    models::{Auditor, Permission, ServerAccess, ServerTemplate, perms},
+    services::{
+        imports::{ImportJob, ImportJobs},
+        templates,
+    },
+    util::Runners,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct SaveTemplateParams {
+    pub name: String,
+
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// Files and folders to seed new servers with, relative to the server directory (e.g. `config`)
+    #[serde(default)]
+    pub files: Vec<String>,
+
+    /// Make the template available to all users. Only honored for superusers.
+    #[serde(default)]
+    pub public: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct CloneParams {
+    pub name: String,
+
+    /// Also copy the world folders. The server must be stopped.
+    #[serde(default)]
+    pub include_worlds: bool,
+}
+
+/// Saves the server's version, loader, properties, memory settings and installed catalog content as a template.
+/// Seeding the template with files also requires the `files` permission.
+#[openapi(tag = "Servers", tag = "Templates")]
+#[post("/<id>/template", data = "<params>")]
+async fn save_as_template(
+    access: ServerAccess<perms::Properties>,
+    config: AppConfig,
+    audit: Auditor,
+    id: Uuid,
+    params: Json<SaveTemplateParams>,
+) -> ApiResult<Json<ServerTemplate>> {
+    let params = params.into_inner();
+    if !params.files.is_empty() {
+        access.require(Permission::Files)?;
+    }
+    let user = access.user.clone();
+    let server = access.server(id)?;
+    let template = templates::save_as_template(
+        &server,
+        &user,
+        &config,
+        params.name,
+        params.description,
+        params.files,
+        params.public,
+    )
+    .await?;
+    audit
+        .entry("templates.create")
+        .server(server.id)
+        .target(&template.name)
+        .details(serde_json::json!({ "template": template.id.to_string() }))
+        .record()
+        .await;
+    Ok(Json(template))
+}
+
+/// Copies the server's settings, files and scheduled tasks to a new server, in the background. Poll the
+/// returned job for the created server.
+#[openapi(tag = "Servers", tag = "Templates")]
+#[post("/<id>/clone", data = "<params>")]
+async fn clone_server(
+    access: ServerAccess<perms::Files>,
+    config: AppConfig,
+    runners: Runners,
+    jobs: ImportJobs,
+    audit: Auditor,
+    id: Uuid,
+    params: Json<CloneParams>,
+) -> ApiResult<Json<ImportJob>> {
+    let user = access.user.clone();
+    let server = access.server(id)?;
+    let params = params.into_inner();
+    if params.include_worlds {
+        templates::ensure_stopped(&server, &runners).await?;
+    }
+    let job = ImportJob::new(user.id, server.name.clone());
+    audit
+        .entry("servers.clone")
+        .server(server.id)
+        .target(&params.name)
+        .details(serde_json::json!({ "job": job.id.to_string(), "include_worlds": params.include_worlds }))
+        .record()
+        .await;
+    Ok(Json(jobs.spawn(
+        job,
+        templates::clone_server(server, params.name, params.include_worlds, user, config),
+    )))
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![save_as_template, clone_server]
+}
@@ -0,0 +1,59 @@
+use manor::Model;
+use okapi::openapi3::OpenApi;
+use rocket::serde::json::Json;
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{ApiError, ApiResult, Error};
+
+use crate::models::{Auditor, SecuritySettings, User};
+
+fn require_superuser(user: &User) -> ApiResult<()> {
+    if !user.superuser {
+        return Err(ApiError::missing_auth("Superuser"));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct SecuritySettingsUpdate {
+    #[serde(default)]
+    pub require_two_factor: Option<bool>,
+}
+
+#[openapi(tag = "Settings")]
+#[get("/security")]
+async fn get_security(user: User) -> ApiResult<Json<SecuritySettings>> {
+    require_superuser(&user)?;
+    Ok(Json(SecuritySettings::current().await?))
+}
+
+/// Changes instance-wide security settings. Requiring two-factor authentication is refused until the
+/// superuser making the change has enabled it, so that they are not locked out.
+#[openapi(tag = "Settings")]
+#[patch("/security", data = "<params>")]
+async fn update_security(
+    user: User,
+    audit: Auditor,
+    params: Json<SecuritySettingsUpdate>,
+) -> ApiResult<Json<SecuritySettings>> {
+    require_superuser(&user)?;
+    let mut settings = SecuritySettings::current().await?;
+    let previous = settings.clone();
+    if let Some(required) = params.require_two_factor {
+        if required && !user.two_factor_enabled() {
+            return Err(ApiError::invalid_state("Enable two-factor authentication for yourself first"));
+        }
+        settings.require_two_factor = required;
+    }
+    settings
+        .save()
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+    audit.entry("settings.security").diff(&previous, &settings).record().await;
+    Ok(Json(settings))
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![get_security, update_security]
+}
@@ -0,0 +1,156 @@
+use bytesize::ByteSize;
+use manor::Model;
+use okapi::openapi3::OpenApi;
+use rocket::serde::json::Json;
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{
+    ApiError, ApiResult, Error,
+    types::{AppConfig, ServerProperties},
+};
+use uuid::Uuid;
+
+use crate::{
+    models::{Auditor, ServerTemplate, TemplateContent, User},
+    services::{
+        content::ContentManager,
+        imports::{ImportJob, ImportJobs},
+        templates,
+    },
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct TemplateUpdateParams {
+    #[serde(default)]
+    pub name: Option<String>,
+
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// Only superusers can publish templates to all users
+    #[serde(default)]
+    pub public: Option<bool>,
+
+    #[serde(default)]
+    pub properties: Option<ServerProperties>,
+
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    pub max_memory: Option<ByteSize>,
+
+    #[serde(default)]
+    pub java_args: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub content: Option<Vec<TemplateContent>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct FromTemplateParams {
+    pub name: String,
+}
+
+/// Lists the user's own templates and public ones.
+#[openapi(tag = "Templates")]
+#[get("/")]
+async fn list_templates(user: User) -> ApiResult<Json<Vec<ServerTemplate>>> {
+    Ok(Json(ServerTemplate::for_user(&user).await?))
+}
+
+#[openapi(tag = "Templates")]
+#[get("/<id>")]
+async fn get_template(user: User, id: Uuid) -> ApiResult<Json<ServerTemplate>> {
+    Ok(Json(ServerTemplate::get_for(id, &user).await?))
+}
+
+#[openapi(tag = "Templates")]
+#[put("/<id>", data = "<params>")]
+async fn update_template(
+    user: User,
+    audit: Auditor,
+    id: Uuid,
+    params: Json<TemplateUpdateParams>,
+) -> ApiResult<Json<ServerTemplate>> {
+    let mut template = ServerTemplate::get_for(id, &user).await?;
+    if !template.editable_by(&user) {
+        return Err(ApiError::missing_auth("Template owner"));
+    }
+    let params = params.into_inner();
+    if params.public.is_some() && !user.superuser {
+        return Err(ApiError::missing_auth("Superuser"));
+    }
+    let previous = template.clone();
+
+    template.name = params.name.unwrap_or(template.name);
+    template.description = params.description.or(template.description);
+    template.public = params.public.unwrap_or(template.public);
+    template.properties = params.properties.unwrap_or(template.properties);
+    template.max_memory = params.max_memory.unwrap_or(template.max_memory);
+    template.java_args = params.java_args.unwrap_or(template.java_args);
+    template.content = params.content.unwrap_or(template.content);
+    template
+        .save()
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(format!("{e:?}"))))?;
+    audit
+        .entry("templates.update")
+        .target(template.id)
+        .diff(&previous, &template)
+        .record()
+        .await;
+    Ok(Json(template))
+}
+
+#[openapi(tag = "Templates")]
+#[delete("/<id>")]
+async fn delete_template(user: User, config: AppConfig, audit: Auditor, id: Uuid) -> ApiResult<()> {
+    let template = ServerTemplate::get_for(id, &user).await?;
+    if !template.editable_by(&user) {
+        return Err(ApiError::missing_auth("Template owner"));
+    }
+    let entry = audit
+        .entry("templates.delete")
+        .target(template.id)
+        .details(serde_json::json!({ "name": template.name }));
+    templates::delete_template(template, &config).await?;
+    entry.record().await;
+    Ok(())
+}
+
+/// Creates a new server from a template, in the background. Poll the returned job (under
+/// `/servers/import/jobs`) for the created server and any content that could not be installed.
+#[openapi(tag = "Templates")]
+#[post("/<id>/create", data = "<params>")]
+async fn create_from_template(
+    user: User,
+    config: AppConfig,
+    jobs: ImportJobs,
+    manager: ContentManager,
+    audit: Auditor,
+    id: Uuid,
+    params: Json<FromTemplateParams>,
+) -> ApiResult<Json<ImportJob>> {
+    let template = ServerTemplate::get_for(id, &user).await?;
+    let job = ImportJob::new(user.id, template.name.clone());
+    audit
+        .entry("templates.instantiate")
+        .target(template.id)
+        .details(serde_json::json!({ "job": job.id.to_string() }))
+        .record()
+        .await;
+    Ok(Json(jobs.spawn(
+        job,
+        templates::create_from_template(template, params.into_inner().name, user, config, manager),
+    )))
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![
+        list_templates,
+        get_template,
+        update_template,
+        delete_template,
+        create_from_template
+    ]
+}
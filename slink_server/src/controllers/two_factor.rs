@@ -0,0 +1,211 @@
+use log::debug;
+use manor::Model;
+use okapi::openapi3::OpenApi;
+use rocket::{http::CookieJar, serde::json::Json};
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{
+    ApiError, ApiResult, Error, RECOVERY_CODE_COUNT, TOTP_ISSUER, TOTP_SECRET_BYTES,
+    types::AppConfig,
+    utilities::{base32_decode, base32_encode, otpauth_uri, verify_totp},
+};
+
+use crate::{
+    models::{Auditor, PendingUser, RedactedUser, RequestToken, Session, TwoFactor, User, normalize_recovery_code},
+    util::{
+        rate_limit::LoginThrottle,
+        security::{HashedPassword, random_bytes},
+    },
+};
+
+/// Generates new recovery codes, returning the plaintext codes along with their hashes.
+fn recovery_codes() -> ApiResult<(Vec<String>, Vec<HashedPassword>)> {
+    let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    let mut hashed = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let code = base32_encode(&random_bytes(6)?).to_lowercase();
+        hashed.push(HashedPassword::new(normalize_recovery_code(&code))?);
+        codes.push(format!("{}-{}", &code[..5], &code[5..]));
+    }
+    Ok((codes, hashed))
+}
+
+async fn save_user(user: &User) -> ApiResult<()> {
+    user.save()
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct EnrollmentModel {
+    /// Base32-encoded secret, for entering manually
+    pub secret: String,
+
+    /// `otpauth://` URI, usually shown as a QR code
+    pub uri: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct CodeModel {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct RecoveryCodesModel {
+    /// Single-use codes that replace a TOTP code when the authenticator is lost. They are only shown once.
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct DisableModel {
+    pub password: String,
+    pub code: String,
+}
+
+/// Starts enrollment with a new secret, replacing any unconfirmed one. Enrollment is completed by confirming a
+/// first code.
+#[openapi(tag = "Authentication")]
+#[post("/2fa/enroll")]
+pub async fn enroll(user: PendingUser, token: RequestToken) -> ApiResult<Json<EnrollmentModel>> {
+    token.require_session()?;
+    let PendingUser(mut user) = user;
+    if user.two_factor_enabled() {
+        return Err(ApiError::invalid_state("Two-factor authentication is already enabled"));
+    }
+
+    let secret = random_bytes(TOTP_SECRET_BYTES)?;
+    user.two_factor = Some(TwoFactor {
+        secret: base32_encode(&secret),
+        enabled: false,
+        recovery_codes: Vec::new(),
+        last_step: None,
+    });
+    save_user(&user).await?;
+
+    Ok(Json(EnrollmentModel {
+        secret: base32_encode(&secret),
+        uri: otpauth_uri(TOTP_ISSUER, &user.username, &secret),
+    }))
+}
+
+/// Enables two-factor authentication once the first code from the authenticator is valid.
+#[openapi(tag = "Authentication")]
+#[post("/2fa/confirm", data = "<confirm>")]
+pub async fn confirm(
+    user: PendingUser,
+    token: RequestToken,
+    audit: Auditor,
+    confirm: Json<CodeModel>,
+) -> ApiResult<Json<RecoveryCodesModel>> {
+    token.require_session()?;
+    let PendingUser(mut user) = user;
+    let Some(two_factor) = user.two_factor.as_mut().filter(|t| !t.enabled) else {
+        return Err(ApiError::invalid_state("No two-factor enrollment in progress"));
+    };
+    let secret = base32_decode(&two_factor.secret).ok_or(ApiError::Uncaught("Stored TOTP secret is not valid base32".to_string()))?;
+    let Some(step) = verify_totp(&secret, &confirm.code, chrono::Utc::now().timestamp()) else {
+        return Err(ApiError::bad_request("Invalid code"));
+    };
+
+    let (codes, hashed) = recovery_codes()?;
+    two_factor.enabled = true;
+    two_factor.last_step = Some(step);
+    two_factor.recovery_codes = hashed;
+    save_user(&user).await?;
+    audit.entry("auth.2fa.enable").target(&user.username).record().await;
+    debug!("User {} ({}) enabled two-factor authentication.", user.username, user.id);
+
+    Ok(Json(RecoveryCodesModel { recovery_codes: codes }))
+}
+
+/// Completes a login that is waiting for a second factor, with a TOTP code or a recovery code.
+#[openapi(tag = "Authentication")]
+#[post("/2fa/verify", data = "<verify>")]
+pub async fn verify(
+    mut session: Session,
+    cookies: &CookieJar<'_>,
+    config: AppConfig,
+    throttle: LoginThrottle,
+    verify: Json<CodeModel>,
+) -> ApiResult<Json<RedactedUser>> {
+    let (true, Some(link)) = (session.pending_second_factor, session.user.as_mut()) else {
+        return Err(ApiError::invalid_state("No login is waiting for a second factor"));
+    };
+    let mut user = link.resolve().await.or(Err(ApiError::missing_auth("requires_login")))?.clone();
+    if user.disabled {
+        return Err(ApiError::missing_auth("account_disabled"));
+    }
+    throttle.attempt(&user.username)?;
+    if !user.verify_second_factor(&verify.code) {
+        throttle.failed(&user.username).await;
+        return Err(ApiError::bad_login());
+    }
+    throttle.succeeded(&user.username);
+    save_user(&user).await?;
+
+    session.pending_second_factor = false;
+    session.rotate(cookies, &config).await?;
+    debug!("User {} ({}) completed two-factor login.", user.username, user.id);
+    Ok(Json(user.redact()))
+}
+
+/// Replaces the recovery codes, invalidating the old ones. Failed codes count towards the login limits.
+#[openapi(tag = "Authentication")]
+#[post("/2fa/recovery_codes", data = "<verify>")]
+pub async fn regenerate_recovery_codes(
+    user: User,
+    token: RequestToken,
+    audit: Auditor,
+    throttle: LoginThrottle,
+    verify: Json<CodeModel>,
+) -> ApiResult<Json<RecoveryCodesModel>> {
+    token.require_session()?;
+    let mut user = user;
+    throttle.attempt(&user.username)?;
+    if !user.verify_second_factor(&verify.code) {
+        throttle.failed(&user.username).await;
+        return Err(ApiError::bad_login());
+    }
+    throttle.succeeded(&user.username);
+
+    let (codes, hashed) = recovery_codes()?;
+    if let Some(two_factor) = user.two_factor.as_mut() {
+        two_factor.recovery_codes = hashed;
+    }
+    save_user(&user).await?;
+    audit.entry("auth.2fa.recovery_codes").target(&user.username).record().await;
+    Ok(Json(RecoveryCodesModel { recovery_codes: codes }))
+}
+
+/// Turns off two-factor authentication, after checking both the password and a code. Failures count towards the
+/// login limits.
+#[openapi(tag = "Authentication")]
+#[delete("/2fa", data = "<disable>")]
+pub async fn disable(
+    user: User,
+    token: RequestToken,
+    audit: Auditor,
+    throttle: LoginThrottle,
+    disable: Json<DisableModel>,
+) -> ApiResult<Json<RedactedUser>> {
+    token.require_session()?;
+    let mut user = user;
+    throttle.attempt(&user.username)?;
+    if !user.hashed_password.verify(disable.password.clone()) || !user.verify_second_factor(&disable.code) {
+        throttle.failed(&user.username).await;
+        return Err(ApiError::bad_login());
+    }
+    throttle.succeeded(&user.username);
+
+    user.two_factor = None;
+    save_user(&user).await?;
+    audit.entry("auth.2fa.disable").target(&user.username).record().await;
+    debug!("User {} ({}) disabled two-factor authentication.", user.username, user.id);
+    Ok(Json(user.redact()))
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![enroll, confirm, verify, regenerate_recovery_codes, disable]
+}
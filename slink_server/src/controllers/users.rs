@@ -0,0 +1,265 @@
+use bson::doc;
+use futures::TryStreamExt;
+use manor::{Collection, Model};
+use okapi::openapi3::OpenApi;
+use rocket::serde::json::Json;
+use rocket_okapi::{openapi, openapi_get_routes_spec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{ApiError, ApiResult, Error, types::AppConfig};
+use uuid::Uuid;
+
+use crate::models::{
+    ApiToken, Auditor, CommandFilter, MinecraftServer, RedactedUser, Role, ServerGrant, Session, SessionInfo, User,
+};
+
+fn require_superuser(user: &User) -> ApiResult<()> {
+    if !user.superuser {
+        return Err(ApiError::missing_auth("Superuser"));
+    }
+    Ok(())
+}
+
+fn default_reset_required() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct UserCreationParams {
+    pub username: String,
+    pub password: String,
+
+    #[serde(default)]
+    pub superuser: bool,
+
+    /// Make the user choose a new password on first login
+    #[serde(default = "default_reset_required")]
+    pub password_reset_required: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct UserUpdateParams {
+    #[serde(default)]
+    pub username: Option<String>,
+
+    /// Sets a new password, ending the user's sessions and revoking their API tokens. Combine with
+    /// `password_reset_required` for temporary passwords.
+    #[serde(default)]
+    pub password: Option<String>,
+
+    #[serde(default)]
+    pub superuser: Option<bool>,
+
+    /// Disabling a user logs them out everywhere
+    #[serde(default)]
+    pub disabled: Option<bool>,
+
+    #[serde(default)]
+    pub password_reset_required: Option<bool>,
+
+    /// Replaces the roles applied on every server the user has been granted access to
+    #[serde(default)]
+    pub roles: Option<Vec<Uuid>>,
+
+    /// Replaces the console commands the user may send on servers they do not own
+    #[serde(default)]
+    pub commands: Option<CommandFilter>,
+
+    /// Removes the user's two-factor authentication, for users who lost their authenticator and recovery codes
+    #[serde(default)]
+    pub reset_two_factor: bool,
+}
+
+async fn ensure_unique(username: &str) -> ApiResult<()> {
+    if username.trim().is_empty() {
+        return Err(ApiError::bad_request("Usernames cannot be empty"));
+    }
+    match User::from_username(username).await {
+        Some(_) => Err(ApiError::invalid_state(format!("Username {username} is already taken"))),
+        None => Ok(()),
+    }
+}
+
+/// Lists all users. Superuser only.
+#[openapi(tag = "Users")]
+#[get("/")]
+async fn list_users(user: User) -> ApiResult<Json<Vec<RedactedUser>>> {
+    require_superuser(&user)?;
+    let users = Collection::<User>::new()
+        .find_many(doc! {})
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?
+        .try_collect::<Vec<User>>()
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+    Ok(Json(users.iter().map(|u| u.redact()).collect()))
+}
+
+#[openapi(tag = "Users")]
+#[post("/", data = "<params>")]
+async fn create_user(user: User, audit: Auditor, params: Json<UserCreationParams>) -> ApiResult<Json<RedactedUser>> {
+    require_superuser(&user)?;
+    let params = params.into_inner();
+    ensure_unique(&params.username).await?;
+
+    User::validate_password(&params.password)?;
+
+    let mut created = User::create(params.username, params.password)?;
+    created.superuser = params.superuser;
+    created.password_reset_required = params.password_reset_required;
+    created
+        .save()
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+    audit
+        .entry("users.create")
+        .target(&created.username)
+        .details(serde_json::json!({ "user": created.id.to_string(), "superuser": created.superuser }))
+        .record()
+        .await;
+    Ok(Json(created.redact()))
+}
+
+#[openapi(tag = "Users")]
+#[get("/<id>")]
+async fn get_user(user: User, id: Uuid) -> ApiResult<Json<RedactedUser>> {
+    require_superuser(&user)?;
+    Ok(Json(User::get(id).await?.redact()))
+}
+
+/// Updates a user. Superusers cannot disable or demote themselves, so that at least one remains.
+#[openapi(tag = "Users")]
+#[patch("/<id>", data = "<params>")]
+async fn update_user(
+    user: User,
+    audit: Auditor,
+    id: Uuid,
+    params: Json<UserUpdateParams>,
+) -> ApiResult<Json<RedactedUser>> {
+    require_superuser(&user)?;
+    let params = params.into_inner();
+    let mut target = User::get(id).await?;
+    if target.id == user.id && (params.disabled == Some(true) || params.superuser == Some(false)) {
+        return Err(ApiError::invalid_state("You cannot disable or demote yourself"));
+    }
+    let previous = target.redact();
+    let password_changed = params.password.is_some();
+
+    if let Some(username) = params.username.filter(|u| *u != target.username) {
+        ensure_unique(&username).await?;
+        target.username = username;
+    }
+    if let Some(password) = params.password {
+        target.set_password(password)?;
+    }
+    if params.reset_two_factor {
+        target.two_factor = None;
+    }
+    if let Some(roles) = params.roles {
+        target.roles = Role::resolve(&roles).await?;
+    }
+    if let Some(commands) = params.commands {
+        commands.validate()?;
+        target.commands = commands;
+    }
+    target.superuser = params.superuser.unwrap_or(target.superuser);
+    target.disabled = params.disabled.unwrap_or(target.disabled);
+    target.password_reset_required = params.password_reset_required.unwrap_or(target.password_reset_required);
+    target
+        .save()
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+
+    let mut entry = audit.entry("users.update").target(&target.username).diff(&previous, &target.redact());
+    if password_changed {
+        entry = entry.details(serde_json::json!({ "password_changed": true }));
+    }
+    entry.record().await;
+
+    if target.disabled || password_changed {
+        Session::invalidate_user(target.id, None).await?;
+    }
+    if password_changed {
+        ApiToken::revoke_user(target.id).await?;
+    }
+    Ok(Json(target.redact()))
+}
+
+/// Deletes a user, their sessions and API tokens, and the grants shared with them. Users that still own servers cannot be deleted; disable them instead.
+#[openapi(tag = "Users")]
+#[delete("/<id>")]
+async fn delete_user(user: User, audit: Auditor, id: Uuid) -> ApiResult<()> {
+    require_superuser(&user)?;
+    let target = User::get(id).await?;
+    if target.id == user.id {
+        return Err(ApiError::invalid_state("You cannot delete yourself"));
+    }
+    let owned = Collection::<MinecraftServer>::new()
+        .exact_count(doc! {"owner.id": target.id})
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+    if owned > 0 {
+        return Err(ApiError::invalid_state(format!("{} still owns {owned} servers", target.username)));
+    }
+
+    Session::invalidate_user(target.id, None).await?;
+    ServerGrant::revoke_user(target.id).await?;
+    ApiToken::revoke_user(target.id).await?;
+    let entry = audit.entry("users.delete").target(&target.username);
+    target
+        .delete()
+        .await
+        .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+    entry.record().await;
+    Ok(())
+}
+
+/// Lists a user's active sessions, most recently used first. Superuser only.
+#[openapi(tag = "Users")]
+#[get("/<id>/sessions")]
+async fn list_user_sessions(user: User, session: Session, config: AppConfig, id: Uuid) -> ApiResult<Json<Vec<SessionInfo>>> {
+    require_superuser(&user)?;
+    let target = User::get(id).await?;
+    let sessions = Session::for_user(target.id, config.authentication.session_max_lifetime).await?;
+    Ok(Json(sessions.iter().map(|s| s.listed(session.id)).collect()))
+}
+
+/// Logs a user out of one of their sessions. Superuser only.
+#[openapi(tag = "Users")]
+#[delete("/<id>/sessions/<session_id>")]
+async fn revoke_user_session(user: User, audit: Auditor, id: Uuid, session_id: Uuid) -> ApiResult<()> {
+    require_superuser(&user)?;
+    let target = User::get(id).await?;
+    Session::revoke(target.id, session_id).await?;
+    audit
+        .entry("users.sessions.revoke")
+        .target(&target.username)
+        .details(serde_json::json!({ "session": session_id }))
+        .record()
+        .await;
+    Ok(())
+}
+
+/// Logs a user out everywhere. Superuser only.
+#[openapi(tag = "Users")]
+#[delete("/<id>/sessions")]
+async fn revoke_user_sessions(user: User, audit: Auditor, id: Uuid) -> ApiResult<()> {
+    require_superuser(&user)?;
+    let target = User::get(id).await?;
+    Session::invalidate_user(target.id, None).await?;
+    audit.entry("users.sessions.revoke_all").target(&target.username).record().await;
+    Ok(())
+}
+
+pub fn routes() -> (Vec<rocket::Route>, OpenApi) {
+    openapi_get_routes_spec![
+        list_users,
+        create_user,
+        get_user,
+        update_user,
+        delete_user,
+        list_user_sessions,
+        revoke_user_session,
+        revoke_user_sessions
+    ]
+}
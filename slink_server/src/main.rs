@@ -8,10 +8,12 @@ use manor::{Client, Model};
 use models::User;
 use rocket::{fairing::AdHoc, http::Status, Request};
 use slink_common::{types::{AppConfig, DatabaseConfig, RequestId}, utilities::{Expiration, ResponseCache}, ApiError};
-use util::fairings::SessionFairing;
+use services::{archives::ArchiveJobs, backups::BackupEngine, content::ContentManager, imports::ImportJobs, schedules::{Scheduler, TaskContext}};
+use util::{fairings::SessionFairing, rate_limit::LoginLimiter, Runners};
 mod util;
 mod controllers;
 mod models;
+mod services;
 
 #[macro_use] extern crate rocket;
 
@@ -74,7 +76,28 @@ fn rocket() -> _ {
             }
 
         })))
+        .attach(AdHoc::on_liftoff("Start Scheduler", |rocket| Box::pin(async move {
+            let context = TaskContext {
+                config: rocket.figment().extract_inner("slink").unwrap(),
+                runners: rocket.state::<Runners>().expect("No runner registry initialized.").clone(),
+                engine: rocket.state::<BackupEngine>().expect("No backup engine initialized.").clone(),
+            };
+            rocket.state::<Scheduler>().expect("No scheduler initialized.").spawn(context);
+            info!("Started task scheduler");
+        })))
+        .attach(AdHoc::on_liftoff("Start Session Sweeper", |rocket| Box::pin(async move {
+            let conf: AppConfig = rocket.figment().extract_inner("slink").unwrap();
+            services::sessions::spawn_sweeper(conf.authentication.session_max_lifetime);
+            info!("Started session sweeper");
+        })))
         .attach(SessionFairing)
+        .manage(Runners::default())
+        .manage(ArchiveJobs::default())
+        .manage(BackupEngine::default())
+        .manage(Scheduler::default())
+        .manage(ContentManager::default())
+        .manage(ImportJobs::default())
+        .manage(LoginLimiter::default())
         .manage(ResponseCache::new(Expiration {lifetime: Some(TimeDelta::minutes(5)), idletime: Some(TimeDelta::seconds(30))}))
         .register("/", catchers![handle_error])
 }
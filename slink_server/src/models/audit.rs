@@ -0,0 +1,264 @@
+use bson::{Document, Uuid, doc};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use log::warn;
+use manor::{Collection, Model, schema};
+use mongodb::options::FindOptions;
+use rocket::{
+    Request,
+    request::{self, FromRequest},
+};
+use rocket_okapi::request::OpenApiFromRequest;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use slink_common::{ApiError, ApiResult, Error, types::RequestId};
+
+use super::{auth::OptionalUser, tokens::bearer_user};
+use crate::util::types::bson_date;
+
+/// Append-only record of a security-relevant or administrative action.
+#[schema(collection = "audit")]
+#[derive(JsonSchema)]
+pub struct AuditEntry {
+    #[field(id = Uuid::new)]
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+
+    #[serde(deserialize_with = "bson_date::deserialize")]
+    #[schemars(with = "DateTime<Utc>")]
+    pub timestamp: bson::DateTime,
+
+    /// User who acted, if known
+    #[serde(default)]
+    #[schemars(with = "Option<uuid::Uuid>")]
+    pub actor: Option<Uuid>,
+
+    #[serde(default)]
+    pub actor_name: Option<String>,
+
+    /// API token the actor authenticated with, if any
+    #[serde(default)]
+    #[schemars(with = "Option<uuid::Uuid>")]
+    pub token: Option<Uuid>,
+
+    /// Dotted action name, such as `server.start`
+    pub action: String,
+
+    #[serde(default)]
+    #[schemars(with = "Option<uuid::Uuid>")]
+    pub server: Option<Uuid>,
+
+    /// What the action applied to within the server or instance, such as a username or path
+    #[serde(default)]
+    pub target: Option<String>,
+
+    /// The `X-SLR-ID` of the request that performed the action
+    #[serde(default)]
+    pub request_id: Option<String>,
+
+    #[serde(default)]
+    pub ip: Option<String>,
+
+    #[serde(default)]
+    pub details: Option<Value>,
+
+    /// Changed fields of an edited configuration, as `{"field.path": {"before": ..., "after": ...}}`
+    #[serde(default)]
+    pub diff: Option<Value>,
+}
+
+/// An audit entry as shown by the API, with an RFC 3339 timestamp.
+#[derive(Serialize, Clone, Debug, JsonSchema)]
+pub struct AuditEntryInfo {
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+    pub timestamp: DateTime<Utc>,
+
+    #[schemars(with = "Option<uuid::Uuid>")]
+    pub actor: Option<Uuid>,
+    pub actor_name: Option<String>,
+
+    #[schemars(with = "Option<uuid::Uuid>")]
+    pub token: Option<Uuid>,
+    pub action: String,
+
+    #[schemars(with = "Option<uuid::Uuid>")]
+    pub server: Option<Uuid>,
+    pub target: Option<String>,
+    pub request_id: Option<String>,
+    pub ip: Option<String>,
+    pub details: Option<Value>,
+    pub diff: Option<Value>,
+}
+
+/// Collects the fields that differ between two JSON documents, keyed by their dotted path. Arrays are compared
+/// as a whole.
+fn collect_changes(path: String, before: &Value, after: &Value, changes: &mut Map<String, Value>) {
+    match (before, after) {
+        (Value::Object(b), Value::Object(a)) => {
+            let keys = b.keys().chain(a.keys().filter(|k| !b.contains_key(*k)));
+            for key in keys {
+                let child = if path.is_empty() { key.clone() } else { format!("{path}.{key}") };
+                collect_changes(
+                    child,
+                    b.get(key).unwrap_or(&Value::Null),
+                    a.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        (b, a) if b != a => {
+            changes.insert(path, serde_json::json!({ "before": b, "after": a }));
+        }
+        _ => {}
+    }
+}
+
+impl AuditEntry {
+    pub fn new(action: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new(),
+            timestamp: bson::DateTime::now(),
+            actor: None,
+            actor_name: None,
+            token: None,
+            action: action.into(),
+            server: None,
+            target: None,
+            request_id: None,
+            ip: None,
+            details: None,
+            diff: None,
+            _collection: None,
+        }
+    }
+
+    pub fn server(mut self, server: impl Into<Uuid>) -> Self {
+        self.server = Some(server.into());
+        self
+    }
+
+    pub fn target(mut self, target: impl ToString) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    pub fn details(mut self, details: impl Serialize) -> Self {
+        self.details = serde_json::to_value(details).ok();
+        self
+    }
+
+    /// Records the fields changed between two versions of a configuration. Nothing is recorded if they are equal.
+    pub fn diff(mut self, before: &impl Serialize, after: &impl Serialize) -> Self {
+        let (Ok(before), Ok(after)) = (serde_json::to_value(before), serde_json::to_value(after)) else {
+            return self;
+        };
+        let mut changes = Map::new();
+        collect_changes(String::new(), &before, &after, &mut changes);
+        if !changes.is_empty() {
+            self.diff = Some(Value::Object(changes));
+        }
+        self
+    }
+
+    pub fn info(&self) -> AuditEntryInfo {
+        AuditEntryInfo {
+            id: self.id,
+            timestamp: self.timestamp.to_chrono(),
+            actor: self.actor,
+            actor_name: self.actor_name.clone(),
+            token: self.token,
+            action: self.action.clone(),
+            server: self.server,
+            target: self.target.clone(),
+            request_id: self.request_id.clone(),
+            ip: self.ip.clone(),
+            details: self.details.clone(),
+            diff: self.diff.clone(),
+        }
+    }
+
+    /// Stores the entry. Failing to audit is logged but never fails the audited action.
+    pub async fn record(self) {
+        if let Err(e) = self.save().await {
+            warn!("Failed to record audit entry {}: {e:?}", self.action);
+        }
+    }
+
+    /// Up to `limit` entries matching a query, ordered by time.
+    pub async fn find(filter: Document, newest_first: bool, limit: Option<i64>) -> ApiResult<Vec<Self>> {
+        let options = FindOptions::builder()
+            .sort(doc! {"timestamp": if newest_first { -1 } else { 1 }})
+            .limit(limit)
+            .build();
+        let collection = Collection::<Self>::new();
+        let cursor = collection
+            .collection()
+            .find(filter)
+            .with_options(options)
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+        collection
+            .cursor(cursor)
+            .try_collect::<Vec<Self>>()
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))
+    }
+}
+
+/// Who is making a request, and from where. Starts audit entries attributed to the request.
+#[derive(Clone, Debug)]
+pub struct Auditor {
+    actor: Option<Uuid>,
+    actor_name: Option<String>,
+    token: Option<Uuid>,
+    request_id: Option<String>,
+    ip: Option<String>,
+}
+
+impl Auditor {
+    pub fn entry(&self, action: impl Into<String>) -> AuditEntry {
+        AuditEntry {
+            actor: self.actor,
+            actor_name: self.actor_name.clone(),
+            token: self.token,
+            request_id: self.request_id.clone(),
+            ip: self.ip.clone(),
+            ..AuditEntry::new(action)
+        }
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for Auditor {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let token = match bearer_user(req).await {
+            Some(Ok((token, _))) => Some(token.id),
+            _ => None,
+        };
+        let user = match req.guard::<OptionalUser>().await {
+            request::Outcome::Success(user) => (*user).clone(),
+            _ => None,
+        };
+        request::Outcome::Success(Self {
+            actor: user.as_ref().map(|u| u.id),
+            actor_name: user.map(|u| u.username),
+            token,
+            request_id: Some(req.local_cache(RequestId::new).to_string()),
+            ip: req.client_ip().map(|ip| ip.to_string()),
+        })
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for Auditor {
+    fn from_request_input(
+        _gen: &mut rocket_okapi::r#gen::OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<rocket_okapi::request::RequestHeaderInput> {
+        Ok(rocket_okapi::request::RequestHeaderInput::None)
+    }
+}
@@ -1,17 +1,25 @@
 use std::ops::{Deref, DerefMut};
 
-use chrono::{DateTime, Utc};
-use manor::{schema, Collection, Link};
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use futures::TryStreamExt;
+use manor::{schema, Collection, Link, Model};
 use rocket::request::{self, FromRequest};
+use rocket::http::CookieJar;
 use rocket::Request;
-use rocket_okapi::OpenApiFromRequest;
+use rocket_okapi::request::OpenApiFromRequest;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use slink_common::{ApiError, ApiResult};
+use slink_common::{
+    ApiError, ApiResult, Error, PASSWORD_MIN_LENGTH, SESSION_COOKIE,
+    types::AppConfig,
+    utilities::{base32_decode, verify_totp},
+};
 use bson::{doc, Uuid};
-use crate::util::types::TSLink;
+use crate::util::types::{TSLink, bson_date};
 
-use crate::util::security::HashedPassword;
+use crate::util::{fairings::session_cookie, security::{HashedPassword, session_security}};
+
+use super::{permissions::CommandFilter, settings::SecuritySettings, tokens::bearer_user};
 
 #[schema(collection = "sessions")]
 #[derive(JsonSchema, OpenApiFromRequest)]
@@ -20,24 +28,191 @@ pub struct Session {
     #[schemars(with = "uuid::Uuid")]
     pub id: Uuid,
 
-    pub created: DateTime<Utc>,
-    pub last_connection: DateTime<Utc>,
+    #[serde(deserialize_with = "bson_date::deserialize")]
+    #[schemars(with = "DateTime<Utc>")]
+    pub created: bson::DateTime,
+
+    /// Stored as a date, so that expired sessions can be found by the database
+    #[serde(deserialize_with = "bson_date::deserialize")]
+    #[schemars(with = "DateTime<Utc>")]
+    pub last_connection: bson::DateTime,
 
     #[serde(default)]
     #[schemars(with = "Option<TSLink>")]
     pub user: Option<Link<User>>,
+
+    /// The user entered their password, but still has to enter a two-factor code
+    #[serde(default)]
+    pub pending_second_factor: bool,
+
+    #[serde(default)]
+    #[schemars(skip)]
+    pub oidc: Option<OidcLogin>,
+
+    /// ID token of an OpenID Connect login, sent as a hint when logging out at the provider. Only set for
+    /// sessions signed in through the provider, which are exempt from required two-factor enrollment.
+    #[serde(default)]
+    #[schemars(skip)]
+    pub oidc_id_token: Option<String>,
+
+    /// Address and user agent of the last request, so that users can tell their sessions apart
+    #[serde(default)]
+    pub ip: Option<String>,
+
+    #[serde(default)]
+    pub user_agent: Option<String>,
+}
+
+/// What clients may see of a session. The ID is only useful together with the server's cookie secret.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct SessionInfo {
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+    pub created: DateTime<Utc>,
+    pub last_connection: DateTime<Utc>,
+    pub authenticated: bool,
+    pub pending_second_factor: bool,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+
+    /// Whether this is the session making the request
+    pub current: bool
+}
+
+/// An OpenID Connect login in progress, between the redirect to the provider and the callback.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OidcLogin {
+    pub state: String,
+    pub nonce: String,
+    pub verifier: String,
+    pub started: DateTime<Utc>,
+
+    /// Path to return to after logging in
+    #[serde(default)]
+    pub redirect: Option<String>,
+
+    /// Links the identity to this logged-in user instead of logging in
+    #[serde(default)]
+    pub link_user: Option<Uuid>,
 }
 
 impl Session {
     pub fn create() -> Self {
         Session {
             id: Uuid::new(),
-            created: Utc::now(),
-            last_connection: Utc::now(),
+            created: bson::DateTime::now(),
+            last_connection: bson::DateTime::now(),
             user: None,
+            pending_second_factor: false,
+            oidc: None,
+            oidc_id_token: None,
+            ip: None,
+            user_agent: None,
             _collection: None
         }
     }
+
+    pub fn expired(&self, lifetime: TimeDelta) -> bool {
+        self.last_connection.to_chrono() + lifetime < Utc::now()
+    }
+
+    /// Saves the session and sends its cookie. Anonymous sessions only get a cookie once they are persisted.
+    pub async fn persist(&self, cookies: &CookieJar<'_>, config: &AppConfig) -> ApiResult<()> {
+        self.save().await.or_else(|e| Err(ApiError::from(Error::Unexpected(e.to_string()))))?;
+        cookies.add_private(session_cookie(self, config));
+        Ok(())
+    }
+
+    /// Moves the session to a new ID and persists it. Called whenever the session's privileges change, so that an
+    /// ID observed before a login cannot be used after it.
+    pub async fn rotate(&mut self, cookies: &CookieJar<'_>, config: &AppConfig) -> ApiResult<()> {
+        let _ = Collection::<Self>::new().delete_many(doc! {"_id": self.id}).await;
+        self.id = Uuid::new();
+        self.created = bson::DateTime::now();
+        self.last_connection = bson::DateTime::now();
+        self.persist(cookies, config).await
+    }
+
+    /// Deletes the session and its cookie, on logout.
+    pub async fn end(self, cookies: &CookieJar<'_>) -> ApiResult<()> {
+        cookies.remove_private(SESSION_COOKIE);
+        Collection::<Self>::new()
+            .delete_many(doc! {"_id": self.id})
+            .await
+            .or_else(|e| Err(ApiError::from(Error::Unexpected(e.to_string()))))?;
+        Ok(())
+    }
+
+    /// Describes the request's own session.
+    pub fn info(&self) -> SessionInfo {
+        SessionInfo {
+            id: self.id,
+            created: self.created.to_chrono(),
+            last_connection: self.last_connection.to_chrono(),
+            authenticated: self.user.is_some() && !self.pending_second_factor,
+            pending_second_factor: self.pending_second_factor,
+            ip: self.ip.clone(),
+            user_agent: self.user_agent.clone(),
+            current: true
+        }
+    }
+
+    /// Describes one of the sessions listed to the user of the `current` session.
+    pub fn listed(&self, current: Uuid) -> SessionInfo {
+        SessionInfo {
+            current: self.id == current,
+            ..self.info()
+        }
+    }
+
+    /// Query for sessions used at or after `cutoff`. Sessions saved by earlier versions store their dates as
+    /// RFC 3339 strings, which sort chronologically.
+    pub fn active_since(cutoff: DateTime<Utc>) -> bson::Document {
+        doc! {"$or": [
+            {"last_connection": {"$gte": bson::DateTime::from_chrono(cutoff)}},
+            {"last_connection": {"$type": "string", "$gte": cutoff.to_rfc3339_opts(SecondsFormat::AutoSi, true)}},
+        ]}
+    }
+
+    /// A user's sessions that have not expired, most recently used first.
+    pub async fn for_user(user: Uuid, lifetime: TimeDelta) -> ApiResult<Vec<Self>> {
+        let mut filter = Self::active_since(Utc::now() - lifetime);
+        filter.insert("user.id", user);
+        let mut sessions: Vec<Self> = Collection::<Self>::new()
+            .find_many(filter)
+            .await
+            .or_else(|e| Err(ApiError::from(Error::Unexpected(e.to_string()))))?
+            .try_collect::<Vec<Self>>()
+            .await
+            .or_else(|e| Err(ApiError::from(Error::Unexpected(e.to_string()))))?;
+        sessions.sort_by(|a, b| b.last_connection.cmp(&a.last_connection));
+        Ok(sessions)
+    }
+
+    /// Logs a user out of one of their sessions.
+    pub async fn revoke(user: Uuid, id: impl Into<Uuid>) -> ApiResult<()> {
+        let id: Uuid = id.into();
+        let removed = Collection::<Self>::new()
+            .delete_many(doc! {"_id": id, "user.id": user})
+            .await
+            .or_else(|e| Err(ApiError::from(Error::Unexpected(e.to_string()))))?;
+        if removed == 0 {
+            return Err(ApiError::not_found(format!("Session: {id}")));
+        }
+        Ok(())
+    }
+
+    /// Logs a user out of every session, except `keep` if given. Returns the number of sessions removed.
+    pub async fn invalidate_user(user: Uuid, keep: Option<Uuid>) -> ApiResult<u64> {
+        let filter = match keep {
+            Some(keep) => doc! {"user.id": user, "_id": {"$ne": keep}},
+            None => doc! {"user.id": user}
+        };
+        Collection::<Self>::new()
+            .delete_many(filter)
+            .await
+            .or_else(|e| Err(ApiError::from(Error::Unexpected(e.to_string()))))
+    }
 }
 
 #[async_trait]
@@ -54,7 +229,7 @@ impl<'r> FromRequest<'r> for Session {
 }
 
 #[schema(collection = "users")]
-#[derive(JsonSchema, OpenApiFromRequest)]
+#[derive(JsonSchema)]
 pub struct User {
     #[field(id = Uuid::new)]
     #[schemars(with = "uuid::Uuid")]
@@ -65,7 +240,47 @@ pub struct User {
     pub hashed_password: HashedPassword,
 
     #[serde(default)]
-    pub superuser: bool
+    pub superuser: bool,
+
+    /// Disabled accounts cannot log in, and their sessions are invalidated
+    #[serde(default)]
+    pub disabled: bool,
+
+    /// The user has to change their password before doing anything else
+    #[serde(default)]
+    pub password_reset_required: bool,
+
+    /// Roles applied on every server the user has been granted access to, on top of the grant
+    #[serde(default)]
+    #[schemars(with = "Vec<uuid::Uuid>")]
+    pub roles: Vec<Uuid>,
+
+    #[serde(default)]
+    #[schemars(skip)]
+    pub two_factor: Option<TwoFactor>,
+
+    /// `<issuer>#<subject>` of the OpenID Connect identity linked to this user
+    #[serde(default)]
+    pub oidc_subject: Option<String>,
+
+    /// Restricts the console commands the user may send on servers they do not own
+    #[serde(default)]
+    pub commands: CommandFilter
+}
+
+/// A user's TOTP secret. Only active once a first code was verified.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TwoFactor {
+    /// Base32-encoded shared secret
+    pub secret: String,
+    pub enabled: bool,
+
+    #[serde(default)]
+    pub recovery_codes: Vec<HashedPassword>,
+
+    /// Time step of the last accepted code, so that codes cannot be replayed
+    #[serde(default)]
+    pub last_step: Option<u64>
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
@@ -73,7 +288,15 @@ pub struct RedactedUser {
     #[schemars(with = "uuid::Uuid")]
     pub id: Uuid,
     pub username: String,
-    pub superuser: bool
+    pub superuser: bool,
+    pub disabled: bool,
+    pub password_reset_required: bool,
+
+    #[schemars(with = "Vec<uuid::Uuid>")]
+    pub roles: Vec<Uuid>,
+    pub two_factor_enabled: bool,
+    pub oidc_linked: bool,
+    pub commands: CommandFilter
 }
 
 impl User {
@@ -83,6 +306,12 @@ impl User {
             username: username.into(),
             hashed_password: HashedPassword::new(password)?,
             superuser: false,
+            disabled: false,
+            password_reset_required: false,
+            roles: Vec::new(),
+            two_factor: None,
+            oidc_subject: None,
+            commands: CommandFilter::default(),
             _collection: None
         })
     }
@@ -93,12 +322,80 @@ impl User {
             username: username.into(),
             hashed_password: HashedPassword::new(password)?,
             superuser: true,
+            disabled: false,
+            password_reset_required: false,
+            roles: Vec::new(),
+            two_factor: None,
+            oidc_subject: None,
+            commands: CommandFilter::default(),
             _collection: None
         })
     }
 
     pub fn redact(&self) -> RedactedUser {
-        RedactedUser { id: self.id.clone(), username: self.username.clone(), superuser: self.superuser }
+        RedactedUser {
+            id: self.id.clone(),
+            username: self.username.clone(),
+            superuser: self.superuser,
+            disabled: self.disabled,
+            password_reset_required: self.password_reset_required,
+            roles: self.roles.clone(),
+            two_factor_enabled: self.two_factor_enabled(),
+            oidc_linked: self.oidc_subject.is_some(),
+            commands: self.commands.clone()
+        }
+    }
+
+    pub fn validate_password(password: &str) -> ApiResult<()> {
+        if password.chars().count() < PASSWORD_MIN_LENGTH {
+            return Err(ApiError::bad_request(format!("Passwords must be at least {PASSWORD_MIN_LENGTH} characters long")));
+        }
+        Ok(())
+    }
+
+    pub fn set_password(&mut self, password: impl Into<String>) -> ApiResult<()> {
+        let password: String = password.into();
+        Self::validate_password(&password)?;
+        self.hashed_password = HashedPassword::new(password)?;
+        Ok(())
+    }
+
+    pub fn two_factor_enabled(&self) -> bool {
+        self.two_factor.as_ref().is_some_and(|t| t.enabled)
+    }
+
+    /// Checks a TOTP code or, failing that, a recovery code, which is then used up. The user has to be saved
+    /// afterwards to record the used code.
+    pub fn verify_second_factor(&mut self, code: &str) -> bool {
+        let Some(two_factor) = self.two_factor.as_mut().filter(|t| t.enabled) else {
+            return false;
+        };
+        if let Some(secret) = base32_decode(&two_factor.secret) {
+            if let Some(step) = verify_totp(&secret, code, Utc::now().timestamp()) {
+                if two_factor.last_step.is_some_and(|last| step <= last) {
+                    return false;
+                }
+                two_factor.last_step = Some(step);
+                return true;
+            }
+        }
+
+        let normalized = normalize_recovery_code(code);
+        match two_factor.recovery_codes.iter().position(|c| c.verify(normalized.clone())) {
+            Some(index) => {
+                two_factor.recovery_codes.remove(index);
+                true
+            }
+            None => false
+        }
+    }
+
+    pub async fn get(id: impl Into<Uuid>) -> ApiResult<Self> {
+        let id: Uuid = id.into();
+        match Collection::<Self>::new().get(id).await {
+            Ok(Some(user)) => Ok(user),
+            _ => Err(ApiError::not_found(format!("User: {id}")))
+        }
     }
 
     pub async fn from_username(username: impl Into<String>) -> Option<User> {
@@ -106,15 +403,96 @@ impl User {
     }
 }
 
+/// Recovery codes are shown as `xxxxx-xxxxx`, but accepted in any case and without the dash.
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_lowercase()
+}
+
+/// Whether the request is authenticated by a session that signed in through OpenID Connect, rather than with
+/// a password or an API token.
+async fn signed_in_with_oidc(req: &Request<'_>) -> bool {
+    if bearer_user(req).await.is_some() {
+        return false;
+    }
+    matches!(req.guard::<Session>().await, request::Outcome::Success(session) if session.oidc_id_token.is_some())
+}
+
 #[async_trait]
 impl<'r> FromRequest<'r> for User {
     type Error = ApiError;
 
     async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match req.guard::<PendingUser>().await {
+            request::Outcome::Success(PendingUser(user)) if user.password_reset_required => {
+                ApiError::missing_auth("password_reset_required").respond(&req)
+            }
+            request::Outcome::Success(PendingUser(user)) if !user.two_factor_enabled() => {
+                if signed_in_with_oidc(req).await {
+                    return request::Outcome::Success(user);
+                }
+                match SecuritySettings::for_request(req).await {
+                    Ok(settings) if settings.require_two_factor => {
+                        ApiError::missing_auth("two_factor_enrollment_required").respond(&req)
+                    }
+                    Ok(_) => request::Outcome::Success(user),
+                    Err(e) => e.respond(&req)
+                }
+            }
+            request::Outcome::Success(PendingUser(user)) => request::Outcome::Success(user),
+            request::Outcome::Error(e) => request::Outcome::Error(e),
+            request::Outcome::Forward(f) => request::Outcome::Forward(f)
+        }
+    }
+}
+
+/// A logged-in user that may still have to complete a required step, such as changing their password or
+/// enrolling in two-factor authentication.
+/// Only for the endpoints that complete those steps; everything else uses [User].
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct PendingUser(pub User);
+
+impl<'r> OpenApiFromRequest<'r> for User {
+    fn from_request_input(
+        _gen: &mut rocket_okapi::r#gen::OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<rocket_okapi::request::RequestHeaderInput> {
+        Ok(session_security())
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for PendingUser {
+    fn from_request_input(
+        _gen: &mut rocket_okapi::r#gen::OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<rocket_okapi::request::RequestHeaderInput> {
+        Ok(session_security())
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for PendingUser {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match bearer_user(req).await {
+            Some(Ok((_, user))) if user.disabled => return ApiError::missing_auth("account_disabled").respond(&req),
+            Some(Ok((_, user))) => return request::Outcome::Success(Self(user)),
+            Some(Err(e)) => return e.respond(&req),
+            None => {}
+        }
+
         if let request::Outcome::Success(session) = req.guard::<Session>().await {
             if let Some(mut link) = session.user {
                 if let Ok(user) = link.resolve().await {
-                    return request::Outcome::Success(user.clone());
+                    if user.disabled {
+                        return ApiError::missing_auth("account_disabled").respond(&req);
+                    }
+                    if session.pending_second_factor {
+                        return ApiError::missing_auth("second_factor_required").respond(&req);
+                    }
+                    return request::Outcome::Success(Self(user.clone()));
                 }
             }
 
@@ -160,10 +538,18 @@ impl<'r> FromRequest<'r> for OptionalUser {
     type Error = ApiError;
 
     async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match bearer_user(req).await {
+            Some(Ok((_, user))) if !user.disabled => return request::Outcome::Success(Self::some(user)),
+            Some(_) => return request::Outcome::Success(Self::none()),
+            None => {}
+        }
+
         if let request::Outcome::Success(session) = req.guard::<Session>().await {
             if let Some(mut link) = session.user {
                 if let Ok(user) = link.resolve().await {
-                    return request::Outcome::Success(Self::some(user.clone()));
+                    if !user.disabled && !session.pending_second_factor {
+                        return request::Outcome::Success(Self::some(user.clone()));
+                    }
                 }
             }
 
@@ -173,3 +559,80 @@ impl<'r> FromRequest<'r> for OptionalUser {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use slink_common::utilities::{base32_encode, hotp, totp_step};
+
+    use super::*;
+
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    fn enrolled_user(recovery_codes: &[&str]) -> User {
+        let mut user = User::create("steve", "correct horse battery").unwrap();
+        user.two_factor = Some(TwoFactor {
+            secret: base32_encode(SECRET),
+            enabled: true,
+            recovery_codes: recovery_codes
+                .iter()
+                .map(|code| HashedPassword::new(normalize_recovery_code(code)).unwrap())
+                .collect(),
+            last_step: None,
+        });
+        user
+    }
+
+    fn current_code() -> String {
+        format!("{:06}", hotp(SECRET, totp_step(Utc::now().timestamp())))
+    }
+
+    #[test]
+    fn totp_codes_cannot_be_replayed() {
+        let mut user = enrolled_user(&[]);
+        let code = current_code();
+        assert!(user.verify_second_factor(&code));
+        assert!(!user.verify_second_factor(&code));
+    }
+
+    #[test]
+    fn recovery_codes_are_used_up() {
+        let mut user = enrolled_user(&["abcde-fghij", "klmno-pqrst"]);
+        assert!(user.verify_second_factor("ABCDE FGHIJ"));
+        assert!(!user.verify_second_factor("abcde-fghij"));
+        assert_eq!(user.two_factor.as_ref().unwrap().recovery_codes.len(), 1);
+
+        assert!(!user.verify_second_factor("zzzzz-zzzzz"));
+        assert_eq!(user.two_factor.as_ref().unwrap().recovery_codes.len(), 1);
+        assert!(user.verify_second_factor("klmnopqrst"));
+        assert!(user.two_factor.as_ref().unwrap().recovery_codes.is_empty());
+    }
+
+    #[test]
+    fn unconfirmed_enrollments_accept_nothing() {
+        let mut user = enrolled_user(&["abcde-fghij"]);
+        user.two_factor.as_mut().unwrap().enabled = false;
+        assert!(!user.verify_second_factor(&current_code()));
+        assert!(!user.verify_second_factor("abcde-fghij"));
+        assert!(!user.two_factor_enabled());
+
+        user.two_factor = None;
+        assert!(!user.verify_second_factor(&current_code()));
+    }
+
+    #[test]
+    fn active_sessions_match_dates_and_legacy_strings() {
+        let cutoff = chrono::TimeZone::with_ymd_and_hms(&Utc, 2024, 6, 1, 12, 0, 0).unwrap();
+        assert_eq!(
+            Session::active_since(cutoff),
+            doc! {"$or": [
+                {"last_connection": {"$gte": bson::DateTime::from_chrono(cutoff)}},
+                {"last_connection": {"$type": "string", "$gte": "2024-06-01T12:00:00Z"}},
+            ]}
+        );
+    }
+
+    #[test]
+    fn recovery_codes_are_normalized() {
+        assert_eq!(normalize_recovery_code(" AbCdE-fGhIj\n"), "abcdefghij");
+    }
+}
@@ -0,0 +1,122 @@
+use std::path::PathBuf;
+
+use bson::{Uuid, doc};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use manor::{Collection, schema};
+use schemars::JsonSchema;
+use slink_common::{
+    ApiError, ApiResult, Error,
+    providers::servers::ServerBinaryVersion,
+    types::{AppConfig, BackupFormat, BackupTrigger},
+};
+
+use super::MinecraftServer;
+
+/// A compressed snapshot of a server directory.
+#[schema(collection = "backups")]
+#[derive(JsonSchema)]
+pub struct Backup {
+    #[field(id = Uuid::new)]
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+
+    #[schemars(with = "uuid::Uuid")]
+    pub server: Uuid,
+    pub created: DateTime<Utc>,
+    pub trigger: BackupTrigger,
+
+    #[serde(default)]
+    pub format: BackupFormat,
+
+    #[serde(default)]
+    pub note: Option<String>,
+
+    /// Archive location relative to the backup directory, or the snapshot ID for incremental backups
+    pub file: String,
+
+    /// Remote target holding the archive, if it was moved off the local backup directory
+    #[serde(default)]
+    pub target: Option<String>,
+
+    /// Archive size, or the total size of the snapshotted files for incremental backups, in bytes
+    pub size: u64,
+
+    /// Hex-encoded SHA-256 of the archive, or of the snapshot manifest
+    pub checksum: String,
+    pub minecraft_version: String,
+
+    #[serde(default)]
+    pub modloader_version: Option<ServerBinaryVersion>,
+}
+
+impl Backup {
+    pub fn create(server: &MinecraftServer, trigger: BackupTrigger, format: BackupFormat, note: Option<String>) -> Self {
+        let id = Uuid::new();
+        let created = Utc::now();
+        Backup {
+            id,
+            server: server.id,
+            created,
+            trigger,
+            format,
+            note,
+            file: match format {
+                BackupFormat::Archive => format!("{}/{}-{id}.tar.gz", server.id, created.format("%Y%m%d-%H%M%S")),
+                BackupFormat::Incremental => id.to_string(),
+            },
+            target: None,
+            size: 0,
+            checksum: String::new(),
+            minecraft_version: server.minecraft_version.version.id.clone(),
+            modloader_version: server.modloader_version.clone(),
+            _collection: None,
+        }
+    }
+
+    /// Local location of the archive, or of the snapshot manifest for incremental backups.
+    pub fn path(&self, config: &AppConfig) -> PathBuf {
+        match self.format {
+            BackupFormat::Archive => config.backup_directory().join(&self.file),
+            BackupFormat::Incremental => config
+                .backup_repository()
+                .join("snapshots")
+                .join(format!("{}.json", self.file)),
+        }
+    }
+
+    /// IDs of all incremental snapshots that are still referenced by a backup.
+    pub async fn snapshot_ids() -> ApiResult<Vec<String>> {
+        Ok(Collection::<Self>::new()
+            .find_many(doc! {"format": "incremental"})
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?
+            .try_collect::<Vec<Self>>()
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?
+            .into_iter()
+            .map(|backup| backup.file)
+            .collect())
+    }
+
+    /// Lists a server's backups, newest first.
+    pub async fn for_server(server: Uuid) -> ApiResult<Vec<Self>> {
+        let mut backups = Collection::<Self>::new()
+            .find_many(doc! {"server": server})
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?
+            .try_collect::<Vec<Self>>()
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+        backups.sort_by_key(|b| std::cmp::Reverse(b.created));
+        Ok(backups)
+    }
+
+    pub async fn get_for(server: Uuid, id: impl Into<Uuid>) -> ApiResult<Self> {
+        let id: Uuid = id.into();
+        match Collection::<Self>::new().get(id).await {
+            Ok(Some(backup)) if backup.server == server => Ok(backup),
+            _ => Err(ApiError::not_found(format!("Backup: {id}"))),
+        }
+    }
+}
@@ -1,11 +1,24 @@
+use std::path::PathBuf;
+
 use crate::util::types::TSLink;
-use bson::Uuid;
+use bson::{Bson, Uuid};
+use bytesize::ByteSize;
 use manor::{Link, schema};
 use schemars::JsonSchema;
-use slink_common::{providers::servers::ServerBinaryVersion, types::MinecraftVersionMetadata};
+use slink_common::{
+    ApiError, ApiResult, Error, SERVER_BINARY_NAME,
+    providers::servers::ServerBinaryVersion,
+    runners::{MinecraftRunnerConfig, MinecraftRunnerPort, PortExposure},
+    types::{AppConfig, MinecraftVersionMetadata, RetentionPolicy, ServerProperties},
+    utilities::Sandbox,
+};
 
 use super::User;
 
+fn default_max_memory() -> ByteSize {
+    ByteSize::gib(2)
+}
+
 #[schema(collection = "servers")]
 #[derive(JsonSchema)]
 pub struct MinecraftServer {
@@ -19,5 +32,102 @@ pub struct MinecraftServer {
     pub minecraft_version: MinecraftVersionMetadata,
 
     #[serde(default)]
-    pub modloader_version: Option<ServerBinaryVersion>
+    pub modloader_version: Option<ServerBinaryVersion>,
+
+    #[serde(default = "default_max_memory")]
+    #[schemars(with = "String")]
+    pub max_memory: ByteSize,
+
+    #[serde(default)]
+    pub java_args: Vec<String>,
+
+    /// Port exposed on the runner's host, if different from the configured server port.
+    #[serde(default)]
+    pub port: Option<u16>,
+
+    /// Backup retention for this server, overriding the configured default.
+    #[serde(default)]
+    pub backup_retention: Option<RetentionPolicy>,
+
+    /// Named backup target that archive backups are uploaded to, instead of staying in the local backup directory.
+    #[serde(default)]
+    pub backup_target: Option<String>
+}
+
+impl MinecraftServer {
+    pub fn create(name: impl Into<String>, owner: User, minecraft_version: MinecraftVersionMetadata, modloader_version: Option<ServerBinaryVersion>) -> Self {
+        MinecraftServer {
+            id: Uuid::new(),
+            name: name.into(),
+            owner: Link::from(owner),
+            minecraft_version,
+            modloader_version,
+            max_memory: default_max_memory(),
+            java_args: Vec::new(),
+            port: None,
+            backup_retention: None,
+            backup_target: None,
+            _collection: None
+        }
+    }
+
+    pub fn owned_by(&self, user: &User) -> bool {
+        user.superuser || Bson::from(self.owner.id.clone()) == Bson::from(user.id)
+    }
+
+    pub fn directory(&self, config: &AppConfig) -> PathBuf {
+        config.runner.server_directory(self.id)
+    }
+
+    /// Opens the server's directory as a sandbox, creating it if it does not exist yet.
+    pub async fn sandbox(&self, config: &AppConfig) -> ApiResult<Sandbox> {
+        let directory = self.directory(config);
+        tokio::fs::create_dir_all(&directory)
+            .await
+            .or_else(|e| Err(ApiError::from(Error::Unexpected(e.to_string()))))?;
+        Ok(Sandbox::new(directory).await?)
+    }
+
+    /// Reads the server's `server.properties`, falling back to defaults if it does not exist yet.
+    pub async fn properties(&self, config: &AppConfig) -> ServerProperties {
+        ServerProperties::from_file(self.directory(config).join("server.properties"))
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Loader name as used by mod catalogs, or `None` for vanilla servers.
+    pub fn loader(&self) -> Option<&'static str> {
+        match self.modloader_version {
+            Some(ServerBinaryVersion::Fabric(_)) => Some("fabric"),
+            None => None,
+        }
+    }
+
+    pub fn retention(&self, config: &AppConfig) -> RetentionPolicy {
+        self.backup_retention.clone().unwrap_or(config.backups.retention.clone())
+    }
+
+    pub fn runner_config(&self, properties: &ServerProperties) -> MinecraftRunnerConfig {
+        let mut ports = vec![MinecraftRunnerPort::Server(
+            properties.server_port,
+            self.port.unwrap_or(properties.server_port),
+            PortExposure::Global,
+        )];
+        if properties.enable_rcon {
+            ports.push(MinecraftRunnerPort::Rcon(properties.rcon_port, properties.rcon_port, PortExposure::Runner));
+        }
+        if properties.enable_query {
+            ports.push(MinecraftRunnerPort::Query(properties.query_port, properties.query_port, PortExposure::Runner));
+        }
+
+        MinecraftRunnerConfig {
+            runner_id: self.id.into(),
+            java_version: self.minecraft_version.java_version.clone(),
+            max_memory: self.max_memory,
+            binary: SERVER_BINARY_NAME.to_string(),
+            java_args: self.java_args.clone(),
+            minecraft_args: vec![String::from("nogui")],
+            ports
+        }
+    }
 }
@@ -1,5 +1,21 @@
+mod audit;
 mod auth;
+mod backups;
 mod minecraft_server;
+mod permissions;
+mod players;
+mod schedules;
+mod settings;
+mod templates;
+mod tokens;
 
-pub use auth::{Session, User, RedactedUser, OptionalUser};
-pub use minecraft_server::*;
\ No newline at end of file
+pub use audit::{AuditEntry, AuditEntryInfo, Auditor};
+pub use auth::{Session, SessionInfo, User, RedactedUser, OptionalUser, PendingUser, TwoFactor, OidcLogin, normalize_recovery_code};
+pub use backups::*;
+pub use minecraft_server::*;
+pub use permissions::*;
+pub use players::*;
+pub use schedules::*;
+pub use settings::SecuritySettings;
+pub use templates::*;
+pub use tokens::{ApiToken, RedactedToken, RequestToken, TokenScope};
@@ -0,0 +1,588 @@
+use std::{collections::HashSet, marker::PhantomData};
+
+use bson::{Uuid, doc};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use log::warn;
+use manor::{Collection, schema};
+use regex::Regex;
+use rocket::{
+    Request,
+    http::Method,
+    request::{self, FromRequest},
+};
+use rocket_okapi::request::OpenApiFromRequest;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{ApiError, ApiResult, Error};
+
+use super::{Auditor, MinecraftServer, User, tokens::bearer_user};
+use crate::util::security::session_security;
+
+/// Something a user may do on a server.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, JsonSchema, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// See the server, its status and its players
+    View,
+    ConsoleRead,
+    ConsoleWrite,
+
+    /// Start and stop the server
+    Power,
+
+    /// Browse and change files, archives and installed content
+    Files,
+    Backups,
+
+    /// Change server settings, player lists and scheduled tasks
+    Properties,
+    Delete,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 8] = [
+        Self::View,
+        Self::ConsoleRead,
+        Self::ConsoleWrite,
+        Self::Power,
+        Self::Files,
+        Self::Backups,
+        Self::Properties,
+        Self::Delete,
+    ];
+}
+
+/// Matches console commands, ignoring a leading `/` and the namespace of the command name.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandRule {
+    /// Matches a command by its leading words, case-insensitively: `whitelist` matches `whitelist add Steve` but
+    /// not `whitelisted`
+    Prefix(String),
+
+    /// Matches commands the regular expression finds a match in. Anchor it with `^` to match from the start.
+    Regex(String),
+}
+
+impl CommandRule {
+    fn matches(&self, command: &str) -> bool {
+        match self {
+            Self::Prefix(prefix) => {
+                let prefix = prefix.trim().trim_start_matches('/').to_lowercase();
+                let command = command.to_lowercase();
+                command
+                    .strip_prefix(&prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+            }
+            Self::Regex(pattern) => match Regex::new(pattern) {
+                Ok(regex) => regex.is_match(command),
+                Err(e) => {
+                    warn!("Ignoring invalid command pattern {pattern}: {e}");
+                    false
+                }
+            },
+        }
+    }
+}
+
+/// Console commands a user or role may send. Commands matching a deny rule are refused; when there are allow
+/// rules, commands must also match one of them.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema, PartialEq, Eq)]
+pub struct CommandFilter {
+    #[serde(default)]
+    pub allow: Vec<CommandRule>,
+
+    #[serde(default)]
+    pub deny: Vec<CommandRule>,
+}
+
+impl CommandFilter {
+    /// Fails if a rule is empty or not a valid regular expression.
+    pub fn validate(&self) -> ApiResult<()> {
+        for rule in self.allow.iter().chain(&self.deny) {
+            match rule {
+                CommandRule::Prefix(prefix) if prefix.trim().trim_start_matches('/').is_empty() => {
+                    return Err(ApiError::bad_request("Command prefixes cannot be empty"));
+                }
+                CommandRule::Regex(pattern) => {
+                    Regex::new(pattern)
+                        .map_err(|e| ApiError::bad_request(format!("Invalid command pattern {pattern}: {e}")))?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Combines the rules of several filters, such as a user's and those of their roles.
+    pub fn extend(&mut self, other: CommandFilter) {
+        self.allow.extend(other.allow);
+        self.deny.extend(other.deny);
+    }
+
+    /// Whether the filter lets a command through. Commands nested in `execute … run` have to pass too.
+    pub fn allows(&self, command: &str) -> bool {
+        Self::commands(command).iter().all(|command| {
+            !self.deny.iter().any(|rule| rule.matches(command))
+                && (self.allow.is_empty() || self.allow.iter().any(|rule| rule.matches(command)))
+        })
+    }
+
+    /// A command without its leading `/` and the namespace of its name, so `/minecraft:op Steve` becomes
+    /// `op Steve`.
+    fn normalize(command: &str) -> String {
+        let command = command.trim().trim_start_matches('/').trim_start();
+        let (name, arguments) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        let name = name.rsplit_once(':').map(|(_, name)| name).unwrap_or(name);
+        format!("{name} {arguments}").trim_end().to_string()
+    }
+
+    /// The command and every command `execute` would run on its behalf. The words after each `run` are treated as
+    /// a command, since a player or entity named `run` makes the real one ambiguous. This also covers nested
+    /// `execute` commands.
+    fn commands(command: &str) -> Vec<String> {
+        let command = Self::normalize(command);
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let mut commands = Vec::new();
+        if words.first().is_some_and(|name| name.eq_ignore_ascii_case("execute")) {
+            commands = words
+                .iter()
+                .enumerate()
+                .filter(|(_, word)| word.eq_ignore_ascii_case("run"))
+                .map(|(index, _)| Self::normalize(&words[index + 1..].join(" ")))
+                .collect();
+        }
+        commands.insert(0, command);
+        commands
+    }
+
+    /// Fails for commands the filter refuses, auditing the attempt.
+    pub async fn require(&self, server: Uuid, command: &str, audit: &Auditor) -> ApiResult<()> {
+        if !self.allows(command) {
+            audit.entry("console.denied").server(server).target(command.trim()).record().await;
+            return Err(ApiError::missing_auth("command_denied"));
+        }
+        Ok(())
+    }
+}
+
+/// A named set of permissions, granted per server or to users globally.
+#[schema(collection = "roles")]
+#[derive(JsonSchema)]
+pub struct Role {
+    #[field(id = Uuid::new)]
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+    pub name: String,
+
+    #[serde(default)]
+    pub description: Option<String>,
+    pub permissions: Vec<Permission>,
+
+    /// Restricts the console commands of users with this role
+    #[serde(default)]
+    pub commands: CommandFilter,
+}
+
+impl Role {
+    pub fn new(name: impl Into<String>, description: Option<String>, permissions: Vec<Permission>) -> Self {
+        Self {
+            id: Uuid::new(),
+            name: name.into(),
+            description,
+            permissions,
+            commands: CommandFilter::default(),
+            _collection: None,
+        }
+    }
+
+    pub async fn get(id: impl Into<Uuid>) -> ApiResult<Self> {
+        let id: Uuid = id.into();
+        match Collection::<Self>::new().get(id).await {
+            Ok(Some(role)) => Ok(role),
+            _ => Err(ApiError::not_found(format!("Role: {id}"))),
+        }
+    }
+
+    pub async fn all() -> ApiResult<Vec<Self>> {
+        Self::find(doc! {}).await
+    }
+
+    pub async fn many(ids: &[Uuid]) -> ApiResult<Vec<Self>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        Self::find(doc! {"_id": {"$in": ids.to_vec()}}).await
+    }
+
+    /// Checks that every role ID exists, returning them without duplicates.
+    pub async fn resolve(ids: &[uuid::Uuid]) -> ApiResult<Vec<Uuid>> {
+        let mut unique: Vec<Uuid> = Vec::new();
+        for id in ids.iter().map(|id| Uuid::from(*id)) {
+            if !unique.contains(&id) {
+                unique.push(id);
+            }
+        }
+        let found = Self::many(&unique).await?;
+        match unique.iter().find(|id| !found.iter().any(|role| role.id == **id)) {
+            Some(missing) => Err(ApiError::not_found(format!("Role: {missing}"))),
+            None => Ok(unique),
+        }
+    }
+
+    async fn find(filter: bson::Document) -> ApiResult<Vec<Self>> {
+        Collection::<Self>::new()
+            .find_many(filter)
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?
+            .try_collect::<Vec<Self>>()
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))
+    }
+}
+
+/// Access to one server given to a user other than its owner.
+#[schema(collection = "grants")]
+#[derive(JsonSchema)]
+pub struct ServerGrant {
+    #[field(id = Uuid::new)]
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+
+    #[schemars(with = "uuid::Uuid")]
+    pub server: Uuid,
+
+    #[schemars(with = "uuid::Uuid")]
+    pub user: Uuid,
+
+    #[serde(default)]
+    #[schemars(with = "Vec<uuid::Uuid>")]
+    pub roles: Vec<Uuid>,
+
+    /// Permissions granted in addition to those of the roles
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+
+    #[schemars(with = "uuid::Uuid")]
+    pub granted_by: Uuid,
+    pub updated: DateTime<Utc>,
+}
+
+impl ServerGrant {
+    /// A grant without permissions, to be filled in by the caller.
+    pub fn new(server: Uuid, user: Uuid, granted_by: Uuid) -> Self {
+        Self {
+            id: Uuid::new(),
+            server,
+            user,
+            roles: Vec::new(),
+            permissions: Vec::new(),
+            granted_by,
+            updated: Utc::now(),
+            _collection: None,
+        }
+    }
+
+    async fn find(filter: bson::Document) -> ApiResult<Vec<Self>> {
+        Collection::<Self>::new()
+            .find_many(filter)
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?
+            .try_collect::<Vec<Self>>()
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))
+    }
+
+    pub async fn for_server(server: Uuid) -> ApiResult<Vec<Self>> {
+        Self::find(doc! {"server": server}).await
+    }
+
+    pub async fn for_user(user: Uuid) -> ApiResult<Vec<Self>> {
+        Self::find(doc! {"user": user}).await
+    }
+
+    pub async fn get(server: Uuid, user: Uuid) -> ApiResult<Option<Self>> {
+        Collection::<Self>::new()
+            .find_one(doc! {"server": server, "user": user})
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))
+    }
+
+    /// Removes all grants on a server, when it is deleted.
+    pub async fn revoke_all(server: Uuid) -> ApiResult<u64> {
+        Self::revoke(doc! {"server": server}).await
+    }
+
+    /// Removes all grants given to a user, when they are deleted.
+    pub async fn revoke_user(user: Uuid) -> ApiResult<u64> {
+        Self::revoke(doc! {"user": user}).await
+    }
+
+    async fn revoke(filter: bson::Document) -> ApiResult<u64> {
+        Collection::<Self>::new()
+            .delete_many(filter)
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))
+    }
+}
+
+impl MinecraftServer {
+    /// What the user may do on this server: everything for superusers and the owner, nothing without a grant,
+    /// and otherwise the union of the grant and the user's global roles.
+    pub async fn permissions(&self, user: &User) -> ApiResult<HashSet<Permission>> {
+        if self.owned_by(user) {
+            return Ok(HashSet::from(Permission::ALL));
+        }
+        let Some(grant) = ServerGrant::get(self.id, user.id).await? else {
+            return Ok(HashSet::new());
+        };
+
+        let mut permissions: HashSet<Permission> = grant.permissions.into_iter().collect();
+        let mut roles = user.roles.clone();
+        roles.extend(grant.roles);
+        for role in Role::many(&roles).await? {
+            permissions.extend(role.permissions);
+        }
+        Ok(permissions)
+    }
+
+    /// The console commands the user may send: unrestricted for superusers and the owner, otherwise the rules of
+    /// the user and of their granted roles combined, along with their global roles if they have a grant.
+    pub async fn command_filter(&self, user: &User) -> ApiResult<CommandFilter> {
+        if self.owned_by(user) {
+            return Ok(CommandFilter::default());
+        }
+
+        let mut filter = user.commands.clone();
+        let mut roles = Vec::new();
+        if let Some(grant) = ServerGrant::get(self.id, user.id).await? {
+            roles.extend(user.roles.clone());
+            roles.extend(grant.roles);
+        }
+        for role in Role::many(&roles).await? {
+            filter.extend(role.commands);
+        }
+        Ok(filter)
+    }
+}
+
+/// Type-level permission, for [ServerAccess].
+pub trait RequiredPermission: Send + Sync {
+    const PERMISSION: Permission;
+}
+
+/// Marker types naming each [Permission] for use as `ServerAccess<perms::Files>`.
+pub mod perms {
+    use super::{Permission, RequiredPermission};
+
+    macro_rules! marker {
+        ($($name:ident),*) => {
+            $(
+                pub struct $name;
+
+                impl RequiredPermission for $name {
+                    const PERMISSION: Permission = Permission::$name;
+                }
+            )*
+        };
+    }
+
+    marker!(View, ConsoleRead, ConsoleWrite, Power, Files, Backups, Properties, Delete);
+}
+
+/// Request guard resolving the server addressed by the first path segment after the mount point, and
+/// requiring the user to hold `P` on it. Servers the user cannot view are reported as nonexistent.
+pub struct ServerAccess<P: RequiredPermission> {
+    pub server: MinecraftServer,
+    pub user: User,
+    pub permissions: HashSet<Permission>,
+
+    /// Whether the request was authenticated with an API token that may only read. Checked by routes that
+    /// write from a `GET` request, such as the console socket.
+    pub read_only: bool,
+    _permission: PhantomData<P>,
+}
+
+impl<P: RequiredPermission> ServerAccess<P> {
+    pub fn has(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
+    }
+
+    pub fn require(&self, permission: Permission) -> ApiResult<()> {
+        match self.has(permission) {
+            true => Ok(()),
+            false => Err(ApiError::missing_auth(format!("permission:{}", permission_name(permission)))),
+        }
+    }
+
+    /// The console commands the user may send on the server.
+    pub async fn commands(&self) -> ApiResult<CommandFilter> {
+        self.server.command_filter(&self.user).await
+    }
+
+    /// Fails unless the user may send `command` on the server, for routes that send commands on their behalf.
+    pub async fn require_command(&self, command: &str, audit: &Auditor) -> ApiResult<()> {
+        self.require(Permission::ConsoleWrite)?;
+        self.commands().await?.require(self.server.id, command, audit).await
+    }
+
+    /// Returns the resolved server, checking that it is the one the route addresses.
+    pub fn server(self, id: impl Into<Uuid>) -> ApiResult<MinecraftServer> {
+        self.server_ref(id)?;
+        Ok(self.server)
+    }
+
+    /// Like [ServerAccess::server], for routes that keep the access to check further permissions.
+    pub fn server_ref(&self, id: impl Into<Uuid>) -> ApiResult<&MinecraftServer> {
+        let id: Uuid = id.into();
+        match self.server.id == id {
+            true => Ok(&self.server),
+            false => Err(ApiError::Uncaught(format!(
+                "Server access resolved {} for a route addressing {id}. This indicates a bug in the server software.",
+                self.server.id
+            ))),
+        }
+    }
+}
+
+fn permission_name(permission: Permission) -> String {
+    serde_json::to_value(permission)
+        .ok()
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl<'r, P: RequiredPermission> FromRequest<'r> for ServerAccess<P> {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let user = match req.guard::<User>().await {
+            request::Outcome::Success(user) => user,
+            request::Outcome::Error(e) => return request::Outcome::Error(e),
+            request::Outcome::Forward(f) => return request::Outcome::Forward(f),
+        };
+        let Some(Ok(id)) = req.param::<uuid::Uuid>(0) else {
+            return ApiError::not_found("Server").respond(req);
+        };
+        let id: Uuid = id.into();
+
+        let server = match Collection::<MinecraftServer>::new().get(id).await {
+            Ok(Some(server)) => server,
+            _ => return ApiError::not_found(format!("Server: {id}")).respond(req),
+        };
+        let permissions = match server.permissions(&user).await {
+            Ok(permissions) => permissions,
+            Err(e) => return e.respond(req),
+        };
+        if !permissions.contains(&Permission::View) {
+            return ApiError::not_found(format!("Server: {id}")).respond(req);
+        }
+
+        let read_only = match bearer_user(req).await {
+            Some(Ok((token, _))) => !token.allows(Method::Post),
+            _ => false,
+        };
+        let access = Self {
+            server,
+            user,
+            permissions,
+            read_only,
+            _permission: PhantomData,
+        };
+        match access.require(P::PERMISSION) {
+            Ok(()) => request::Outcome::Success(access),
+            Err(e) => e.respond(req),
+        }
+    }
+}
+
+impl<'r, P: RequiredPermission> OpenApiFromRequest<'r> for ServerAccess<P> {
+    fn from_request_input(
+        _gen: &mut rocket_okapi::r#gen::OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<rocket_okapi::request::RequestHeaderInput> {
+        Ok(session_security())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(allow: Vec<CommandRule>, deny: Vec<CommandRule>) -> CommandFilter {
+        CommandFilter { allow, deny }
+    }
+
+    fn prefix(value: &str) -> CommandRule {
+        CommandRule::Prefix(value.to_string())
+    }
+
+    fn regex(value: &str) -> CommandRule {
+        CommandRule::Regex(value.to_string())
+    }
+
+    #[test]
+    fn prefixes_match_whole_words() {
+        let filter = rules(vec![], vec![prefix("whitelist"), prefix("/op")]);
+        assert!(!filter.allows("whitelist add Steve"));
+        assert!(!filter.allows("  /WHITELIST"));
+        assert!(!filter.allows("op Steve"));
+        assert!(filter.allows("whitelisted"));
+        assert!(filter.allows("opt Steve"));
+        assert!(filter.allows("say op Steve"));
+    }
+
+    #[test]
+    fn regexes_match_normalized_commands() {
+        let filter = rules(vec![], vec![regex("^gamerule\\s+keepInventory"), regex("@e")]);
+        assert!(!filter.allows("/gamerule keepInventory true"));
+        assert!(!filter.allows("minecraft:gamerule keepInventory true"));
+        assert!(!filter.allows("kill @e[type=item]"));
+        assert!(filter.allows("gamerule doDaylightCycle false"));
+    }
+
+    #[test]
+    fn allow_rules_restrict_commands() {
+        let filter = rules(vec![prefix("say"), prefix("tell")], vec![prefix("tell Notch")]);
+        assert!(filter.allows("say hello"));
+        assert!(filter.allows("/minecraft:tell Steve hi"));
+        assert!(!filter.allows("tell Notch hi"));
+        assert!(!filter.allows("stop"));
+        assert!(CommandFilter::default().allows("stop"));
+    }
+
+    #[test]
+    fn namespaces_do_not_bypass_rules() {
+        let filter = rules(vec![], vec![prefix("op"), prefix("stop")]);
+        assert!(!filter.allows("minecraft:op Steve"));
+        assert!(!filter.allows("/minecraft:stop"));
+        assert!(!filter.allows("MINECRAFT:STOP"));
+        assert!(filter.allows("minecraft:say stop"));
+    }
+
+    #[test]
+    fn execute_runs_are_filtered() {
+        let filter = rules(vec![], vec![prefix("op"), prefix("stop")]);
+        assert!(!filter.allows("execute run op Steve"));
+        assert!(!filter.allows("execute as @a at @s run minecraft:op Steve"));
+        assert!(!filter.allows("minecraft:execute as @p run execute run stop"));
+        assert!(!filter.allows("execute as run run op Steve"));
+        assert!(filter.allows("execute as @a run say hi"));
+
+        let filter = rules(vec![prefix("execute"), prefix("say")], vec![]);
+        assert!(filter.allows("execute as @a run say hi"));
+        assert!(!filter.allows("execute as @a run give @s diamond"));
+    }
+
+    #[test]
+    fn validation_rejects_empty_prefixes_and_bad_patterns() {
+        assert!(rules(vec![prefix(" / ")], vec![]).validate().is_err());
+        assert!(rules(vec![], vec![regex("(")]).validate().is_err());
+        assert!(rules(vec![prefix("say")], vec![regex("^op")]).validate().is_ok());
+    }
+}
@@ -0,0 +1,107 @@
+use bson::{Uuid, doc};
+use chrono::{DateTime, Utc};
+use manor::{Collection, schema};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Aggregated history of a player on one server.
+#[schema(collection = "players")]
+#[derive(JsonSchema)]
+pub struct PlayerRecord {
+    #[field(id = Uuid::new)]
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+
+    #[schemars(with = "uuid::Uuid")]
+    pub server: Uuid,
+    pub name: String,
+
+    #[serde(default)]
+    #[schemars(with = "Option<uuid::Uuid>")]
+    pub player_uuid: Option<Uuid>,
+
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+
+    /// Total time spent online, in seconds
+    #[serde(default)]
+    pub playtime: i64,
+
+    #[serde(default)]
+    pub sessions: u64,
+}
+
+impl PlayerRecord {
+    pub async fn find(server: Uuid, name: impl AsRef<str>) -> Option<Self> {
+        Collection::<Self>::new()
+            .find_one(doc! {"server": server, "name": name.as_ref()})
+            .await
+            .unwrap_or(None)
+    }
+
+    pub fn create(server: Uuid, name: impl Into<String>) -> Self {
+        PlayerRecord {
+            id: Uuid::new(),
+            server,
+            name: name.into(),
+            player_uuid: None,
+            first_seen: Utc::now(),
+            last_seen: Utc::now(),
+            playtime: 0,
+            sessions: 0,
+            _collection: None,
+        }
+    }
+}
+
+/// A single stay of a player on a server, from join to leave.
+#[schema(collection = "player_sessions")]
+#[derive(JsonSchema)]
+pub struct PlayerSession {
+    #[field(id = Uuid::new)]
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+
+    #[schemars(with = "uuid::Uuid")]
+    pub server: Uuid,
+    pub name: String,
+
+    #[serde(default)]
+    pub address: Option<String>,
+    pub joined: DateTime<Utc>,
+
+    #[serde(default)]
+    pub left: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl PlayerSession {
+    pub fn create(server: Uuid, name: impl Into<String>, address: Option<String>) -> Self {
+        PlayerSession {
+            id: Uuid::new(),
+            server,
+            name: name.into(),
+            address,
+            joined: Utc::now(),
+            left: None,
+            reason: None,
+            _collection: None,
+        }
+    }
+}
+
+/// A player currently connected to a running server.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct OnlinePlayer {
+    pub name: String,
+
+    #[schemars(with = "Option<uuid::Uuid>")]
+    pub uuid: Option<Uuid>,
+    pub address: Option<String>,
+    pub joined: DateTime<Utc>,
+
+    #[schemars(with = "uuid::Uuid")]
+    pub session: Uuid,
+}
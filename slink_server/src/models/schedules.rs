@@ -0,0 +1,284 @@
+use std::str::FromStr;
+
+use bson::{Uuid, doc};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use cron::Schedule;
+use futures::TryStreamExt;
+use manor::{Collection, schema};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{ApiError, ApiResult, Error, types::BackupFormat};
+
+fn default_warnings() -> Vec<u64> {
+    vec![600, 300, 60, 30, 10]
+}
+
+fn default_timezone() -> String {
+    String::from("UTC")
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// What a scheduled task does when it fires.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ScheduledAction {
+    /// Restarts a running server, announcing it in chat beforehand. Stopped servers are left alone.
+    Restart {
+        /// Seconds before the restart at which players are warned
+        #[serde(default = "default_warnings")]
+        warnings: Vec<u64>,
+
+        #[serde(default)]
+        message: Option<String>,
+    },
+    Backup {
+        #[serde(default)]
+        format: BackupFormat,
+
+        #[serde(default)]
+        note: Option<String>,
+    },
+    Broadcast {
+        message: String,
+    },
+    Command {
+        command: String,
+    },
+}
+
+impl ScheduledAction {
+    /// The console command the action sends, for actions that send one.
+    pub fn command(&self) -> Option<String> {
+        match self {
+            Self::Broadcast { message } => Some(format!("say {message}")),
+            Self::Command { command } => Some(command.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// How to handle a run that was missed by more than the grace period, e.g. while Slink was down.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MissedRunPolicy {
+    #[default]
+    Skip,
+
+    /// Runs once as soon as possible, however many runs were missed
+    RunOnce,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct TaskRun {
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    pub success: bool,
+
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// A cron-scheduled action on one server.
+#[schema(collection = "schedules")]
+#[derive(JsonSchema)]
+pub struct ScheduledTask {
+    #[field(id = Uuid::new)]
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+
+    #[schemars(with = "uuid::Uuid")]
+    pub server: Uuid,
+    pub name: String,
+
+    /// Cron expression, either `min hour day month weekday` or with a leading seconds field
+    pub cron: String,
+
+    /// IANA time zone the cron expression is evaluated in
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    pub action: ScheduledAction,
+
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    #[serde(default)]
+    pub missed: MissedRunPolicy,
+
+    /// Persisted so that runs due while Slink was down can be detected on startup
+    #[serde(default)]
+    pub next_run: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub last_run: Option<TaskRun>,
+
+    /// User who last saved the task. Commands and broadcasts only run while this user may send them.
+    #[serde(default)]
+    #[schemars(with = "Option<uuid::Uuid>")]
+    pub author: Option<Uuid>,
+}
+
+impl ScheduledTask {
+    pub fn create(
+        server: Uuid,
+        name: String,
+        cron: String,
+        timezone: String,
+        action: ScheduledAction,
+        missed: MissedRunPolicy,
+        author: Uuid,
+    ) -> ApiResult<Self> {
+        let mut task = ScheduledTask {
+            id: Uuid::new(),
+            server,
+            name,
+            cron,
+            timezone,
+            action,
+            enabled: true,
+            missed,
+            next_run: None,
+            last_run: None,
+            author: Some(author),
+            _collection: None,
+        };
+        task.reschedule(Utc::now())?;
+        Ok(task)
+    }
+
+    fn schedule(&self) -> ApiResult<(Schedule, Tz)> {
+        let expression = match self.cron.split_whitespace().count() {
+            5 => format!("0 {}", self.cron),
+            _ => self.cron.clone(),
+        };
+        let schedule = Schedule::from_str(&expression)
+            .map_err(|e| ApiError::bad_request(format!("Invalid cron expression {:?}: {e}", self.cron)))?;
+        let timezone = Tz::from_str(&self.timezone)
+            .map_err(|_| ApiError::bad_request(format!("Unknown time zone: {}", self.timezone)))?;
+        Ok((schedule, timezone))
+    }
+
+    /// Validates the schedule and sets the next run after `after`, or clears it if the task is disabled.
+    pub fn reschedule(&mut self, after: DateTime<Utc>) -> ApiResult<()> {
+        let (schedule, timezone) = self.schedule()?;
+        self.next_run = match self.enabled {
+            true => schedule
+                .after(&after.with_timezone(&timezone))
+                .next()
+                .map(|next| next.with_timezone(&Utc)),
+            false => None,
+        };
+        Ok(())
+    }
+
+    pub async fn for_server(server: Uuid) -> ApiResult<Vec<Self>> {
+        Collection::<Self>::new()
+            .find_many(doc! {"server": server})
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?
+            .try_collect::<Vec<Self>>()
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))
+    }
+
+    pub async fn enabled() -> ApiResult<Vec<Self>> {
+        Collection::<Self>::new()
+            .find_many(doc! {"enabled": true})
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?
+            .try_collect::<Vec<Self>>()
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))
+    }
+
+    pub async fn get_for(server: Uuid, id: impl Into<Uuid>) -> ApiResult<Self> {
+        let id: Uuid = id.into();
+        match Collection::<Self>::new().get(id).await {
+            Ok(Some(task)) if task.server == server => Ok(task),
+            _ => Err(ApiError::not_found(format!("Scheduled task: {id}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn new_task(cron: &str, timezone: &str) -> ApiResult<ScheduledTask> {
+        ScheduledTask::create(
+            Uuid::new(),
+            String::from("Nightly"),
+            cron.to_string(),
+            timezone.to_string(),
+            ScheduledAction::Broadcast { message: String::from("hi") },
+            MissedRunPolicy::Skip,
+            Uuid::new(),
+        )
+    }
+
+    fn next(task: &mut ScheduledTask, after: DateTime<Utc>) -> DateTime<Utc> {
+        task.reschedule(after).unwrap();
+        task.next_run.unwrap()
+    }
+
+    #[test]
+    fn evaluates_cron_in_the_task_time_zone() {
+        let mut task = new_task("0 3 * * *", "Europe/Berlin").unwrap();
+        assert_eq!(
+            next(&mut task, Utc.with_ymd_and_hms(2024, 1, 15, 0, 0, 0).unwrap()),
+            Utc.with_ymd_and_hms(2024, 1, 15, 2, 0, 0).unwrap()
+        );
+        assert_eq!(
+            next(&mut task, Utc.with_ymd_and_hms(2024, 7, 15, 0, 0, 0).unwrap()),
+            Utc.with_ymd_and_hms(2024, 7, 15, 1, 0, 0).unwrap()
+        );
+
+        let mut task = new_task("0 3 * * *", "UTC").unwrap();
+        assert_eq!(
+            next(&mut task, Utc.with_ymd_and_hms(2024, 7, 15, 0, 0, 0).unwrap()),
+            Utc.with_ymd_and_hms(2024, 7, 15, 3, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn follows_daylight_saving_changes() {
+        // Berlin moves from UTC+1 to UTC+2 on 2024-03-31
+        let mut task = new_task("0 12 * * *", "Europe/Berlin").unwrap();
+        let before = next(&mut task, Utc.with_ymd_and_hms(2024, 3, 30, 0, 0, 0).unwrap());
+        let after = next(&mut task, before);
+        assert_eq!(before, Utc.with_ymd_and_hms(2024, 3, 30, 11, 0, 0).unwrap());
+        assert_eq!(after, Utc.with_ymd_and_hms(2024, 3, 31, 10, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn accepts_five_and_six_field_expressions() {
+        let after = Utc.with_ymd_and_hms(2024, 1, 15, 0, 0, 0).unwrap();
+        assert_eq!(
+            next(&mut new_task("*/15 * * * *", "UTC").unwrap(), after),
+            Utc.with_ymd_and_hms(2024, 1, 15, 0, 15, 0).unwrap()
+        );
+        assert_eq!(
+            next(&mut new_task("30 */15 * * * *", "UTC").unwrap(), after),
+            Utc.with_ymd_and_hms(2024, 1, 15, 0, 0, 30).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_schedules() {
+        assert!(new_task("every night", "UTC").is_err());
+        assert!(new_task("0 3 * * *", "Mars/Olympus_Mons").is_err());
+    }
+
+    #[test]
+    fn disabled_tasks_have_no_next_run() {
+        let mut task = new_task("0 3 * * *", "UTC").unwrap();
+        task.enabled = false;
+        task.reschedule(Utc::now()).unwrap();
+        assert_eq!(task.next_run, None);
+    }
+}
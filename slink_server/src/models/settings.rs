@@ -0,0 +1,45 @@
+use bson::Uuid;
+use manor::{Collection, schema};
+use rocket::Request;
+use schemars::JsonSchema;
+use slink_common::{ApiError, ApiResult, Error};
+
+/// Instance-wide settings changed at runtime by superusers, as opposed to the configuration file.
+#[schema(collection = "settings")]
+#[derive(JsonSchema)]
+pub struct SecuritySettings {
+    #[field(id = Uuid::new)]
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+
+    /// Users without two-factor authentication can only enroll until they set it up. Sessions signed in
+    /// through OpenID Connect are exempt, since the provider handles their second factor.
+    #[serde(default)]
+    pub require_two_factor: bool,
+}
+
+impl SecuritySettings {
+    /// The settings are a single document with a fixed ID.
+    fn key() -> Uuid {
+        Uuid::from_bytes([0; 16])
+    }
+
+    pub async fn current() -> ApiResult<Self> {
+        match Collection::<Self>::new().get(Self::key()).await {
+            Ok(Some(settings)) => Ok(settings),
+            Ok(None) => Ok(Self {
+                id: Self::key(),
+                require_two_factor: false,
+                _collection: None,
+            }),
+            Err(e) => Err(ApiError::from(Error::Unexpected(e.to_string()))),
+        }
+    }
+
+    /// The current settings, loaded at most once per request.
+    pub async fn for_request(req: &Request<'_>) -> ApiResult<Self> {
+        req.local_cache_async(async { Self::current().await })
+            .await
+            .clone()
+    }
+}
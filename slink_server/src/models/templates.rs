@@ -0,0 +1,123 @@
+use bson::{Uuid, doc};
+use bytesize::ByteSize;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use manor::{Collection, schema};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{
+    ApiError, ApiResult, Error,
+    providers::servers::ServerBinaryVersion,
+    types::{ContentKind, ServerProperties},
+};
+
+use super::User;
+
+/// A catalog project installed on servers created from a template.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct TemplateContent {
+    /// Modrinth project ID or slug
+    pub project: String,
+
+    /// Specific version to install, instead of the latest compatible one
+    #[serde(default)]
+    pub version: Option<String>,
+
+    #[serde(default)]
+    pub kind: ContentKind,
+}
+
+/// Reusable starting point for new servers.
+#[schema(collection = "templates")]
+#[derive(JsonSchema)]
+pub struct ServerTemplate {
+    #[field(id = Uuid::new)]
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+    pub name: String,
+
+    #[serde(default)]
+    pub description: Option<String>,
+
+    #[schemars(with = "uuid::Uuid")]
+    pub owner: Uuid,
+
+    /// Whether every user can create servers from this template, rather than only its owner
+    #[serde(default)]
+    pub public: bool,
+    pub minecraft_version: String,
+
+    #[serde(default)]
+    pub modloader_version: Option<ServerBinaryVersion>,
+
+    #[serde(default)]
+    pub properties: ServerProperties,
+
+    #[schemars(with = "String")]
+    pub max_memory: ByteSize,
+
+    #[serde(default)]
+    pub java_args: Vec<String>,
+
+    #[serde(default)]
+    pub content: Vec<TemplateContent>,
+
+    /// Seed files copied into new servers, relative to the server directory
+    #[serde(default)]
+    pub files: Vec<String>,
+    pub created: DateTime<Utc>,
+}
+
+impl ServerTemplate {
+    /// An empty template, to be filled in from a server by the caller.
+    pub fn new(name: String, owner: Uuid, minecraft_version: String, max_memory: ByteSize) -> Self {
+        Self {
+            id: Uuid::new(),
+            name,
+            description: None,
+            owner,
+            public: false,
+            minecraft_version,
+            modloader_version: None,
+            properties: ServerProperties::default(),
+            max_memory,
+            java_args: Vec::new(),
+            content: Vec::new(),
+            files: Vec::new(),
+            created: Utc::now(),
+            _collection: None,
+        }
+    }
+
+    pub fn visible_to(&self, user: &User) -> bool {
+        self.public || user.superuser || self.owner == user.id
+    }
+
+    pub fn editable_by(&self, user: &User) -> bool {
+        user.superuser || self.owner == user.id
+    }
+
+    /// Fetches a template by ID, treating templates the user cannot see as nonexistent.
+    pub async fn get_for(id: impl Into<Uuid>, user: &User) -> ApiResult<Self> {
+        let id: Uuid = id.into();
+        match Collection::<Self>::new().get(id).await {
+            Ok(Some(template)) if template.visible_to(user) => Ok(template),
+            _ => Err(ApiError::not_found(format!("Template: {id}"))),
+        }
+    }
+
+    /// Templates owned by the user, and public ones.
+    pub async fn for_user(user: &User) -> ApiResult<Vec<Self>> {
+        let filter = match user.superuser {
+            true => doc! {},
+            false => doc! {"$or": [{"owner": user.id}, {"public": true}]},
+        };
+        Collection::<Self>::new()
+            .find_many(filter)
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?
+            .try_collect::<Vec<Self>>()
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))
+    }
+}
@@ -0,0 +1,246 @@
+use bson::{Uuid, doc};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use log::debug;
+use manor::{Collection, Model, schema};
+use rocket::{
+    Request,
+    http::Method,
+    request::{self, FromRequest},
+};
+use rocket_okapi::request::OpenApiFromRequest;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{ApiError, ApiResult, Error};
+
+use super::{PendingUser, User};
+use crate::util::security::HashedPassword;
+
+/// Prefix of every API token, so that leaked tokens are easy to recognize.
+const TOKEN_PREFIX: &str = "slink";
+
+/// What a request authenticated with an API token may do.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// Only `GET` and `HEAD` requests
+    Read,
+
+    /// Any request the user could make, except superuser operations
+    Write,
+
+    /// Superuser operations, if the user is a superuser
+    Admin,
+}
+
+/// Personal access token, used with `Authorization: Bearer` instead of a session cookie.
+#[schema(collection = "tokens")]
+#[derive(JsonSchema)]
+pub struct ApiToken {
+    #[field(id = Uuid::new)]
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+
+    #[schemars(with = "uuid::Uuid")]
+    pub user: Uuid,
+    pub name: String,
+
+    #[schemars(with = "String")]
+    pub hashed_secret: HashedPassword,
+    pub scopes: Vec<TokenScope>,
+    pub created: DateTime<Utc>,
+
+    #[serde(default)]
+    pub expires: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+/// An [ApiToken] without its hashed secret.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct RedactedToken {
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+fn unexpected(e: impl ToString) -> ApiError {
+    ApiError::from(Error::Unexpected(e.to_string()))
+}
+
+impl ApiToken {
+    /// Creates a token, returning it along with the plaintext value. The value is not stored and cannot be
+    /// retrieved again.
+    pub fn create(
+        user: &User,
+        name: impl Into<String>,
+        scopes: Vec<TokenScope>,
+        expires: Option<DateTime<Utc>>,
+    ) -> ApiResult<(Self, String)> {
+        let id = uuid::Uuid::new_v4();
+        let secret = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+        let token = Self {
+            id: id.into(),
+            user: user.id,
+            name: name.into(),
+            hashed_secret: HashedPassword::new(secret.clone())?,
+            scopes,
+            created: Utc::now(),
+            expires,
+            last_used: None,
+            _collection: None,
+        };
+        Ok((token, format!("{TOKEN_PREFIX}_{}_{secret}", id.simple())))
+    }
+
+    pub fn redact(&self) -> RedactedToken {
+        RedactedToken {
+            id: self.id,
+            name: self.name.clone(),
+            scopes: self.scopes.clone(),
+            created: self.created,
+            expires: self.expires,
+            last_used: self.last_used,
+        }
+    }
+
+    pub fn expired(&self) -> bool {
+        self.expires.is_some_and(|e| e <= Utc::now())
+    }
+
+    /// Whether the token's scopes allow a request with this method.
+    pub fn allows(&self, method: Method) -> bool {
+        match method {
+            Method::Get | Method::Head | Method::Options => !self.scopes.is_empty(),
+            _ => self.scopes.iter().any(|s| *s != TokenScope::Read),
+        }
+    }
+
+    pub async fn for_user(user: Uuid) -> ApiResult<Vec<Self>> {
+        Collection::<Self>::new()
+            .find_many(doc! {"user": user})
+            .await
+            .map_err(unexpected)?
+            .try_collect::<Vec<Self>>()
+            .await
+            .map_err(unexpected)
+    }
+
+    /// Fetches one of the user's tokens, treating other users' tokens as nonexistent.
+    pub async fn get_for(id: impl Into<Uuid>, user: Uuid) -> ApiResult<Self> {
+        let id: Uuid = id.into();
+        match Collection::<Self>::new().get(id).await {
+            Ok(Some(token)) if token.user == user => Ok(token),
+            _ => Err(ApiError::not_found(format!("Token: {id}"))),
+        }
+    }
+
+    /// Revokes every token of a user. Returns the number of tokens removed.
+    pub async fn revoke_user(user: Uuid) -> ApiResult<u64> {
+        Collection::<Self>::new()
+            .delete_many(doc! {"user": user})
+            .await
+            .map_err(unexpected)
+    }
+
+    /// Resolves a plaintext token to the token and its user, recording when it was used.
+    async fn authenticate(value: &str) -> ApiResult<(Self, User)> {
+        let invalid = || ApiError::missing_auth("invalid_token");
+        let mut parts = value.splitn(3, '_');
+        let (Some(TOKEN_PREFIX), Some(id), Some(secret)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        let id = Uuid::parse_str(id).or(Err(invalid()))?;
+
+        let mut token = match Collection::<Self>::new().get(id).await {
+            Ok(Some(token)) if token.hashed_secret.verify(secret) => token,
+            _ => return Err(invalid()),
+        };
+        if token.expired() {
+            return Err(ApiError::missing_auth("token_expired"));
+        }
+        let user = User::get(token.user).await.or(Err(invalid()))?;
+
+        token.last_used = Some(Utc::now());
+        if let Err(e) = token.save().await {
+            debug!("Failed to record use of token {}: {e:?}", token.id);
+        }
+        Ok((token, user))
+    }
+}
+
+/// Result of authenticating the request's bearer token, cached for the request's lifetime.
+struct BearerAuthentication(Option<ApiResult<(ApiToken, User)>>);
+
+/// Authenticates the request's `Authorization: Bearer` header, if it has one. The returned user has their
+/// superuser status removed unless the token has the admin scope.
+pub(crate) async fn bearer_user(req: &Request<'_>) -> Option<ApiResult<(ApiToken, User)>> {
+    let cached = req
+        .local_cache_async(async {
+            let Some(header) = req.headers().get_one("Authorization") else {
+                return BearerAuthentication(None);
+            };
+            let Some(value) = header.strip_prefix("Bearer ") else {
+                return BearerAuthentication(Some(Err(ApiError::missing_auth("invalid_token"))));
+            };
+            BearerAuthentication(Some(ApiToken::authenticate(value.trim()).await))
+        })
+        .await;
+
+    cached.0.clone().map(|result| {
+        result.and_then(|(token, mut user)| {
+            if !token.allows(req.method()) {
+                return Err(ApiError::missing_auth("token_scope"));
+            }
+            user.superuser = user.superuser && token.scopes.contains(&TokenScope::Admin);
+            Ok((token, user))
+        })
+    })
+}
+
+/// The API token the request was authenticated with, if any. Used to keep tokens from managing other
+/// credentials.
+pub struct RequestToken(pub Option<ApiToken>);
+
+impl RequestToken {
+    /// Fails for requests authenticated with a token rather than a session.
+    pub fn require_session(&self) -> ApiResult<()> {
+        match self.0 {
+            Some(_) => Err(ApiError::missing_auth("requires_session")),
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for RequestToken {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match req.guard::<PendingUser>().await {
+            request::Outcome::Success(_) => {}
+            request::Outcome::Error(e) => return request::Outcome::Error(e),
+            request::Outcome::Forward(f) => return request::Outcome::Forward(f),
+        }
+        match bearer_user(req).await {
+            Some(Ok((token, _))) => request::Outcome::Success(Self(Some(token))),
+            Some(Err(e)) => e.respond(req),
+            None => request::Outcome::Success(Self(None)),
+        }
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for RequestToken {
+    fn from_request_input(
+        _gen: &mut rocket_okapi::r#gen::OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<rocket_okapi::request::RequestHeaderInput> {
+        Ok(rocket_okapi::request::RequestHeaderInput::None)
+    }
+}
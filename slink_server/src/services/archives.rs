@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use bson::Uuid;
+use chrono::{DateTime, TimeDelta, Utc};
+use log::warn;
+use rocket::{
+    Request,
+    request::{self, FromRequest},
+};
+use rocket_okapi::OpenApiFromRequest;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{
+    ApiError,
+    utilities::{Archive, ArchiveLimits, ArchiveProgress, ExtractionSummary},
+};
+use tokio::{
+    io::{AsyncWriteExt, DuplexStream},
+    runtime::Handle,
+};
+
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ArchiveJobState {
+    Running,
+    Completed { summary: ExtractionSummary },
+    Failed { error: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct ArchiveJob {
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+
+    #[schemars(with = "uuid::Uuid")]
+    pub server: Uuid,
+
+    /// Archive path, relative to the server directory
+    pub archive: String,
+
+    /// Extraction directory, relative to the server directory
+    pub destination: String,
+    pub state: ArchiveJobState,
+    pub progress: ArchiveProgress,
+    pub started: DateTime<Utc>,
+    pub finished: Option<DateTime<Utc>>,
+}
+
+impl ArchiveJob {
+    pub fn new(server: Uuid, archive: impl Into<String>, destination: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new(),
+            server,
+            archive: archive.into(),
+            destination: destination.into(),
+            state: ArchiveJobState::Running,
+            progress: ArchiveProgress::default(),
+            started: Utc::now(),
+            finished: None,
+        }
+    }
+}
+
+/// Tracks archive extractions running in the background so that clients can poll their progress.
+#[derive(Clone, Default, OpenApiFromRequest)]
+pub struct ArchiveJobs(Arc<Mutex<HashMap<Uuid, ArchiveJob>>>);
+
+impl ArchiveJobs {
+    /// How long finished jobs remain available for polling.
+    const RETENTION: TimeDelta = TimeDelta::hours(1);
+
+    pub fn get(&self, server: Uuid, id: Uuid) -> Option<ArchiveJob> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(&id)
+            .filter(|job| job.server == server)
+            .cloned()
+    }
+
+    pub fn list(&self, server: Uuid) -> Vec<ArchiveJob> {
+        let mut jobs: Vec<ArchiveJob> = self
+            .0
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|job| job.server == server)
+            .cloned()
+            .collect();
+        jobs.sort_by_key(|job| job.started);
+        jobs
+    }
+
+    /// Starts extracting `archive` into `destination` on a blocking thread. If `cleanup` is set, the archive
+    /// file is removed once the job finishes, whether it succeeded or not.
+    pub fn extract(
+        &self,
+        job: ArchiveJob,
+        archive: Archive,
+        destination: PathBuf,
+        limits: ArchiveLimits,
+        cleanup: Option<PathBuf>,
+    ) -> ArchiveJob {
+        {
+            let mut jobs = self.0.lock().unwrap();
+            jobs.retain(|_, existing| {
+                existing
+                    .finished
+                    .is_none_or(|finished| Utc::now() - finished < Self::RETENTION)
+            });
+            jobs.insert(job.id, job.clone());
+        }
+
+        let registry = self.0.clone();
+        let id = job.id;
+        tokio::task::spawn_blocking(move || {
+            let result = archive.extract(&destination, &limits, |progress| {
+                if let Some(job) = registry.lock().unwrap().get_mut(&id) {
+                    job.progress = progress.clone();
+                }
+            });
+
+            if let Some(path) = cleanup {
+                let _ = std::fs::remove_file(path);
+            }
+
+            if let Some(job) = registry.lock().unwrap().get_mut(&id) {
+                job.finished = Some(Utc::now());
+                job.state = match result {
+                    Ok(summary) => ArchiveJobState::Completed { summary },
+                    Err(e) => {
+                        warn!("Archive extraction {id} for {} failed: {e:?}", job.server);
+                        ArchiveJobState::Failed { error: e.to_string() }
+                    }
+                };
+            }
+        });
+
+        job
+    }
+
+    /// Streams `source` as a gzipped tarball, compressing on a blocking thread as the client reads.
+    pub fn stream_directory(source: PathBuf, name: String) -> DuplexStream {
+        let (reader, writer) = tokio::io::duplex(STREAM_BUFFER_SIZE);
+        let writer = BlockingWriter {
+            handle: Handle::current(),
+            inner: writer,
+        };
+
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = Archive::write_tar_gz(&source, &name, writer) {
+                warn!("Streaming archive of {} stopped: {e:?}", source.display());
+            }
+        });
+
+        reader
+    }
+}
+
+/// Adapts the async end of a duplex pipe for the blocking archive writers.
+struct BlockingWriter {
+    handle: Handle,
+    inner: DuplexStream,
+}
+
+impl Write for BlockingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.handle.block_on(self.inner.write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.handle.block_on(self.inner.flush())
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for ArchiveJobs {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(
+            req.rocket()
+                .state::<ArchiveJobs>()
+                .expect("No archive job registry initialized.")
+                .clone(),
+        )
+    }
+}
@@ -0,0 +1,431 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bson::Uuid;
+use chrono::Utc;
+use log::{info, warn};
+use manor::Model;
+use rocket::{
+    Request,
+    request::{self, FromRequest},
+};
+use rocket_okapi::OpenApiFromRequest;
+use slink_common::{
+    ApiError, ApiResult, BACKUP_SAVE_TIMEOUT_SECONDS, Error,
+    runners::MinecraftRunner,
+    storage::{BackupTarget, target_for},
+    types::{AppConfig, BackupFormat, BackupTrigger},
+    utilities::{
+        Archive, ArchiveFormat, ArchiveLimits, BackupRepository, ChecksumWriter, RepositoryCheck, RepositoryPrune,
+        sha256_file,
+    },
+};
+use tokio::sync::{RwLock, broadcast::error::RecvError};
+
+use crate::{
+    models::{Backup, MinecraftServer, User},
+    util::{Runners, ServerInstance},
+};
+
+/// Marks a server as busy with a backup operation until dropped.
+struct BackupLock {
+    active: Arc<Mutex<HashSet<Uuid>>>,
+    server: Uuid,
+}
+
+impl Drop for BackupLock {
+    fn drop(&mut self) {
+        self.active.lock().unwrap().remove(&self.server);
+    }
+}
+
+/// Creates, restores and prunes server backups, allowing one operation per server at a time.
+#[derive(Clone, Default, OpenApiFromRequest)]
+pub struct BackupEngine {
+    active: Arc<Mutex<HashSet<Uuid>>>,
+
+    /// Held shared while snapshots write to the repository, and exclusively while it is pruned
+    repository: Arc<RwLock<()>>,
+}
+
+fn unexpected(error: impl ToString) -> ApiError {
+    ApiError::from(Error::Unexpected(error.to_string()))
+}
+
+impl BackupEngine {
+    fn lock(&self, server: Uuid) -> ApiResult<BackupLock> {
+        if !self.active.lock().unwrap().insert(server) {
+            return Err(ApiError::invalid_state("A backup operation is already running for this server"));
+        }
+        Ok(BackupLock {
+            active: self.active.clone(),
+            server,
+        })
+    }
+
+    /// Snapshots the server directory. If the server is online, saving is paused and flushed
+    /// first so that the world on disk is consistent, and resumed afterwards.
+    pub async fn create(
+        &self,
+        server: &MinecraftServer,
+        config: &AppConfig,
+        runners: &Runners,
+        trigger: BackupTrigger,
+        format: BackupFormat,
+        note: Option<String>,
+    ) -> ApiResult<Backup> {
+        let _lock = self.lock(server.id)?;
+        self.snapshot(server, config, runners, trigger, format, note).await
+    }
+
+    async fn snapshot(
+        &self,
+        server: &MinecraftServer,
+        config: &AppConfig,
+        runners: &Runners,
+        trigger: BackupTrigger,
+        format: BackupFormat,
+        note: Option<String>,
+    ) -> ApiResult<Backup> {
+        let mut backup = Backup::create(server, trigger, format, note);
+        let target = backup.path(config);
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(unexpected)?;
+        }
+        let _repository = self.repository.read().await;
+
+        let instance = runners.get(&server.id).await;
+        let paused = match &instance {
+            Some(instance) => Self::pause_saving(instance).await?,
+            None => false,
+        };
+
+        let source = server.directory(config);
+        let name = server.id.to_string();
+        let destination = target.clone();
+        let repository = config.backup_repository();
+        let snapshot = backup.file.clone();
+        let result = tokio::task::spawn_blocking(move || -> ApiResult<(String, u64)> {
+            if format == BackupFormat::Incremental {
+                let stats = BackupRepository::open(&repository)?.snapshot(&source, &snapshot)?;
+                return Ok((stats.checksum, stats.bytes));
+            }
+
+            let file = File::create(&destination).map_err(|e| ApiError::from(Error::path(destination.display(), e)))?;
+            let mut writer = ChecksumWriter::new(BufWriter::new(file));
+            Archive::write_tar_gz(&source, &name, &mut writer)?;
+            let (mut inner, checksum, size) = writer.finish();
+            inner.flush().map_err(|e| ApiError::from(Error::path(destination.display(), e)))?;
+            Ok((checksum, size))
+        })
+        .await
+        .map_err(unexpected)
+        .and_then(|r| r);
+
+        if let (true, Some(instance)) = (paused, &instance)
+            && let Err(e) = instance.runner.lock().await.send_command("save-on").await
+        {
+            warn!("Failed to resume saving on {} after backup: {e:?}", server.id);
+        }
+
+        let (checksum, size) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&target).await;
+                return Err(e);
+            }
+        };
+
+        backup.checksum = checksum;
+        backup.size = size;
+        backup.save().await.map_err(|e| unexpected(format!("{e:?}")))?;
+        info!("Created {:?} backup {} of {}", backup.trigger, backup.id, server.id);
+
+        // A failed upload keeps the local archive, so it can be retried later
+        if let (BackupFormat::Archive, Some(target)) = (format, &server.backup_target)
+            && let Err(e) = Self::upload_to(&mut backup, target, config).await
+        {
+            warn!("Failed to upload backup {} to {target}: {e:?}", backup.id);
+        }
+        Ok(backup)
+    }
+
+    fn target(config: &AppConfig, name: &str) -> ApiResult<Box<dyn BackupTarget>> {
+        config
+            .backups
+            .targets
+            .get(name)
+            .map(|target| target_for(name, target))
+            .ok_or(ApiError::configuration(format!("Unknown backup target: {name}")))
+    }
+
+    /// Moves a local archive backup to a remote target. Retrying resumes an interrupted upload.
+    pub async fn upload(&self, backup: &mut Backup, target: &str, config: &AppConfig) -> ApiResult<()> {
+        let _lock = self.lock(backup.server)?;
+        Self::upload_to(backup, target, config).await
+    }
+
+    async fn upload_to(backup: &mut Backup, target: &str, config: &AppConfig) -> ApiResult<()> {
+        if backup.format != BackupFormat::Archive {
+            return Err(ApiError::bad_request("Only archive backups can be uploaded to a target"));
+        }
+        if backup.target.as_deref() == Some(target) {
+            return Ok(());
+        }
+
+        let local = Self::fetch(backup, config).await?;
+        Self::target(config, target)?.upload(&local, &backup.file).await?;
+        if let Some(previous) = backup.target.replace(target.to_string())
+            && let Err(e) = Self::target(config, &previous)?.delete(&backup.file).await
+        {
+            warn!("Failed to remove backup {} from {previous}: {e:?}", backup.id);
+        }
+        backup.save().await.map_err(|e| unexpected(format!("{e:?}")))?;
+
+        let _ = tokio::fs::remove_file(&local).await;
+        info!("Uploaded backup {} to {target}", backup.id);
+        Ok(())
+    }
+
+    /// Returns a local copy of an archive backup, downloading it from its target if needed.
+    pub async fn fetch(backup: &Backup, config: &AppConfig) -> ApiResult<PathBuf> {
+        let path = backup.path(config);
+        if tokio::fs::metadata(&path).await.is_ok() {
+            return Ok(path);
+        }
+        let Some(target) = &backup.target else {
+            return Err(ApiError::not_found(format!("Backup archive: {}", backup.id)));
+        };
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(unexpected)?;
+        }
+        let partial = path.with_extension("download");
+        if let Err(e) = Self::target(config, target)?.download(&backup.file, &partial).await {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(e.into());
+        }
+        tokio::fs::rename(&partial, &path).await.map_err(unexpected)?;
+        Ok(path)
+    }
+
+    /// Turns off autosaving and waits for a full flush. Returns whether saving was paused.
+    async fn pause_saving(instance: &ServerInstance) -> ApiResult<bool> {
+        let mut runner = instance.runner.lock().await;
+        if !runner.status().await.online() {
+            return Ok(false);
+        }
+
+        let mut receiver = instance.subscribe().await;
+        let paused = match runner.send_command("save-off").await {
+            Ok(()) => runner.send_command("save-all flush").await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = paused {
+            // Never leave autosaving off because the backup could not start
+            if let Err(e) = runner.send_command("save-on").await {
+                warn!("Failed to turn saving back on for {}: {e:?}", instance.server);
+            }
+            return Err(e.into());
+        }
+        drop(runner);
+
+        if let Some(receiver) = receiver.as_mut() {
+            let flushed = tokio::time::timeout(Duration::from_secs(BACKUP_SAVE_TIMEOUT_SECONDS), async {
+                loop {
+                    match receiver.recv().await {
+                        Ok(chunk) if String::from_utf8_lossy(&chunk).contains("Saved the game") => break,
+                        Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            })
+            .await;
+            if flushed.is_err() {
+                warn!("Timed out waiting for {} to flush the world, backing up anyway", instance.server);
+            }
+        }
+
+        Ok(true)
+    }
+
+    async fn verify(backup: &Backup, config: &AppConfig) -> ApiResult<PathBuf> {
+        let path = match backup.format {
+            BackupFormat::Archive => Self::fetch(backup, config).await?,
+            BackupFormat::Incremental => backup.path(config),
+        };
+        let expected = backup.checksum.clone();
+        let file = path.clone();
+        let actual = tokio::task::spawn_blocking(move || sha256_file(file))
+            .await
+            .map_err(unexpected)??;
+        if actual != expected {
+            return Err(ApiError::invalid_state(format!("Backup {} is corrupted (checksum mismatch)", backup.id)));
+        }
+        Ok(path)
+    }
+
+    /// Unpacks a backup into a fresh directory. Backups are trusted, so no archive size limits apply.
+    async fn unpack(backup: &Backup, archive: PathBuf, config: &AppConfig, destination: PathBuf) -> ApiResult<()> {
+        let format = backup.format;
+        let snapshot = backup.file.clone();
+        let repository = config.backup_repository();
+        let limits = ArchiveLimits {
+            max_size: u64::MAX,
+            max_entries: u64::MAX,
+        };
+        tokio::task::spawn_blocking(move || match format {
+            BackupFormat::Archive => Archive::new(&archive, ArchiveFormat::TarGz)
+                .extract(&destination, &limits, |_| {})
+                .and(Ok(())),
+            BackupFormat::Incremental => BackupRepository::open(&repository)?.restore(&snapshot, &destination),
+        })
+        .await
+        .map_err(unexpected)??;
+        Ok(())
+    }
+
+    /// Replaces the server directory with the contents of a backup. The server must be stopped,
+    /// and its current state is backed up first.
+    pub async fn restore(
+        &self,
+        backup: &Backup,
+        server: &MinecraftServer,
+        config: &AppConfig,
+        runners: &Runners,
+    ) -> ApiResult<()> {
+        let _lock = self.lock(server.id)?;
+        if let Some(instance) = runners.get(&server.id).await
+            && instance.runner.lock().await.status().await.running()
+        {
+            return Err(ApiError::invalid_state("Server must be stopped to restore a backup"));
+        }
+
+        let archive = Self::verify(backup, config).await?;
+        let directory = server.directory(config);
+        if tokio::fs::metadata(&directory).await.is_ok() {
+            let note = format!("Before restoring {}", backup.id);
+            self.snapshot(server, config, runners, BackupTrigger::PreRestore, backup.format, Some(note))
+                .await?;
+        }
+
+        let staging = sibling(&directory, "restore");
+        if let Err(e) = Self::unpack(backup, archive, config, staging.clone()).await {
+            let _ = tokio::fs::remove_dir_all(&staging).await;
+            return Err(e);
+        }
+
+        let previous = sibling(&directory, "previous");
+        if tokio::fs::metadata(&directory).await.is_ok() {
+            tokio::fs::rename(&directory, &previous).await.map_err(unexpected)?;
+        }
+        tokio::fs::rename(&staging, &directory).await.map_err(unexpected)?;
+        let _ = tokio::fs::remove_dir_all(&previous).await;
+
+        info!("Restored backup {} onto {}", backup.id, server.id);
+        Ok(())
+    }
+
+    /// Creates a new server owned by `owner` from a backup, keeping the source server's settings.
+    pub async fn restore_as_new(
+        &self,
+        backup: &Backup,
+        source: &MinecraftServer,
+        name: String,
+        owner: User,
+        config: &AppConfig,
+    ) -> ApiResult<MinecraftServer> {
+        let archive = Self::verify(backup, config).await?;
+        let mut server = MinecraftServer::create(name, owner, source.minecraft_version.clone(), backup.modloader_version.clone());
+        server.max_memory = source.max_memory;
+        server.java_args = source.java_args.clone();
+        server.backup_retention = source.backup_retention.clone();
+        server.backup_target = source.backup_target.clone();
+
+        let directory = server.directory(config);
+        if let Err(e) = Self::unpack(backup, archive, config, directory.clone()).await {
+            let _ = tokio::fs::remove_dir_all(&directory).await;
+            return Err(e);
+        }
+
+        server.save().await.map_err(|e| unexpected(format!("{e:?}")))?;
+        Ok(server)
+    }
+
+    /// Deletes a backup. For incremental backups only the snapshot is removed; its objects are
+    /// reclaimed by the next repository prune.
+    pub async fn delete(backup: Backup, config: &AppConfig) -> ApiResult<()> {
+        if let Some(target) = &backup.target {
+            Self::target(config, target)?.delete(&backup.file).await?;
+        }
+        match tokio::fs::remove_file(backup.path(config)).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(unexpected(e)),
+        }
+        backup.delete().await.map_err(|e| unexpected(format!("{e:?}")))?;
+        Ok(())
+    }
+
+    /// Deletes the backups of a server that fall outside its retention policy. Returns the deleted backups.
+    pub async fn apply_retention(server: &MinecraftServer, config: &AppConfig) -> ApiResult<Vec<Backup>> {
+        let backups = Backup::for_server(server.id).await?;
+        let created: Vec<_> = backups.iter().map(|b| b.created).collect();
+        let keep = server.retention(config).retain(&created, Utc::now());
+
+        let mut deleted = Vec::new();
+        for (backup, keep) in backups.into_iter().zip(keep) {
+            if !keep {
+                Self::delete(backup.clone(), config).await?;
+                deleted.push(backup);
+            }
+        }
+        Ok(deleted)
+    }
+
+    pub async fn check_repository(&self, config: &AppConfig) -> ApiResult<RepositoryCheck> {
+        let _repository = self.repository.read().await;
+        let root = config.backup_repository();
+        Ok(tokio::task::spawn_blocking(move || BackupRepository::open(&root)?.check())
+            .await
+            .map_err(unexpected)??)
+    }
+
+    /// Removes snapshots that no backup refers to anymore, and all objects only they referenced.
+    pub async fn prune_repository(&self, config: &AppConfig) -> ApiResult<RepositoryPrune> {
+        let _repository = self.repository.write().await;
+        let keep: HashSet<String> = Backup::snapshot_ids().await?.into_iter().collect();
+        let root = config.backup_repository();
+        Ok(tokio::task::spawn_blocking(move || BackupRepository::open(&root)?.prune(&keep))
+            .await
+            .map_err(unexpected)??)
+    }
+}
+
+fn sibling(directory: &Path, purpose: &str) -> PathBuf {
+    let name = directory
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    directory.with_file_name(format!(".{name}.{purpose}-{}", Uuid::new()))
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for BackupEngine {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(
+            req.rocket()
+                .state::<BackupEngine>()
+                .expect("No backup engine initialized.")
+                .clone(),
+        )
+    }
+}
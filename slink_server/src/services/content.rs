@@ -0,0 +1,275 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use bson::Uuid;
+use chrono::Utc;
+use log::{info, warn};
+use rocket::{
+    Request,
+    request::{self, FromRequest},
+};
+use rocket_okapi::OpenApiFromRequest;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{
+    ApiError, ApiResult, Error,
+    providers::modrinth::{ModrinthClient, ResolvedVersion, VersionFilter},
+    types::{AppConfig, ContentKind, ContentLock, ContentSource, LockedContent, RestrictedContent},
+    utilities::Sandbox,
+};
+
+use crate::models::MinecraftServer;
+
+/// Loaders that plugin versions are matched against.
+const PLUGIN_LOADERS: [&str; 5] = ["paper", "purpur", "spigot", "bukkit", "folia"];
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct ContentChanges {
+    pub installed: Vec<LockedContent>,
+
+    /// Required dependencies that have no version for this server
+    pub missing: Vec<String>,
+
+    /// Projects that were skipped because they do not run on servers
+    pub client_only: Vec<String>,
+}
+
+/// Marks a server's content as being modified until dropped.
+struct ContentLockGuard {
+    active: Arc<Mutex<HashSet<Uuid>>>,
+    server: Uuid,
+}
+
+impl Drop for ContentLockGuard {
+    fn drop(&mut self) {
+        self.active.lock().unwrap().remove(&self.server);
+    }
+}
+
+/// Installs, updates and removes catalog content, keeping each server's lockfile in sync.
+#[derive(Clone, Default, OpenApiFromRequest)]
+pub struct ContentManager {
+    active: Arc<Mutex<HashSet<Uuid>>>,
+}
+
+impl ContentManager {
+    fn lock(&self, server: Uuid) -> ApiResult<ContentLockGuard> {
+        if !self.active.lock().unwrap().insert(server) {
+            return Err(ApiError::invalid_state("Content is already being changed on this server"));
+        }
+        Ok(ContentLockGuard {
+            active: self.active.clone(),
+            server,
+        })
+    }
+
+    pub fn client(config: &AppConfig) -> ModrinthClient {
+        ModrinthClient::new(&config.content.modrinth_api)
+    }
+
+    /// Minecraft version and loaders that content installed on the server must support.
+    pub fn filter(server: &MinecraftServer, kind: ContentKind) -> ApiResult<VersionFilter> {
+        let loaders = match kind {
+            ContentKind::Mod => vec![
+                server
+                    .loader()
+                    .ok_or(ApiError::bad_request("Mods require a server with a mod loader"))?
+                    .to_string(),
+            ],
+            ContentKind::Plugin => PLUGIN_LOADERS.iter().map(|l| l.to_string()).collect(),
+        };
+        Ok(VersionFilter {
+            game_version: server.minecraft_version.version.id.clone(),
+            loaders,
+        })
+    }
+
+    /// Installs a project (at a specific version, or the latest compatible one) and its required dependencies.
+    pub async fn install(
+        &self,
+        server: &MinecraftServer,
+        config: &AppConfig,
+        kind: ContentKind,
+        project: &str,
+        version: Option<&str>,
+    ) -> ApiResult<ContentChanges> {
+        let _lock = self.lock(server.id)?;
+        let sandbox = server.sandbox(config).await?;
+        let mut lock = ContentLock::load(sandbox.root()).await?;
+
+        let installed: HashSet<String> = lock.content.iter().map(|c| c.project_id.clone()).collect();
+        let resolution = Self::client(config)
+            .resolve(project, version, &Self::filter(server, kind)?, &installed)
+            .await?;
+
+        let mut changes = ContentChanges {
+            missing: resolution.missing,
+            client_only: resolution.client_only,
+            ..Default::default()
+        };
+        for resolved in resolution.versions {
+            changes
+                .installed
+                .push(Self::apply(&sandbox, &mut lock, config, kind, resolved).await?);
+        }
+        lock.save(sandbox.root()).await?;
+        Ok(changes)
+    }
+
+    /// Moves installed content to its latest compatible version, for one project or all of them.
+    pub async fn update(
+        &self,
+        server: &MinecraftServer,
+        config: &AppConfig,
+        project: Option<&str>,
+    ) -> ApiResult<ContentChanges> {
+        let _lock = self.lock(server.id)?;
+        let sandbox = server.sandbox(config).await?;
+        let mut lock = ContentLock::load(sandbox.root()).await?;
+        let client = Self::client(config);
+
+        let targets: Vec<LockedContent> = match project {
+            Some(project) => vec![
+                lock.get(project)
+                    .cloned()
+                    .ok_or(ApiError::not_found(format!("Installed project: {project}")))?,
+            ],
+            None => lock.content.clone(),
+        };
+
+        let mut changes = ContentChanges::default();
+        for entry in targets {
+            let filter = Self::filter(server, entry.kind)?;
+            let Some(latest) = client.latest(&entry.project_id, &filter).await? else {
+                warn!("No compatible version of {} for {}", entry.project_id, server.id);
+                continue;
+            };
+            if latest.id == entry.version_id {
+                continue;
+            }
+
+            let installed: HashSet<String> = lock.content.iter().map(|c| c.project_id.clone()).collect();
+            let resolution = client
+                .resolve(&entry.project_id, Some(&latest.id), &filter, &installed)
+                .await?;
+            changes.missing.extend(resolution.missing);
+            changes.client_only.extend(resolution.client_only);
+            for mut resolved in resolution.versions {
+                if resolved.project.id == entry.project_id {
+                    resolved.dependency = entry.dependency;
+                }
+                changes
+                    .installed
+                    .push(Self::apply(&sandbox, &mut lock, config, entry.kind, resolved).await?);
+            }
+        }
+        lock.save(sandbox.root()).await?;
+        Ok(changes)
+    }
+
+    /// Removes an installed project's file and lockfile entry. Its dependencies are left installed.
+    pub async fn remove(&self, server: &MinecraftServer, config: &AppConfig, project: &str) -> ApiResult<LockedContent> {
+        let _lock = self.lock(server.id)?;
+        let sandbox = server.sandbox(config).await?;
+        let mut lock = ContentLock::load(sandbox.root()).await?;
+
+        let entry = lock
+            .remove(project)
+            .ok_or(ApiError::not_found(format!("Installed project: {project}")))?;
+        Self::remove_file(&sandbox, &entry.file).await?;
+        lock.save(sandbox.root()).await?;
+        info!("Removed {} from {}", entry.name, server.id);
+        Ok(entry)
+    }
+
+    /// Files from imported packs that still have to be uploaded by hand. Entries whose file has since been
+    /// uploaded are dropped from the lockfile.
+    pub async fn restricted(&self, server: &MinecraftServer, config: &AppConfig) -> ApiResult<Vec<RestrictedContent>> {
+        let _lock = self.lock(server.id)?;
+        let sandbox = server.sandbox(config).await?;
+        let mut lock = ContentLock::load(sandbox.root()).await?;
+
+        let mut pending = Vec::new();
+        for entry in &lock.restricted {
+            let uploaded = match sandbox.resolve(&entry.file).await {
+                Ok(path) => tokio::fs::metadata(path).await.is_ok_and(|m| m.is_file()),
+                Err(_) => false,
+            };
+            if !uploaded {
+                pending.push(entry.clone());
+            }
+        }
+        if pending.len() != lock.restricted.len() {
+            lock.restricted = pending.clone();
+            lock.save(sandbox.root()).await?;
+        }
+        Ok(pending)
+    }
+
+    async fn remove_file(sandbox: &Sandbox, file: &str) -> ApiResult<()> {
+        match tokio::fs::remove_file(sandbox.resolve_entry(file).await?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::path(file, e).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Downloads a resolved version into place and records it, replacing any previously installed version.
+    async fn apply(
+        sandbox: &Sandbox,
+        lock: &mut ContentLock,
+        config: &AppConfig,
+        kind: ContentKind,
+        resolved: ResolvedVersion,
+    ) -> ApiResult<LockedContent> {
+        let file = resolved
+            .version
+            .primary_file()
+            .ok_or(ApiError::bad_request(format!("Version {} has no files", resolved.version.id)))?;
+        let relative = format!("{}/{}", kind.directory(), file.filename);
+        let destination = sandbox.resolve(&relative).await?;
+        if let Some(parent) = destination.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| ApiError::from(Error::path(kind.directory(), e)))?;
+        }
+        Self::client(config).download(file, &destination).await?;
+
+        // Content requested explicitly stays explicit, even when another project depends on it
+        let previous = lock.get(&resolved.project.id).cloned();
+        let entry = LockedContent {
+            source: ContentSource::Modrinth,
+            project_id: resolved.project.id.clone(),
+            version_id: resolved.version.id.clone(),
+            name: resolved.project.title.clone(),
+            version_number: resolved.version.version_number.clone(),
+            kind,
+            file: relative,
+            sha512: file.hashes.sha512.clone(),
+            dependency: resolved.dependency && previous.as_ref().is_none_or(|p| p.dependency),
+            installed: Utc::now(),
+        };
+        if let Some(previous) = lock.insert(entry.clone())
+            && previous.file != entry.file
+        {
+            Self::remove_file(sandbox, &previous.file).await?;
+        }
+        info!("Installed {} {} to {}", entry.name, entry.version_number, entry.file);
+        Ok(entry)
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for ContentManager {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(
+            req.rocket()
+                .state::<ContentManager>()
+                .expect("No content manager initialized.")
+                .clone(),
+        )
+    }
+}
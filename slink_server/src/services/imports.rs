@@ -0,0 +1,197 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use bson::Uuid;
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{info, warn};
+use rocket::{
+    Request,
+    request::{self, FromRequest},
+};
+use rocket_okapi::OpenApiFromRequest;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{
+    ApiError, ApiResult,
+    types::{RestrictedContent, ServerProperties},
+};
+
+use crate::models::MinecraftServer;
+
+/// A file from an imported pack that was not installed.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+/// What an import did, and what it could not do.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct ImportReport {
+    pub minecraft_version: String,
+
+    #[serde(default)]
+    pub loader: Option<String>,
+
+    #[serde(default)]
+    pub loader_version: Option<String>,
+
+    /// Files downloaded into the server directory
+    pub installed: Vec<String>,
+
+    /// Files that were left out, such as client-only mods or failed downloads
+    pub skipped: Vec<SkippedFile>,
+
+    /// Files whose environment entries could not be interpreted, and were installed anyway
+    pub unsupported_env: Vec<SkippedFile>,
+
+    /// Files the pack needs that could not be downloaded automatically and have to be uploaded by hand
+    #[serde(default)]
+    pub restricted: Vec<RestrictedContent>,
+
+    /// Settings read from an imported `server.properties`
+    #[serde(default)]
+    pub properties: Option<ServerProperties>,
+    pub warnings: Vec<String>,
+}
+
+impl ImportReport {
+    pub fn skip(&mut self, path: impl Into<String>, reason: impl Into<String>) {
+        self.skipped.push(SkippedFile {
+            path: path.into(),
+            reason: reason.into(),
+        });
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ImportJobState {
+    Running,
+    Completed {
+        #[schemars(with = "uuid::Uuid")]
+        server: Uuid,
+        report: Box<ImportReport>,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct ImportJob {
+    #[schemars(with = "uuid::Uuid")]
+    pub id: Uuid,
+
+    #[schemars(with = "uuid::Uuid")]
+    pub owner: Uuid,
+
+    /// Name of the imported file or directory
+    pub source: String,
+    pub state: ImportJobState,
+    pub started: DateTime<Utc>,
+    pub finished: Option<DateTime<Utc>>,
+}
+
+impl ImportJob {
+    pub fn new(owner: Uuid, source: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new(),
+            owner,
+            source: source.into(),
+            state: ImportJobState::Running,
+            started: Utc::now(),
+            finished: None,
+        }
+    }
+}
+
+/// Tracks server imports running in the background so that clients can poll them.
+#[derive(Clone, Default, OpenApiFromRequest)]
+pub struct ImportJobs(Arc<Mutex<HashMap<Uuid, ImportJob>>>);
+
+impl ImportJobs {
+    /// How long finished jobs remain available for polling.
+    const RETENTION: TimeDelta = TimeDelta::hours(1);
+
+    pub fn get(&self, owner: Uuid, id: Uuid) -> Option<ImportJob> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(&id)
+            .filter(|job| job.owner == owner)
+            .cloned()
+    }
+
+    pub fn list(&self, owner: Uuid) -> Vec<ImportJob> {
+        let mut jobs: Vec<ImportJob> = self
+            .0
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|job| job.owner == owner)
+            .cloned()
+            .collect();
+        jobs.sort_by_key(|job| job.started);
+        jobs
+    }
+
+    /// Runs an import in the background, recording the created server and report when it finishes.
+    pub fn spawn<F>(&self, job: ImportJob, import: F) -> ImportJob
+    where
+        F: Future<Output = ApiResult<(MinecraftServer, ImportReport)>> + Send + 'static,
+    {
+        {
+            let mut jobs = self.0.lock().unwrap();
+            jobs.retain(|_, existing| {
+                existing
+                    .finished
+                    .is_none_or(|finished| Utc::now() - finished < Self::RETENTION)
+            });
+            jobs.insert(job.id, job.clone());
+        }
+
+        let registry = self.0.clone();
+        let id = job.id;
+        tokio::spawn(async move {
+            let result = import.await;
+            if let Some(job) = registry.lock().unwrap().get_mut(&id) {
+                job.finished = Some(Utc::now());
+                job.state = match result {
+                    Ok((server, report)) => {
+                        info!("Imported {} as server {}", job.source, server.id);
+                        ImportJobState::Completed {
+                            server: server.id,
+                            report: Box::new(report),
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Import {id} of {} failed: {e:?}", job.source);
+                        ImportJobState::Failed {
+                            error: format!("{e:?}"),
+                        }
+                    }
+                };
+            }
+        });
+
+        job
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for ImportJobs {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(
+            req.rocket()
+                .state::<ImportJobs>()
+                .expect("No import job registry initialized.")
+                .clone(),
+        )
+    }
+}
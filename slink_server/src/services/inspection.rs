@@ -0,0 +1,154 @@
+use std::{collections::HashSet, path::Path};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slink_common::{
+    ApiError, ApiResult, Error,
+    types::{AppConfig, ContentKind},
+    utilities::{JarEnvironment, JarMetadata, inspect_jar},
+};
+
+use crate::models::MinecraftServer;
+
+/// Dependency IDs that the game or loader itself provides.
+const PLATFORM_IDS: [&str; 8] = [
+    "minecraft",
+    "java",
+    "fabricloader",
+    "fabric-loader",
+    "quilt_loader",
+    "forge",
+    "neoforge",
+    "mixinextras",
+];
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct InspectedJar {
+    /// Path relative to the server directory
+    pub file: String,
+    pub kind: ContentKind,
+
+    /// Mods or plugins declared by the jar; empty if it has no known metadata
+    pub declared: Vec<JarMetadata>,
+
+    /// Why the jar could not be read
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct MissingDependency {
+    pub file: String,
+
+    /// ID of the mod or plugin that declares the dependency
+    pub required_by: String,
+    pub dependency: String,
+    pub version: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct ContentProblem {
+    pub file: String,
+    pub reason: String,
+}
+
+/// What is installed in `mods/` and `plugins/`, and what will likely stop the server from starting.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct ContentInspection {
+    pub jars: Vec<InspectedJar>,
+    pub missing_dependencies: Vec<MissingDependency>,
+
+    /// Jars that only work on clients
+    pub client_only: Vec<String>,
+
+    /// Jars built for a different loader than the server runs
+    pub incompatible: Vec<ContentProblem>,
+}
+
+fn unexpected(e: impl ToString) -> ApiError {
+    ApiError::from(Error::Unexpected(e.to_string()))
+}
+
+fn scan_directory(root: &Path, kind: ContentKind, jars: &mut Vec<InspectedJar>) {
+    let Ok(entries) = std::fs::read_dir(root.join(kind.directory())) else {
+        return;
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .filter_map(|e| e.file_name().to_str().map(|n| n.to_string()))
+        .filter(|n| n.ends_with(".jar"))
+        .collect();
+    names.sort();
+
+    for name in names {
+        let file = format!("{}/{name}", kind.directory());
+        let (declared, error) = match inspect_jar(root.join(&file)) {
+            Ok(declared) => (declared, None),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        };
+        jars.push(InspectedJar {
+            file,
+            kind,
+            declared,
+            error,
+        });
+    }
+}
+
+fn analyze(root: &Path, loader: Option<&str>) -> ContentInspection {
+    let mut inspection = ContentInspection::default();
+    scan_directory(root, ContentKind::Mod, &mut inspection.jars);
+    scan_directory(root, ContentKind::Plugin, &mut inspection.jars);
+
+    let provided: HashSet<String> = inspection
+        .jars
+        .iter()
+        .flat_map(|jar| &jar.declared)
+        .flat_map(|m| std::iter::once(m.id.clone()).chain(m.provides.iter().cloned()))
+        .chain(PLATFORM_IDS.iter().map(|id| id.to_string()))
+        .map(|id| id.to_lowercase())
+        .collect();
+
+    for jar in &inspection.jars {
+        for declared in &jar.declared {
+            if declared.environment == JarEnvironment::Client && !inspection.client_only.contains(&jar.file) {
+                inspection.client_only.push(jar.file.clone());
+            }
+            if jar.kind == ContentKind::Mod {
+                let supported = declared.format.loaders();
+                match loader {
+                    Some(loader) if !supported.contains(&loader) => inspection.incompatible.push(ContentProblem {
+                        file: jar.file.clone(),
+                        reason: format!("{} is built for {}, the server runs {loader}", declared.id, supported.join("/")),
+                    }),
+                    None => inspection.incompatible.push(ContentProblem {
+                        file: jar.file.clone(),
+                        reason: format!("{} needs a mod loader, the server has none", declared.id),
+                    }),
+                    _ => {}
+                }
+            }
+            for dependency in declared.dependencies.iter().filter(|d| d.required) {
+                if !provided.contains(&dependency.id.to_lowercase()) {
+                    inspection.missing_dependencies.push(MissingDependency {
+                        file: jar.file.clone(),
+                        required_by: declared.id.clone(),
+                        dependency: dependency.id.clone(),
+                        version: dependency.version.clone(),
+                    });
+                }
+            }
+        }
+    }
+    inspection
+}
+
+/// Reads the metadata of every jar in the server's `mods/` and `plugins/` directories, without consulting
+/// any catalog.
+pub async fn inspect(server: &MinecraftServer, config: &AppConfig) -> ApiResult<ContentInspection> {
+    let root = server.sandbox(config).await?.root().to_path_buf();
+    let loader = server.loader();
+    tokio::task::spawn_blocking(move || analyze(&root, loader))
+        .await
+        .map_err(unexpected)
+}
@@ -0,0 +1,11 @@
+pub mod archives;
+pub mod backups;
+pub mod content;
+pub mod imports;
+pub mod inspection;
+pub mod modpacks;
+pub mod oidc;
+pub mod players;
+pub mod schedules;
+pub mod sessions;
+pub mod templates;
@@ -0,0 +1,547 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use bson::Uuid;
+use chrono::Utc;
+use futures::{StreamExt, stream};
+use log::warn;
+use manor::Model;
+use slink_common::{
+    ApiError, ApiResult, CURSEFORGE_MANIFEST, Error, MODPACK_DOWNLOAD_CONCURRENCY, MODPACK_INDEX,
+    providers::{
+        curseforge::{CLASS_MODS, CurseFile, CurseForgeClient, CurseManifest, CurseMod},
+        downloads::{FileHash, download_verified},
+        modrinth::{ModpackFile, ModpackIndex},
+        servers::{FabricServerBinaryVersion, ServerBinaryVersion},
+    },
+    types::{
+        AppConfig, ContentKind, ContentLock, ContentSource, LockedContent, MinecraftVersion, RestrictedContent,
+        ServerProperties,
+    },
+    utilities::{Archive, ArchiveFormat, Sandbox, detect_server, server_root},
+};
+
+use crate::{
+    models::{MinecraftServer, User},
+    services::imports::{ImportReport, SkippedFile},
+};
+
+fn unexpected(e: impl ToString) -> ApiError {
+    ApiError::from(Error::Unexpected(e.to_string()))
+}
+
+/// A scratch directory next to the server directories, removed when dropped.
+pub struct Staging(pub PathBuf);
+
+impl Staging {
+    pub fn new(config: &AppConfig) -> Self {
+        Self(config.runner.server_directory(format!(".import-{}", Uuid::new())))
+    }
+
+    /// Extracts an uploaded archive into a new staging directory.
+    pub async fn extract(archive: &Path, format: ArchiveFormat, config: &AppConfig) -> ApiResult<Self> {
+        let staging = Self::new(config);
+        let (archive, destination, limits) = (Archive::new(archive, format), staging.0.clone(), config.archives.clone());
+        tokio::task::spawn_blocking(move || archive.extract(&destination, &limits, |_| {}))
+            .await
+            .map_err(unexpected)??;
+        Ok(staging)
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Copies a directory tree over `destination`, replacing existing files. Symlinks are skipped.
+pub fn copy_tree(source: &Path, destination: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(destination)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let kind = entry.file_type()?;
+        let target = destination.join(entry.file_name());
+        if kind.is_dir() {
+            copy_tree(&entry.path(), &target)?;
+        } else if kind.is_file() {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks that an overrides folder named by a modpack is a single plain folder name, so that it cannot
+/// point outside of the extracted pack.
+fn check_override_folder(folder: &str) -> ApiResult<()> {
+    if folder.is_empty() || folder.contains("..") || folder.contains(['/', '\\']) || Path::new(folder).is_absolute() {
+        return Err(ApiError::bad_request(format!("Invalid overrides folder {folder:?}")));
+    }
+    Ok(())
+}
+
+/// Copies each of the named folders from `staging` (if present) over the server directory, in order.
+pub async fn apply_overrides(staging: &Path, directory: &Path, folders: &[&str]) -> ApiResult<()> {
+    let root = tokio::fs::canonicalize(staging)
+        .await
+        .map_err(|e| ApiError::from(Error::path(staging.display(), e)))?;
+    for folder in folders {
+        check_override_folder(folder)?;
+        let Ok(source) = tokio::fs::canonicalize(root.join(folder)).await else {
+            continue;
+        };
+        if !source.starts_with(&root) {
+            return Err(ApiError::bad_request(format!("Invalid overrides folder {folder:?}")));
+        }
+        if !tokio::fs::metadata(&source).await.is_ok_and(|m| m.is_dir()) {
+            continue;
+        }
+        let destination = directory.to_path_buf();
+        tokio::task::spawn_blocking(move || copy_tree(&source, &destination))
+            .await
+            .map_err(unexpected)?
+            .map_err(|e| ApiError::from(Error::path(folder, e)))?;
+    }
+    Ok(())
+}
+
+/// Maps a loader name and version to the matching server binary provider.
+pub fn loader_version(loader: &str, version: &str) -> ApiResult<ServerBinaryVersion> {
+    match loader {
+        "fabric" | "fabric-loader" => Ok(ServerBinaryVersion::Fabric(FabricServerBinaryVersion::Loader {
+            version: version.to_string(),
+            stable: true,
+        })),
+        other => Err(ApiError::bad_request(format!(
+            "Unsupported mod loader {other} {version}; only Fabric servers can be created"
+        ))),
+    }
+}
+
+/// Builds (without saving) a server for a Minecraft version and optional loader.
+pub async fn new_server(
+    name: String,
+    owner: User,
+    minecraft_version: &str,
+    loader: Option<ServerBinaryVersion>,
+) -> ApiResult<MinecraftServer> {
+    let version = MinecraftVersion::from_id(minecraft_version)
+        .await?
+        .ok_or(ApiError::not_found(format!("Minecraft version: {minecraft_version}")))?;
+    Ok(MinecraftServer::create(name, owner, version.metadata().await?, loader))
+}
+
+/// Runs an import into a new server's directory, removing the directory again if it fails.
+pub async fn populate<F>(server: &MinecraftServer, config: &AppConfig, populate: F) -> ApiResult<()>
+where
+    F: AsyncFnOnce(&Sandbox) -> ApiResult<()>,
+{
+    let sandbox = server.sandbox(config).await?;
+    let result = match populate(&sandbox).await {
+        Ok(()) => server.save().await.map_err(|e| unexpected(format!("{e:?}"))),
+        Err(e) => Err(e),
+    };
+    if result.is_err() {
+        let _ = tokio::fs::remove_dir_all(server.directory(config)).await;
+    }
+    result
+}
+
+/// Downloads one pack file, trying each of its mirrors until one matches the expected hash.
+async fn download(sandbox: &Sandbox, file: &ModpackFile) -> Result<PathBuf, String> {
+    let target = sandbox.resolve(&file.path).await.map_err(|e| e.to_string())?;
+    if let Some(parent) = target.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(|e| e.to_string())?;
+    }
+
+    let mut errors = Vec::new();
+    for url in &file.downloads {
+        match download_verified(url, &FileHash::Sha512(file.hashes.sha512.clone()), &target).await {
+            Ok(()) => return Ok(target),
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    match errors.is_empty() {
+        true => Err(String::from("No download URLs")),
+        false => Err(errors.join("; ")),
+    }
+}
+
+fn locked_content(file: &ModpackFile) -> Option<LockedContent> {
+    let (project_id, version_id) = file.modrinth_ids()?;
+    let kind = match file.path.split('/').next()? {
+        "mods" => ContentKind::Mod,
+        "plugins" => ContentKind::Plugin,
+        _ => return None,
+    };
+    let name = file.path.rsplit('/').next()?.trim_end_matches(".jar").to_string();
+    Some(LockedContent {
+        source: ContentSource::Modrinth,
+        project_id,
+        version_number: version_id.clone(),
+        version_id,
+        name,
+        kind,
+        file: file.path.clone(),
+        sha512: file.hashes.sha512.clone(),
+        dependency: false,
+        installed: Utc::now(),
+    })
+}
+
+/// Creates a server from an `.mrpack` file: the Minecraft version and loader come from the index,
+/// server-side files are downloaded and verified, and `overrides` then `server-overrides` are applied.
+pub async fn import_mrpack(
+    pack: PathBuf,
+    name: Option<String>,
+    owner: User,
+    config: AppConfig,
+) -> ApiResult<(MinecraftServer, ImportReport)> {
+    let staging = Staging::extract(&pack, ArchiveFormat::Zip, &config).await;
+    let _ = tokio::fs::remove_file(&pack).await;
+    let staging = staging?;
+
+    let index_path = staging.0.join(MODPACK_INDEX);
+    let index: ModpackIndex = serde_json::from_slice(
+        &tokio::fs::read(&index_path)
+            .await
+            .map_err(|_| ApiError::bad_request(format!("Pack has no {MODPACK_INDEX}")))?,
+    )
+    .map_err(|e| ApiError::bad_request(format!("Invalid {MODPACK_INDEX}: {e}")))?;
+    if index.game != "minecraft" {
+        return Err(ApiError::bad_request(format!("Unsupported game: {}", index.game)));
+    }
+
+    let minecraft_version = index
+        .dependencies
+        .get("minecraft")
+        .ok_or(ApiError::bad_request("Pack does not declare a Minecraft version"))?
+        .clone();
+    let mut report = ImportReport {
+        minecraft_version: minecraft_version.clone(),
+        ..Default::default()
+    };
+    let mut loader = None;
+    for (dependency, version) in index.dependencies.iter().filter(|(d, _)| *d != "minecraft") {
+        report.loader = Some(dependency.trim_end_matches("-loader").to_string());
+        report.loader_version = Some(version.clone());
+        loader = Some(loader_version(dependency, version)?);
+    }
+
+    let server = new_server(
+        name.unwrap_or(format!("{} {}", index.name, index.version_id)),
+        owner,
+        &minecraft_version,
+        loader,
+    )
+    .await?;
+
+    populate(&server, &config, async |sandbox: &Sandbox| {
+        let mut wanted = Vec::new();
+        for file in &index.files {
+            let env = file.env.as_ref().and_then(|env| env.server.clone());
+            match env.as_deref() {
+                None | Some("required") => {}
+                Some("optional") => report
+                    .warnings
+                    .push(format!("{} is optional on servers and was installed", file.path)),
+                Some("unsupported") => {
+                    report.skip(&file.path, "Client-only");
+                    continue;
+                }
+                Some(other) => report.unsupported_env.push(SkippedFile {
+                    path: file.path.clone(),
+                    reason: format!("Unknown server environment {other:?}"),
+                }),
+            }
+            wanted.push(file.clone());
+        }
+
+        let results: Vec<(ModpackFile, Result<PathBuf, String>)> = stream::iter(wanted)
+            .map(|file| async move {
+                let result = download(sandbox, &file).await;
+                (file, result)
+            })
+            .buffer_unordered(MODPACK_DOWNLOAD_CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut lock = ContentLock::default();
+        for (file, result) in results {
+            match result {
+                Ok(_) => {
+                    report.installed.push(file.path.clone());
+                    if let Some(entry) = locked_content(&file) {
+                        lock.insert(entry);
+                    }
+                }
+                Err(reason) => {
+                    warn!("Failed to download {} for {}: {reason}", file.path, server.id);
+                    report.skip(&file.path, reason);
+                }
+            }
+        }
+        report.installed.sort();
+
+        apply_overrides(&staging.0, sandbox.root(), &["overrides", "server-overrides"]).await?;
+        lock.save(sandbox.root()).await?;
+        Ok(())
+    })
+    .await?;
+
+    Ok((server, report))
+}
+
+fn curseforge_client(config: &AppConfig) -> ApiResult<CurseForgeClient> {
+    let key = config
+        .content
+        .curseforge_api_key
+        .clone()
+        .ok_or(ApiError::configuration("content.curseforge_api_key is required for CurseForge imports"))?;
+    Ok(CurseForgeClient::new(&config.content.curseforge_api, key))
+}
+
+/// Creates a server from a CurseForge pack. Client packs (with a `manifest.json`) have their mods downloaded
+/// through the CurseForge API and their overrides applied; files whose authors disallow third-party downloads
+/// are recorded for manual upload. Server packs are copied as-is, with the version and loader detected from
+/// their contents.
+pub async fn import_curseforge(
+    pack: PathBuf,
+    name: Option<String>,
+    owner: User,
+    config: AppConfig,
+) -> ApiResult<(MinecraftServer, ImportReport)> {
+    let staging = Staging::extract(&pack, ArchiveFormat::Zip, &config).await;
+    let _ = tokio::fs::remove_file(&pack).await;
+    let staging = staging?;
+
+    match tokio::fs::read(staging.0.join(CURSEFORGE_MANIFEST)).await {
+        Ok(manifest) => {
+            let manifest: CurseManifest = serde_json::from_slice(&manifest)
+                .map_err(|e| ApiError::bad_request(format!("Invalid {CURSEFORGE_MANIFEST}: {e}")))?;
+            import_curseforge_manifest(&staging, manifest, name, owner, &config).await
+        }
+        Err(_) => import_server_directory(server_root(&staging.0), name, None, owner, &config).await,
+    }
+}
+
+async fn import_curseforge_manifest(
+    staging: &Staging,
+    manifest: CurseManifest,
+    name: Option<String>,
+    owner: User,
+    config: &AppConfig,
+) -> ApiResult<(MinecraftServer, ImportReport)> {
+    if manifest.manifest_type != "minecraftModpack" {
+        return Err(ApiError::bad_request(format!(
+            "Unsupported manifest type: {}",
+            manifest.manifest_type
+        )));
+    }
+    let client = curseforge_client(config)?;
+
+    let mut report = ImportReport {
+        minecraft_version: manifest.minecraft.version.clone(),
+        ..Default::default()
+    };
+    let loader = match manifest.loader() {
+        Some((loader, version)) => {
+            report.loader = Some(loader.clone());
+            report.loader_version = Some(version.clone());
+            Some(loader_version(&loader, &version)?)
+        }
+        None => None,
+    };
+
+    check_override_folder(&manifest.overrides)?;
+
+    let wanted: Vec<u64> = manifest.files.iter().filter(|f| f.required).map(|f| f.file_id).collect();
+    for optional in manifest.files.iter().filter(|f| !f.required) {
+        report.skip(format!("{}/{}", optional.project_id, optional.file_id), "Optional");
+    }
+    let files = match wanted.is_empty() {
+        true => Vec::new(),
+        false => client.files(&wanted).await?,
+    };
+    let mods: HashMap<u64, CurseMod> = match files.is_empty() {
+        true => HashMap::new(),
+        false => client
+            .mods(&files.iter().map(|f| f.mod_id).collect::<Vec<u64>>())
+            .await?
+            .into_iter()
+            .map(|m| (m.id, m))
+            .collect(),
+    };
+    for missing in wanted.iter().filter(|id| !files.iter().any(|f| f.id == **id)) {
+        report.skip(missing.to_string(), "File not found on CurseForge");
+    }
+
+    let server = new_server(
+        name.unwrap_or(match &manifest.version {
+            Some(version) => format!("{} {version}", manifest.name),
+            None => manifest.name.clone(),
+        }),
+        owner,
+        &manifest.minecraft.version,
+        loader,
+    )
+    .await?;
+
+    populate(&server, config, async |sandbox: &Sandbox| {
+        let mut downloads = Vec::new();
+        for file in &files {
+            let path = format!("mods/{}", file.file_name);
+            let project = mods.get(&file.mod_id);
+            if project.is_some_and(|m| m.class_id.is_some_and(|c| c != CLASS_MODS)) {
+                report.skip(&path, "Not a mod, only needed on clients");
+                continue;
+            }
+            if file.download_url.is_none() {
+                report.restricted.push(RestrictedContent {
+                    project_id: file.mod_id,
+                    file_id: file.id,
+                    name: project.map_or(file.display_name.clone(), |m| m.name.clone()),
+                    file: path,
+                    url: match project.and_then(|m| m.links.website_url.clone()) {
+                        Some(url) => format!("{}/files/{}", url.trim_end_matches('/'), file.id),
+                        None => format!("https://www.curseforge.com/projects/{}", file.mod_id),
+                    },
+                });
+                continue;
+            }
+            downloads.push((path, file.clone()));
+        }
+
+        let client = &client;
+        let results: Vec<(String, Result<(), String>)> = stream::iter(downloads)
+            .map(|(path, file): (String, CurseFile)| async move {
+                let result = match sandbox.resolve(&path).await {
+                    Ok(target) => match tokio::fs::create_dir_all(sandbox.root().join("mods")).await {
+                        Ok(()) => client.download(&file, &target).await.map_err(|e| e.to_string()),
+                        Err(e) => Err(e.to_string()),
+                    },
+                    Err(e) => Err(e.to_string()),
+                };
+                (path, result)
+            })
+            .buffer_unordered(MODPACK_DOWNLOAD_CONCURRENCY)
+            .collect()
+            .await;
+
+        for (path, result) in results {
+            match result {
+                Ok(()) => report.installed.push(path),
+                Err(reason) => {
+                    warn!("Failed to download {path} for {}: {reason}", server.id);
+                    report.skip(path, reason);
+                }
+            }
+        }
+        report.installed.sort();
+
+        apply_overrides(&staging.0, sandbox.root(), &[manifest.overrides.as_str()]).await?;
+        if !report.restricted.is_empty() {
+            report.warnings.push(format!(
+                "{} files have to be downloaded manually before the server will start",
+                report.restricted.len()
+            ));
+            let mut lock = ContentLock::load(sandbox.root()).await?;
+            lock.restricted = report.restricted.clone();
+            lock.save(sandbox.root()).await?;
+        }
+        Ok(())
+    })
+    .await?;
+
+    Ok((server, report))
+}
+
+/// Creates a server from a complete server directory, such as an extracted server pack or an existing
+/// installation. The Minecraft version and loader are detected from its contents unless `version` is given,
+/// and the directory is copied as-is.
+pub async fn import_server_directory(
+    root: PathBuf,
+    name: Option<String>,
+    version: Option<String>,
+    owner: User,
+    config: &AppConfig,
+) -> ApiResult<(MinecraftServer, ImportReport)> {
+    let detected = {
+        let root = root.clone();
+        tokio::task::spawn_blocking(move || detect_server(root))
+            .await
+            .map_err(unexpected)?
+    };
+    let minecraft_version = version.or(detected.minecraft_version.clone()).ok_or(ApiError::bad_request(
+        "Could not detect the Minecraft version; specify it explicitly",
+    ))?;
+    let loader = match (&detected.loader, &detected.loader_version) {
+        (Some(loader), Some(version)) => Some(loader_version(loader, version)?),
+        (Some(loader), None) => {
+            return Err(ApiError::bad_request(format!("Could not determine the {loader} version")));
+        }
+        _ => None,
+    };
+
+    let mut report = ImportReport {
+        minecraft_version: minecraft_version.clone(),
+        loader: detected.loader.clone(),
+        loader_version: detected.loader_version.clone(),
+        warnings: detected.evidence.iter().map(|e| format!("Detected from {e}")).collect(),
+        ..Default::default()
+    };
+    let properties = root.join("server.properties");
+    if tokio::fs::metadata(&properties).await.is_ok() {
+        match ServerProperties::from_file(&properties).await {
+            Ok(properties) => report.properties = Some(properties),
+            Err(e) => report
+                .warnings
+                .push(format!("server.properties could not be read and will be replaced: {e}")),
+        }
+    }
+
+    let server = new_server(
+        name.unwrap_or(
+            root.file_name()
+                .and_then(|n| n.to_str())
+                .filter(|n| !n.starts_with(".import-"))
+                .unwrap_or("Imported server")
+                .to_string(),
+        ),
+        owner,
+        &minecraft_version,
+        loader,
+    )
+    .await?;
+
+    populate(&server, config, async |sandbox: &Sandbox| {
+        let (source, destination) = (root.clone(), sandbox.root().to_path_buf());
+        tokio::task::spawn_blocking(move || copy_tree(&source, &destination))
+            .await
+            .map_err(unexpected)?
+            .map_err(|e| ApiError::from(Error::path(root.display(), e)))?;
+        report.installed.push(String::from("."));
+        Ok(())
+    })
+    .await?;
+
+    Ok((server, report))
+}
+
+/// Creates a server from an uploaded `.zip` or `.tar.gz` of a server directory. A single top-level folder
+/// wrapping the server is unpacked.
+pub async fn import_archive(
+    archive: PathBuf,
+    format: ArchiveFormat,
+    name: Option<String>,
+    version: Option<String>,
+    owner: User,
+    config: AppConfig,
+) -> ApiResult<(MinecraftServer, ImportReport)> {
+    let staging = Staging::extract(&archive, format, &config).await;
+    let _ = tokio::fs::remove_file(&archive).await;
+    let staging = staging?;
+    import_server_directory(server_root(&staging.0), name, version, owner, &config).await
+}
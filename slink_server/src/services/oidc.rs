@@ -0,0 +1,102 @@
+use bson::doc;
+use log::info;
+use manor::{Collection, Model};
+use slink_common::{ApiError, ApiResult, Error, providers::oidc::OidcIdentity, types::OidcConfig};
+
+use crate::{
+    models::{Role, User},
+    util::security::random_bytes,
+};
+
+fn unexpected(e: impl ToString) -> ApiError {
+    ApiError::from(Error::Unexpected(e.to_string()))
+}
+
+/// How an identity is stored on its user.
+pub fn subject_key(identity: &OidcIdentity) -> String {
+    format!("{}#{}", identity.issuer, identity.subject)
+}
+
+async fn linked_user(key: &str) -> ApiResult<Option<User>> {
+    Collection::<User>::new()
+        .find_one(doc! {"oidc_subject": key})
+        .await
+        .map_err(unexpected)
+}
+
+/// Applies the group mapping: superuser status when `superuser_groups` is set, and global roles when
+/// `role_groups` is set. Unknown role names are ignored.
+async fn apply_groups(user: &mut User, identity: &OidcIdentity, config: &OidcConfig) -> ApiResult<()> {
+    let groups = identity.list_claim(&config.groups_claim);
+    if !config.superuser_groups.is_empty() {
+        user.superuser = groups.iter().any(|g| config.superuser_groups.contains(g));
+    }
+    if !config.role_groups.is_empty() {
+        let names: Vec<&String> = groups
+            .iter()
+            .filter_map(|g| config.role_groups.get(g))
+            .flatten()
+            .collect();
+        user.roles = Role::all()
+            .await?
+            .into_iter()
+            .filter(|r| names.contains(&&r.name))
+            .map(|r| r.id)
+            .collect();
+    }
+    Ok(())
+}
+
+/// Links an identity to a logged-in user, failing if it already belongs to someone else.
+pub async fn link(mut user: User, identity: &OidcIdentity) -> ApiResult<User> {
+    let key = subject_key(identity);
+    if let Some(existing) = linked_user(&key).await?
+        && existing.id != user.id
+    {
+        return Err(ApiError::invalid_state("This identity is already linked to another user"));
+    }
+    user.oidc_subject = Some(key);
+    user.save().await.map_err(unexpected)?;
+    info!("Linked OpenID Connect identity to user {} ({})", user.username, user.id);
+    Ok(user)
+}
+
+/// Finds the user an identity logs in as: the linked user, else an unlinked local user with the same name if
+/// `link_by_username` is set, else a new user if `auto_provision` is set. Group mappings are applied on every
+/// login.
+pub async fn resolve_user(identity: &OidcIdentity, config: &OidcConfig) -> ApiResult<User> {
+    let key = subject_key(identity);
+    let username = identity
+        .string_claim(&config.username_claim)
+        .filter(|u| !u.trim().is_empty())
+        .unwrap_or(identity.subject.clone());
+
+    let mut user = match linked_user(&key).await? {
+        Some(user) => user,
+        None => match User::from_username(username.clone()).await {
+            Some(mut local) if config.link_by_username && local.oidc_subject.is_none() => {
+                local.oidc_subject = Some(key);
+                info!("Linked OpenID Connect identity to existing user {username} ({})", local.id);
+                local
+            }
+            Some(_) => {
+                return Err(ApiError::invalid_state(format!(
+                    "A local user named {username} already exists and is not linked to this identity"
+                )));
+            }
+            None if config.auto_provision => {
+                // Provisioned users log in through the provider only, so their password is never shown.
+                let password = slink_common::utilities::base32_encode(&random_bytes(32)?);
+                let mut created = User::create(username.clone(), password)?;
+                created.oidc_subject = Some(key);
+                info!("Provisioned user {username} ({}) from OpenID Connect", created.id);
+                created
+            }
+            None => return Err(ApiError::missing_auth("oidc_user_not_provisioned")),
+        },
+    };
+
+    apply_groups(&mut user, identity, config).await?;
+    user.save().await.map_err(unexpected)?;
+    Ok(user)
+}
@@ -0,0 +1,145 @@
+use std::{collections::HashMap, str::FromStr};
+
+use bson::Uuid;
+use bytes::Bytes;
+use chrono::Utc;
+use log::{debug, warn};
+use manor::{Collection, Model};
+use slink_common::{
+    console::{ConsoleEvent, ConsoleParser, LogFormat},
+    providers::servers::ServerBinaryVersion,
+    types::Version,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{
+    models::{MinecraftServer, OnlinePlayer, PlayerRecord, PlayerSession},
+    util::ServerInstance,
+};
+
+/// Details logged before a player actually joins.
+#[derive(Clone, Debug, Default)]
+struct PendingLogin {
+    uuid: Option<Uuid>,
+    address: Option<String>,
+}
+
+/// Follows a server's console for one run, keeping its online player list and session history up to date.
+pub struct PlayerTracker {
+    instance: ServerInstance,
+    parser: ConsoleParser,
+    pending: HashMap<String, PendingLogin>,
+}
+
+impl PlayerTracker {
+    pub fn log_format(server: &MinecraftServer) -> LogFormat {
+        match server.modloader_version {
+            Some(ServerBinaryVersion::Fabric(_)) => LogFormat::Fabric,
+            None => LogFormat::Vanilla,
+        }
+    }
+
+    pub fn spawn(instance: ServerInstance, server: &MinecraftServer, receiver: broadcast::Receiver<Bytes>) {
+        let version = Version::from_str(&server.minecraft_version.version.id).ok();
+        let tracker = Self {
+            instance,
+            parser: ConsoleParser::new(Self::log_format(server), version.as_ref()),
+            pending: HashMap::new(),
+        };
+
+        tokio::spawn(tracker.run(receiver));
+    }
+
+    async fn run(mut self, mut receiver: broadcast::Receiver<Bytes>) {
+        loop {
+            match receiver.recv().await {
+                Ok(chunk) => {
+                    for event in self.parser.feed(&chunk) {
+                        self.handle(event).await;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Player tracker for {} skipped {skipped} console chunks", self.instance.server);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+
+        self.disconnect_all("Server stopped").await;
+    }
+
+    async fn handle(&mut self, event: ConsoleEvent) {
+        debug!("Console event on {}: {event:?}", self.instance.server);
+        match event {
+            ConsoleEvent::Authenticated { player, uuid } => {
+                self.pending.entry(player).or_default().uuid = Some(uuid.into());
+            }
+            ConsoleEvent::Login { player, address } => {
+                self.pending.entry(player).or_default().address = Some(address);
+            }
+            ConsoleEvent::Join { player } => self.join(player).await,
+            ConsoleEvent::Leave { player } => self.leave(&player, None).await,
+            ConsoleEvent::Disconnect { player, reason } => self.leave(&player, Some(reason)).await,
+            ConsoleEvent::ServerStopping => self.disconnect_all("Server stopping").await,
+            ConsoleEvent::ServerReady | ConsoleEvent::Chat { .. } | ConsoleEvent::Death { .. } => {}
+        }
+    }
+
+    async fn join(&mut self, name: String) {
+        let server = self.instance.server;
+        let pending = self.pending.remove(&name).unwrap_or_default();
+        let session = PlayerSession::create(server, name.clone(), pending.address.clone());
+        if let Err(e) = session.save().await {
+            warn!("Failed to record session for {name} on {server}: {e:?}");
+        }
+
+        let mut record = PlayerRecord::find(server, &name)
+            .await
+            .unwrap_or_else(|| PlayerRecord::create(server, name.clone()));
+        record.last_seen = session.joined;
+        record.sessions += 1;
+        if pending.uuid.is_some() {
+            record.player_uuid = pending.uuid;
+        }
+        if let Err(e) = record.save().await {
+            warn!("Failed to update player record for {name} on {server}: {e:?}");
+        }
+
+        self.instance.players.write().await.insert(
+            name.clone(),
+            OnlinePlayer {
+                name,
+                uuid: record.player_uuid,
+                address: pending.address,
+                joined: session.joined,
+                session: session.id,
+            },
+        );
+    }
+
+    async fn leave(&mut self, name: &str, reason: Option<String>) {
+        let Some(online) = self.instance.players.write().await.remove(name) else {
+            return;
+        };
+        let now = Utc::now();
+
+        if let Ok(Some(mut session)) = Collection::<PlayerSession>::new().get(online.session).await {
+            session.left = Some(now);
+            session.reason = reason;
+            let _ = session.save().await;
+        }
+
+        if let Some(mut record) = PlayerRecord::find(self.instance.server, name).await {
+            record.last_seen = now;
+            record.playtime += (now - online.joined).num_seconds().max(0);
+            let _ = record.save().await;
+        }
+    }
+
+    async fn disconnect_all(&mut self, reason: &str) {
+        let online: Vec<String> = self.instance.players.read().await.keys().cloned().collect();
+        for name in online {
+            self.leave(&name, Some(reason.to_string())).await;
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bson::Uuid;
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{info, warn};
+use manor::{Collection, Model};
+use rocket::{
+    Request,
+    request::{self, FromRequest},
+};
+use rocket_okapi::OpenApiFromRequest;
+use slink_common::{
+    ApiError, ApiResult, Error, SCHEDULE_MISSED_GRACE_SECONDS, SCHEDULER_TICK_SECONDS,
+    runners::MinecraftRunner,
+    types::{AppConfig, BackupTrigger},
+};
+
+use crate::{
+    models::{AuditEntry, MinecraftServer, MissedRunPolicy, Permission, ScheduledAction, ScheduledTask, TaskRun, User},
+    services::backups::BackupEngine,
+    util::Runners,
+};
+
+const DEFAULT_RESTART_MESSAGE: &str = "Server restarting in {time}";
+
+fn unexpected(e: impl ToString) -> ApiError {
+    ApiError::from(Error::Unexpected(e.to_string()))
+}
+
+fn describe(seconds: u64) -> String {
+    match seconds {
+        1 => String::from("1 second"),
+        s if s < 60 || s % 60 != 0 => format!("{s} seconds"),
+        60 => String::from("1 minute"),
+        s => format!("{} minutes", s / 60),
+    }
+}
+
+/// Everything a task needs to run, cloned out of Rocket's managed state.
+#[derive(Clone)]
+pub struct TaskContext {
+    pub config: AppConfig,
+    pub runners: Runners,
+    pub engine: BackupEngine,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Due {
+    NotYet,
+
+    /// Missed by more than the grace period, with a policy of skipping such runs
+    Skip,
+
+    /// Starts now, to run its action at the given time
+    Run(DateTime<Utc>),
+}
+
+/// Runs scheduled tasks when they are due. Next-run times live in the database, so runs missed
+/// while Slink was down are noticed on the next tick and handled by the task's missed-run policy.
+#[derive(Clone, Default, OpenApiFromRequest)]
+pub struct Scheduler {
+    running: Arc<Mutex<HashSet<Uuid>>>,
+}
+
+/// Marks a task as running until dropped.
+struct TaskLock {
+    running: Arc<Mutex<HashSet<Uuid>>>,
+    task: Uuid,
+}
+
+impl Drop for TaskLock {
+    fn drop(&mut self) {
+        self.running.lock().unwrap().remove(&self.task);
+    }
+}
+
+impl Scheduler {
+    fn lock(&self, task: Uuid) -> ApiResult<TaskLock> {
+        if !self.running.lock().unwrap().insert(task) {
+            return Err(ApiError::invalid_state("This task is already running"));
+        }
+        Ok(TaskLock {
+            running: self.running.clone(),
+            task,
+        })
+    }
+
+    /// How long before its scheduled time a task starts, so that restarts happen on time after their warnings.
+    fn lead(task: &ScheduledTask) -> TimeDelta {
+        match &task.action {
+            ScheduledAction::Restart { warnings, .. } => {
+                TimeDelta::seconds(warnings.iter().max().copied().unwrap_or(0) as i64)
+            }
+            _ => TimeDelta::zero(),
+        }
+    }
+
+    pub fn spawn(&self, context: TaskContext) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(SCHEDULER_TICK_SECONDS));
+            loop {
+                interval.tick().await;
+                if let Err(e) = scheduler.tick(&context).await {
+                    warn!("Scheduler tick failed: {e:?}");
+                }
+            }
+        });
+    }
+
+    /// Decides what a tick at `now` does with a task due at `scheduled`.
+    fn due(task: &ScheduledTask, scheduled: DateTime<Utc>, now: DateTime<Utc>) -> Due {
+        let start = scheduled - Self::lead(task);
+        if start > now {
+            return Due::NotYet;
+        }
+        if now - start > TimeDelta::seconds(SCHEDULE_MISSED_GRACE_SECONDS) && task.missed == MissedRunPolicy::Skip {
+            return Due::Skip;
+        }
+        Due::Run(scheduled.max(now + Self::lead(task)))
+    }
+
+    async fn tick(&self, context: &TaskContext) -> ApiResult<()> {
+        let now = Utc::now();
+        for mut task in ScheduledTask::enabled().await? {
+            let Some(scheduled) = task.next_run else {
+                continue;
+            };
+            let due = Self::due(&task, scheduled, now);
+            if due == Due::NotYet {
+                continue;
+            }
+            let Ok(lock) = self.lock(task.id) else {
+                continue;
+            };
+
+            // Persist the following run before starting, so that a crash mid-run does not repeat it. Rescheduling
+            // after now also folds any number of missed runs into one.
+            if let Err(e) = task.reschedule(now.max(scheduled)) {
+                warn!("Cannot schedule task {} on {}: {e:?}", task.id, task.server);
+                continue;
+            }
+            task.save().await.map_err(|e| unexpected(format!("{e:?}")))?;
+
+            match due {
+                Due::Run(at) => {
+                    tokio::spawn(Self::run(lock, task, at, context.clone()));
+                }
+                _ => info!("Skipping missed run of task {} on {} (due {scheduled})", task.id, task.server),
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts a task in the background, as if it were scheduled for now plus its lead time.
+    pub fn run_now(&self, task: ScheduledTask, context: TaskContext) -> ApiResult<()> {
+        let lock = self.lock(task.id)?;
+        let at = Utc::now() + Self::lead(&task);
+        tokio::spawn(Self::run(lock, task, at, context));
+        Ok(())
+    }
+
+    /// Runs a task for its scheduled time `at` and records the outcome on it.
+    async fn run(_lock: TaskLock, task: ScheduledTask, at: DateTime<Utc>, context: TaskContext) {
+        let started = Utc::now();
+        let result = Self::execute(&task, at, &context).await;
+        let run = TaskRun {
+            started,
+            finished: Utc::now(),
+            success: result.is_ok(),
+            message: match result {
+                Ok(message) => message,
+                Err(e) => Some(format!("{e:?}")),
+            },
+        };
+        match run.success {
+            true => info!("Ran scheduled task {} on {}", task.id, task.server),
+            false => warn!("Scheduled task {} on {} failed: {:?}", task.id, task.server, run.message),
+        }
+
+        // Reload the task, as it may have been edited while running
+        if let Ok(Some(mut current)) = Collection::<ScheduledTask>::new().get(task.id).await {
+            current.last_run = Some(run);
+            if let Err(e) = current.save().await {
+                warn!("Failed to record run of task {}: {e:?}", task.id);
+            }
+        }
+    }
+
+    async fn execute(task: &ScheduledTask, at: DateTime<Utc>, context: &TaskContext) -> ApiResult<Option<String>> {
+        let server = Collection::<MinecraftServer>::new()
+            .get(task.server)
+            .await
+            .map_err(|e| unexpected(format!("{e:?}")))?
+            .ok_or(ApiError::not_found(format!("Server: {}", task.server)))?;
+
+        match &task.action {
+            ScheduledAction::Command { .. } | ScheduledAction::Broadcast { .. } => {
+                Self::command(task, &server, context).await
+            }
+            ScheduledAction::Backup { format, note } => {
+                let backup = context
+                    .engine
+                    .create(
+                        &server,
+                        &context.config,
+                        &context.runners,
+                        BackupTrigger::Scheduled,
+                        *format,
+                        note.clone().or(Some(task.name.clone())),
+                    )
+                    .await?;
+                BackupEngine::apply_retention(&server, &context.config).await?;
+                Ok(Some(format!("Created backup {}", backup.id)))
+            }
+            ScheduledAction::Restart { warnings, message } => {
+                Self::restart(&server, at, warnings, message.as_deref(), context).await
+            }
+        }
+    }
+
+    /// Checks that the task's author may still send its command, as their permissions or command filters may
+    /// have changed since the task was saved.
+    async fn authorize(task: &ScheduledTask, server: &MinecraftServer, command: &str) -> ApiResult<()> {
+        let author = match task.author {
+            Some(id) => User::get(id).await.ok().filter(|user| !user.disabled),
+            None => None,
+        };
+        let Some(author) = author else {
+            return Err(ApiError::missing_auth("schedule_author_required"));
+        };
+        if !server.permissions(&author).await?.contains(&Permission::ConsoleWrite) {
+            return Err(ApiError::missing_auth("permission:console_write"));
+        }
+        if !server.command_filter(&author).await?.allows(command) {
+            let mut entry = AuditEntry::new("console.denied")
+                .server(server.id)
+                .target(command.trim())
+                .details(serde_json::json!({ "task": task.id.to_string() }));
+            entry.actor = Some(author.id);
+            entry.actor_name = Some(author.username.clone());
+            entry.record().await;
+            return Err(ApiError::missing_auth("command_denied"));
+        }
+        Ok(())
+    }
+
+    async fn command(task: &ScheduledTask, server: &MinecraftServer, context: &TaskContext) -> ApiResult<Option<String>> {
+        let Some(command) = task.action.command() else {
+            return Ok(None);
+        };
+        Self::authorize(task, server, &command).await?;
+        let instance = context
+            .runners
+            .get(&server.id)
+            .await
+            .ok_or(ApiError::invalid_state("Server is not running"))?;
+        instance
+            .execute(command, &server.properties(&context.config).await)
+            .await
+    }
+
+    /// Warns players at each warning offset before `at`, then stops and starts the server.
+    async fn restart(
+        server: &MinecraftServer,
+        at: DateTime<Utc>,
+        warnings: &[u64],
+        message: Option<&str>,
+        context: &TaskContext,
+    ) -> ApiResult<Option<String>> {
+        let Some(instance) = context.runners.get(&server.id).await else {
+            return Ok(Some(String::from("Server is not running, skipped restart")));
+        };
+        if !instance.runner.lock().await.status().await.running() {
+            return Ok(Some(String::from("Server is not running, skipped restart")));
+        }
+
+        let mut warnings = warnings.to_vec();
+        warnings.sort_unstable_by(|a, b| b.cmp(a));
+        warnings.dedup();
+        let properties = server.properties(&context.config).await;
+        for seconds in warnings {
+            let warn_at = at - TimeDelta::seconds(seconds as i64);
+            if let Ok(wait) = (warn_at - Utc::now()).to_std() {
+                tokio::time::sleep(wait).await;
+            }
+            let text = message
+                .unwrap_or(DEFAULT_RESTART_MESSAGE)
+                .replace("{time}", &describe(seconds));
+            if let Err(e) = instance.execute(format!("say {text}"), &properties).await {
+                warn!("Failed to send restart warning to {}: {e:?}", server.id);
+            }
+        }
+        if let Ok(wait) = (at - Utc::now()).to_std() {
+            tokio::time::sleep(wait).await;
+        }
+
+        if instance.runner.lock().await.status().await.running() {
+            instance.stop().await?;
+        }
+        let instance = context.runners.prepare(server, &context.config).await?;
+        instance.start(server).await?;
+        Ok(Some(String::from("Server restarted")))
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for Scheduler {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(
+            req.rocket()
+                .state::<Scheduler>()
+                .expect("No scheduler initialized.")
+                .clone(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn new_task(action: ScheduledAction, missed: MissedRunPolicy) -> ScheduledTask {
+        ScheduledTask::create(
+            Uuid::new(),
+            String::from("Hourly"),
+            String::from("0 * * * *"),
+            String::from("UTC"),
+            action,
+            missed,
+            Uuid::new(),
+        )
+        .unwrap()
+    }
+
+    fn broadcast() -> ScheduledAction {
+        ScheduledAction::Broadcast { message: String::from("hi") }
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn waits_for_the_scheduled_time() {
+        let task = new_task(broadcast(), MissedRunPolicy::Skip);
+        assert_eq!(Scheduler::due(&task, now() + TimeDelta::seconds(1), now()), Due::NotYet);
+        assert_eq!(Scheduler::due(&task, now(), now()), Due::Run(now()));
+    }
+
+    #[test]
+    fn runs_late_tasks_within_the_grace_period() {
+        let task = new_task(broadcast(), MissedRunPolicy::Skip);
+        let scheduled = now() - TimeDelta::seconds(SCHEDULE_MISSED_GRACE_SECONDS);
+        assert_eq!(Scheduler::due(&task, scheduled, now()), Due::Run(now()));
+    }
+
+    #[test]
+    fn skips_missed_runs() {
+        let task = new_task(broadcast(), MissedRunPolicy::Skip);
+        let scheduled = now() - TimeDelta::seconds(SCHEDULE_MISSED_GRACE_SECONDS + 1);
+        assert_eq!(Scheduler::due(&task, scheduled, now()), Due::Skip);
+    }
+
+    #[test]
+    fn runs_missed_runs_once() {
+        let mut task = new_task(broadcast(), MissedRunPolicy::RunOnce);
+        let scheduled = now() - TimeDelta::hours(5);
+        assert_eq!(Scheduler::due(&task, scheduled, now()), Due::Run(now()));
+
+        // The next run is the first one after now, not the next of the missed ones
+        task.reschedule(now().max(scheduled)).unwrap();
+        let next = task.next_run.unwrap();
+        assert_eq!(next, now() + TimeDelta::hours(1));
+        assert_eq!(Scheduler::due(&task, next, now()), Due::NotYet);
+    }
+
+    #[test]
+    fn starts_restarts_early_for_their_warnings() {
+        let restart = ScheduledAction::Restart { warnings: vec![60, 600, 10], message: None };
+        let task = new_task(restart.clone(), MissedRunPolicy::Skip);
+        assert_eq!(Scheduler::lead(&task), TimeDelta::seconds(600));
+
+        let scheduled = now() + TimeDelta::seconds(600);
+        assert_eq!(Scheduler::due(&task, scheduled + TimeDelta::seconds(1), now()), Due::NotYet);
+        assert_eq!(Scheduler::due(&task, scheduled, now()), Due::Run(scheduled));
+
+        // A restart started late still gives players the full warning
+        let late = new_task(restart, MissedRunPolicy::RunOnce);
+        assert_eq!(Scheduler::due(&late, now(), now()), Due::Run(now() + TimeDelta::seconds(600)));
+    }
+
+    #[test]
+    fn describes_warning_times() {
+        assert_eq!(describe(1), "1 second");
+        assert_eq!(describe(30), "30 seconds");
+        assert_eq!(describe(90), "90 seconds");
+        assert_eq!(describe(60), "1 minute");
+        assert_eq!(describe(600), "10 minutes");
+    }
+}
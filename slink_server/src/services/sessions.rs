@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+use bson::doc;
+use chrono::{TimeDelta, Utc};
+use log::{debug, warn};
+use manor::Collection;
+use slink_common::{ApiError, ApiResult, Error, SESSION_SWEEP_SECONDS};
+
+use crate::models::Session;
+
+fn unexpected(e: impl ToString) -> ApiError {
+    ApiError::from(Error::Unexpected(e.to_string()))
+}
+
+/// Deletes sessions that were not used within their lifetime, or have no usable connection time. Returns the
+/// number of sessions removed.
+pub async fn sweep(lifetime: TimeDelta) -> ApiResult<u64> {
+    Collection::<Session>::new()
+        .delete_many(doc! {"$nor": [Session::active_since(Utc::now() - lifetime)]})
+        .await
+        .map_err(unexpected)
+}
+
+/// Periodically purges expired sessions in the background.
+pub fn spawn_sweeper(lifetime: TimeDelta) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(SESSION_SWEEP_SECONDS));
+        loop {
+            interval.tick().await;
+            match sweep(lifetime).await {
+                Ok(0) => {}
+                Ok(removed) => debug!("Removed {removed} expired sessions"),
+                Err(e) => warn!("Session sweep failed: {e:?}"),
+            }
+        }
+    });
+}
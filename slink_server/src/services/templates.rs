@@ -0,0 +1,287 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use bson::Uuid;
+use chrono::Utc;
+use log::warn;
+use manor::{Link, Model};
+use slink_common::{
+    ApiError, ApiResult, Error,
+    runners::MinecraftRunner,
+    types::{AppConfig, ContentLock},
+    utilities::Sandbox,
+};
+
+use crate::{
+    models::{MinecraftServer, ScheduledTask, ServerTemplate, TemplateContent, User},
+    services::{
+        content::ContentManager,
+        imports::ImportReport,
+        modpacks::{copy_tree, new_server, populate},
+    },
+    util::Runners,
+};
+
+fn unexpected(e: impl ToString) -> ApiError {
+    ApiError::from(Error::Unexpected(e.to_string()))
+}
+
+/// Where a template's seed files are kept.
+pub fn seed_directory(template: &ServerTemplate, config: &AppConfig) -> PathBuf {
+    config.template_directory().join(template.id.to_string())
+}
+
+/// Copies a file or directory, creating missing parents of `destination`.
+fn copy_entry(source: &Path, destination: &Path) -> std::io::Result<()> {
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match std::fs::metadata(source)?.is_dir() {
+        true => copy_tree(source, destination),
+        false => std::fs::copy(source, destination).and(Ok(())),
+    }
+}
+
+/// Copies the top-level entries of a server directory, except the excluded names. Returns the copied names.
+fn copy_directory_except(source: &Path, destination: &Path, excluded: &HashSet<String>) -> std::io::Result<Vec<String>> {
+    std::fs::create_dir_all(destination)?;
+    let mut copied = Vec::new();
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let kind = entry.file_type()?;
+        if excluded.contains(&name) || !(kind.is_dir() || kind.is_file()) {
+            continue;
+        }
+        copy_entry(&entry.path(), &destination.join(&name))?;
+        copied.push(name);
+    }
+    copied.sort();
+    Ok(copied)
+}
+
+/// Captures a server's version, loader, properties, memory settings and catalog content as a new template.
+/// `files` are copied from the server directory and seeded into servers created from the template.
+pub async fn save_as_template(
+    server: &MinecraftServer,
+    owner: &User,
+    config: &AppConfig,
+    name: String,
+    description: Option<String>,
+    files: Vec<String>,
+    public: bool,
+) -> ApiResult<ServerTemplate> {
+    let sandbox = server.sandbox(config).await?;
+    let content = ContentLock::load(sandbox.root())
+        .await?
+        .content
+        .into_iter()
+        .filter(|c| !c.dependency)
+        .map(|c| TemplateContent {
+            project: c.project_id,
+            version: Some(c.version_id),
+            kind: c.kind,
+        })
+        .collect();
+
+    let mut template = ServerTemplate::new(name, owner.id, server.minecraft_version.version.id.clone(), server.max_memory);
+    template.description = description;
+    template.public = public && owner.superuser;
+    template.modloader_version = server.modloader_version.clone();
+    template.properties = server.properties(config).await;
+    template.java_args = server.java_args.clone();
+    template.content = content;
+
+    let seeds = seed_directory(&template, config);
+    for file in files {
+        let source = sandbox.resolve(&file).await?;
+        let relative = sandbox.relative(&source);
+        if relative.is_empty() {
+            return Err(ApiError::bad_request("Seed files must be inside the server directory"));
+        }
+        if tokio::fs::metadata(&source).await.is_err() {
+            let _ = tokio::fs::remove_dir_all(&seeds).await;
+            return Err(ApiError::not_found(format!("File: {relative}")));
+        }
+
+        let destination = seeds.join(&relative);
+        let copied = tokio::task::spawn_blocking(move || copy_entry(&source, &destination))
+            .await
+            .map_err(unexpected)?;
+        if let Err(e) = copied {
+            let _ = tokio::fs::remove_dir_all(&seeds).await;
+            return Err(Error::path(relative, e).into());
+        }
+        template.files.push(relative);
+    }
+
+    if let Err(e) = template.save().await {
+        let _ = tokio::fs::remove_dir_all(&seeds).await;
+        return Err(unexpected(format!("{e:?}")));
+    }
+    Ok(template)
+}
+
+/// Deletes a template along with its seed files.
+pub async fn delete_template(template: ServerTemplate, config: &AppConfig) -> ApiResult<()> {
+    let seeds = seed_directory(&template, config);
+    template.delete().await.map_err(|e| unexpected(format!("{e:?}")))?;
+    match tokio::fs::remove_dir_all(&seeds).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::path(seeds.display(), e).into()),
+        _ => Ok(()),
+    }
+}
+
+/// Creates a server from a template: seed files are copied, `server.properties` is written and the template's
+/// content is installed. Content that cannot be installed is reported rather than failing the server.
+pub async fn create_from_template(
+    template: ServerTemplate,
+    name: String,
+    owner: User,
+    config: AppConfig,
+    manager: ContentManager,
+) -> ApiResult<(MinecraftServer, ImportReport)> {
+    let mut server = new_server(
+        name,
+        owner,
+        &template.minecraft_version,
+        template.modloader_version.clone(),
+    )
+    .await?;
+    server.max_memory = template.max_memory;
+    server.java_args = template.java_args.clone();
+
+    let mut report = ImportReport {
+        minecraft_version: template.minecraft_version.clone(),
+        loader: server.loader().map(|l| l.to_string()),
+        ..Default::default()
+    };
+    let seeds = seed_directory(&template, &config);
+
+    populate(&server, &config, async |sandbox: &Sandbox| {
+        if tokio::fs::metadata(&seeds).await.is_ok_and(|m| m.is_dir()) {
+            let (source, destination) = (seeds.clone(), sandbox.root().to_path_buf());
+            tokio::task::spawn_blocking(move || copy_tree(&source, &destination))
+                .await
+                .map_err(unexpected)?
+                .map_err(|e| ApiError::from(Error::path(seeds.display(), e)))?;
+            report.installed.extend(template.files.iter().cloned());
+        }
+        template
+            .properties
+            .to_file(sandbox.root().join("server.properties"))
+            .await?;
+
+        for content in &template.content {
+            match manager
+                .install(&server, &config, content.kind, &content.project, content.version.as_deref())
+                .await
+            {
+                Ok(changes) => {
+                    report.installed.extend(changes.installed.into_iter().map(|c| c.file));
+                    for missing in changes.missing {
+                        report
+                            .warnings
+                            .push(format!("{} requires {missing}, which has no compatible version", content.project));
+                    }
+                    for client_only in changes.client_only {
+                        report.skip(client_only, "Client-only");
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to install {} for {}: {e:?}", content.project, server.id);
+                    report.skip(&content.project, format!("{e:?}"));
+                }
+            }
+        }
+        report.installed.sort();
+        Ok(())
+    })
+    .await?;
+
+    Ok((server, report))
+}
+
+/// Fails if the server is running, since its world data would be copied mid-write.
+pub async fn ensure_stopped(server: &MinecraftServer, runners: &Runners) -> ApiResult<()> {
+    if let Some(instance) = runners.get(&server.id).await
+        && instance.runner.lock().await.status().await.running()
+    {
+        return Err(ApiError::invalid_state("Server must be stopped to copy its world data"));
+    }
+    Ok(())
+}
+
+/// Copies a server's settings, files and scheduled tasks to a new server owned by `owner`. World folders are
+/// only copied when `include_worlds` is set.
+pub async fn clone_server(
+    source: MinecraftServer,
+    name: String,
+    include_worlds: bool,
+    owner: User,
+    config: AppConfig,
+) -> ApiResult<(MinecraftServer, ImportReport)> {
+    let author = owner.id;
+    let mut server = source.clone();
+    server.id = Uuid::new();
+    server.name = name;
+    server.owner = Link::from(owner);
+    server.port = None;
+
+    let level = source.properties(&config).await.level_name;
+    let worlds: HashSet<String> = match include_worlds {
+        true => HashSet::new(),
+        false => HashSet::from([format!("{level}_nether"), format!("{level}_the_end"), level]),
+    };
+    let mut report = ImportReport {
+        minecraft_version: source.minecraft_version.version.id.clone(),
+        loader: source.loader().map(|l| l.to_string()),
+        ..Default::default()
+    };
+
+    let from = source.directory(&config);
+    populate(&server, &config, async |sandbox: &Sandbox| {
+        if tokio::fs::metadata(&from).await.is_ok_and(|m| m.is_dir()) {
+            let (source, destination, excluded) = (from.clone(), sandbox.root().to_path_buf(), worlds.clone());
+            report.installed = tokio::task::spawn_blocking(move || copy_directory_except(&source, &destination, &excluded))
+                .await
+                .map_err(unexpected)?
+                .map_err(|e| ApiError::from(Error::path(from.display(), e)))?;
+        }
+        for world in &worlds {
+            if tokio::fs::metadata(from.join(world)).await.is_ok() {
+                report.skip(world, "World data not included");
+            }
+        }
+        Ok(())
+    })
+    .await?;
+
+    for task in ScheduledTask::for_server(source.id).await? {
+        let copied = ScheduledTask::create(
+            server.id,
+            task.name.clone(),
+            task.cron.clone(),
+            task.timezone.clone(),
+            task.action.clone(),
+            task.missed,
+            author,
+        )
+        .and_then(|mut copy| {
+            copy.enabled = task.enabled;
+            copy.reschedule(Utc::now())?;
+            Ok(copy)
+        });
+        let saved = match copied {
+            Ok(copy) => copy.save().await.map_err(|e| unexpected(format!("{e:?}"))),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = saved {
+            report.warnings.push(format!("Scheduled task {} was not copied: {e:?}", task.name));
+        }
+    }
+
+    Ok((server, report))
+}
@@ -1,15 +1,39 @@
 use bson::Uuid;
-use chrono::Utc;
+use chrono::{TimeDelta, Utc};
 use manor::{Collection, Model};
 use rocket::{
-    Data, Request, Response,
+    Data, Request,
     fairing::{Fairing, Info, Kind},
-    http::Cookie,
+    http::{Cookie, SameSite},
 };
-use slink_common::types::AppConfig;
+use slink_common::{SESSION_COOKIE, SESSION_TOUCH_SECONDS, SESSION_USER_AGENT_LENGTH, types::AppConfig};
 
 use crate::models::Session;
 
+/// The encrypted session cookie. It expires along with the session, and is kept from scripts and other sites.
+pub fn session_cookie(session: &Session, config: &AppConfig) -> Cookie<'static> {
+    let remaining = (session.last_connection.to_chrono() + config.authentication.session_max_lifetime) - Utc::now();
+    Cookie::build((SESSION_COOKIE, session.id.to_string()))
+        .path("/")
+        .http_only(true)
+        .secure(config.authentication.secure_cookies)
+        .same_site(SameSite::Lax)
+        .max_age(rocket::time::Duration::seconds(remaining.num_seconds().max(0)))
+        .build()
+}
+
+/// Address and user agent shown in session listings. User agents are cut short, since clients choose them freely.
+fn client_details(req: &Request<'_>) -> (Option<String>, Option<String>) {
+    let ip = req.client_ip().and_then(|ip| Some(ip.to_string()));
+    let user_agent = req
+        .headers()
+        .get_one("User-Agent")
+        .and_then(|agent| Some(agent.chars().take(SESSION_USER_AGENT_LENGTH).collect()));
+    (ip, user_agent)
+}
+
+/// Resolves the request's session from its cookie, and records where it was last used from. Requests without a
+/// valid session get an anonymous one that is only stored, and given a cookie, once something is saved in it.
 pub struct SessionFairing;
 
 #[async_trait]
@@ -17,7 +41,7 @@ impl Fairing for SessionFairing {
     fn info(&self) -> rocket::fairing::Info {
         Info {
             name: "Session Manager",
-            kind: Kind::Request | Kind::Response,
+            kind: Kind::Request,
         }
     }
 
@@ -27,30 +51,40 @@ impl Fairing for SessionFairing {
             .figment()
             .extract_inner::<AppConfig>("slink")
             .unwrap();
-        if let Some(token) = req.cookies().get("slink.token") {
-            if let Ok(id) = Uuid::parse_str(token.value()) {
-                if let Ok(Some(existing)) = Collection::<Session>::new().get(id).await {
-                    if existing.last_connection + config.authentication.session_max_lifetime
-                        >= Utc::now()
-                    {
-                        let mut existing_mut = existing.clone();
-                        existing_mut.last_connection = Utc::now();
-                        req.local_cache(|| Some(existing_mut));
-                        return;
-                    } else {
-                        let _ = existing.delete().await;
+        let (ip, user_agent) = client_details(req);
+        let cookie_id = req
+            .cookies()
+            .get_private(SESSION_COOKIE)
+            .and_then(|token| Uuid::parse_str(token.value()).ok());
+
+        if let Some(id) = cookie_id {
+            match Collection::<Session>::new().get(id).await {
+                Ok(Some(existing)) if !existing.expired(config.authentication.session_max_lifetime) => {
+                    let mut existing = existing;
+                    // Only write the connection time occasionally, rather than on every request
+                    let stale = Utc::now() - existing.last_connection.to_chrono() > TimeDelta::seconds(SESSION_TOUCH_SECONDS);
+                    let moved = existing.ip != ip || existing.user_agent != user_agent;
+                    if stale || moved {
+                        existing.last_connection = bson::DateTime::now();
+                        existing.ip = ip;
+                        existing.user_agent = user_agent;
+                        let _ = existing.save().await;
+                        req.cookies().add_private(session_cookie(&existing, &config));
                     }
+                    req.local_cache(|| Some(existing));
+                    return;
+                }
+                Ok(Some(expired)) => {
+                    let _ = expired.delete().await;
                 }
+                _ => {}
             }
+            req.cookies().remove_private(SESSION_COOKIE);
         }
 
-        let new_session = req.local_cache(|| Some(Session::create())).clone().unwrap();
-        new_session.save().await.unwrap();
-        return;
-    }
-
-    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
-        let mut session = req.local_cache(|| Some(Session::create())).clone().unwrap();
-        res.set_header(Cookie::new("slink.token", session.id.to_string()));
+        let mut fresh = Session::create();
+        fresh.ip = ip;
+        fresh.user_agent = user_agent;
+        req.local_cache(|| Some(fresh));
     }
 }
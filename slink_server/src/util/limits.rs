@@ -0,0 +1,29 @@
+use rocket::{
+    Request,
+    data::{ByteUnit, ToByteUnit},
+    request::{self, FromRequest},
+};
+use rocket_okapi::request::OpenApiFromRequest;
+use slink_common::ApiError;
+
+/// The configured `file` data limit, used to cap streamed uploads.
+#[derive(Clone, Copy, Debug)]
+pub struct UploadLimit(pub ByteUnit);
+
+#[async_trait]
+impl<'r> FromRequest<'r> for UploadLimit {
+    type Error = ApiError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(Self(req.limits().get("file").unwrap_or(4.gibibytes())))
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for UploadLimit {
+    fn from_request_input(
+        _gen: &mut rocket_okapi::r#gen::OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<rocket_okapi::request::RequestHeaderInput> {
+        Ok(rocket_okapi::request::RequestHeaderInput::None)
+    }
+}
@@ -1,6 +1,11 @@
 pub mod fairings;
+pub mod rate_limit;
 pub mod security;
 pub mod types;
 mod database;
+mod limits;
+mod runners;
 
-pub use database::Docs;
\ No newline at end of file
+pub use database::Docs;
+pub use limits::UploadLimit;
+pub use runners::{Runners, ServerInstance};
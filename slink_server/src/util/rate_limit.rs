@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use log::warn;
+use rocket::{
+    Request,
+    request::{self, FromRequest},
+};
+use rocket_okapi::request::OpenApiFromRequest;
+use slink_common::{
+    ApiError, ApiResult, RATE_LIMIT_MAX_KEYS,
+    types::{AppConfig, LoginLimits},
+};
+
+use crate::models::Auditor;
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Failures {
+    count: u32,
+    last_failure: DateTime<Utc>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+impl Failures {
+    /// Whether the failures are old enough to forget: the account is not locked, and there were none within
+    /// the failure window.
+    fn expired(&self, now: DateTime<Utc>, limits: &LoginLimits) -> bool {
+        self.locked_until.is_none_or(|until| until <= now)
+            && self.last_failure + TimeDelta::seconds(limits.failure_window_seconds as i64) <= now
+    }
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    buckets: HashMap<String, Bucket>,
+    failures: HashMap<String, Failures>,
+}
+
+impl LimiterState {
+    /// Takes one token from a bucket, refilling it for the time passed since it was last used.
+    fn take(&mut self, key: String, capacity: u32, refill_seconds: u64) -> bool {
+        let now = Instant::now();
+        let capacity = capacity.max(1) as f64;
+        let bucket = self.buckets.entry(key).or_insert(Bucket { tokens: capacity, updated: now });
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() / refill_seconds.max(1) as f64;
+        bucket.tokens = (bucket.tokens + refilled).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    fn len(&self) -> usize {
+        self.buckets.len() + self.failures.len()
+    }
+
+    /// Makes room for `room` new keys without exceeding [RATE_LIMIT_MAX_KEYS]. Buckets that have refilled
+    /// completely and expired failures are forgotten first, then the least recently used buckets, and lockouts
+    /// only as a last resort.
+    fn prune(&mut self, limits: &LoginLimits, room: usize) {
+        if self.len() + room <= RATE_LIMIT_MAX_KEYS {
+            return;
+        }
+        let (now, utc_now) = (Instant::now(), Utc::now());
+        let slowest = limits.ip_refill_seconds.max(limits.username_refill_seconds) as f64;
+        let capacity = limits.ip_capacity.max(limits.username_capacity) as f64;
+        self.buckets
+            .retain(|_, b| now.duration_since(b.updated).as_secs_f64() < slowest * capacity);
+        self.failures.retain(|_, f| !f.expired(utc_now, limits));
+
+        let excess = (self.len() + room).saturating_sub(RATE_LIMIT_MAX_KEYS);
+        let mut buckets: Vec<(Instant, String)> = self.buckets.iter().map(|(k, b)| (b.updated, k.clone())).collect();
+        buckets.sort_unstable();
+        for (_, key) in buckets.into_iter().take(excess) {
+            self.buckets.remove(&key);
+        }
+
+        let excess = (self.len() + room).saturating_sub(RATE_LIMIT_MAX_KEYS);
+        let mut failures: Vec<(bool, DateTime<Utc>, String)> = self
+            .failures
+            .iter()
+            .map(|(k, f)| (f.locked_until.is_some_and(|until| until > utc_now), f.last_failure, k.clone()))
+            .collect();
+        failures.sort_unstable();
+        for (_, _, key) in failures.into_iter().take(excess) {
+            self.failures.remove(&key);
+        }
+    }
+}
+
+/// In-memory token buckets per client IP and per username, and lockouts after repeated failed logins.
+#[derive(Clone, Default)]
+pub struct LoginLimiter(Arc<Mutex<LimiterState>>);
+
+impl LoginLimiter {
+    fn key(username: &str) -> String {
+        username.trim().to_lowercase()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, LimiterState> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Counts an attempt, failing if the account is locked or either bucket is empty.
+    pub fn attempt(&self, ip: Option<IpAddr>, username: &str, limits: &LoginLimits) -> ApiResult<()> {
+        let username = Self::key(username);
+        let mut state = self.state();
+        state.prune(limits, 2);
+
+        if let Some(until) = state.failures.get(&username).and_then(|f| f.locked_until)
+            && until > Utc::now()
+        {
+            return Err(ApiError::rate_limited(format!("Account locked until {}", until.to_rfc3339())));
+        }
+        if let Some(ip) = ip
+            && !state.take(format!("ip:{ip}"), limits.ip_capacity, limits.ip_refill_seconds)
+        {
+            return Err(ApiError::rate_limited("Too many login attempts from this address"));
+        }
+        if !state.take(format!("user:{username}"), limits.username_capacity, limits.username_refill_seconds) {
+            return Err(ApiError::rate_limited("Too many login attempts for this account"));
+        }
+        Ok(())
+    }
+
+    /// Records a failed login. Returns when the account is locked until, if this failure locked it.
+    pub fn failure(&self, username: &str, limits: &LoginLimits) -> Option<DateTime<Utc>> {
+        let now = Utc::now();
+        let mut state = self.state();
+        state.prune(limits, 1);
+        let failures = state.failures.entry(Self::key(username)).or_default();
+        if failures.expired(now, limits) {
+            *failures = Failures::default();
+        }
+        failures.count += 1;
+        failures.last_failure = now;
+        if failures.count < limits.lockout_threshold.max(1) {
+            return None;
+        }
+
+        let doublings = (failures.count - limits.lockout_threshold.max(1)).min(20);
+        let seconds = limits
+            .lockout_base_seconds
+            .saturating_mul(1 << doublings)
+            .min(limits.lockout_max_seconds);
+        let until = now + TimeDelta::seconds(seconds as i64);
+        failures.locked_until = Some(until);
+        Some(until)
+    }
+
+    pub fn success(&self, username: &str) {
+        self.state().failures.remove(&Self::key(username));
+    }
+}
+
+/// Request guard combining the limiter with the configured limits and the client's address.
+pub struct LoginThrottle {
+    limiter: LoginLimiter,
+    limits: LoginLimits,
+    auditor: Auditor,
+    pub ip: Option<IpAddr>,
+}
+
+impl LoginThrottle {
+    pub fn attempt(&self, username: &str) -> ApiResult<()> {
+        self.limiter.attempt(self.ip, username, &self.limits)
+    }
+
+    /// Records a failed attempt, auditing the lockout it may cause.
+    pub async fn failed(&self, username: &str) {
+        if let Some(until) = self.limiter.failure(username, &self.limits) {
+            warn!("Locked logins for {username} until {until} after repeated failures");
+            self.auditor
+                .entry("auth.lockout")
+                .target(username)
+                .details(serde_json::json!({ "locked_until": until }))
+                .record()
+                .await;
+        }
+    }
+
+    pub fn succeeded(&self, username: &str) {
+        self.limiter.success(username);
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for LoginThrottle {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let config = match req.guard::<AppConfig>().await {
+            request::Outcome::Success(config) => config,
+            request::Outcome::Error(e) => return request::Outcome::Error(e),
+            request::Outcome::Forward(f) => return request::Outcome::Forward(f),
+        };
+        let auditor = match req.guard::<Auditor>().await {
+            request::Outcome::Success(auditor) => auditor,
+            request::Outcome::Error(e) => return request::Outcome::Error(e),
+            request::Outcome::Forward(f) => return request::Outcome::Forward(f),
+        };
+        request::Outcome::Success(Self {
+            limiter: req
+                .rocket()
+                .state::<LoginLimiter>()
+                .expect("No login limiter initialized.")
+                .clone(),
+            limits: config.authentication.login_limits,
+            auditor,
+            ip: req.client_ip(),
+        })
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for LoginThrottle {
+    fn from_request_input(
+        _gen: &mut rocket_okapi::r#gen::OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<rocket_okapi::request::RequestHeaderInput> {
+        Ok(rocket_okapi::request::RequestHeaderInput::None)
+    }
+}
@@ -0,0 +1,179 @@
+use std::{collections::HashMap, sync::Arc};
+
+use bson::Uuid;
+use bytes::Bytes;
+use futures::StreamExt;
+use rocket::{
+    Request,
+    request::{self, FromRequest},
+};
+use rocket_okapi::OpenApiFromRequest;
+use slink_common::{
+    ApiError, ApiResult, Error,
+    protocols::RconClient,
+    runners::{MinecraftRunner, MinecraftRunnerStatus, docker_host::DockerHostRunner},
+    types::{AppConfig, ServerProperties},
+};
+use tokio::sync::{Mutex, RwLock, broadcast};
+
+use crate::{
+    models::{MinecraftServer, OnlinePlayer},
+    services::players::PlayerTracker,
+};
+
+const CONSOLE_BUFFER_SIZE: usize = 1024;
+
+#[derive(Clone)]
+pub struct ServerInstance {
+    pub server: Uuid,
+    pub runner: Arc<Mutex<DockerHostRunner>>,
+    pub players: Arc<RwLock<HashMap<String, OnlinePlayer>>>,
+    console: Arc<RwLock<Option<broadcast::Sender<Bytes>>>>,
+}
+
+impl ServerInstance {
+    fn new(server: Uuid, runner: DockerHostRunner) -> Self {
+        Self {
+            server,
+            runner: Arc::new(Mutex::new(runner)),
+            players: Arc::new(RwLock::new(HashMap::new())),
+            console: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Installs the runner if needed, starts it, and attaches the console pump and player tracker.
+    pub async fn start(&self, server: &MinecraftServer) -> ApiResult<MinecraftRunnerStatus> {
+        let status = {
+            let mut runner = self.runner.lock().await;
+            if !runner.status().await.initialized() {
+                runner.install().await?;
+            }
+            runner.start().await?
+        };
+
+        let receiver = self.attach_console().await?;
+        PlayerTracker::spawn(self.clone(), server, receiver);
+        Ok(status)
+    }
+
+    pub async fn stop(&self) -> ApiResult<MinecraftRunnerStatus> {
+        Ok(self.runner.lock().await.stop().await?)
+    }
+
+    /// Subscribes to the console output of the current run, if the server is running.
+    pub async fn subscribe(&self) -> Option<broadcast::Receiver<Bytes>> {
+        self.console
+            .read()
+            .await
+            .as_ref()
+            .map(|sender| sender.subscribe())
+    }
+
+    /// Runs a console command, over RCON when it is available so that the output can be returned.
+    pub async fn execute(&self, command: impl AsRef<str>, properties: &ServerProperties) -> ApiResult<Option<String>> {
+        if command.as_ref().contains(['\r', '\n']) {
+            return Err(Error::value_error(command.as_ref(), "Commands must be a single line").into());
+        }
+
+        let mut runner = self.runner.lock().await;
+        let status = runner.status().await;
+        if !status.running() {
+            return Err(ApiError::invalid_state("Server is not running"));
+        }
+
+        if let (true, true, Some(password)) = (status.online(), properties.enable_rcon, properties.rcon_password.clone()) {
+            let mut client = RconClient::connect(runner.address().await?, properties.rcon_port, password).await?;
+            return Ok(Some(client.execute(command.as_ref()).await?));
+        }
+
+        runner.send_command(command.as_ref()).await?;
+        Ok(None)
+    }
+
+    /// Reads the runner's output in a single task and fans it out to all subscribers until the process exits.
+    async fn attach_console(&self) -> ApiResult<broadcast::Receiver<Bytes>> {
+        let mut reader = self.runner.lock().await.get_reader().await?;
+        let (sender, receiver) = broadcast::channel::<Bytes>(CONSOLE_BUFFER_SIZE);
+        *self.console.write().await = Some(sender.clone());
+
+        let console = self.console.clone();
+        tokio::spawn(async move {
+            while let Some(output) = reader.next().await {
+                if let Some(chunk) = output {
+                    let _ = sender.send(chunk);
+                }
+            }
+
+            let mut current = console.write().await;
+            if current.as_ref().is_some_and(|s| s.same_channel(&sender)) {
+                *current = None;
+            }
+        });
+
+        Ok(receiver)
+    }
+}
+
+#[derive(Clone, Default, OpenApiFromRequest)]
+pub struct Runners(Arc<RwLock<HashMap<Uuid, ServerInstance>>>);
+
+impl Runners {
+    pub async fn get(&self, id: &Uuid) -> Option<ServerInstance> {
+        self.0.read().await.get(id).cloned()
+    }
+
+    /// Forgets a server's instance, once the server has been deleted.
+    pub async fn remove(&self, id: &Uuid) -> Option<ServerInstance> {
+        self.0.write().await.remove(id)
+    }
+
+    /// Returns the live instance for a server, creating an idle one if none exists yet.
+    pub async fn instance(&self, server: &MinecraftServer, config: &AppConfig) -> ApiResult<ServerInstance> {
+        if let Some(existing) = self.get(&server.id).await {
+            return Ok(existing);
+        }
+
+        self.create(server, config).await
+    }
+
+    /// Returns an instance ready to be started, rebuilding the runner from the current
+    /// server document and properties unless it is already running.
+    pub async fn prepare(&self, server: &MinecraftServer, config: &AppConfig) -> ApiResult<ServerInstance> {
+        if let Some(existing) = self.get(&server.id).await
+            && existing.runner.lock().await.status().await.running()
+        {
+            return Ok(existing);
+        }
+
+        tokio::fs::create_dir_all(server.directory(config))
+            .await
+            .map_err(|e| ApiError::from(Error::Unexpected(e.to_string())))?;
+        self.create(server, config).await
+    }
+
+    async fn create(&self, server: &MinecraftServer, config: &AppConfig) -> ApiResult<ServerInstance> {
+        let options = config
+            .runner
+            .as_docker_host()
+            .ok_or(ApiError::configuration("Unsupported runner mode"))?;
+        let runner = DockerHostRunner::new(server.runner_config(&server.properties(config).await), options)?;
+        let instance = ServerInstance::new(server.id, runner);
+
+        self.0.write().await.insert(server.id, instance.clone());
+        Ok(instance)
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for Runners {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(
+            req.rocket()
+                .state::<Runners>()
+                .expect("No runner registry initialized.")
+                .clone(),
+        )
+    }
+}
@@ -1,4 +1,8 @@
+use std::sync::OnceLock;
+
+use okapi::openapi3::{Object, SecurityRequirement, SecurityScheme, SecuritySchemeData};
 use orion::pwhash::{self, PasswordHash};
+use rocket_okapi::request::RequestHeaderInput;
 use serde::{Deserialize, Serialize};
 use slink_common::{ApiError, ApiResult, HASHING_ITERATIONS, HASHING_MEMORY};
 
@@ -18,6 +22,18 @@ impl HashedPassword {
         PasswordHash::from_encoded(&self.0).expect("Unable to decode encoded password hash.")
     }
 
+    /// Hash of a random password, for verifying against when a user does not exist, so that unknown usernames
+    /// take as long to reject as wrong passwords.
+    pub fn dummy() -> &'static Self {
+        static DUMMY: OnceLock<HashedPassword> = OnceLock::new();
+        DUMMY.get_or_init(|| {
+            let password = random_bytes(32)
+                .and_then(|b| Ok(slink_common::utilities::base32_encode(&b)))
+                .expect("Unable to generate dummy password.");
+            Self::new(password).expect("Unable to hash dummy password.")
+        })
+    }
+
     pub fn verify(&self, password: impl Into<String>) -> bool {
         if let Ok(parsed) =
             orion::pwhash::Password::from_slice(Into::<String>::into(password).as_bytes())
@@ -34,3 +50,30 @@ impl Into<String> for HashedPassword {
         self.0.clone()
     }
 }
+
+/// Cryptographically secure random bytes, for secrets handed to users or providers.
+pub fn random_bytes(length: usize) -> ApiResult<Vec<u8>> {
+    let mut bytes = vec![0u8; length];
+    orion::util::secure_rand_bytes(&mut bytes).or_else(|e| Err(ApiError::CryptographicError(e.to_string())))?;
+    Ok(bytes)
+}
+
+/// OpenAPI security requirement of endpoints that need a logged-in user: an API token, or the session cookie.
+pub fn session_security() -> RequestHeaderInput {
+    let mut requirement = SecurityRequirement::new();
+    requirement.insert("ApiToken".to_string(), Vec::new());
+    RequestHeaderInput::Security(
+        "ApiToken".to_string(),
+        SecurityScheme {
+            description: Some(
+                "Personal API token created under `/auth/tokens`. Browsers authenticate with the `slink.token` session cookie instead.".to_string(),
+            ),
+            data: SecuritySchemeData::Http {
+                scheme: "bearer".to_string(),
+                bearer_format: Some("slink_<id>_<secret>".to_string()),
+            },
+            extensions: Object::default(),
+        },
+        requirement,
+    )
+}
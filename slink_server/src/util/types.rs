@@ -17,3 +17,49 @@ impl<T: Model> From<Link<T>> for TSLink {
         }
     }
 }
+
+/// Reads a [bson::DateTime], also accepting the RFC 3339 strings that earlier versions stored dates as. Use with
+/// `#[serde(deserialize_with = "bson_date::deserialize")]`; dates are always written as BSON dates.
+pub mod bson_date {
+    use bson::Bson;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, de::Error};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bson::DateTime, D::Error> {
+        match Bson::deserialize(deserializer)? {
+            Bson::DateTime(date) => Ok(date),
+            Bson::String(text) => DateTime::parse_from_rfc3339(&text)
+                .map(|date| bson::DateTime::from_chrono(date.with_timezone(&Utc)))
+                .map_err(D::Error::custom),
+            other => Err(D::Error::custom(format!("Expected a date, found {other}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{Bson, doc};
+    use chrono::{TimeZone, Utc};
+    use serde::Deserialize;
+
+    use super::bson_date;
+
+    #[derive(Deserialize)]
+    struct Stored {
+        #[serde(deserialize_with = "bson_date::deserialize")]
+        date: bson::DateTime,
+    }
+
+    #[test]
+    fn reads_dates_and_legacy_strings() {
+        let expected = Utc.with_ymd_and_hms(2024, 6, 1, 12, 30, 0).unwrap();
+        let stored: Stored = bson::from_document(doc! {"date": bson::DateTime::from_chrono(expected)}).unwrap();
+        assert_eq!(stored.date.to_chrono(), expected);
+
+        let legacy: Stored = bson::from_document(doc! {"date": "2024-06-01T14:30:00+02:00"}).unwrap();
+        assert_eq!(legacy.date.to_chrono(), expected);
+
+        assert!(bson::from_document::<Stored>(doc! {"date": "yesterday"}).is_err());
+        assert!(bson::from_document::<Stored>(doc! {"date": Bson::Int32(5)}).is_err());
+    }
+}